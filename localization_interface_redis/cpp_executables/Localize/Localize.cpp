@@ -5,6 +5,9 @@
 #include <iomanip>
 #include <algorithm>
 #include <cctype>
+#include <chrono>
+#include <fstream>
+#include <stdexcept>
 
 #if defined(_WIN32)
 #include <windows.h>
@@ -13,6 +16,7 @@
 #endif
 
 #include <PhoLocalization.h>
+#include <nlohmann/json.hpp>
 
 #if defined(_WIN32)
 #define LOCAL_CROSS_SLEEP(Millis) Sleep(Millis)
@@ -24,7 +28,13 @@
 
 using namespace pho::sdk;
 
+// Bump together with REQUEST_SCHEMA_VERSION in src/core/request_file.rs
+// whenever the request document changes shape.
+#define LOCALIZE_REQUEST_SCHEMA_VERSION 1
+
 // ARGUMENTS:
+// Either a single --request=<path> pointing to a JSON request file
+// (see LoadLocalizeRequestFile), or the legacy positional arguments:
 // 1 - hardware_identification
 // 2 - scene_name
 // 3 - target_name
@@ -59,12 +69,170 @@ bool to_bool(std::string str) {
 	return b;
 }
 
+struct LocalizeRequest
+{
+    std::string HardwareIdentification;
+    std::string SceneName;
+    std::string TargetName;
+    std::string SourceFormat;
+    int StopAtTimeout = 0;
+    int StopAtNumber = 1;
+    bool SceneNoiseReduction = true;
+    bool SmartMemory = false;
+    std::string SceneClusteringLevel;
+    int SceneMinimalClusterSize = 0;
+    int SceneMaximalClusterSize = 0;
+    std::string MatchingAlgorithm;
+    std::string ModelKeypointsSampling;
+    std::string LocalSearchRadius;
+    std::string FineAlignmentPointSet;
+    std::string FineAlignmentPointSetSampling;
+    int ProjectionTolerance = 0;
+    int ProjectionHiddenPartTolerance = 0;
+    double Overlap = 0;
+    std::string PrawsInputFolder;
+    std::string PlysInputFolder;
+    std::string PlcfsInputFolder;
+};
+
+LocalizeRequest LoadLocalizeRequestFile(const std::string &path) {
+    std::ifstream file(path);
+    if (!file) {
+        throw std::runtime_error("Could not open request file " + path);
+    }
+
+    nlohmann::json document;
+    try {
+        file >> document;
+    } catch (const nlohmann::json::exception &ex) {
+        throw std::runtime_error("Request file " + path + " is not valid JSON: " + ex.what());
+    }
+
+    const int schema_version = document.value("schema_version", 0);
+    if (schema_version != LOCALIZE_REQUEST_SCHEMA_VERSION) {
+        throw std::runtime_error("Request file schema version " + std::to_string(schema_version) +
+                                 " is not supported, expected " +
+                                 std::to_string(LOCALIZE_REQUEST_SCHEMA_VERSION));
+    }
+
+    LocalizeRequest request;
+    try {
+        request.HardwareIdentification = document.at("scanner").at("hardware_identification").get<std::string>();
+        request.SceneName = document.at("scene_name").get<std::string>();
+        request.TargetName = document.at("target_name").get<std::string>();
+        request.SourceFormat = document.at("source_format").get<std::string>();
+        request.StopAtTimeout = document.at("stop_criteria").at("timeout").get<int>();
+        request.StopAtNumber = document.at("stop_criteria").at("number_of_results").get<int>();
+
+        const auto &settings = document.at("settings");
+        request.SceneNoiseReduction = settings.at("scene_noise_reduction").get<bool>();
+        request.SmartMemory = settings.at("smart_memory").get<bool>();
+        request.SceneClusteringLevel = settings.at("scene_clustering_level").get<std::string>();
+        request.SceneMinimalClusterSize = settings.at("scene_minimal_cluster_size").get<int>();
+        request.SceneMaximalClusterSize = settings.at("scene_maximal_cluster_size").get<int>();
+        request.MatchingAlgorithm = settings.at("matching_algorithm").get<std::string>();
+        request.ModelKeypointsSampling = settings.at("model_keypoints_sampling").get<std::string>();
+        request.LocalSearchRadius = settings.at("local_search_radius").get<std::string>();
+        request.FineAlignmentPointSet = settings.at("fine_alignment_point_set").get<std::string>();
+        request.FineAlignmentPointSetSampling = settings.at("fine_alignment_point_set_sampling").get<std::string>();
+        request.ProjectionTolerance = settings.at("projection_tolerance").get<int>();
+        request.ProjectionHiddenPartTolerance = settings.at("projection_hidden_part_tolerance").get<int>();
+        request.Overlap = settings.at("overlap").get<double>();
+
+        const auto &directories = document.at("directories");
+        request.PrawsInputFolder = directories.at("praw_dir").get<std::string>();
+        request.PlysInputFolder = directories.at("ply_dir").get<std::string>();
+        request.PlcfsInputFolder = directories.at("plcf_dir").get<std::string>();
+    } catch (const nlohmann::json::exception &ex) {
+        throw std::runtime_error("Request file " + path + " is incomplete: " + ex.what());
+    }
+
+    return request;
+}
+
+LocalizeRequest LoadLegacyLocalizeRequest(int argc, char* argv[]) {
+    if (argc < 26) {
+        throw std::runtime_error("Expected 25 arguments, got " + std::to_string(argc - 1));
+    }
+
+    LocalizeRequest request;
+    request.HardwareIdentification = argv[1];
+    request.SceneName = argv[2];
+    request.TargetName = argv[3];
+    request.SourceFormat = argv[4];
+    request.StopAtTimeout = std::stoi(argv[5]);
+    request.StopAtNumber = std::stoi(argv[6]);
+    request.SceneNoiseReduction = to_bool(std::string(argv[7]));
+    request.SmartMemory = to_bool(std::string(argv[8]));
+    request.SceneClusteringLevel = argv[9];
+    request.SceneMinimalClusterSize = std::stoi(argv[10]);
+    request.SceneMaximalClusterSize = std::stoi(argv[11]);
+    request.MatchingAlgorithm = argv[12];
+    request.ModelKeypointsSampling = argv[13];
+    request.LocalSearchRadius = argv[14];
+    request.FineAlignmentPointSet = argv[18];
+    request.FineAlignmentPointSetSampling = argv[19];
+    request.ProjectionTolerance = std::stoi(argv[20]);
+    request.ProjectionHiddenPartTolerance = std::stoi(argv[21]);
+    request.Overlap = std::stod(argv[22]);
+    request.PrawsInputFolder = argv[23];
+    request.PlysInputFolder = argv[24];
+    request.PlcfsInputFolder = argv[25];
+    return request;
+}
+
+LocalizeRequest LoadLocalizeRequest(int argc, char* argv[]) {
+    const std::string request_flag = "--request=";
+    if (argc == 2 && std::string(argv[1]).rfind(request_flag, 0) == 0) {
+        return LoadLocalizeRequestFile(std::string(argv[1]).substr(request_flag.size()));
+    }
+    return LoadLegacyLocalizeRequest(argc, argv);
+}
+
+// Result records, one JSON object per line, parsed by result_protocol.rs.
+long long ElapsedMs(const std::chrono::steady_clock::time_point &start) {
+    return std::chrono::duration_cast<std::chrono::milliseconds>(
+        std::chrono::steady_clock::now() - start).count();
+}
+
+void PrintDetection(std::size_t index, const LocalizationPose &pose, long long elapsed_ms) {
+    nlohmann::json matrix = nlohmann::json::array();
+    for (std::size_t row = 0; row < 4; row++) {
+        nlohmann::json values = nlohmann::json::array();
+        for (std::size_t column = 0; column < 4; column++) {
+            values.push_back(pose.Transformation[row][column]);
+        }
+        matrix.push_back(values);
+    }
+    nlohmann::json record = {
+        {"type", "detection"},
+        {"index", index},
+        {"matrix", matrix},
+        {"overlap", pose.VisibleOverlap},
+        {"elapsed_ms", elapsed_ms},
+    };
+    std::cout << record.dump() << std::endl;
+}
+
+void PrintSummary(std::size_t count, long long elapsed_ms) {
+    nlohmann::json record = {
+        {"type", "summary"},
+        {"count", count},
+        {"elapsed_ms", elapsed_ms},
+    };
+    std::cout << record.dump() << std::endl;
+}
+
 int main(int argc, char* argv[]) {
     std::unique_ptr<PhoLocalization> localization;
 
-    std::string PrawsInputFolder = argv[23];
-    std::string PlysInputFolder = argv[24];
-	std::string PlcfsInputFolder = argv[25];
+    LocalizeRequest request;
+    try {
+        request = LoadLocalizeRequest(argc, argv);
+    } catch (const std::exception &ex) {
+        std::cout << "Request Error: " << ex.what() << std::endl;
+        return EXIT_FAILURE;
+    }
 
     try {
         localization.reset(new PhoLocalization());
@@ -75,17 +243,17 @@ int main(int argc, char* argv[]) {
 
     try {
         SceneSource scene;
-        if (std::string(argv[4]) == "praw")
+        if (request.SourceFormat == "praw")
         {
-			scene = SceneSource::File(PrawsInputFolder + DELIMITER + argv[2] + ".praw");
+			scene = SceneSource::File(request.PrawsInputFolder + DELIMITER + request.SceneName + ".praw");
         }
-		if (std::string(argv[4]) == "ply")
+		if (request.SourceFormat == "ply")
         {
-            scene = SceneSource::File(PlysInputFolder + DELIMITER + argv[2] + ".ply");
+            scene = SceneSource::File(request.PlysInputFolder + DELIMITER + request.SceneName + ".ply");
         }
-		if (std::string(argv[4]) == "live")
+		if (request.SourceFormat == "live")
         {
-			scene = SceneSource::PhoXi(std::string(argv[1]));
+			scene = SceneSource::PhoXi(request.HardwareIdentification);
         }
         localization->SetSceneSource(scene);
     } catch (const PhoLocalizationException &ex) {
@@ -94,37 +262,38 @@ int main(int argc, char* argv[]) {
     }
 
     try {
-		localization->LoadLocalizationConfiguration(PlcfsInputFolder + DELIMITER + std::string(argv[3]) + ".plcf");
+		localization->LoadLocalizationConfiguration(request.PlcfsInputFolder + DELIMITER + request.TargetName + ".plcf");
     } catch (const IOException &ex) {
         std::cout << "Error loading plcf file: " << ex.what() << std::endl;
         return EXIT_FAILURE;
     }
 
     localization->ClearStopCriteria();
-    localization->SetStopCriterion(StopCriterion::Timeout(std::stoi(argv[5])));
-    localization->SetStopCriterion(StopCriterion::NumberOfResults(std::stoi(argv[6])));
-
-    localization->setSetting("Scene Noise Reduction", to_bool(std::string(argv[7])));
-	localization->setSetting("Smart Memory", to_bool(std::string(argv[8])));
-    localization->setSetting("Scene Clustering Level", std::string(argv[9]));
-    localization->setSetting("Scene Minimal Cluster Size", std::stoi(argv[10]));
-    localization->setSetting("Scene Maximal Cluster Size", std::stoi(argv[11]));
-    localization->setSetting("Matching Algorithm", std::string(argv[12]));
-    localization->setSetting("Model Keypoints Sampling", std::string(argv[13]));
-    localization->setSetting("Local Search Radius", std::string(argv[14]));
+    localization->SetStopCriterion(StopCriterion::Timeout(request.StopAtTimeout));
+    localization->SetStopCriterion(StopCriterion::NumberOfResults(request.StopAtNumber));
+
+    localization->setSetting("Scene Noise Reduction", request.SceneNoiseReduction);
+	localization->setSetting("Smart Memory", request.SmartMemory);
+    localization->setSetting("Scene Clustering Level", request.SceneClusteringLevel);
+    localization->setSetting("Scene Minimal Cluster Size", request.SceneMinimalClusterSize);
+    localization->setSetting("Scene Maximal Cluster Size", request.SceneMaximalClusterSize);
+    localization->setSetting("Matching Algorithm", request.MatchingAlgorithm);
+    localization->setSetting("Model Keypoints Sampling", request.ModelKeypointsSampling);
+    localization->setSetting("Local Search Radius", request.LocalSearchRadius);
 
 	// These are problematic, don't know how to se them, what is their type? 
 	//localization->setSetting("Feature Fit Consideration Level", sscanf(argv[15], "%zu"));
     //localization->setSetting("Global Maximal Feature Fit Overflow", std::stod(argv[16]));
     //localization->setSetting("Fine Alignment Iterations", std::stoi(argv[17]));
     
-	localization->setSetting("Fine Alignment Point Set", std::string(argv[18]));
-    localization->setSetting("Fine Alignment Point Set Sampling", std::string(argv[19]));
-    localization->setSetting("Projection Tolerance", std::stoi(argv[20]));
-    localization->setSetting("Projection Hidden Part Tolerance", std::stoi(argv[21]));
-    localization->setSetting("Overlap", std::stod(argv[22]));
+	localization->setSetting("Fine Alignment Point Set", request.FineAlignmentPointSet);
+    localization->setSetting("Fine Alignment Point Set Sampling", request.FineAlignmentPointSetSampling);
+    localization->setSetting("Projection Tolerance", request.ProjectionTolerance);
+    localization->setSetting("Projection Hidden Part Tolerance", request.ProjectionHiddenPartTolerance);
+    localization->setSetting("Overlap", request.Overlap);
 
     AsynchroneResultQueue queue;
+    const auto start = std::chrono::steady_clock::now();
     try {
         queue = localization->StartAsync();
     } catch (const PhoLocalizationException &ex) {
@@ -132,13 +301,14 @@ int main(int argc, char* argv[]) {
         return EXIT_FAILURE;
     }
 
-    TransformationMatrix4x4 result;
+    LocalizationPose result;
     std::cout << "Localization results:" << std::endl;
 	std::size_t i = 0;
     while (queue.GetNext(result)) {
-		std::cout << "RESULT " + std::to_string(i) + ": " << result << std::endl;
+		PrintDetection(i, result, ElapsedMs(start));
 		i++;
     }
+    PrintSummary(i, ElapsedMs(start));
     std::cout << "Localization finished" << std::endl;
 
     return EXIT_SUCCESS;
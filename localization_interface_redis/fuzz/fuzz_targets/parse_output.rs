@@ -0,0 +1,44 @@
+// Feeds arbitrary stdout to the parser of the Localize output. It must not
+// panic, and every detection it keeps must have a rigid matrix that turns
+// into a unit quaternion. Seed it with the golden transcripts:
+//
+//     cargo fuzz run parse_output ../tests/data/localize
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use localization_interface_redis::core::filter::ResultFilter;
+use localization_interface_redis::core::parser::{
+    is_rigid, parse_result, read_lines, rotation_matrix_to_quaternion,
+};
+use localization_interface_redis::core::state::LocalizeRequest;
+
+fuzz_target!(|data: &[u8]| {
+    let request = LocalizeRequest {
+        scene_name: "scene".to_string(),
+        target_name: "part".to_string(),
+        source_format: "ply".to_string(),
+        stop_at_timeout: 0,
+        stop_at_number: 1,
+        praw_dir: String::new(),
+        ply_dir: String::new(),
+        plcf_dir: String::new(),
+        settings: "default".to_string(),
+    };
+    let lines = read_lines(data);
+    let parsed = parse_result(&request, &ResultFilter::default(), &lines);
+
+    assert_eq!(parsed.count, parsed.results.len());
+    assert_eq!(parsed.success, parsed.count > 0);
+    for detection in &parsed.results {
+        let m = &detection.matrix;
+        assert!(is_rigid(m));
+        let rotation = [
+            [m[0][0], m[0][1], m[0][2]],
+            [m[1][0], m[1][1], m[1][2]],
+            [m[2][0], m[2][1], m[2][2]],
+        ];
+        let (w, x, y, z) = rotation_matrix_to_quaternion(&rotation);
+        assert!(((w * w + x * x + y * y + z * z).sqrt() - 1.0).abs() < 1e-9);
+    }
+});
@@ -0,0 +1,91 @@
+use async_trait::async_trait;
+use std::io;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+use super::interface::call_exec;
+use super::request_file::RequestProtocol;
+use super::simulation::SimulatedEngine;
+use super::state::LocalizeRequest;
+
+// The localization engine. Returns the lines the engine printed, which
+// are parsed by the interface, see result_protocol. An engine that failed
+// after it started keeps what it printed, see executor::CommandFailure.
+// The interface cancels the token when it no longer waits for the result.
+#[async_trait]
+pub trait LocalizationBackend: Send + Sync {
+    async fn localize(
+        &self,
+        request: &LocalizeRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Vec<u8>>, io::Error>;
+}
+
+// PHOLOC_BACKEND selects the backend, "subprocess" (the default) runs the
+// Localize executable, "simulated" runs without the SDK, see SimulatedEngine.
+pub fn backend_from_env(
+    photoneo_id: &str,
+    localization_interface_path: &str,
+    request_protocol: RequestProtocol,
+) -> Arc<dyn LocalizationBackend> {
+    let subprocess = || -> Arc<dyn LocalizationBackend> {
+        Arc::new(SubprocessBackend::new(
+            photoneo_id,
+            localization_interface_path,
+            request_protocol,
+        ))
+    };
+    match std::env::var("PHOLOC_BACKEND") {
+        Ok(backend) if backend.eq_ignore_ascii_case("simulated") => Arc::new(
+            SimulatedEngine::load(localization_interface_path, photoneo_id),
+        ),
+        Ok(backend) if backend.eq_ignore_ascii_case("subprocess") => subprocess(),
+        Ok(backend) => {
+            log::warn!(target: "phoxi_localization_interface",
+                "Unknown PHOLOC_BACKEND '{}', using 'subprocess'.", backend);
+            subprocess()
+        }
+        Err(_) => subprocess(),
+    }
+}
+
+// Runs cpp_executables/dev/Localize/bin/Localize_Release, which needs the
+// Photoneo Localization SDK.
+#[derive(Debug, Clone)]
+pub struct SubprocessBackend {
+    photoneo_id: String,
+    localization_interface_path: String,
+    request_protocol: RequestProtocol,
+}
+
+impl SubprocessBackend {
+    pub fn new(
+        photoneo_id: &str,
+        localization_interface_path: &str,
+        request_protocol: RequestProtocol,
+    ) -> SubprocessBackend {
+        SubprocessBackend {
+            photoneo_id: photoneo_id.to_string(),
+            localization_interface_path: localization_interface_path.to_string(),
+            request_protocol,
+        }
+    }
+}
+
+#[async_trait]
+impl LocalizationBackend for SubprocessBackend {
+    async fn localize(
+        &self,
+        request: &LocalizeRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Vec<u8>>, io::Error> {
+        call_exec(
+            request,
+            &self.localization_interface_path,
+            &self.photoneo_id,
+            self.request_protocol,
+            cancel,
+        )
+        .await
+    }
+}
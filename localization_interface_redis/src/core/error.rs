@@ -0,0 +1,91 @@
+use std::fmt;
+use std::io;
+
+use super::executor::command_failure;
+
+pub use photoneo_common::error::NO_ERROR;
+
+// Why a localization request failed or found nothing, published as
+// {id}_localization_error_code next to {id}_localization_error_recoverable
+// so that planners can branch on it. The codes are part of the interface,
+// do not rename them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    SettingsInvalid,
+    PlcfMissing,
+    SceneMissing,
+    LocalizationTimeout,
+    ParseError,
+    Cancelled,
+    Unknown,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::SettingsInvalid => "settings_invalid",
+            ErrorCode::PlcfMissing => "plcf_missing",
+            ErrorCode::SceneMissing => "scene_missing",
+            ErrorCode::LocalizationTimeout => "localization_timeout",
+            ErrorCode::ParseError => "parse_error",
+            ErrorCode::Cancelled => "cancelled",
+            ErrorCode::Unknown => "unknown",
+        }
+    }
+
+    // Whether the request can succeed when it is sent again, possibly after
+    // a new scan. The others need someone to look at the setup.
+    pub fn is_recoverable(&self) -> bool {
+        match self {
+            ErrorCode::SceneMissing
+            | ErrorCode::LocalizationTimeout
+            | ErrorCode::ParseError
+            | ErrorCode::Cancelled => true,
+            ErrorCode::SettingsInvalid | ErrorCode::PlcfMissing | ErrorCode::Unknown => false,
+        }
+    }
+
+    // The Localize executable reports what went wrong in the lines it
+    // prints, see its error handling in Localize.cpp, and the latest one
+    // tells. Otherwise the kind of the error decides, the interface gives
+    // its own errors one, see call_exec and preprocess::SceneError.
+    pub fn classify(error: &io::Error) -> ErrorCode {
+        if let Some(failure) = command_failure(error) {
+            if let Some(code) = failure
+                .stdout
+                .iter()
+                .chain(&failure.stderr)
+                .rev()
+                .find_map(|line| ErrorCode::from_line(line))
+            {
+                return code;
+            }
+        }
+        match error.kind() {
+            io::ErrorKind::InvalidInput => ErrorCode::SettingsInvalid,
+            io::ErrorKind::NotFound => ErrorCode::SceneMissing,
+            io::ErrorKind::TimedOut => ErrorCode::LocalizationTimeout,
+            io::ErrorKind::Interrupted => ErrorCode::Cancelled,
+            _ => ErrorCode::Unknown,
+        }
+    }
+
+    // See the std::cout and std::cerr lines in Localize.cpp.
+    fn from_line(line: &str) -> Option<ErrorCode> {
+        if line.contains("settings file") || line.contains("Settings file") {
+            Some(ErrorCode::SettingsInvalid)
+        } else if line.contains("Error loading plcf file") {
+            Some(ErrorCode::PlcfMissing)
+        } else if line.contains("SceneSource Error") {
+            Some(ErrorCode::SceneMissing)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,88 @@
+use micro_sp::*;
+use nalgebra::{Isometry3, Quaternion, Translation3, UnitQuaternion};
+use ordered_float::OrderedFloat;
+use photoneo_common::parameters::ExtrinsicParameters;
+use serde_json::Value;
+
+// Pose of the camera (the scanning frame) in a world or robot base frame.
+// Either published as the transform {id}_camera_extrinsic, saved by the
+// calibration in the shared parameters, or configured as "extrinsic" in
+// parameters/scanners/{id}.json, see photoneo_common::parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraExtrinsic {
+    pub parent_frame_id: String,
+    pub child_frame_id: String,
+    pub pose: Isometry3<f64>,
+}
+
+impl CameraExtrinsic {
+    pub fn from_transform(transform: &SPTransformStamped) -> CameraExtrinsic {
+        CameraExtrinsic {
+            parent_frame_id: transform.parent_frame_id.clone(),
+            child_frame_id: transform.child_frame_id.clone(),
+            pose: to_isometry(&transform.transform),
+        }
+    }
+
+    // None if the parameters have no extrinsic.
+    pub fn from_parameters(parameters: &Value) -> Option<Result<CameraExtrinsic, String>> {
+        Some(ExtrinsicParameters::from_parameters(parameters)?.map(|e| CameraExtrinsic {
+            parent_frame_id: e.parent_frame_id,
+            child_frame_id: e.child_frame_id,
+            pose: Isometry3::from_parts(
+                Translation3::new(e.translation.x, e.translation.y, e.translation.z),
+                UnitQuaternion::from_quaternion(Quaternion::new(
+                    e.rotation.w,
+                    e.rotation.x,
+                    e.rotation.y,
+                    e.rotation.z,
+                )),
+            ),
+        }))
+    }
+
+    // The same detections expressed in the parent frame of the extrinsic.
+    // Child frame ids are kept, so consumers pick the frame they need.
+    pub fn to_parent_frame(&self, transforms: &[SPTransformStamped]) -> Vec<SPTransformStamped> {
+        transforms
+            .iter()
+            .map(|transform| SPTransformStamped {
+                parent_frame_id: self.parent_frame_id.clone(),
+                transform: from_isometry(&(self.pose * to_isometry(&transform.transform))),
+                ..transform.clone()
+            })
+            .collect()
+    }
+}
+
+pub fn to_isometry(transform: &SPTransform) -> Isometry3<f64> {
+    let t = &transform.translation;
+    let r = &transform.rotation;
+    Isometry3::from_parts(
+        Translation3::new(t.x.into_inner(), t.y.into_inner(), t.z.into_inner()),
+        UnitQuaternion::from_quaternion(Quaternion::new(
+            r.w.into_inner(),
+            r.x.into_inner(),
+            r.y.into_inner(),
+            r.z.into_inner(),
+        )),
+    )
+}
+
+pub fn from_isometry(pose: &Isometry3<f64>) -> SPTransform {
+    let t = &pose.translation.vector;
+    let r = pose.rotation.quaternion();
+    SPTransform {
+        translation: SPTranslation {
+            x: OrderedFloat(t.x),
+            y: OrderedFloat(t.y),
+            z: OrderedFloat(t.z),
+        },
+        rotation: SPRotation {
+            x: OrderedFloat(r.i),
+            y: OrderedFloat(r.j),
+            z: OrderedFloat(r.k),
+            w: OrderedFloat(r.w),
+        },
+    }
+}
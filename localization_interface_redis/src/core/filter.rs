@@ -0,0 +1,150 @@
+use serde::Deserialize;
+use std::cmp::Ordering;
+
+use super::parser::Detection;
+
+// Post-filters applied to the detections before they are published.
+// The defaults keep every detection in the order the engine reported them.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Engine,
+    Score,
+    Distance,
+}
+
+impl SortOrder {
+    pub fn from_request(sort_by: &str) -> SortOrder {
+        match sort_by.to_lowercase().as_str() {
+            "score" => SortOrder::Score,
+            "distance" => SortOrder::Distance,
+            "" | "none" | "unknown" => SortOrder::Engine,
+            other => {
+                log::warn!(target: "phoxi_localization_interface",
+                    "Unknown sort order '{}', keeping the engine order.", other);
+                SortOrder::Engine
+            }
+        }
+    }
+}
+
+// Axis aligned box in the scanning frame, in meters. Configured per
+// scanner as "workspace" in parameters/scanners/{id}.json.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Workspace {
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+}
+
+impl Workspace {
+    pub fn contains(&self, point: [f64; 3]) -> bool {
+        (0..3).all(|i| point[i] >= self.min[i] && point[i] <= self.max[i])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultFilter {
+    // 0.0 keeps everything.
+    pub minimum_overlap: f64,
+    // 0 keeps everything.
+    pub maximum_detections: usize,
+    pub sort_by: SortOrder,
+    pub workspace: Option<Workspace>,
+}
+
+impl Default for ResultFilter {
+    fn default() -> Self {
+        ResultFilter {
+            minimum_overlap: 0.0,
+            maximum_detections: 0,
+            sort_by: SortOrder::Engine,
+            workspace: None,
+        }
+    }
+}
+
+impl ResultFilter {
+    pub fn apply(&self, detections: Vec<Detection>) -> Vec<Detection> {
+        let mut kept: Vec<Detection> = detections
+            .into_iter()
+            .filter(|detection| self.in_workspace(detection) && self.overlaps_enough(detection))
+            .collect();
+
+        match self.sort_by {
+            SortOrder::Engine => (),
+            SortOrder::Score => kept.sort_by(|a, b| compare_scores(b.score, a.score)),
+            SortOrder::Distance => kept.sort_by(|a, b| {
+                distance(a)
+                    .partial_cmp(&distance(b))
+                    .unwrap_or(Ordering::Equal)
+            }),
+        }
+
+        if self.maximum_detections > 0 {
+            kept.truncate(self.maximum_detections);
+        }
+
+        kept
+    }
+
+    fn in_workspace(&self, detection: &Detection) -> bool {
+        match &self.workspace {
+            Some(workspace) if !workspace.contains(position(detection)) => {
+                log::info!(target: "phoxi_localization_interface",
+                    "Rejecting detection {} of '{}' at {:?}, outside of the workspace.",
+                    detection.index, detection.target_name, position(detection));
+                false
+            }
+            _ => true,
+        }
+    }
+
+    fn overlaps_enough(&self, detection: &Detection) -> bool {
+        if self.minimum_overlap <= 0.0 {
+            return true;
+        }
+        match detection.overlap {
+            Some(overlap) if overlap < self.minimum_overlap => {
+                log::info!(target: "phoxi_localization_interface",
+                    "Rejecting detection {} of '{}' with overlap {} below {}.",
+                    detection.index, detection.target_name, overlap, self.minimum_overlap);
+                false
+            }
+            Some(_) => true,
+            None => {
+                // Legacy executables do not report the overlap, so there
+                // is nothing to compare against.
+                log::warn!(target: "phoxi_localization_interface",
+                    "Detection {} of '{}' has no overlap, keeping it.",
+                    detection.index, detection.target_name);
+                true
+            }
+        }
+    }
+}
+
+// Translation of the detection in meters, the engine reports millimeters.
+pub fn position(detection: &Detection) -> [f64; 3] {
+    [
+        detection.matrix[0][3] / 1000.0,
+        detection.matrix[1][3] / 1000.0,
+        detection.matrix[2][3] / 1000.0,
+    ]
+}
+
+// Distance from the origin of the scanning frame, i.e. the camera.
+fn distance(detection: &Detection) -> f64 {
+    let [x, y, z] = position(detection);
+    (x * x + y * y + z * z).sqrt()
+}
+
+// Detections without a score go last.
+fn compare_scores(a: Option<f64>, b: Option<f64>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Greater,
+        (None, Some(_)) => Ordering::Less,
+        (None, None) => Ordering::Equal,
+    }
+}
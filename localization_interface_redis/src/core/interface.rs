@@ -3,18 +3,36 @@ use ordered_float::OrderedFloat;
 use serde_json::Value;
 use tokio::time::{interval, Duration};
 
-use std::{
-    sync::{mpsc as sync_mpsc, Arc},
-    time::SystemTime,
-};
-
-use super::state::LocalizeRequest;
+use std::{io, path::Path, sync::Arc, time::SystemTime};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use super::backend::LocalizationBackend;
+use super::error::{ErrorCode, NO_ERROR};
+use super::executor::{failure_info, partial_output, run_executable};
+use super::parameters::load_shared_parameters;
+use super::extrinsic::CameraExtrinsic;
+use super::filter::{ResultFilter, SortOrder, Workspace};
+use super::naming::NamingStrategy;
+use super::parser::{parse_result, rotation_matrix_to_quaternion, Detection, ParsedResult};
+use super::plane::RansacConfig;
+use super::preprocess::{preprocess_scene, Preprocessing};
+use super::request_file::{LocalizeRequestDocument, RequestFile, RequestProtocol};
+use super::retry::{FailCounters, RetryPolicy};
+use super::settings::{LocalizationSettings, SettingsError, Timeout};
+use super::state::{LocalizationCommand, LocalizeRequest, REQUEST_CANCELLED};
+use super::support_plane::SupportPlane;
+use super::tracking::{Tracker, TrackingConfig, TrackingUpdate};
+
+pub const DEFAULT_LOCALIZATION_TIMEOUT_MS: u64 = 60000;
+pub const LOCALIZATION_TIMEOUT_MARGIN_MS: u64 = 3000;
 
 pub async fn photoneo_localization_interface(
     photoneo_id: &str,
     phoxi_scans_path: &str,
     plcfs_path: &str,
     localization_interface_path: &str,
+    backend: &Arc<dyn LocalizationBackend>,
     connection_manager: &Arc<ConnectionManager>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut interval = interval(Duration::from_millis(100));
@@ -24,6 +42,8 @@ pub async fn photoneo_localization_interface(
     let keys: Vec<String> = vec![
         &format!("{}_localization_request_trigger", photoneo_id),
         &format!("{}_localization_request_state", photoneo_id),
+        &format!("{}_localization_request_abort", photoneo_id),
+        &format!("{}_localization_command", photoneo_id),
         &format!("{}_localization_scene_name", photoneo_id),
         &format!("{}_localization_target_name", photoneo_id),
         &format!("{}_localization_source_format", photoneo_id),
@@ -31,135 +51,184 @@ pub async fn photoneo_localization_interface(
         &format!("{}_localization_stop_at_number", photoneo_id),
         &format!("{}_localization_settings", photoneo_id),
         &format!("{}_localization_scanning_frame", photoneo_id),
+        &format!("{}_localization_minimum_overlap", photoneo_id),
+        &format!("{}_localization_maximum_detections", photoneo_id),
+        &format!("{}_localization_sort_by", photoneo_id),
+        &format!("{}_localization_naming", photoneo_id),
+        &format!("{}_localization_naming_tolerance", photoneo_id),
+        &format!("{}_localization_tracking_tolerance", photoneo_id),
+        &format!("{}_localization_tracking_window", photoneo_id),
+        &format!("{}_localization_tracking_max_missed", photoneo_id),
         &format!("{}_localization_success", photoneo_id),
+        &format!("{}_localization_error_code", photoneo_id),
+        &format!("{}_localization_error_recoverable", photoneo_id),
+        &format!("{}_localization_raw_info", photoneo_id),
+        &format!("{}_localization_total_fail_counter", photoneo_id),
+        &format!("{}_localization_subsequent_fail_counter", photoneo_id),
+        &format!("{}_localization_needs_attention", photoneo_id),
+        &format!("{}_localization_retry_max_attempts", photoneo_id),
+        &format!("{}_localization_retry_backoff", photoneo_id),
+        &format!("{}_localization_attention_threshold", photoneo_id),
         &format!("{}_localization_stop_criteria_met", photoneo_id),
         &format!("{}_localization_count", photoneo_id),
         &format!("{}_localization_transforms", photoneo_id),
+        &format!("{}_localization_tracked_transforms", photoneo_id),
+        &format!("{}_localization_appeared", photoneo_id),
+        &format!("{}_localization_disappeared", photoneo_id),
+        &format!("{}_localization_world_transforms", photoneo_id),
+        &format!("{}_localization_plane_distance_threshold", photoneo_id),
+        &format!("{}_localization_plane_iterations", photoneo_id),
+        &format!("{}_plane_transform", photoneo_id),
+        &format!("{}_plane_world_transform", photoneo_id),
+        &format!("{}_plane_statistics", photoneo_id),
+        &format!("{}_camera_extrinsic", photoneo_id),
     ]
     .iter()
     .map(|k| k.to_string())
     .collect();
 
+    // Lives as long as the interface, so tracks survive between requests.
+    let mut tracker = Tracker::new();
+
     let mut con = connection_manager.get_connection().await;
+    // Set while Redis is unreachable, the old connection does not survive it.
+    let mut reconnect = false;
+    let mut running: Option<RunningRequest> = None;
     loop {
         interval.tick().await;
         if let Err(_) = connection_manager.check_redis_health(&log_target).await {
+            reconnect = true;
             continue;
         }
+        if reconnect {
+            con = connection_manager.get_connection().await;
+            reconnect = false;
+        }
         let state = match StateManager::get_state_for_keys(&mut con, &keys).await {
             Some(s) => s,
             None => continue,
         };
 
-        let mut request_trigger = state.get_bool_or_default_to_false(
+        let request_trigger = state.get_bool_or_default_to_false(
             &format!("{}_localization_request_trigger", photoneo_id),
             &log_target,
         );
 
-        let mut request_state = state.get_string_or_default_to_unknown(
+        let request_state = state.get_string_or_default_to_unknown(
             &format!("{}_localization_request_state", photoneo_id),
             &log_target,
         );
 
-        if request_trigger {
-            request_trigger = false;
-            if request_state == ServiceRequestState::Initial.to_string() {
-                let scene_name = state.get_string_or_default_to_unknown(
-                    &format!("{}_localization_scene_name", photoneo_id),
-                    &log_target,
-                );
-
-                let target_name = state.get_string_or_default_to_unknown(
-                    &format!("{}_localization_target_name", photoneo_id),
-                    &log_target,
-                );
-
-                let source_format = state.get_string_or_default_to_unknown(
-                    &format!("{}_localization_source_format", photoneo_id),
-                    &log_target,
-                );
-
-                let stop_at_timeout = state.get_int_or_default_to_zero(
-                    &format!("{}_localization_stop_at_timeout", photoneo_id),
-                    &log_target,
-                );
-
-                let stop_at_number = state.get_int_or_default_to_zero(
-                    &format!("{}_localization_stop_at_number", photoneo_id),
-                    &log_target,
-                );
-
-                let settings = state.get_string_or_default_to_unknown(
-                    &format!("{}_localization_settings", photoneo_id),
-                    &log_target,
-                );
-
-                let scanning_frame = state.get_string_or_default_to_unknown(
-                    &format!("{}_localization_scanning_frame", photoneo_id),
-                    &log_target,
-                );
+        let request_abort = state.get_bool_or_default_to_false(
+            &format!("{}_localization_request_abort", photoneo_id),
+            &log_target,
+        );
 
-                let praw_dir = format!("{phoxi_scans_path}/praw");
-                let ply_dir = format!("{phoxi_scans_path}/ply");
-                let plcf_dir = format!("{plcfs_path}");
-
-                let localize_request = LocalizeRequest {
-                    scene_name,
-                    target_name,
-                    source_format,
-                    stop_at_timeout,
-                    stop_at_number,
-                    praw_dir,
-                    ply_dir,
-                    plcf_dir,
-                    settings,
-                };
-
-                let mut success = false;
-                let mut stop_criteria_met = false;
-                let mut count = 0;
-                let mut transforms: Vec<SPTransformStamped> = vec![];
-
-                match call_blocking_exec(
-                    &localize_request,
-                    localization_interface_path,
-                    &photoneo_id,
-                ) {
-                    Ok(output_lines) => {
-                        log::info!(target: &&format!(
-                            "phoxi_localization_interface"),
-                            "Localization request succeeded."
-                        );
-                        request_state = ServiceRequestState::Succeeded.to_string();
-                        let result = parse_result(&localize_request, &output_lines);
-                        let resulting_tfs = make_transforms(&result.results, &scanning_frame);
-                        success = result.success;
-                        stop_criteria_met = result.stop_criteria_met;
-                        count = result.count;
-                        transforms = resulting_tfs;
+        if let Some(mut finished) = running.take_if(|r| r.handle.is_finished()) {
+            let outcome = (&mut finished.handle)
+                .await
+                .unwrap_or_else(|e| {
+                    Err(io::Error::other(format!("The localization task failed: {}", e)))
+                });
+
+            let request_state;
+            let mut success = false;
+            let mut stop_criteria_met = false;
+            let mut count = 0;
+            let mut transforms: Vec<SPTransformStamped> = vec![];
+            let mut tracking = TrackingUpdate::default();
+            let mut world_transforms: Vec<SPTransformStamped> = vec![];
+            let mut plane = None;
+            let mut error_code = None;
+            let raw_info;
+
+            match outcome {
+                Ok(Found::Detections(result, resulting_tfs)) => {
+                    log::info!(target: &&format!(
+                        "phoxi_localization_interface"),
+                        "Localization request succeeded."
+                    );
+                    request_state = ServiceRequestState::Succeeded.to_string();
+                    raw_info = format!("Found {} result(s).", result.count);
+                    success = result.success;
+                    stop_criteria_met = result.stop_criteria_met;
+                    count = result.count;
+                    // Nothing found because every result was unusable is
+                    // not the same as nothing in the scene.
+                    if result.count == 0 && result.discarded > 0 {
+                        error_code = Some(ErrorCode::ParseError);
                     }
-                    Err(e) => {
-                        log::error!(target: &&format!(
-                            "phoxi_localization_interface"),
-                            "Photoneo failed with error: {}.", e
-                        );
-                        request_state = ServiceRequestState::Failed.to_string();
+                    tracking = tracker.update(&resulting_tfs, &finished.tracking_config);
+                    match &finished.extrinsic {
+                        Some(extrinsic) if extrinsic.child_frame_id == finished.scanning_frame => {
+                            world_transforms = extrinsic.to_parent_frame(&resulting_tfs);
+                        }
+                        Some(extrinsic) => log::warn!(target: "phoxi_localization_interface",
+                            "Camera extrinsic is given for '{}', but the scanning frame is '{}'.",
+                            extrinsic.child_frame_id, finished.scanning_frame),
+                        None => (),
                     }
-                };
-
-                let new_state = state
-                    .update(
-                        &format!("{photoneo_id}_localization_request_trigger"),
-                        request_trigger.to_spvalue(),
-                    )
-                    .update(
-                        &format!("{photoneo_id}_localization_request_state"),
-                        request_state.to_spvalue(),
-                    )
-                    .update(
-                        &format!("{photoneo_id}_localization_success"),
-                        success.to_spvalue(),
-                    )
+                    transforms = resulting_tfs;
+                }
+                Ok(Found::Plane(found)) => {
+                    log::info!(target: log_target,
+                        "Found a plane with {} of {} points, {:.1} mm from the camera.",
+                        found.fit.inliers.len(), found.points, -found.fit.plane.offset);
+                    request_state = ServiceRequestState::Succeeded.to_string();
+                    raw_info = format!(
+                        "Found a plane with {} of {} points.",
+                        found.fit.inliers.len(),
+                        found.points
+                    );
+                    success = true;
+                    plane = Some(found);
+                }
+                Err(e) if finished.cancel.is_cancelled() => {
+                    log::warn!(target: &log_target, "Localization request was aborted.");
+                    request_state = REQUEST_CANCELLED.to_string();
+                    let output = partial_output(&e);
+                    raw_info = if output.is_empty() {
+                        e.to_string()
+                    } else {
+                        output.join("\n")
+                    };
+                    error_code = Some(ErrorCode::Cancelled);
+                }
+                Err(e) => {
+                    let code = ErrorCode::classify(&e);
+                    log::error!(target: &&format!(
+                        "phoxi_localization_interface"),
+                        "Photoneo failed with error ({}): {}.", code, e
+                    );
+                    request_state = ServiceRequestState::Failed.to_string();
+                    raw_info = failure_info(&e);
+                    error_code = Some(code);
+                }
+            };
+
+            let new_state = state
+                .update(
+                    &format!("{photoneo_id}_localization_request_trigger"),
+                    false.to_spvalue(),
+                )
+                .update(
+                    &format!("{photoneo_id}_localization_request_abort"),
+                    false.to_spvalue(),
+                )
+                .update(
+                    &format!("{photoneo_id}_localization_request_state"),
+                    request_state.to_spvalue(),
+                )
+                .update(
+                    &format!("{photoneo_id}_localization_success"),
+                    success.to_spvalue(),
+                )
+                .update(
+                    &format!("{photoneo_id}_localization_raw_info"),
+                    raw_info.to_spvalue(),
+                );
+            let new_state = match finished.command {
+                LocalizationCommand::Localize => new_state
                     .update(
                         &format!("{photoneo_id}_localization_stop_criteria_met"),
                         stop_criteria_met.to_spvalue(),
@@ -171,253 +240,570 @@ pub async fn photoneo_localization_interface(
                     .update(
                         &format!("{photoneo_id}_localization_transforms"),
                         transforms.to_spvalue(),
-                    );
+                    )
+                    .update(
+                        &format!("{photoneo_id}_localization_world_transforms"),
+                        world_transforms.to_spvalue(),
+                    )
+                    .update(
+                        &format!("{photoneo_id}_localization_tracked_transforms"),
+                        tracking.transforms.to_spvalue(),
+                    )
+                    .update(
+                        &format!("{photoneo_id}_localization_appeared"),
+                        tracking.appeared.to_spvalue(),
+                    )
+                    .update(
+                        &format!("{photoneo_id}_localization_disappeared"),
+                        tracking.disappeared.to_spvalue(),
+                    ),
+                // The detections and tracks of the last localization stay
+                // as they are.
+                LocalizationCommand::FitPlane => with_plane(
+                    new_state,
+                    photoneo_id,
+                    plane.as_ref(),
+                    &finished.scanning_frame,
+                    finished.extrinsic.as_ref(),
+                ),
+            };
+            let new_state = with_error_code(new_state, photoneo_id, error_code);
 
-                let modified_state = state.get_diff_partial_state(&new_state);
-                StateManager::set_state(&mut con, &modified_state).await;
+            let counters = FailCounters {
+                total: state.get_int_or_default_to_zero(
+                    &format!("{}_localization_total_fail_counter", photoneo_id),
+                    &log_target,
+                ),
+                subsequent: state.get_int_or_default_to_zero(
+                    &format!("{}_localization_subsequent_fail_counter", photoneo_id),
+                    &log_target,
+                ),
+            };
+            let counters = if request_state == ServiceRequestState::Succeeded.to_string() {
+                counters.succeeded()
+            } else if request_state == ServiceRequestState::Failed.to_string() {
+                let counters = counters.failed();
+                if counters.needs_attention(&finished.retry) {
+                    log::error!(target: &log_target,
+                        "{} localizations failed in a row, the interface needs attention.",
+                        counters.subsequent);
+                }
+                counters
+            } else {
+                counters
+            };
+            let new_state = with_fail_counters(new_state, photoneo_id, counters, &finished.retry);
+
+            let modified_state = state.get_diff_partial_state(&new_state);
+            StateManager::set_state(&mut con, &modified_state).await;
+        } else if request_abort {
+            match &running {
+                Some(running) => {
+                    if !running.cancel.is_cancelled() {
+                        log::info!(target: &log_target, "Aborting the running localization.");
+                        running.cancel.cancel();
+                    }
+                }
+                // Nothing to abort, unless the request was triggered but
+                // did not start yet, which is then never started.
+                None => {
+                    let mut new_state = state.update(
+                        &format!("{photoneo_id}_localization_request_abort"),
+                        false.to_spvalue(),
+                    );
+                    if request_trigger && request_state == ServiceRequestState::Initial.to_string()
+                    {
+                        new_state = new_state
+                            .update(
+                                &format!("{photoneo_id}_localization_request_trigger"),
+                                false.to_spvalue(),
+                            )
+                            .update(
+                                &format!("{photoneo_id}_localization_request_state"),
+                                REQUEST_CANCELLED.to_spvalue(),
+                            );
+                        new_state =
+                            with_error_code(new_state, photoneo_id, Some(ErrorCode::Cancelled));
+                    }
+                    let modified_state = state.get_diff_partial_state(&new_state);
+                    StateManager::set_state(&mut con, &modified_state).await;
+                }
             }
-        }
-    }
-}
+        } else if running.is_none()
+            && request_trigger
+            && request_state == ServiceRequestState::Initial.to_string()
+        {
+            let command =
+                LocalizationCommand::from_request(&state.get_string_or_default_to_unknown(
+                    &format!("{}_localization_command", photoneo_id),
+                    log_target,
+                ));
+
+            let scene_name = state.get_string_or_default_to_unknown(
+                &format!("{}_localization_scene_name", photoneo_id),
+                &log_target,
+            );
 
-pub fn call_blocking_exec(
-    request: &LocalizeRequest,
-    localization_interface_path: &str,
-    photoneo_id: &str,
-) -> Result<Vec<Vec<u8>>, String> {
-    let args = prepare_arguments(request, localization_interface_path, photoneo_id);
-    if args.is_empty() {
-        return Err("No command arguments prepared.".to_string());
-    }
+            let target_name = state.get_string_or_default_to_unknown(
+                &format!("{}_localization_target_name", photoneo_id),
+                &log_target,
+            );
 
-    let mut child = Command::new(&args[0])
-        .args(&args[1..])
-        .stdout(Stdio::piped())
-        // .stderr(Stdio::null()) // Can we use this?
-        .spawn()
-        .map_err(|e| format!("Failed to spawn process '{}': {}", args[0], e))?;
-
-    match child.stdout.take() {
-        Some(stdout_pipe) => {
-            let reader = BufReader::new(stdout_pipe);
-            let (tx, rx) = sync_mpsc::channel::<Vec<Vec<u8>>>();
-            thread::spawn(move || {
-                let lines_as_bytes: Vec<Vec<u8>> = reader
-                    .lines()
-                    .map(|line_result| {
-                        line_result
-                            .unwrap_or_else(|e| {
-                                log::warn!(
-                                    target: "phoxi_localization_interface",
-                                    "Error reading a line from stdout: {}", e
-                                );
-                                String::new()
-                            })
-                            .into_bytes()
-                    })
-                    .filter(|v_u8| !v_u8.is_empty()) // Filter out empty lines
-                    .collect();
+            let source_format = state.get_string_or_default_to_unknown(
+                &format!("{}_localization_source_format", photoneo_id),
+                &log_target,
+            );
 
-                if let Err(e) = tx.send(lines_as_bytes) {
-                    log::warn!(
-                        target: "phoxi_localization_interface",
-                        "Failed to send captured stdout lines to main thread (receiver dropped): {}",
-                        e
-                    );
-                }
-            });
+            let stop_at_timeout = state.get_int_or_default_to_zero(
+                &format!("{}_localization_stop_at_timeout", photoneo_id),
+                &log_target,
+            );
 
-            let timeout_duration = Duration::from_millis(request.stop_at_timeout as u64 + 3000);
+            let stop_at_number = state.get_int_or_default_to_zero(
+                &format!("{}_localization_stop_at_number", photoneo_id),
+                &log_target,
+            );
 
-            match rx.recv_timeout(timeout_duration) {
-                Ok(output_lines_bytes) => {
-                    let _ = child
-                        .wait()
-                        .map_err(|e| format!("Error waiting for child process to exit: {}", e));
-                    Ok(output_lines_bytes)
-                }
-                Err(recv_timeout_err) => {
-                    log::warn!(
-                        target: "phoxi_localization_interface",
-                        "Command execution timed out or channel disconnected: {:?}", recv_timeout_err
-                    );
+            let settings = state.get_string_or_default_to_unknown(
+                &format!("{}_localization_settings", photoneo_id),
+                &log_target,
+            );
+
+            let scanning_frame = state.get_string_or_default_to_unknown(
+                &format!("{}_localization_scanning_frame", photoneo_id),
+                &log_target,
+            );
+
+            // Read once, the workspace, the extrinsic and the preprocessing
+            // of the request all come from it.
+            let parameters = load_parameters(localization_interface_path, photoneo_id);
+
+            let result_filter = ResultFilter {
+                minimum_overlap: state.get_float_or_default_to_zero(
+                    &format!("{}_localization_minimum_overlap", photoneo_id),
+                    &log_target,
+                ),
+                maximum_detections: state
+                    .get_int_or_default_to_zero(
+                        &format!("{}_localization_maximum_detections", photoneo_id),
+                        &log_target,
+                    )
+                    .max(0) as usize,
+                sort_by: SortOrder::from_request(&state.get_string_or_default_to_unknown(
+                    &format!("{}_localization_sort_by", photoneo_id),
+                    &log_target,
+                )),
+                workspace: load_workspace(&parameters, &photoneo_id),
+            };
+
+            let naming = NamingStrategy::from_request(
+                &state.get_string_or_default_to_unknown(
+                    &format!("{}_localization_naming", photoneo_id),
+                    &log_target,
+                ),
+                state.get_float_or_default_to_zero(
+                    &format!("{}_localization_naming_tolerance", photoneo_id),
+                    &log_target,
+                ),
+            );
 
-                    if let Err(e) = child.kill() {
-                        log::error!(
-                            target: "phoxi_localization_interface",
-                            "Failed to kill timed-out process: {}", e
-                        );
+            let previous_transforms: Vec<SPTransformStamped> = state
+                .get_array_or_default_to_empty(
+                    &format!("{}_localization_transforms", photoneo_id),
+                    &log_target,
+                )
+                .into_iter()
+                .filter_map(|value| match value {
+                    SPValue::Transform(TransformOrUnknown::Transform(transform)) => {
+                        Some(transform)
                     }
-                    let _ = child.wait();
-                    Err(format!(
-                        "Command execution timed out or channel issue: {:?}",
-                        recv_timeout_err
-                    ))
-                }
-            }
-        }
-        None => {
-            log::error!(
-                target: "phoxi_localization_interface",
-                "Failed to capture stdout from child process."
+                    _ => None,
+                })
+                .collect();
+
+            let tracking_config = TrackingConfig::from_request(
+                state.get_float_or_default_to_zero(
+                    &format!("{}_localization_tracking_tolerance", photoneo_id),
+                    &log_target,
+                ),
+                state.get_int_or_default_to_zero(
+                    &format!("{}_localization_tracking_window", photoneo_id),
+                    &log_target,
+                ),
+                state.get_int_or_default_to_zero(
+                    &format!("{}_localization_tracking_max_missed", photoneo_id),
+                    &log_target,
+                ),
+            );
+
+            let extrinsic = load_extrinsic(&state, phoxi_scans_path, &parameters, &photoneo_id);
+
+            let retry = RetryPolicy::from_request(
+                state.get_int_or_default_to_zero(
+                    &format!("{}_localization_retry_max_attempts", photoneo_id),
+                    &log_target,
+                ),
+                state.get_int_or_default_to_zero(
+                    &format!("{}_localization_retry_backoff", photoneo_id),
+                    &log_target,
+                ),
+                // There is no connection to the engine to set up again.
+                false,
+                state.get_int_or_default_to_zero(
+                    &format!("{}_localization_attention_threshold", photoneo_id),
+                    &log_target,
+                ),
             );
-            let _ = child.wait();
-            Err("Failed to capture stdout from child process.".to_string())
+
+            // Only plys can be preprocessed, praw scenes go to the engine as they are.
+            let preprocessing = match source_format.eq_ignore_ascii_case("ply") {
+                true => load_preprocessing(&parameters, &photoneo_id),
+                false => None,
+            };
+
+            let praw_dir = format!("{phoxi_scans_path}/praw");
+            let ply_dir = format!("{phoxi_scans_path}/ply");
+            let plcf_dir = format!("{plcfs_path}");
+
+            let localize_request = LocalizeRequest {
+                scene_name,
+                target_name,
+                source_format,
+                stop_at_timeout,
+                stop_at_number,
+                praw_dir,
+                ply_dir,
+                plcf_dir,
+                settings,
+            };
+
+            // The engine runs in its own task, so the loop keeps serving
+            // Redis while it searches. The output is parsed there as well,
+            // only the tracker has to be updated from the loop.
+            let cancel = CancellationToken::new();
+            let task_backend = backend.clone();
+            let task_cancel = cancel.clone();
+            let task_scanning_frame = scanning_frame.clone();
+            let handle = match command {
+                LocalizationCommand::Localize => tokio::task::spawn(async move {
+                    let localize_request = match preprocessing {
+                        Some(preprocessing) => {
+                            let ply_dir = localize_request.ply_dir.clone();
+                            let scene_name = localize_request.scene_name.clone();
+                            let preprocessed_dir = tokio::task::spawn_blocking(move || {
+                                preprocess_scene(&ply_dir, &scene_name, &preprocessing)
+                            })
+                            .await
+                            .map_err(|e| {
+                                io::Error::other(format!("The preprocessing task failed: {}", e))
+                            })??;
+                            LocalizeRequest {
+                                ply_dir: preprocessed_dir,
+                                ..localize_request
+                            }
+                        }
+                        None => localize_request,
+                    };
+                    let output_lines = localize_with_retry(
+                        task_backend.as_ref(),
+                        &localize_request,
+                        &retry,
+                        &task_cancel,
+                    )
+                    .await?;
+                    let result = parse_result(&localize_request, &result_filter, &output_lines);
+                    let transforms = make_transforms(
+                        &result.results,
+                        &task_scanning_frame,
+                        &naming,
+                        &previous_transforms,
+                    );
+                    Ok(Found::Detections(result, transforms))
+                }),
+                LocalizationCommand::FitPlane => {
+                    let config = RansacConfig::from_request(
+                        state.get_float_or_default_to_zero(
+                            &format!("{}_localization_plane_distance_threshold", photoneo_id),
+                            log_target,
+                        ),
+                        state.get_int_or_default_to_zero(
+                            &format!("{}_localization_plane_iterations", photoneo_id),
+                            log_target,
+                        ),
+                    );
+                    // The plane is searched in the original scan, only the
+                    // crop box of the preprocessing applies.
+                    let crop = load_preprocessing(&parameters, &photoneo_id)
+                        .and_then(|preprocessing| preprocessing.crop);
+                    tokio::task::spawn(async move {
+                        let estimate = tokio::task::spawn_blocking(move || {
+                            SupportPlane::from_scene(
+                                &localize_request.ply_dir,
+                                &localize_request.scene_name,
+                                crop.as_ref(),
+                                &config,
+                            )
+                        });
+                        // RANSAC cannot be interrupted, an aborted request
+                        // does not wait for it.
+                        tokio::select! {
+                            plane = estimate => plane
+                                .map_err(|e| {
+                                    io::Error::other(format!("The plane task failed: {}", e))
+                                })?
+                                .map(Found::Plane)
+                                .map_err(io::Error::from),
+                            _ = task_cancel.cancelled() => Err(io::Error::new(
+                                io::ErrorKind::Interrupted,
+                                "The plane estimation was cancelled.",
+                            )),
+                        }
+                    })
+                }
+            };
+            running = Some(RunningRequest {
+                command,
+                cancel,
+                handle,
+                retry,
+                scanning_frame,
+                tracking_config,
+                extrinsic,
+            });
         }
     }
 }
 
-struct ParsedResult {
-    success: bool,
-    stop_criteria_met: bool,
-    count: usize,
-    results: Vec<([[f64; 4]; 4], String)>,
+// What a finished request found, depending on its command.
+enum Found {
+    Detections(ParsedResult, Vec<SPTransformStamped>),
+    Plane(SupportPlane),
 }
 
-impl ParsedResult {
-    fn new() -> Self {
-        ParsedResult {
-            success: false,
-            stop_criteria_met: false,
-            count: 0,
-            results: Vec::new(),
-        }
-    }
+// A request whose localization is still running, with what is needed to
+// publish its results. Dropping it, e.g. when the interface stops, cancels
+// the localization.
+struct RunningRequest {
+    command: LocalizationCommand,
+    cancel: CancellationToken,
+    handle: JoinHandle<Result<Found, io::Error>>,
+    scanning_frame: String,
+    tracking_config: TrackingConfig,
+    extrinsic: Option<CameraExtrinsic>,
+    retry: RetryPolicy,
 }
 
-// C++ documentation
-// Typedef Documentation
-// typedef std::array<std::array<float, 4>, 4> TransformationMatrix4x4
-// Transformation matrix 4x4 t
-// t[0][0]  t[0][1] t[0][2] t[0][3]       r[0][0] r[0][1] r[0][2] 	Tx
-// t[1][0]  t[1][1] t[1][2] t[1][3]   =   r[1][0] r[1][1] r[1][2] 	Ty
-// t[2][0]  t[2][1] t[2][2] t[2][3]       r[2][0] r[2][1] r[2][2] 	Tz
-// t[3][0]  t[3][1] t[3][2] t[3][3]             0       0       0 	 1
-fn parse_result(request: &LocalizeRequest, data: &[Vec<u8>]) -> ParsedResult {
-    let mut parsed = ParsedResult::new();
-    let mut result_lines: Vec<usize> = Vec::new();
-
-    // Find "RESULT" lines
-    for (i, line) in data.iter().enumerate() {
-        let split_line: Vec<&[u8]> = line.split(|&c| c == b' ').collect();
-        if split_line.contains(&&b"RESULT"[..]) {
-            result_lines.push(i);
-        }
-    }
-
-    if result_lines.is_empty() {
-        return parsed;
+impl Drop for RunningRequest {
+    fn drop(&mut self) {
+        self.cancel.cancel();
     }
+}
 
-    for &index in &result_lines {
-        if index + 2 >= data.len() {
-            continue;
-        }
+pub async fn call_exec(
+    request: &LocalizeRequest,
+    localization_interface_path: &str,
+    photoneo_id: &str,
+    request_protocol: RequestProtocol,
+    cancel: &CancellationToken,
+) -> Result<Vec<Vec<u8>>, io::Error> {
+    let settings = load_settings(request, localization_interface_path)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let parameters = load_parameters(localization_interface_path, photoneo_id);
+    let executable = format!(
+        "{}/cpp_executables/dev/Localize/bin/Localize_Release",
+        localization_interface_path,
+    );
 
-        let m1_line = &data[index];
-        let m2_line = &data[index + 1];
-        let m3_line = &data[index + 2];
-
-        let m1: Vec<f64> = m1_line
-            .split(|&c| c == b' ')
-            .filter_map(|x| parse_float(x))
-            .collect();
-        let m2: Vec<f64> = m2_line
-            .split(|&c| c == b' ')
-            .filter_map(|x| parse_float(x))
-            .collect();
-        let m3: Vec<f64> = m3_line
-            .split(|&c| c == b' ')
-            .filter_map(|x| parse_float(x))
-            .collect();
-
-        let m4 = vec![0.0, 0.0, 0.0, 1.0];
-
-        if m1.len() == 4 && m2.len() == 4 && m3.len() == 4 {
-            parsed.results.push((
-                [
-                    [m1[0], m1[1], m1[2], m1[3]],
-                    [m2[0], m2[1], m2[2], m2[3]],
-                    [m3[0], m3[1], m3[2], m3[3]],
-                    [m4[0], m4[1], m4[2], m4[3]],
-                ],
-                request.target_name.clone(),
-            ));
+    // Has to outlive the child process, the file is removed on drop.
+    let request_file;
+    let args = match request_protocol {
+        RequestProtocol::File => {
+            let document = LocalizeRequestDocument::new(request, &settings, &parameters);
+            request_file = RequestFile::write(&document, photoneo_id)?;
+            vec![executable, request_file.argument()]
         }
+        RequestProtocol::Argv => prepare_arguments(request, &executable, &settings, &parameters),
+    };
+
+    let timeout = execution_timeout(request.stop_at_timeout, settings.timeout_criterion.value);
+    let output = run_executable(&args, timeout, cancel, "phoxi_localization_interface").await?;
+    Ok(output
+        .stdout
+        .into_iter()
+        .filter(|line| !line.is_empty())
+        .map(String::into_bytes)
+        .collect())
+}
 
-        log::info!(target: &&format!(
-            "phoxi_localization_interface"),
-            "DETECTED ITEMS: {:?}", parsed.results
-        );
-    }
+// How long the executable may run before it is killed. It gets three
+// seconds on top of the requested timeout to report what it found. Without
+// one in the request, the timeout_criterion of the settings applies, and
+// DEFAULT_LOCALIZATION_TIMEOUT_MS if that is 0 as well. Only a negative
+// stop_at_timeout lets the executable run until it finishes or is aborted.
+pub fn execution_timeout(stop_at_timeout: i64, settings_timeout: Timeout) -> Option<Duration> {
+    let timeout_ms = match stop_at_timeout {
+        t if t < 0 => return None,
+        0 if settings_timeout.0 > 0 => settings_timeout.0,
+        0 => DEFAULT_LOCALIZATION_TIMEOUT_MS,
+        t => t as u64,
+    };
+    Some(Duration::from_millis(timeout_ms + LOCALIZATION_TIMEOUT_MARGIN_MS))
+}
 
-    parsed.count = parsed.results.len();
+// Every finished request publishes its error code, NO_ERROR on success.
+fn with_error_code(state: State, photoneo_id: &str, error_code: Option<ErrorCode>) -> State {
+    state
+        .update(
+            &format!("{photoneo_id}_localization_error_code"),
+            error_code
+                .map_or(NO_ERROR, |code| code.as_str())
+                .to_spvalue(),
+        )
+        .update(
+            &format!("{photoneo_id}_localization_error_recoverable"),
+            error_code
+                .is_some_and(|code| code.is_recoverable())
+                .to_spvalue(),
+        )
+}
 
-    if parsed.count == 0 {
-        parsed.success = false;
-        parsed.stop_criteria_met = false;
-    } else if request.stop_at_number as usize > parsed.count {
-        parsed.success = true;
-        parsed.stop_criteria_met = false;
-    } else {
-        parsed.success = true;
-        parsed.stop_criteria_met = true;
-    }
+// A plane request publishes the plane in the scanning frame, and in the
+// parent frame of the camera extrinsic if there is one for that frame, like
+// the detections. Unknown when none was found.
+fn with_plane(
+    state: State,
+    photoneo_id: &str,
+    plane: Option<&SupportPlane>,
+    scanning_frame: &str,
+    extrinsic: Option<&CameraExtrinsic>,
+) -> State {
+    let Some(plane) = plane else {
+        return state
+            .update(
+                &format!("{photoneo_id}_plane_transform"),
+                SPValue::Transform(TransformOrUnknown::UNKNOWN),
+            )
+            .update(
+                &format!("{photoneo_id}_plane_world_transform"),
+                SPValue::Transform(TransformOrUnknown::UNKNOWN),
+            )
+            .update(
+                &format!("{photoneo_id}_plane_statistics"),
+                SPValue::Map(MapOrUnknown::UNKNOWN),
+            );
+    };
+    let extrinsic = extrinsic.filter(|extrinsic| {
+        if extrinsic.child_frame_id != scanning_frame {
+            log::warn!(target: "phoxi_localization_interface",
+                "Camera extrinsic is given for '{}', but the scanning frame is '{}'.",
+                extrinsic.child_frame_id, scanning_frame);
+        }
+        extrinsic.child_frame_id == scanning_frame
+    });
+    let transform = plane.to_transform(scanning_frame, &format!("{photoneo_id}_plane"));
+    let world_transform = match extrinsic {
+        Some(extrinsic) => SPValue::Transform(TransformOrUnknown::Transform(
+            extrinsic
+                .to_parent_frame(std::slice::from_ref(&transform))
+                .remove(0),
+        )),
+        None => SPValue::Transform(TransformOrUnknown::UNKNOWN),
+    };
+    state
+        .update(
+            &format!("{photoneo_id}_plane_transform"),
+            SPValue::Transform(TransformOrUnknown::Transform(transform)),
+        )
+        .update(
+            &format!("{photoneo_id}_plane_world_transform"),
+            world_transform,
+        )
+        .update(
+            &format!("{photoneo_id}_plane_statistics"),
+            SPValue::Map(plane.statistics(extrinsic)),
+        )
+}
 
-    parsed
+fn with_fail_counters(
+    state: State,
+    photoneo_id: &str,
+    counters: FailCounters,
+    retry: &RetryPolicy,
+) -> State {
+    state
+        .update(
+            &format!("{photoneo_id}_localization_total_fail_counter"),
+            counters.total.to_spvalue(),
+        )
+        .update(
+            &format!("{photoneo_id}_localization_subsequent_fail_counter"),
+            counters.subsequent.to_spvalue(),
+        )
+        .update(
+            &format!("{photoneo_id}_localization_needs_attention"),
+            counters.needs_attention(retry).to_spvalue(),
+        )
 }
 
-fn parse_float(data: &[u8]) -> Option<f64> {
-    if let Ok(string) = std::str::from_utf8(data) {
-        if string.contains('.') {
-            return string.parse::<f64>().ok();
+// Runs the localization again while it fails with a recoverable error, as
+// the policy allows. The error of the last attempt is the one reported.
+async fn localize_with_retry(
+    backend: &dyn LocalizationBackend,
+    request: &LocalizeRequest,
+    retry: &RetryPolicy,
+    cancel: &CancellationToken,
+) -> Result<Vec<Vec<u8>>, io::Error> {
+    let mut attempt = 1;
+    loop {
+        let error = match backend.localize(request, cancel).await {
+            Ok(output) => return Ok(output),
+            Err(e) => e,
+        };
+        let error_code = ErrorCode::classify(&error);
+        if attempt >= retry.max_attempts || cancel.is_cancelled() || !error_code.is_recoverable()
+        {
+            return Err(error);
         }
+        log::warn!(target: "phoxi_localization_interface",
+            "Attempt {} of {} failed ({}): {}. Retrying in {:?}.",
+            attempt, retry.max_attempts, error_code, error, retry.delay(attempt));
+        if !retry.wait(attempt, cancel).await {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "Command execution was cancelled.",
+            ));
+        }
+        attempt += 1;
     }
-    None
 }
 
-type MatrixDataInternal = [[f64; 4]; 4];
-
-fn rotation_matrix_to_quaternion(m: &[[f64; 3]; 3]) -> (f64, f64, f64, f64) {
-    let trace = m[0][0] + m[1][1] + m[2][2];
-    let (w, x, y, z);
-
-    if trace > 0.0 {
-        let s = (trace + 1.0).sqrt() * 2.0;
-        w = 0.25 * s;
-        x = (m[2][1] - m[1][2]) / s;
-        y = (m[0][2] - m[2][0]) / s;
-        z = (m[1][0] - m[0][1]) / s;
-    } else if (m[0][0] > m[1][1]) && (m[0][0] > m[2][2]) {
-        let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
-        w = (m[2][1] - m[1][2]) / s;
-        x = 0.25 * s;
-        y = (m[0][1] + m[1][0]) / s;
-        z = (m[0][2] + m[2][0]) / s;
-    } else if m[1][1] > m[2][2] {
-        let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
-        w = (m[0][2] - m[2][0]) / s;
-        x = (m[0][1] + m[1][0]) / s;
-        y = 0.25 * s;
-        z = (m[1][2] + m[2][1]) / s;
-    } else {
-        let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
-        w = (m[1][0] - m[0][1]) / s;
-        x = (m[0][2] + m[2][0]) / s;
-        y = (m[1][2] + m[2][1]) / s;
-        z = 0.25 * s;
+// Keys planners can use to rank candidates or reject weak matches.
+// Score and overlap are left out when the executable did not report them.
+fn detection_metadata(detection: &Detection) -> MapOrUnknown {
+    let mut metadata = vec![
+        ("target_name".to_spvalue(), detection.target_name.to_spvalue()),
+        ("detection_index".to_spvalue(), (detection.index as i64).to_spvalue()),
+    ];
+    if let Some(score) = detection.score {
+        metadata.push(("score".to_spvalue(), score.to_spvalue()));
     }
-
-    (w, x, y, z)
+    if let Some(overlap) = detection.overlap {
+        metadata.push(("overlap".to_spvalue(), overlap.to_spvalue()));
+    }
+    MapOrUnknown::Map(metadata)
 }
 
 pub fn make_transforms(
-    matrices: &[(MatrixDataInternal, String)],
+    detections: &[Detection],
     scanning_frame: &str,
+    naming: &NamingStrategy,
+    previous_transforms: &[SPTransformStamped],
 ) -> Vec<SPTransformStamped> {
     let mut transforms: Vec<SPTransformStamped> = Vec::new();
+    let child_frame_ids = naming.child_frame_ids(detections, previous_transforms, scanning_frame);
 
-    for (matrix, child_frame_id) in matrices {
+    for (detection, child_frame_id) in detections.iter().zip(child_frame_ids) {
+        let matrix = &detection.matrix;
         let translation = SPTranslation {
             x: OrderedFloat(matrix[0][3] / 1000.0),
             y: OrderedFloat(matrix[1][3] / 1000.0),
@@ -438,19 +824,18 @@ pub fn make_transforms(
             w: OrderedFloat(w),
         };
 
-        let unique_child_frame_id = format!("{}_{}", child_frame_id, nanoid::nanoid!(6));
         let transform_stamped = SPTransformStamped {
             active_transform: true,
             enable_transform: true,
             time_stamp: SystemTime::now(),
             parent_frame_id: scanning_frame.to_string(),
-            child_frame_id: unique_child_frame_id.clone(),
+            child_frame_id,
             transform: SPTransform {
                 translation,
                 rotation,
             },
 
-            metadata: MapOrUnknown::UNKNOWN,
+            metadata: detection_metadata(detection),
         };
 
         transforms.push(transform_stamped);
@@ -459,53 +844,119 @@ pub fn make_transforms(
     transforms
 }
 
-fn prepare_arguments(
+fn load_settings(
     request: &LocalizeRequest,
     localization_interface_path: &str,
-    photoneo_id: &str,
-) -> Vec<String> {
+) -> Result<LocalizationSettings, SettingsError> {
     let settings_path = format!(
         "{}/parameters/settings/{}.json",
         localization_interface_path, request.settings
     );
 
+    if request.settings == "default" && !Path::new(&settings_path).exists() {
+        log::warn!(
+            target: "phoxi_localization_interface",
+            "Settings file {} not found. Using built-in default settings.",
+            settings_path
+        );
+        LocalizationSettings::builtin()
+    } else {
+        LocalizationSettings::load(&settings_path)
+    }
+}
+
+// The published {id}_camera_extrinsic takes precedence over the one saved
+// by the last calibration in the shared parameters, which takes precedence
+// over the "extrinsic" of the scanner parameters, see CameraExtrinsic.
+pub fn load_extrinsic(
+    state: &State,
+    phoxi_scans_path: &str,
+    parameters: &Value,
+    photoneo_id: &str,
+) -> Option<CameraExtrinsic> {
+    if let Some(SPValue::Transform(TransformOrUnknown::Transform(transform))) = state.get_value(
+        &format!("{}_camera_extrinsic", photoneo_id),
+        "phoxi_localization_interface",
+    ) {
+        return Some(CameraExtrinsic::from_transform(&transform));
+    }
+
+    let shared =
+        load_shared_parameters(phoxi_scans_path, photoneo_id, "phoxi_localization_interface");
+    let sources = [&shared, parameters];
+    sources.iter().find_map(|parameters| {
+        match CameraExtrinsic::from_parameters(parameters)? {
+            Ok(extrinsic) => Some(extrinsic),
+            Err(e) => {
+                log::warn!(target: "phoxi_localization_interface",
+                    "Ignoring invalid extrinsic in the parameters of {}: {}", photoneo_id, e);
+                None
+            }
+        }
+    })
+}
+
+// Optional "workspace" entry of the scanner parameters, see filter::Workspace.
+fn load_workspace(parameters: &Value, photoneo_id: &str) -> Option<Workspace> {
+    match parameters.get("workspace") {
+        None | Some(Value::Null) => None,
+        Some(workspace) => match serde_json::from_value(workspace.clone()) {
+            Ok(workspace) => Some(workspace),
+            Err(e) => {
+                log::warn!(target: "phoxi_localization_interface",
+                    "Ignoring invalid workspace in the parameters of {}: {}", photoneo_id, e);
+                None
+            }
+        },
+    }
+}
+
+// Optional "preprocessing" entry of the scanner parameters, see
+// preprocess::Preprocessing.
+pub fn load_preprocessing(parameters: &Value, photoneo_id: &str) -> Option<Preprocessing> {
+    let preprocessing = match parameters.get("preprocessing") {
+        None | Some(Value::Null) => return None,
+        Some(preprocessing) => serde_json::from_value::<Preprocessing>(preprocessing.clone())
+            .map_err(|e| e.to_string())
+            .and_then(|preprocessing| preprocessing.validate().map(|_| preprocessing)),
+    };
+    match preprocessing {
+        Ok(preprocessing) => Some(preprocessing),
+        Err(e) => {
+            log::warn!(target: "phoxi_localization_interface",
+                "Ignoring invalid preprocessing in the parameters of {}: {}", photoneo_id, e);
+            None
+        }
+    }
+}
+
+// The parameters/scanners/{id}.json of the interface, null if it is missing.
+pub fn load_parameters(localization_interface_path: &str, photoneo_id: &str) -> Value {
     let parameters_path = format!(
         "{}/parameters/scanners/{}.json",
         localization_interface_path, photoneo_id
     );
 
-    let settings: Value = load_json_from_file(&settings_path).unwrap_or_else(|| {
-        log::warn!(
-            "Failed to load settings from {}. Using built-in default settings.",
-            settings_path
-        );
-        serde_json::from_str(crate::core::DEFAULT_SETTINGS_JSON).unwrap_or_else(|parse_err| {
-            log::error!(
-                "CRITICAL: Failed to parse built-in default settings JSON: {}. 
-                        This indicates a bug in the default JSON string. 
-                        Using Value::Null as ultimate fallback.",
-                parse_err
-            );
-            Value::Null
-        })
-    });
-
-    // For parameters, you'd have similar logic or a different default/handling:
-    let parameters: Value = load_json_from_file(&parameters_path).unwrap_or_else(|| {
+    load_json_from_file(&parameters_path).unwrap_or_else(|| {
         log::warn!(
             "Failed to load parameters from {}. Using null as default.",
             parameters_path,
         );
         Value::Null
-    });
+    })
+}
 
+// Legacy positional protocol, see RequestProtocol::Argv.
+fn prepare_arguments(
+    request: &LocalizeRequest,
+    executable: &str,
+    settings: &LocalizationSettings,
+    parameters: &Value,
+) -> Vec<String> {
     let mut args_list: Vec<String> = Vec::new();
 
     // 0 - executable photoneo_id
-    args_list.push(format!(
-        "{}/cpp_executables/dev/Localize/bin/Localize_Release",
-        localization_interface_path,
-    ));
+    args_list.push(executable.to_string());
 
     // 1 - scanner hardware identification
     args_list.push(
@@ -531,130 +982,52 @@ fn prepare_arguments(
     args_list.push(request.stop_at_number.to_string());
 
     // 7 - scene noise reduction
-    args_list.push(bool_to_arg(
-        settings["localization_settings"]["scene_noise_reduction"]["value"]
-            .as_bool()
-            .unwrap_or(true),
-    ));
+    args_list.push(bool_to_arg(settings.scene_noise_reduction.value));
 
     // 8 - smart memory
-    args_list.push(bool_to_arg(
-        settings["localization_settings"]["smart_memory"]["value"]
-            .as_bool()
-            .unwrap_or(false),
-    ));
+    args_list.push(bool_to_arg(settings.smart_memory.value));
 
     // 9 - scene clustering level
-    args_list.push(
-        settings["localization_settings"]["scene_clustering_level"]["value"]
-            .as_str()
-            .unwrap_or("Normal")
-            .to_string(),
-    );
+    args_list.push(settings.scene_clustering_level.value.to_string());
 
     // 10 - scene minimal cluster size
-    args_list.push(
-        settings["localization_settings"]["scene_minimal_cluster_size"]["value"]
-            .as_u64()
-            .unwrap_or(200)
-            .to_string(),
-    );
+    args_list.push(settings.scene_minimal_cluster_size.value.to_string());
 
     // 11 - scene maximal cluster size
-    args_list.push(
-        settings["localization_settings"]["scene_maximal_cluster_size"]["value"]
-            .as_u64()
-            .unwrap_or(350000)
-            .to_string(),
-    );
+    args_list.push(settings.scene_maximal_cluster_size.value.to_string());
 
     // 12 - matching algorithm
-    args_list.push(
-        settings["localization_settings"]["matching_algorithm"]["value"]
-            .as_str()
-            .unwrap_or("Surfaces")
-            .to_string(),
-    );
+    args_list.push(settings.matching_algorithm.value.to_string());
 
     // 13 - model keypoints sampling
-    args_list.push(
-        settings["localization_settings"]["model_keypoints_sampling"]["value"]
-            .as_str()
-            .unwrap_or("Medium")
-            .to_string(),
-    );
+    args_list.push(settings.model_keypoints_sampling.value.to_string());
 
     // 14 - local search radius
-    args_list.push(
-        settings["localization_settings"]["local_search_radius"]["value"]
-            .as_str()
-            .unwrap_or("Normal")
-            .to_string(),
-    );
+    args_list.push(settings.local_search_radius.value.to_string());
 
     // 15 - feature fit consideration level
-    args_list.push(
-        settings["localization_settings"]["feature_fit_consideration_level"]["value"]
-            .as_u64()
-            .unwrap_or(15)
-            .to_string(),
-    );
+    args_list.push(settings.feature_fit_consideration_level.value.to_string());
 
     // 16 - global maximal feature fit overflow
-    args_list.push(
-        settings["localization_settings"]["global_maximal_feature_fit_overflow"]["value"]
-            .as_u64()
-            .unwrap_or(20)
-            .to_string(),
-    );
+    args_list.push(settings.global_maximal_feature_fit_overflow.value.to_string());
 
     // 17 - fine alignment iterations
-    args_list.push(
-        settings["localization_settings"]["fine_alignment_iterations"]["value"]
-            .as_u64()
-            .unwrap_or(30)
-            .to_string(),
-    );
+    args_list.push(settings.fine_alignment_iterations.value.to_string());
 
     // 18 - fine alignment point set
-    args_list.push(
-        settings["localization_settings"]["fine_alignment_point_set"]["value"]
-            .as_str()
-            .unwrap_or("Surface")
-            .to_string(),
-    );
+    args_list.push(settings.fine_alignment_point_set.value.to_string());
 
     // 19 - fine alignment point set sampling
-    args_list.push(
-        settings["localization_settings"]["fine_alignment_point_set_sampling"]["value"]
-            .as_str()
-            .unwrap_or("Sampled")
-            .to_string(),
-    );
+    args_list.push(settings.fine_alignment_point_set_sampling.value.to_string());
 
     // 20 - projection tolerance
-    args_list.push(
-        settings["localization_settings"]["projection_tolerance"]["value"]
-            .as_u64()
-            .unwrap_or(100)
-            .to_string(),
-    );
+    args_list.push(settings.projection_tolerance.value.to_string());
 
     // 21 - projection hidden part tolerance
-    args_list.push(
-        settings["localization_settings"]["projection_hidden_part_tolerance"]["value"]
-            .as_u64()
-            .unwrap_or(100)
-            .to_string(),
-    );
+    args_list.push(settings.projection_hidden_part_tolerance.value.to_string());
 
     // 22 - overlap
-    args_list.push(
-        settings["localization_settings"]["overlap"]["value"]
-            .as_f64()
-            .unwrap_or(15.0)
-            .to_string(),
-    );
+    args_list.push(settings.overlap.value.to_string());
 
     // 23 - praw location
     args_list.push(request.praw_dir.clone());
@@ -668,14 +1041,6 @@ fn prepare_arguments(
     args_list
 }
 
-// fn capitalize_first(s: &str) -> String {
-//     let mut chars = s.chars();
-//     match chars.next() {
-//         None => String::new(),
-//         Some(f) => f.to_uppercase().collect::<String>() + chars.as_str(),
-//     }
-// }
-
 fn bool_to_arg(value: bool) -> String {
     if value {
         "1".to_string()
@@ -684,25 +1049,6 @@ fn bool_to_arg(value: bool) -> String {
     }
 }
 
-// fn resolution_to_arg(value: &Value) -> String {
-//     // "0" is 2064x1544 and "1" is 1032x772
-//     if value["width"] == 2064 && value["height"] == 1544 {
-//         "0".to_string()
-//     } else if value["width"] == 1032 && value["height"] == 772 {
-//         "1".to_string()
-//     } else {
-//         log::error!(target: &&format!(
-//             "phoxi_localization_interface"),
-//             "Unsupported Photoneo resolution."
-//         );
-//         log::error!(target: &&format!(
-//             "phoxi_localization_interface"),
-//             "Resolution defaulting to 2064x1544."
-//         );
-//         "0".to_string()
-//     }
-// }
-
 fn load_json_from_file(path: &str) -> Option<Value> {
     match File::open(path) {
         Ok(file) => {
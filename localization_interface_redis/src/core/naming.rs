@@ -0,0 +1,154 @@
+use micro_sp::*;
+use std::collections::HashSet;
+
+use super::filter::position;
+use super::parser::Detection;
+
+// How the child frames of the published transforms are named.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NamingStrategy {
+    // "{target_name}_{nanoid}", a new name on every run.
+    Random,
+    // "{target_name}_{i}" in the published order, see SortOrder.
+    Indexed,
+    // Re-use the name of the previous run's frame of the same target
+    // if it is within `tolerance` meters, index the rest.
+    Nearest { tolerance: f64 },
+}
+
+pub const DEFAULT_NEAREST_TOLERANCE: f64 = 0.02;
+
+impl NamingStrategy {
+    pub fn from_request(naming: &str, tolerance: f64) -> NamingStrategy {
+        match naming.to_lowercase().as_str() {
+            "indexed" => NamingStrategy::Indexed,
+            "nearest" => NamingStrategy::Nearest {
+                tolerance: if tolerance > 0.0 {
+                    tolerance
+                } else {
+                    DEFAULT_NEAREST_TOLERANCE
+                },
+            },
+            "" | "random" | "unknown" => NamingStrategy::Random,
+            other => {
+                log::warn!(target: "phoxi_localization_interface",
+                    "Unknown naming strategy '{}', using random names.", other);
+                NamingStrategy::Random
+            }
+        }
+    }
+
+    // One child frame id per detection, in the same order.
+    pub fn child_frame_ids(
+        &self,
+        detections: &[Detection],
+        previous: &[SPTransformStamped],
+        scanning_frame: &str,
+    ) -> Vec<String> {
+        match self {
+            NamingStrategy::Random => detections
+                .iter()
+                .map(|d| format!("{}_{}", d.target_name, nanoid::nanoid!(6)))
+                .collect(),
+            NamingStrategy::Indexed => detections
+                .iter()
+                .enumerate()
+                .map(|(i, d)| format!("{}_{}", d.target_name, i))
+                .collect(),
+            NamingStrategy::Nearest { tolerance } => {
+                nearest_names(detections, previous, scanning_frame, *tolerance)
+            }
+        }
+    }
+}
+
+fn nearest_names(
+    detections: &[Detection],
+    previous: &[SPTransformStamped],
+    scanning_frame: &str,
+    tolerance: f64,
+) -> Vec<String> {
+    let mut names: Vec<Option<String>> = vec![None; detections.len()];
+    let mut taken: HashSet<String> = HashSet::new();
+
+    // Closest pairs first, so every previous frame is re-used at most once
+    // and always by the detection nearest to it.
+    let mut pairs: Vec<(f64, usize, usize)> = Vec::new();
+    for (i, detection) in detections.iter().enumerate() {
+        for (j, frame) in previous.iter().enumerate() {
+            if frame.parent_frame_id != scanning_frame
+                || frame_target(frame).as_deref() != Some(&detection.target_name)
+            {
+                continue;
+            }
+            let distance = translation_distance(position(detection), frame);
+            if distance <= tolerance {
+                pairs.push((distance, i, j));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut used_previous: HashSet<usize> = HashSet::new();
+    for (_, i, j) in pairs {
+        if names[i].is_some() || used_previous.contains(&j) {
+            continue;
+        }
+        used_previous.insert(j);
+        taken.insert(previous[j].child_frame_id.clone());
+        names[i] = Some(previous[j].child_frame_id.clone());
+    }
+
+    // Frames that were not matched keep their names reserved, so a part
+    // that is missing from one scan gets its name back in the next.
+    for frame in previous {
+        taken.insert(frame.child_frame_id.clone());
+    }
+
+    detections
+        .iter()
+        .zip(names)
+        .map(|(detection, name)| {
+            name.unwrap_or_else(|| {
+                let name = (0..)
+                    .map(|k| format!("{}_{}", detection.target_name, k))
+                    .find(|candidate| !taken.contains(candidate))
+                    .unwrap_or_default();
+                taken.insert(name.clone());
+                name
+            })
+        })
+        .collect()
+}
+
+// The target a published frame belongs to, from its metadata or,
+// for frames published before the metadata existed, from its name.
+fn frame_target(frame: &SPTransformStamped) -> Option<String> {
+    metadata_target_name(frame).or_else(|| {
+        frame
+            .child_frame_id
+            .rsplit_once('_')
+            .map(|(target, _)| target.to_string())
+    })
+}
+
+pub fn metadata_target_name(frame: &SPTransformStamped) -> Option<String> {
+    match &frame.metadata {
+        MapOrUnknown::Map(entries) => entries.iter().find_map(|(key, value)| match (key, value) {
+            (
+                SPValue::String(StringOrUnknown::String(key)),
+                SPValue::String(StringOrUnknown::String(value)),
+            ) if key == "target_name" => Some(value.clone()),
+            _ => None,
+        }),
+        MapOrUnknown::UNKNOWN => None,
+    }
+}
+
+fn translation_distance(point: [f64; 3], frame: &SPTransformStamped) -> f64 {
+    let translation = &frame.transform.translation;
+    let dx = point[0] - translation.x.into_inner();
+    let dy = point[1] - translation.y.into_inner();
+    let dz = point[2] - translation.z.into_inner();
+    (dx * dx + dy * dy + dz * dz).sqrt()
+}
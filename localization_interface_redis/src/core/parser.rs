@@ -0,0 +1,269 @@
+use std::io::BufRead;
+
+use super::filter::ResultFilter;
+use super::result_protocol::parse_records;
+use super::state::LocalizeRequest;
+
+// Turns the stdout of the Localize executable into detections. Everything
+// here has to hold up against whatever the SDK prints, see the golden
+// transcripts in tests/data/localize and the fuzz target in fuzz/.
+
+// How far from orthonormal the rotation of a printed matrix may be. The
+// executable prints six significant digits.
+const RIGIDITY_TOLERANCE: f64 = 1e-3;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParsedResult {
+    pub success: bool,
+    pub stop_criteria_met: bool,
+    pub count: usize,
+    pub results: Vec<Detection>,
+    // Results the executable printed that could not be used, because they
+    // are malformed, truncated or not a rigid transformation.
+    pub discarded: usize,
+}
+
+// One localized instance of the target. Score and overlap are only known
+// when the executable prints result records, not for the legacy text output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection {
+    pub index: usize,
+    pub matrix: MatrixDataInternal,
+    pub target_name: String,
+    pub score: Option<f64>,
+    pub overlap: Option<f64>,
+}
+
+// C++ documentation
+// Typedef Documentation
+// typedef std::array<std::array<float, 4>, 4> TransformationMatrix4x4
+// Transformation matrix 4x4 t
+// t[0][0]  t[0][1] t[0][2] t[0][3]       r[0][0] r[0][1] r[0][2] 	Tx
+// t[1][0]  t[1][1] t[1][2] t[1][3]   =   r[1][0] r[1][1] r[1][2] 	Ty
+// t[2][0]  t[2][1] t[2][2] t[2][3]       r[2][0] r[2][1] r[2][2] 	Tz
+// t[3][0]  t[3][1] t[3][2] t[3][3]             0       0       0 	 1
+pub fn parse_result(
+    request: &LocalizeRequest,
+    result_filter: &ResultFilter,
+    data: &[Vec<u8>],
+) -> ParsedResult {
+    let mut parsed = ParsedResult::default();
+
+    match parse_records(data) {
+        Some(report) => {
+            if let Some(summary) = &report.summary {
+                log::info!(target: "phoxi_localization_interface",
+                    "Localization reported {} result(s) in {} ms.", summary.count, summary.elapsed_ms);
+                if summary.count != report.detections.len() {
+                    log::warn!(target: "phoxi_localization_interface",
+                        "Summary reports {} result(s), but {} detection record(s) were received.",
+                        summary.count, report.detections.len());
+                }
+            }
+            parsed.discarded = report.malformed;
+            parsed.results = report
+                .detections
+                .into_iter()
+                .map(|detection| Detection {
+                    index: detection.index,
+                    matrix: detection.matrix,
+                    target_name: request.target_name.clone(),
+                    score: detection.score,
+                    overlap: detection.overlap,
+                })
+                .collect();
+        }
+        None => {
+            log::debug!(target: "phoxi_localization_interface",
+                "No result records in the output, falling back to the legacy text format.");
+            (parsed.results, parsed.discarded) = parse_legacy_results(request, data);
+        }
+    }
+
+    let printed = parsed.results.len();
+    parsed.results.retain(|detection| {
+        let rigid = is_rigid(&detection.matrix);
+        if !rigid {
+            log::warn!(target: "phoxi_localization_interface",
+                "Discarding result {}, its matrix is not a rigid transformation: {:?}.",
+                detection.index, detection.matrix);
+        }
+        rigid
+    });
+    parsed.discarded += printed - parsed.results.len();
+
+    let detected = parsed.results.len();
+    parsed.results = result_filter.apply(parsed.results);
+    if parsed.results.len() < detected {
+        log::info!(target: "phoxi_localization_interface",
+            "Filtered out {} of {} detection(s).", detected - parsed.results.len(), detected);
+    }
+
+    log::info!(target: "phoxi_localization_interface",
+        "DETECTED ITEMS: {:?}", parsed.results);
+
+    parsed.count = parsed.results.len();
+
+    if parsed.count == 0 {
+        parsed.success = false;
+        parsed.stop_criteria_met = false;
+    } else if request.stop_at_number as usize > parsed.count {
+        parsed.success = true;
+        parsed.stop_criteria_met = false;
+    } else {
+        parsed.success = true;
+        parsed.stop_criteria_met = true;
+    }
+
+    parsed
+}
+
+// Executables without result records print "RESULT i: " followed by the
+// first matrix row, and the remaining two rows on the next lines. Returns
+// the results and how many were discarded.
+fn parse_legacy_results(request: &LocalizeRequest, data: &[Vec<u8>]) -> (Vec<Detection>, usize) {
+    let mut results = Vec::new();
+    let mut discarded = 0;
+    let mut result_lines: Vec<usize> = Vec::new();
+
+    // Find "RESULT" lines
+    for (i, line) in data.iter().enumerate() {
+        if tokens(line).any(|token| token == b"RESULT") {
+            result_lines.push(i);
+        }
+    }
+
+    for &index in &result_lines {
+        // A truncated matrix must not borrow rows from the next result.
+        let complete = index + 2 < data.len()
+            && !result_lines.contains(&(index + 1))
+            && !result_lines.contains(&(index + 2));
+        if !complete {
+            log::warn!(target: "phoxi_localization_interface",
+                "Discarding result at line {}, its matrix is truncated.", index);
+            discarded += 1;
+            continue;
+        }
+
+        let m1: Vec<f64> = tokens(&data[index]).filter_map(parse_float).collect();
+        let m2: Vec<f64> = tokens(&data[index + 1]).filter_map(parse_float).collect();
+        let m3: Vec<f64> = tokens(&data[index + 2]).filter_map(parse_float).collect();
+
+        let m4 = [0.0, 0.0, 0.0, 1.0];
+
+        if m1.len() == 4 && m2.len() == 4 && m3.len() == 4 {
+            results.push(Detection {
+                index: results.len(),
+                matrix: [
+                    [m1[0], m1[1], m1[2], m1[3]],
+                    [m2[0], m2[1], m2[2], m2[3]],
+                    [m3[0], m3[1], m3[2], m3[3]],
+                    [m4[0], m4[1], m4[2], m4[3]],
+                ],
+                target_name: request.target_name.clone(),
+                score: None,
+                overlap: None,
+            });
+        } else {
+            log::warn!(target: "phoxi_localization_interface",
+                "Discarding result at line {}, expected 3 rows of 4 numbers but got {}, {} and {}.",
+                index, m1.len(), m2.len(), m3.len());
+            discarded += 1;
+        }
+    }
+
+    (results, discarded)
+}
+
+fn tokens(line: &[u8]) -> impl Iterator<Item = &[u8]> {
+    line.split(|c| c.is_ascii_whitespace())
+        .filter(|token| !token.is_empty())
+}
+
+// The "RESULT" and "i:" tokens on the first row do not parse as numbers,
+// everything else does, including integers like "0". Non-finite values
+// such as "nan" parse too and are rejected with the whole matrix.
+pub fn parse_float(data: &[u8]) -> Option<f64> {
+    std::str::from_utf8(data).ok()?.parse::<f64>().ok()
+}
+
+pub type MatrixDataInternal = [[f64; 4]; 4];
+
+// Returns (w, x, y, z). The matrix has to be a rotation, see is_rigid.
+pub fn rotation_matrix_to_quaternion(m: &[[f64; 3]; 3]) -> (f64, f64, f64, f64) {
+    let trace = m[0][0] + m[1][1] + m[2][2];
+    let (w, x, y, z);
+
+    if trace > 0.0 {
+        let s = (trace + 1.0).sqrt() * 2.0;
+        w = 0.25 * s;
+        x = (m[2][1] - m[1][2]) / s;
+        y = (m[0][2] - m[2][0]) / s;
+        z = (m[1][0] - m[0][1]) / s;
+    } else if (m[0][0] > m[1][1]) && (m[0][0] > m[2][2]) {
+        let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+        w = (m[2][1] - m[1][2]) / s;
+        x = 0.25 * s;
+        y = (m[0][1] + m[1][0]) / s;
+        z = (m[0][2] + m[2][0]) / s;
+    } else if m[1][1] > m[2][2] {
+        let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+        w = (m[0][2] - m[2][0]) / s;
+        x = (m[0][1] + m[1][0]) / s;
+        y = 0.25 * s;
+        z = (m[1][2] + m[2][1]) / s;
+    } else {
+        let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+        w = (m[1][0] - m[0][1]) / s;
+        x = (m[0][2] + m[2][0]) / s;
+        y = (m[1][2] + m[2][1]) / s;
+        z = 0.25 * s;
+    }
+
+    // The printed matrix is rounded, so the quaternion is off unit length
+    // by about the same amount.
+    let norm = (w * w + x * x + y * y + z * z).sqrt();
+    (w / norm, x / norm, y / norm, z / norm)
+}
+
+// True if the matrix is finite and its rotation is orthonormal with a
+// determinant of one, i.e. not a reflection.
+pub fn is_rigid(matrix: &MatrixDataInternal) -> bool {
+    if !matrix[..3].iter().flatten().all(|v| v.is_finite()) {
+        return false;
+    }
+    let r = |i: usize, j: usize| matrix[i][j];
+    for i in 0..3 {
+        for j in 0..3 {
+            let dot: f64 = (0..3).map(|k| r(k, i) * r(k, j)).sum();
+            let expected = if i == j { 1.0 } else { 0.0 };
+            if (dot - expected).abs() > RIGIDITY_TOLERANCE {
+                return false;
+            }
+        }
+    }
+    let determinant = r(0, 0) * (r(1, 1) * r(2, 2) - r(1, 2) * r(2, 1))
+        - r(0, 1) * (r(1, 0) * r(2, 2) - r(1, 2) * r(2, 0))
+        + r(0, 2) * (r(1, 0) * r(2, 1) - r(1, 1) * r(2, 0));
+    (determinant - 1.0).abs() <= RIGIDITY_TOLERANCE
+}
+
+// Output lines as the interface collects them from the executable, line
+// endings stripped and empty lines dropped.
+pub fn read_lines(reader: impl BufRead) -> Vec<Vec<u8>> {
+    reader
+        .lines()
+        .map(|line_result| {
+            line_result
+                .unwrap_or_else(|e| {
+                    log::warn!(
+                        target: "phoxi_localization_interface",
+                        "Error reading a line from stdout: {}", e
+                    );
+                    String::new()
+                })
+                .into_bytes()
+        })
+        .filter(|v_u8| !v_u8.is_empty()) // Filter out empty lines
+        .collect()
+}
@@ -0,0 +1,204 @@
+use nalgebra::{Matrix3, SymmetricEigen, Vector3};
+use serde::Deserialize;
+
+use super::ply::is_valid_point;
+
+// The plane of the points p with normal . p = offset, in the millimeters of
+// the scans. The normal is a unit vector pointing to the side of the camera,
+// the origin of the scanning frame, so the offset is never positive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: Vector3<f64>,
+    pub offset: f64,
+}
+
+impl Plane {
+    // None if the points are on a line.
+    pub fn through(a: &Vector3<f64>, b: &Vector3<f64>, c: &Vector3<f64>) -> Option<Plane> {
+        let normal = (b - a).cross(&(c - a)).try_normalize(1e-9)?;
+        Some(Plane::facing_the_camera(normal, normal.dot(a)))
+    }
+
+    // Least squares plane through the points, None for fewer than three
+    // points or points on a line.
+    pub fn fit(points: &[Vector3<f64>]) -> Option<Plane> {
+        if points.len() < 3 {
+            return None;
+        }
+        let centroid = points.iter().sum::<Vector3<f64>>() / points.len() as f64;
+        let covariance = points
+            .iter()
+            .map(|p| (p - centroid) * (p - centroid).transpose())
+            .sum::<Matrix3<f64>>();
+        let eigen = SymmetricEigen::new(covariance);
+        let mut eigenvalues: Vec<(usize, f64)> =
+            eigen.eigenvalues.iter().copied().enumerate().collect();
+        eigenvalues.sort_by(|a, b| a.1.total_cmp(&b.1));
+        // The two larger spreads span the plane, a line has only one.
+        if eigenvalues[1].1 <= 1e-12 * eigenvalues[2].1.max(1e-12) {
+            return None;
+        }
+        let normal = eigen.eigenvectors.column(eigenvalues[0].0).into_owned();
+        Some(Plane::facing_the_camera(normal, normal.dot(&centroid)))
+    }
+
+    // Signed, positive on the side of the camera.
+    pub fn distance(&self, point: &Vector3<f64>) -> f64 {
+        self.normal.dot(point) - self.offset
+    }
+
+    fn facing_the_camera(normal: Vector3<f64>, offset: f64) -> Plane {
+        if offset > 0.0 {
+            Plane {
+                normal: -normal,
+                offset: -offset,
+            }
+        } else {
+            Plane { normal, offset }
+        }
+    }
+}
+
+// RANSAC search for the plane with the most points, in millimeters:
+//
+//     {"distance_threshold": 5.0, "iterations": 500}
+//
+// The samples are drawn with a fixed seed, so a scan always gives the
+// same plane.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RansacConfig {
+    // How far from the plane a point can be and still be on it.
+    pub distance_threshold: f64,
+    pub iterations: usize,
+}
+
+impl Default for RansacConfig {
+    fn default() -> Self {
+        RansacConfig {
+            distance_threshold: 5.0,
+            iterations: 500,
+        }
+    }
+}
+
+impl RansacConfig {
+    // Zero or negative values select the defaults.
+    pub fn from_request(distance_threshold: f64, iterations: i64) -> RansacConfig {
+        let default = RansacConfig::default();
+        RansacConfig {
+            distance_threshold: if distance_threshold > 0.0 {
+                distance_threshold
+            } else {
+                default.distance_threshold
+            },
+            iterations: if iterations > 0 {
+                iterations as usize
+            } else {
+                default.iterations
+            },
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.distance_threshold <= 0.0 {
+            return Err(format!(
+                "distance_threshold {} is not positive",
+                self.distance_threshold
+            ));
+        }
+        if self.iterations == 0 {
+            return Err("iterations is 0".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaneFit {
+    pub plane: Plane,
+    // Indices of the points on the plane, in the order of the points.
+    pub inliers: Vec<usize>,
+    // Root mean square distance of the inliers to the plane.
+    pub rms_distance: f64,
+}
+
+// The plane most of the valid points are on. The best sample is refined
+// with a least squares fit to its inliers. None if there are not three
+// valid points that span a plane.
+pub fn fit_plane(points: &[[f32; 3]], config: &RansacConfig) -> Option<PlaneFit> {
+    let valid: Vec<usize> = (0..points.len())
+        .filter(|i| is_valid_point(&points[*i]))
+        .collect();
+    if valid.len() < 3 {
+        return None;
+    }
+    let point = |i: usize| Vector3::from(points[i].map(f64::from));
+    let inliers_of = |plane: &Plane| -> Vec<usize> {
+        valid
+            .iter()
+            .copied()
+            .filter(|i| plane.distance(&point(*i)).abs() <= config.distance_threshold)
+            .collect()
+    };
+
+    let mut random = SplitMix64(0x5eed);
+    let mut best: Option<(Plane, usize)> = None;
+    for _ in 0..config.iterations {
+        let sample = [(); 3].map(|_| point(valid[random.below(valid.len())]));
+        let Some(plane) = Plane::through(&sample[0], &sample[1], &sample[2]) else {
+            continue;
+        };
+        let count = valid
+            .iter()
+            .filter(|i| plane.distance(&point(**i)).abs() <= config.distance_threshold)
+            .count();
+        if best.is_none_or(|(_, best_count)| count > best_count) {
+            best = Some((plane, count));
+        }
+    }
+
+    let (sampled, _) = best?;
+    let sampled_inliers = inliers_of(&sampled);
+    let refined = Plane::fit(
+        &sampled_inliers
+            .iter()
+            .map(|i| point(*i))
+            .collect::<Vec<_>>(),
+    )
+    .filter(|refined| inliers_of(refined).len() >= sampled_inliers.len())
+    .unwrap_or(sampled);
+    let inliers = inliers_of(&refined);
+    let rms_distance = match inliers.len() {
+        0 => 0.0,
+        n => (inliers
+            .iter()
+            .map(|i| refined.distance(&point(*i)).powi(2))
+            .sum::<f64>()
+            / n as f64)
+            .sqrt(),
+    };
+    Some(PlaneFit {
+        plane: refined,
+        inliers,
+        rms_distance,
+    })
+}
+
+// Small and reproducible is all the sampling needs, see
+// https://prng.di.unimi.it/splitmix64.c
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
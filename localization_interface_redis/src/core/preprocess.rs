@@ -0,0 +1,393 @@
+use nalgebra::{Quaternion, UnitQuaternion, Vector3};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::{fmt, io};
+
+use super::plane::{fit_plane, RansacConfig};
+use super::ply::{is_valid_point, PlyError, PlyFormat, PointCloud};
+
+// Steps applied to the ply of the scene before a localization with
+// source_format "ply", so the engine searches less clutter. Configured per
+// scanner as "preprocessing" in parameters/scanners/{id}.json, every step is
+// optional and lengths are in millimeters in the scanning frame, like the
+// points of the scans:
+//
+//     "preprocessing": {
+//         "crop": {"min": [-300, -200, 700], "max": [300, 200, 1200]},
+//         "remove_plane": {"distance_threshold": 4.0, "iterations": 500},
+//         "outlier_removal": {"neighbours": 16, "std_ratio": 2.0},
+//         "voxel_size": 2.0
+//     }
+//
+// An oriented crop box has a center, a size and a rotation instead:
+//
+//         "crop": {"center": [0, 0, 950], "size": [600, 400, 500],
+//                  "rotation": {"x": 0.0, "y": 0.0, "z": 0.3827, "w": 0.9239}}
+//
+// The steps run in this order, after the points without a measurement are
+// dropped. The engine gets the derived ply in {ply_dir}/preprocessed.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preprocessing {
+    pub crop: Option<CropBox>,
+    // Removes the dominant plane, the floor of the bin or the table.
+    pub remove_plane: Option<RansacConfig>,
+    pub outlier_removal: Option<OutlierRemoval>,
+    // Keeps one point per voxel of this size.
+    pub voxel_size: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum CropBox {
+    Aligned(AlignedBox),
+    Oriented(OrientedBox),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlignedBox {
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OrientedBox {
+    pub center: [f64; 3],
+    // Edge lengths along the axes of the box.
+    pub size: [f64; 3],
+    pub rotation: BoxRotation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BoxRotation {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub w: f64,
+}
+
+// Drops points whose mean distance to their nearest neighbours is more than
+// std_ratio standard deviations above the mean over all points.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutlierRemoval {
+    pub neighbours: usize,
+    pub std_ratio: f64,
+}
+
+impl Default for OutlierRemoval {
+    fn default() -> Self {
+        OutlierRemoval {
+            neighbours: 16,
+            std_ratio: 2.0,
+        }
+    }
+}
+
+impl CropBox {
+    pub fn contains(&self, point: &[f32; 3]) -> bool {
+        let point = Vector3::from(point.map(f64::from));
+        match self {
+            CropBox::Aligned(aligned) => {
+                (0..3).all(|i| point[i] >= aligned.min[i] && point[i] <= aligned.max[i])
+            }
+            CropBox::Oriented(oriented) => {
+                let local =
+                    oriented.rotation().inverse() * (point - Vector3::from(oriented.center));
+                (0..3).all(|i| local[i].abs() <= oriented.size[i] / 2.0)
+            }
+        }
+    }
+}
+
+impl OrientedBox {
+    fn rotation(&self) -> UnitQuaternion<f64> {
+        let r = &self.rotation;
+        UnitQuaternion::from_quaternion(Quaternion::new(r.w, r.x, r.y, r.z))
+    }
+}
+
+impl Preprocessing {
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.crop {
+            Some(CropBox::Aligned(aligned)) if (0..3).any(|i| aligned.min[i] > aligned.max[i]) => {
+                return Err(format!(
+                    "crop min {:?} is above max {:?}",
+                    aligned.min, aligned.max
+                ))
+            }
+            Some(CropBox::Oriented(oriented)) => {
+                if oriented.size.iter().any(|s| *s <= 0.0) {
+                    return Err(format!("crop size {:?} is not positive", oriented.size));
+                }
+                let r = &oriented.rotation;
+                if r.x * r.x + r.y * r.y + r.z * r.z + r.w * r.w <= 0.0 {
+                    return Err("crop rotation is not a rotation".to_string());
+                }
+            }
+            _ => (),
+        }
+        if let Some(remove_plane) = &self.remove_plane {
+            remove_plane
+                .validate()
+                .map_err(|e| format!("remove_plane: {}", e))?;
+        }
+        if let Some(outlier_removal) = &self.outlier_removal {
+            if outlier_removal.neighbours == 0 || outlier_removal.std_ratio <= 0.0 {
+                return Err(format!(
+                    "outlier_removal needs neighbours and a positive std_ratio, got {:?}",
+                    outlier_removal
+                ));
+            }
+        }
+        match self.voxel_size {
+            Some(voxel_size) if voxel_size <= 0.0 => {
+                Err(format!("voxel_size {} is not positive", voxel_size))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn apply(&self, cloud: &PointCloud) -> PointCloud {
+        let mut cloud = cloud.filter(|_, point| {
+            is_valid_point(point) && self.crop.as_ref().is_none_or(|crop| crop.contains(point))
+        });
+        if let Some(config) = &self.remove_plane {
+            if let Some(fit) = fit_plane(&cloud.points, config) {
+                let mut on_plane = vec![false; cloud.len()];
+                fit.inliers.iter().for_each(|i| on_plane[*i] = true);
+                cloud = cloud.filter(|i, _| !on_plane[i]);
+            }
+        }
+        if let Some(config) = &self.outlier_removal {
+            cloud = remove_outliers(&cloud, config);
+        }
+        if let Some(voxel_size) = self.voxel_size {
+            cloud = downsample(&cloud, voxel_size);
+        }
+        cloud
+    }
+}
+
+// Why the scene of a request could not be searched, by the interface's own
+// preprocessing or plane estimation rather than the engine.
+#[derive(Debug)]
+pub enum SceneError {
+    Unreadable { path: String, error: PlyError },
+    // Nothing was left to search after the preprocessing.
+    Empty { scene_name: String },
+    Unwritable { path: String, error: PlyError },
+    NoPlane { reason: String },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Unreadable { path, error } => {
+                write!(f, "Failed to read scene {}: {}", path, error)
+            }
+            SceneError::Empty { scene_name } => {
+                write!(f, "Preprocessing left no points of scene {}.", scene_name)
+            }
+            SceneError::Unwritable { path, error } => {
+                write!(f, "Failed to write preprocessed scene {}: {}", path, error)
+            }
+            SceneError::NoPlane { reason } => f.write_str(reason),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+// A scene that cannot be read or has nothing to search is NotFound, see
+// ErrorCode::classify, the rest is up to the setup.
+impl From<SceneError> for io::Error {
+    fn from(error: SceneError) -> Self {
+        let kind = match error {
+            SceneError::Unreadable { .. } | SceneError::Empty { .. } => io::ErrorKind::NotFound,
+            SceneError::Unwritable { .. } | SceneError::NoPlane { .. } => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, error)
+    }
+}
+
+// Reads the scene a localization or plane estimation runs on.
+pub fn read_scene(path: &str) -> Result<PointCloud, SceneError> {
+    PointCloud::read(path).map_err(|error| SceneError::Unreadable {
+        path: path.to_string(),
+        error,
+    })
+}
+
+// Preprocesses {ply_dir}/{scene_name}.ply into the same name in
+// {ply_dir}/preprocessed and returns that directory, for the request to
+// the engine.
+pub fn preprocess_scene(
+    ply_dir: &str,
+    scene_name: &str,
+    preprocessing: &Preprocessing,
+) -> Result<String, SceneError> {
+    let scene = read_scene(&format!("{}/{}.ply", ply_dir, scene_name))?;
+    let preprocessed = preprocessing.apply(&scene);
+    log::info!(target: "phoxi_localization_interface",
+        "Preprocessed scene '{}', kept {} of {} points.", scene_name, preprocessed.len(), scene.len());
+    if preprocessed.is_empty() {
+        return Err(SceneError::Empty {
+            scene_name: scene_name.to_string(),
+        });
+    }
+
+    let preprocessed_dir = format!("{}/preprocessed", ply_dir);
+    let preprocessed_path = format!("{}/{}.ply", preprocessed_dir, scene_name);
+    std::fs::create_dir_all(&preprocessed_dir)
+        .map_err(PlyError::from)
+        .and_then(|_| preprocessed.write(&preprocessed_path, PlyFormat::BinaryLittleEndian))
+        .map_err(|error| SceneError::Unwritable {
+            path: preprocessed_path,
+            error,
+        })?;
+    Ok(preprocessed_dir)
+}
+
+fn remove_outliers(cloud: &PointCloud, config: &OutlierRemoval) -> PointCloud {
+    if cloud.len() <= config.neighbours {
+        return cloud.clone();
+    }
+    let grid = Grid::new(&cloud.points, config.neighbours);
+    let mean_distances: Vec<f64> = (0..cloud.len())
+        .map(|i| grid.mean_neighbour_distance(i, config.neighbours))
+        .collect();
+    let n = mean_distances.len() as f64;
+    let mean = mean_distances.iter().sum::<f64>() / n;
+    let std = (mean_distances
+        .iter()
+        .map(|d| (d - mean).powi(2))
+        .sum::<f64>()
+        / n)
+        .sqrt();
+    let threshold = mean + config.std_ratio * std;
+    cloud.filter(|i, _| mean_distances[i] <= threshold)
+}
+
+// Keeps the point nearest to the centroid of each voxel, rather than the
+// centroid, so every kept point is a measured one with its own normal,
+// color and texture. The points stay in the order of the scan.
+fn downsample(cloud: &PointCloud, voxel_size: f64) -> PointCloud {
+    let voxel = |point: &[f32; 3]| point.map(|v| (v as f64 / voxel_size).floor() as i64);
+    let mut centroids: HashMap<[i64; 3], ([f64; 3], usize)> = HashMap::new();
+    for point in &cloud.points {
+        let (sum, count) = centroids.entry(voxel(point)).or_insert(([0.0; 3], 0));
+        (0..3).for_each(|i| sum[i] += point[i] as f64);
+        *count += 1;
+    }
+    let mut nearest: HashMap<[i64; 3], (usize, f64)> = HashMap::new();
+    for (i, point) in cloud.points.iter().enumerate() {
+        let key = voxel(point);
+        let (sum, count) = centroids[&key];
+        let distance: f64 = (0..3)
+            .map(|j| (point[j] as f64 - sum[j] / count as f64).powi(2))
+            .sum();
+        let entry = nearest.entry(key).or_insert((i, distance));
+        if distance < entry.1 {
+            *entry = (i, distance);
+        }
+    }
+    let mut kept: Vec<usize> = nearest.into_values().map(|(i, _)| i).collect();
+    kept.sort_unstable();
+    cloud.select(&kept)
+}
+
+// Buckets the points into cubic cells for nearest neighbour searches.
+struct Grid<'a> {
+    points: &'a [[f32; 3]],
+    cell_size: f64,
+    cells: HashMap<[i64; 3], Vec<usize>>,
+}
+
+impl<'a> Grid<'a> {
+    // Scans are surfaces, so the cells are sized for about `neighbours`
+    // points each on the area the points spread over.
+    fn new(points: &'a [[f32; 3]], neighbours: usize) -> Grid<'a> {
+        let mut min = [f64::MAX; 3];
+        let mut max = [f64::MIN; 3];
+        for point in points {
+            for i in 0..3 {
+                min[i] = min[i].min(point[i] as f64);
+                max[i] = max[i].max(point[i] as f64);
+            }
+        }
+        let mut extents: Vec<f64> = (0..3).map(|i| max[i] - min[i]).collect();
+        extents.sort_by(|a, b| b.total_cmp(a));
+        let spacing = (extents[0] * extents[1] / points.len() as f64).sqrt();
+        let cell_size = (spacing * (neighbours as f64).sqrt())
+            .max(extents[0] * 1e-6)
+            .max(1e-6);
+
+        let mut grid = Grid {
+            points,
+            cell_size,
+            cells: HashMap::new(),
+        };
+        for (i, point) in points.iter().enumerate() {
+            let cell = grid.cell(point);
+            grid.cells.entry(cell).or_default().push(i);
+        }
+        grid
+    }
+
+    fn cell(&self, point: &[f32; 3]) -> [i64; 3] {
+        point.map(|v| (v as f64 / self.cell_size).floor() as i64)
+    }
+
+    // Searches rings of cells around the point until the k nearest found so
+    // far are closer than anything in the cells not searched yet. Points far
+    // from the others would take many empty rings, once the rings hold more
+    // cells than there are occupied ones every point is measured instead.
+    fn mean_neighbour_distance(&self, index: usize, k: usize) -> f64 {
+        let point = &self.points[index];
+        let distance_to = |j: &usize| {
+            let other = &self.points[*j];
+            (0..3)
+                .map(|i| (point[i] as f64 - other[i] as f64).powi(2))
+                .sum::<f64>()
+                .sqrt()
+        };
+        let center = self.cell(point);
+        let mut distances: Vec<f64> = Vec::new();
+        for ring in 0i64.. {
+            if (2 * ring as usize + 1).pow(3) > self.cells.len() {
+                distances = (0..self.points.len())
+                    .filter(|j| *j != index)
+                    .map(|j| distance_to(&j))
+                    .collect();
+                distances.select_nth_unstable_by(k - 1, |a, b| a.total_cmp(b));
+                distances.truncate(k);
+                break;
+            }
+            for dx in -ring..=ring {
+                for dy in -ring..=ring {
+                    for dz in -ring..=ring {
+                        if dx.abs().max(dy.abs()).max(dz.abs()) != ring {
+                            continue;
+                        }
+                        let cell = [center[0] + dx, center[1] + dy, center[2] + dz];
+                        if let Some(cell) = self.cells.get(&cell) {
+                            distances.extend(cell.iter().filter(|j| **j != index).map(distance_to));
+                        }
+                    }
+                }
+            }
+            if distances.len() >= k {
+                distances.select_nth_unstable_by(k - 1, |a, b| a.total_cmp(b));
+                distances.truncate(k);
+                if distances[k - 1] <= ring as f64 * self.cell_size {
+                    break;
+                }
+            }
+        }
+        distances.iter().sum::<f64>() / k as f64
+    }
+}
@@ -0,0 +1,159 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::settings::LocalizationSettings;
+use super::state::LocalizeRequest;
+
+// Bump together with LOCALIZE_REQUEST_SCHEMA_VERSION in
+// cpp_executables/Localize/Localize.cpp whenever the document changes shape.
+pub const REQUEST_SCHEMA_VERSION: u32 = 1;
+
+// How a request is handed over to the C++ executables. The checked-in
+// Localize_Release.exe only knows the positional arguments, so argv stays
+// the default until it is rebuilt from Localize.cpp, which reads request
+// files as well. PHOLOC_REQUEST_PROTOCOL=file opts in to them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RequestProtocol {
+    File,
+    Argv,
+}
+
+impl RequestProtocol {
+    pub fn from_env() -> RequestProtocol {
+        match std::env::var("PHOLOC_REQUEST_PROTOCOL") {
+            Ok(protocol) if protocol.eq_ignore_ascii_case("argv") => RequestProtocol::Argv,
+            Ok(protocol) if protocol.eq_ignore_ascii_case("file") => RequestProtocol::File,
+            Ok(protocol) => {
+                log::warn!(target: "phoxi_localization_interface",
+                    "Unknown PHOLOC_REQUEST_PROTOCOL '{}', using 'argv'.", protocol);
+                RequestProtocol::Argv
+            }
+            Err(_) => RequestProtocol::Argv,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScannerIdentity {
+    pub name_identification: String,
+    pub hardware_identification: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StopCriteria {
+    pub timeout: i64,
+    pub number_of_results: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InputDirectories {
+    pub praw_dir: String,
+    pub ply_dir: String,
+    pub plcf_dir: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalizeRequestDocument {
+    pub schema_version: u32,
+    pub command: String,
+    pub scene_name: String,
+    pub target_name: String,
+    pub source_format: String,
+    pub stop_criteria: StopCriteria,
+    pub scanner: ScannerIdentity,
+    pub directories: InputDirectories,
+    // Only the effective values of the preset, without default/min/max/info.
+    pub settings: Value,
+}
+
+impl LocalizeRequestDocument {
+    pub fn new(
+        request: &LocalizeRequest,
+        settings: &LocalizationSettings,
+        parameters: &Value,
+    ) -> LocalizeRequestDocument {
+        LocalizeRequestDocument {
+            schema_version: REQUEST_SCHEMA_VERSION,
+            command: "localize".to_string(),
+            scene_name: request.scene_name.clone(),
+            target_name: request.target_name.clone(),
+            source_format: request.source_format.clone(),
+            stop_criteria: StopCriteria {
+                timeout: request.stop_at_timeout,
+                number_of_results: request.stop_at_number,
+            },
+            scanner: ScannerIdentity {
+                name_identification: parameters["name_identification"]
+                    .as_str()
+                    .unwrap_or("photoneo_1708011")
+                    .to_string(),
+                hardware_identification: parameters["hardware_identification"]
+                    .as_str()
+                    .unwrap_or("photoneo_1708011")
+                    .to_string(),
+            },
+            directories: InputDirectories {
+                praw_dir: request.praw_dir.clone(),
+                ply_dir: request.ply_dir.clone(),
+                plcf_dir: request.plcf_dir.clone(),
+            },
+            settings: setting_values(serde_json::to_value(settings).unwrap_or(Value::Null)),
+        }
+    }
+}
+
+// Replaces every {value, default, min, max, info} entry with its value.
+fn setting_values(value: Value) -> Value {
+    match value {
+        Value::Object(mut map) if map.contains_key("value") && map.contains_key("info") => {
+            map.remove("value").unwrap_or(Value::Null)
+        }
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| (key, setting_values(value)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+// A request document on disk, removed again when dropped.
+pub struct RequestFile {
+    path: PathBuf,
+}
+
+impl RequestFile {
+    pub fn write<T: Serialize>(document: &T, prefix: &str) -> io::Result<RequestFile> {
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let path = std::env::temp_dir().join(format!(
+            "{}_{}_{}.json",
+            prefix,
+            std::process::id(),
+            stamp
+        ));
+        let contents = serde_json::to_vec_pretty(document)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut file = std::fs::File::create(&path)?;
+        file.write_all(&contents)?;
+        Ok(RequestFile { path })
+    }
+
+    pub fn argument(&self) -> String {
+        format!("--request={}", self.path.display())
+    }
+}
+
+impl Drop for RequestFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            log::warn!(target: "phoxi_localization_interface",
+                "Failed to remove request file {}: {}", self.path.display(), e);
+        }
+    }
+}
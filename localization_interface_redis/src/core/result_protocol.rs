@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+
+// Machine readable output of the Localize executable. Every record is
+// printed as one JSON object per line, mixed in with whatever else the
+// SDK writes to stdout. See PrintDetection/PrintSummary in Localize.cpp.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResultRecord {
+    Detection(DetectionRecord),
+    Summary(SummaryRecord),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectionRecord {
+    pub index: usize,
+    // Row major, translation in millimeters.
+    pub matrix: [[f64; 4]; 4],
+    #[serde(default)]
+    pub score: Option<f64>,
+    #[serde(default)]
+    pub overlap: Option<f64>,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SummaryRecord {
+    pub count: usize,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResultReport {
+    pub detections: Vec<DetectionRecord>,
+    pub summary: Option<SummaryRecord>,
+    // Lines that look like records but do not parse.
+    pub malformed: usize,
+}
+
+// Collects all result records from the output. Returns None when the
+// output has none, i.e. it comes from an executable that only prints
+// the legacy "RESULT i:" text.
+pub fn parse_records(data: &[Vec<u8>]) -> Option<ResultReport> {
+    let mut report = ResultReport::default();
+    let mut found = false;
+
+    for line in data {
+        let line = match std::str::from_utf8(line) {
+            Ok(line) => line.trim(),
+            Err(_) => continue,
+        };
+        if !line.starts_with('{') {
+            continue;
+        }
+        match serde_json::from_str::<ResultRecord>(line) {
+            Ok(ResultRecord::Detection(detection)) => {
+                found = true;
+                report.detections.push(detection);
+            }
+            Ok(ResultRecord::Summary(summary)) => {
+                found = true;
+                report.summary = Some(summary);
+            }
+            Err(e) => {
+                report.malformed += 1;
+                log::warn!(target: "phoxi_localization_interface",
+                    "Skipping malformed result record '{}': {}", line, e);
+            }
+        }
+    }
+
+    if found {
+        report.detections.sort_by_key(|detection| detection.index);
+        Some(report)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,319 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+// Typed view of the localization_settings section of the presets in
+// parameters/settings/*.json. Every entry has the shape
+// {value, default, min, max, info} and is checked against its own
+// bounds when the preset is loaded. All violations are reported at once.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Setting<T> {
+    pub value: T,
+    pub default: T,
+    pub min: T,
+    pub max: T,
+    pub info: String,
+}
+
+macro_rules! setting_enum {
+    ($name:ident { $($variant:ident => $label:literal),+ $(,)? }) => {
+        // Variants are declared in the order used by the min/max bounds.
+        #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+        pub enum $name {
+            $(#[serde(rename = $label)] $variant),+
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    $($name::$variant => write!(f, $label)),+
+                }
+            }
+        }
+    };
+}
+
+setting_enum!(SceneClusteringLevel {
+    Low => "Low",
+    Normal => "Normal",
+    High => "High",
+    VeryHigh => "Very high",
+});
+
+setting_enum!(MatchingAlgorithm {
+    Edges => "Edges",
+    Combined => "Combined",
+    Surfaces => "Surfaces",
+});
+
+setting_enum!(ModelKeypointsSampling {
+    Sparse => "Sparse",
+    Medium => "Medium",
+    Dense => "Dense",
+});
+
+setting_enum!(LocalSearchRadius {
+    Short => "Short",
+    Normal => "Normal",
+    Medium => "Medium",
+});
+
+setting_enum!(FineAlignmentPointSet {
+    Surface => "Surface",
+    Edges => "Edges",
+});
+
+setting_enum!(FineAlignmentPointSetSampling {
+    Sampled => "Sampled",
+    Complete => "Complete",
+});
+
+// Localization timeout in milliseconds, where 0 stands for "no timeout".
+// It therefore orders above every other timeout, which lets the preset
+// express an unbounded maximum as "max": 0.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Timeout(pub u64);
+
+impl PartialOrd for Timeout {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        let as_key = |t: &Timeout| if t.0 == 0 { u64::MAX } else { t.0 };
+        as_key(self).partial_cmp(&as_key(other))
+    }
+}
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == 0 {
+            write!(f, "0 (no timeout)")
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalizationSettings {
+    pub timeout_criterion: Setting<Timeout>,
+    pub number_of_results_criterion: Setting<u64>,
+    pub scene_noise_reduction: Setting<bool>,
+    pub smart_memory: Setting<bool>,
+    pub scene_clustering_level: Setting<SceneClusteringLevel>,
+    pub scene_minimal_cluster_size: Setting<u64>,
+    pub scene_maximal_cluster_size: Setting<u64>,
+    pub matching_algorithm: Setting<MatchingAlgorithm>,
+    pub model_keypoints_sampling: Setting<ModelKeypointsSampling>,
+    pub local_search_radius: Setting<LocalSearchRadius>,
+    pub feature_fit_consideration_level: Setting<u32>,
+    pub global_maximal_feature_fit_overflow: Setting<u32>,
+    pub fine_alignment_iterations: Setting<u32>,
+    pub fine_alignment_point_set: Setting<FineAlignmentPointSet>,
+    pub fine_alignment_point_set_sampling: Setting<FineAlignmentPointSetSampling>,
+    pub projection_tolerance: Setting<u32>,
+    pub projection_hidden_part_tolerance: Setting<u32>,
+    pub overlap: Setting<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingsViolation {
+    pub field: String,
+    pub reason: String,
+}
+
+impl fmt::Display for SettingsViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.reason)
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Unreadable { file: String, reason: String },
+    Invalid { file: String, violations: Vec<SettingsViolation> },
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Unreadable { file, reason } => {
+                write!(f, "Failed to read settings file '{}': {}", file, reason)
+            }
+            SettingsError::Invalid { file, violations } => {
+                write!(
+                    f,
+                    "Settings file '{}' has {} invalid localization setting(s): ",
+                    file,
+                    violations.len()
+                )?;
+                let report: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+                write!(f, "{}", report.join("; "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+// Reads the fields of one settings section, recording a violation for
+// every field that is missing, has the wrong shape or is not expected.
+struct SectionReader<'a> {
+    section: &'static str,
+    object: Option<&'a serde_json::Map<String, Value>>,
+    violations: &'a mut Vec<SettingsViolation>,
+}
+
+impl<'a> SectionReader<'a> {
+    fn new(
+        root: &'a Value,
+        section: &'static str,
+        violations: &'a mut Vec<SettingsViolation>,
+    ) -> Self {
+        let object = root[section].as_object();
+        if object.is_none() {
+            violations.push(SettingsViolation {
+                field: section.to_string(),
+                reason: "missing section".to_string(),
+            });
+        }
+        SectionReader {
+            section,
+            object,
+            violations,
+        }
+    }
+
+    fn field<T: DeserializeOwned>(&mut self, name: &str) -> Option<T> {
+        let object = self.object?;
+        let reason = match object.get(name) {
+            Some(value) => match T::deserialize(value) {
+                Ok(parsed) => return Some(parsed),
+                Err(e) => e.to_string(),
+            },
+            None => "missing field".to_string(),
+        };
+        self.violations.push(SettingsViolation {
+            field: format!("{}.{}", self.section, name),
+            reason,
+        });
+        None
+    }
+
+    // Like `field`, but also checks the value against the setting's own bounds.
+    fn setting<T>(&mut self, name: &str) -> Option<Setting<T>>
+    where
+        T: DeserializeOwned + PartialOrd + fmt::Display,
+    {
+        let setting: Setting<T> = self.field(name)?;
+        if setting.value < setting.min || setting.value > setting.max {
+            self.violations.push(SettingsViolation {
+                field: format!("{}.{}", self.section, name),
+                reason: format!(
+                    "value {} is outside of the allowed range [{}, {}]",
+                    setting.value, setting.min, setting.max
+                ),
+            });
+        }
+        Some(setting)
+    }
+
+    fn finish(self, expected: &[&str]) {
+        if let Some(object) = self.object {
+            for key in object.keys().filter(|k| !expected.contains(&k.as_str())) {
+                self.violations.push(SettingsViolation {
+                    field: format!("{}.{}", self.section, key),
+                    reason: "unknown field".to_string(),
+                });
+            }
+        }
+    }
+}
+
+macro_rules! read_section {
+    ($root:expr, $section:literal, $violations:expr, $ty:ident { $($field:ident),+ $(,)? }) => {{
+        let mut reader = SectionReader::new($root, $section, $violations);
+        $(let $field = reader.setting(stringify!($field));)+
+        reader.finish(&[$(stringify!($field)),+]);
+        match ($($field,)+) {
+            ($(Some($field),)+) => Some($ty { $($field),+ }),
+            _ => None,
+        }
+    }};
+}
+
+impl LocalizationSettings {
+    pub fn load(path: &str) -> Result<LocalizationSettings, SettingsError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| SettingsError::Unreadable {
+            file: path.to_string(),
+            reason: e.to_string(),
+        })?;
+        let root: Value = serde_json::from_str(&contents).map_err(|e| SettingsError::Unreadable {
+            file: path.to_string(),
+            reason: e.to_string(),
+        })?;
+        LocalizationSettings::from_value(&root, path)
+    }
+
+    pub fn builtin() -> Result<LocalizationSettings, SettingsError> {
+        let file = "<built-in default settings>";
+        let root: Value = serde_json::from_str(crate::core::DEFAULT_SETTINGS_JSON).map_err(|e| {
+            SettingsError::Unreadable {
+                file: file.to_string(),
+                reason: e.to_string(),
+            }
+        })?;
+        LocalizationSettings::from_value(&root, file)
+    }
+
+    pub fn from_value(root: &Value, file: &str) -> Result<LocalizationSettings, SettingsError> {
+        let mut violations = Vec::new();
+
+        let settings = read_section!(root, "localization_settings", &mut violations, LocalizationSettings {
+            timeout_criterion,
+            number_of_results_criterion,
+            scene_noise_reduction,
+            smart_memory,
+            scene_clustering_level,
+            scene_minimal_cluster_size,
+            scene_maximal_cluster_size,
+            matching_algorithm,
+            model_keypoints_sampling,
+            local_search_radius,
+            feature_fit_consideration_level,
+            global_maximal_feature_fit_overflow,
+            fine_alignment_iterations,
+            fine_alignment_point_set,
+            fine_alignment_point_set_sampling,
+            projection_tolerance,
+            projection_hidden_part_tolerance,
+            overlap,
+        });
+
+        if let Some(settings) = &settings {
+            settings.validate(&mut violations);
+        }
+
+        match settings {
+            Some(settings) if violations.is_empty() => Ok(settings),
+            _ => Err(SettingsError::Invalid {
+                file: file.to_string(),
+                violations,
+            }),
+        }
+    }
+
+    // Range checks happen while reading, this only covers rules
+    // that involve more than one setting.
+    fn validate(&self, violations: &mut Vec<SettingsViolation>) {
+        if self.scene_minimal_cluster_size.value > self.scene_maximal_cluster_size.value {
+            violations.push(SettingsViolation {
+                field: "localization_settings.scene_minimal_cluster_size".to_string(),
+                reason: format!(
+                    "value {} is larger than scene_maximal_cluster_size {}",
+                    self.scene_minimal_cluster_size.value, self.scene_maximal_cluster_size.value
+                ),
+            });
+        }
+    }
+}
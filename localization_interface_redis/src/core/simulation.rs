@@ -0,0 +1,253 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use super::backend::LocalizationBackend;
+use super::interface::execution_timeout;
+use super::ply::PointCloud;
+use super::result_protocol::{DetectionRecord, ResultRecord, SummaryRecord};
+use super::settings::Timeout;
+use super::state::LocalizeRequest;
+
+// A localization engine that needs no SDK, for development and CI. It
+// prints the same result records as the Localize executable. Configured
+// per scanner in parameters/simulation/{id}.json, all entries are optional:
+//
+//     {
+//         "latency_ms": 500,
+//         "result_interval_ms": 200,
+//         "scenes": {
+//             "scene_1": {
+//                 "part_a": {
+//                     "detections": [
+//                         {"matrix": [[1, 0, 0, 10], [0, 1, 0, 20], [0, 0, 1, 900], [0, 0, 0, 1]],
+//                          "score": 0.9, "overlap": 0.8}
+//                     ]
+//                 }
+//             },
+//             "*": {
+//                 "part_b": {"hang": true}
+//             }
+//         }
+//     }
+//
+// Fixtures are looked up by scene_name and target_name, "*" matches any
+// scene. Without a fixture the ply of the scene is searched for something
+// standing out of the background, see fit_scene.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+    // Time to load the scene and the target before the search starts.
+    pub latency_ms: u64,
+    // Time to find every result. Results that would be found after
+    // stop_at_timeout are not reported, like with the real engine.
+    pub result_interval_ms: u64,
+    pub scenes: HashMap<String, HashMap<String, Fixture>>,
+    // Use fit_scene for requests without a fixture.
+    pub fit_scenes: bool,
+    // How far in front of the background, in millimeters, a point has
+    // to be to count as part of an object.
+    pub foreground_height: f64,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        SimulationConfig {
+            latency_ms: 200,
+            result_interval_ms: 100,
+            scenes: HashMap::new(),
+            fit_scenes: true,
+            foreground_height: 20.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Fixture {
+    pub detections: Vec<FixtureDetection>,
+    // The engine never returns and the request times out.
+    pub hang: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureDetection {
+    // Row major, translation in millimeters.
+    pub matrix: [[f64; 4]; 4],
+    #[serde(default)]
+    pub score: Option<f64>,
+    #[serde(default)]
+    pub overlap: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SimulatedEngine {
+    config: SimulationConfig,
+}
+
+impl SimulatedEngine {
+    pub fn new(config: SimulationConfig) -> SimulatedEngine {
+        SimulatedEngine { config }
+    }
+
+    // Falls back to the default configuration if there is no file.
+    pub fn load(localization_interface_path: &str, photoneo_id: &str) -> SimulatedEngine {
+        let config_path = format!(
+            "{}/parameters/simulation/{}.json",
+            localization_interface_path, photoneo_id
+        );
+        let config = match fs::read_to_string(&config_path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                log::warn!(target: "phoxi_localization_interface",
+                    "Invalid simulation config {}: {}. Using the defaults.", config_path, e);
+                SimulationConfig::default()
+            }),
+            Err(_) => SimulationConfig::default(),
+        };
+        log::warn!(target: "phoxi_localization_interface",
+            "Using a simulated localization engine for {} with {} fixture scene(s).",
+            photoneo_id, config.scenes.len());
+        SimulatedEngine::new(config)
+    }
+
+    fn fixture(&self, request: &LocalizeRequest) -> Option<&Fixture> {
+        [request.scene_name.as_str(), "*"]
+            .iter()
+            .filter_map(|scene| self.config.scenes.get(*scene))
+            .find_map(|targets| targets.get(&request.target_name))
+    }
+}
+
+#[async_trait]
+impl LocalizationBackend for SimulatedEngine {
+    async fn localize(
+        &self,
+        request: &LocalizeRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<Vec<u8>>, io::Error> {
+        // Killed when the interface would kill the executable. There are no
+        // settings here, a request without a timeout gets the default one.
+        let deadline = execution_timeout(request.stop_at_timeout, Timeout(0))
+            .map(|timeout| timeout.as_millis() as u64);
+        let hangs = self.fixture(request).map(|f| f.hang).unwrap_or(false);
+        if hangs || deadline.is_some_and(|deadline| self.config.latency_ms > deadline) {
+            wait(deadline.unwrap_or(self.config.latency_ms), cancel).await?;
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "Command execution timed out.",
+            ));
+        }
+
+        let detections = match self.fixture(request) {
+            Some(fixture) => fixture.detections.clone(),
+            None if self.config.fit_scenes => fit_scene(request, self.config.foreground_height)
+                .into_iter()
+                .collect(),
+            None => Vec::new(),
+        };
+
+        wait(self.config.latency_ms, cancel).await?;
+        let mut elapsed_ms = self.config.latency_ms;
+        let mut lines = vec![format!(
+            "Simulated localization of '{}' in '{}'.",
+            request.target_name, request.scene_name
+        )];
+
+        let mut count = 0;
+        for detection in detections {
+            if request.stop_at_number > 0 && count >= request.stop_at_number as usize {
+                break;
+            }
+            let found_at = elapsed_ms + self.config.result_interval_ms;
+            if request.stop_at_timeout > 0 && found_at > request.stop_at_timeout as u64 {
+                break;
+            }
+            wait(self.config.result_interval_ms, cancel).await?;
+            elapsed_ms = found_at;
+            lines.push(record_line(&ResultRecord::Detection(DetectionRecord {
+                index: count,
+                matrix: detection.matrix,
+                score: detection.score,
+                overlap: detection.overlap,
+                elapsed_ms,
+            })));
+            count += 1;
+        }
+        lines.push(record_line(&ResultRecord::Summary(SummaryRecord {
+            count,
+            elapsed_ms,
+        })));
+
+        Ok(lines.into_iter().map(String::into_bytes).collect())
+    }
+}
+
+// Sleeps like the engine would take its time, unless the request is cancelled.
+async fn wait(milliseconds: u64, cancel: &CancellationToken) -> Result<(), io::Error> {
+    tokio::select! {
+        _ = tokio::time::sleep(Duration::from_millis(milliseconds)) => Ok(()),
+        _ = cancel.cancelled() => Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "Command execution was cancelled.",
+        )),
+    }
+}
+
+fn record_line(record: &ResultRecord) -> String {
+    serde_json::to_string(record).unwrap_or_default()
+}
+
+// Stand-in for model fitting: the points in the ply of the scene that are
+// in front of the background, taken as the 90th percentile of the depth,
+// are one object. Reports its centroid with the orientation of the camera.
+fn fit_scene(request: &LocalizeRequest, foreground_height: f64) -> Option<FixtureDetection> {
+    let path = format!("{}/{}.ply", request.ply_dir, request.scene_name);
+    let points: Vec<[f64; 3]> = match PointCloud::read(&path) {
+        Ok(cloud) => cloud
+            .points
+            .iter()
+            .filter(|p| p.iter().all(|v| v.is_finite()))
+            .map(|p| p.map(f64::from))
+            .collect(),
+        Err(e) => {
+            log::warn!(target: "phoxi_localization_interface",
+                "Simulated localization can not read {}: {}", path, e);
+            return None;
+        }
+    };
+
+    let mut depths: Vec<f64> = points.iter().map(|p| p[2]).filter(|z| *z > 0.0).collect();
+    if depths.is_empty() {
+        return None;
+    }
+    depths.sort_by(|a, b| a.total_cmp(b));
+    let background = depths[(depths.len() - 1) * 9 / 10];
+
+    let foreground: Vec<&[f64; 3]> = points
+        .iter()
+        .filter(|p| p[2] > 0.0 && p[2] < background - foreground_height)
+        .collect();
+    if foreground.len() < 10 {
+        return None;
+    }
+    let n = foreground.len() as f64;
+    let centroid = foreground.iter().fold([0.0; 3], |c, p| {
+        [c[0] + p[0] / n, c[1] + p[1] / n, c[2] + p[2] / n]
+    });
+
+    Some(FixtureDetection {
+        matrix: [
+            [1.0, 0.0, 0.0, centroid[0]],
+            [0.0, 1.0, 0.0, centroid[1]],
+            [0.0, 0.0, 1.0, centroid[2]],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+        score: None,
+        overlap: None,
+    })
+}
@@ -1,18 +1,47 @@
 use micro_sp::*;
 
+// Request state of an aborted request, next to the ServiceRequestState ones.
+pub const REQUEST_CANCELLED: &str = "cancelled";
+
+// What a request does with its scene, from {id}_localization_command.
+// "localize" searches it for the target, "fit_plane" for the table or the
+// floor of the bin, see SupportPlane. Anything else localizes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LocalizationCommand {
+    Localize,
+    FitPlane,
+}
+
+impl LocalizationCommand {
+    pub fn from_request(command: &str) -> LocalizationCommand {
+        match command.to_lowercase().as_str() {
+            "fit_plane" => LocalizationCommand::FitPlane,
+            "" | "localize" | "unknown" => LocalizationCommand::Localize,
+            other => {
+                log::warn!(target: "phoxi_localization_interface",
+                    "Unknown command '{}', localizing.", other);
+                LocalizationCommand::Localize
+            }
+        }
+    }
+}
+
 pub fn generate_photoneo_localization_interface_state(photoneo_name: &str) -> State {
     let state = State::new();
 
     let request_trigger = bv!(&&format!("{}_localization_request_trigger", photoneo_name));
     let request_state = v!(&&format!("{}_localization_request_state", photoneo_name));
+    let request_abort = bv!(&&format!("{}_localization_request_abort", photoneo_name));
     let total_fail_counter = iv!(&&format!("{}_localization_total_fail_counter", photoneo_name));
     let subsequent_fail_counter = iv!(&&format!("{}_localization_subsequent_fail_counter", photoneo_name));
 
     let state = state.add(assign!(request_trigger, false.to_spvalue()));
     let state = state.add(assign!(request_state, "initial".to_spvalue()));
+    let state = state.add(assign!(request_abort, false.to_spvalue()));
     let state = state.add(assign!(total_fail_counter, 0.to_spvalue()));
     let state = state.add(assign!(subsequent_fail_counter, 0.to_spvalue()));
 
+    let command = v!(&&format!("{}_localization_command", photoneo_name));
     let scene_name = v!(&&format!("{}_localization_scene_name", photoneo_name));
     let target_name = v!(&&format!("{}_localization_target_name", photoneo_name));
     let source_format = v!(&&format!("{}_localization_source_format", photoneo_name));
@@ -24,7 +53,20 @@ pub fn generate_photoneo_localization_interface_state(photoneo_name: &str) -> St
     let transforms = av!(&&format!("{}_localization_transforms", photoneo_name));
     let settings = v!(&&format!("{}_localization_settings", photoneo_name));
     let scanning_frame = v!(&&format!("{}_localization_scanning_frame", photoneo_name));
+    let minimum_overlap = fv!(&&format!("{}_localization_minimum_overlap", photoneo_name));
+    let maximum_detections = iv!(&&format!("{}_localization_maximum_detections", photoneo_name));
+    let sort_by = v!(&&format!("{}_localization_sort_by", photoneo_name));
+    let naming = v!(&&format!("{}_localization_naming", photoneo_name));
+    let naming_tolerance = fv!(&&format!("{}_localization_naming_tolerance", photoneo_name));
+    let tracking_tolerance = fv!(&&format!("{}_localization_tracking_tolerance", photoneo_name));
+    let tracking_window = iv!(&&format!("{}_localization_tracking_window", photoneo_name));
+    let tracking_max_missed = iv!(&&format!("{}_localization_tracking_max_missed", photoneo_name));
+    let tracked_transforms = av!(&&format!("{}_localization_tracked_transforms", photoneo_name));
+    let appeared = av!(&&format!("{}_localization_appeared", photoneo_name));
+    let disappeared = av!(&&format!("{}_localization_disappeared", photoneo_name));
+    let world_transforms = av!(&&format!("{}_localization_world_transforms", photoneo_name));
 
+    let state = state.add(assign!(command, "localize".to_spvalue()));
     let state = state.add(assign!(scene_name, SPValue::String(StringOrUnknown::UNKNOWN)));
     let state = state.add(assign!(target_name, SPValue::String(StringOrUnknown::UNKNOWN)));
     let state = state.add(assign!(source_format, SPValue::String(StringOrUnknown::UNKNOWN)));
@@ -36,6 +78,48 @@ pub fn generate_photoneo_localization_interface_state(photoneo_name: &str) -> St
     let state = state.add(assign!(transforms, SPValue::Array(ArrayOrUnknown::UNKNOWN)));
     let state = state.add(assign!(settings, SPValue::String(StringOrUnknown::UNKNOWN)));
     let state = state.add(assign!(scanning_frame, SPValue::String(StringOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(minimum_overlap, SPValue::Float64(FloatOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(maximum_detections, SPValue::Int64(IntOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(sort_by, SPValue::String(StringOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(naming, SPValue::String(StringOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(naming_tolerance, SPValue::Float64(FloatOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(tracking_tolerance, SPValue::Float64(FloatOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(tracking_window, SPValue::Int64(IntOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(tracking_max_missed, SPValue::Int64(IntOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(tracked_transforms, SPValue::Array(ArrayOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(appeared, SPValue::Array(ArrayOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(disappeared, SPValue::Array(ArrayOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(world_transforms, SPValue::Array(ArrayOrUnknown::UNKNOWN)));
+
+    let plane_distance_threshold = fv!(&&format!("{}_localization_plane_distance_threshold", photoneo_name));
+    let plane_iterations = iv!(&&format!("{}_localization_plane_iterations", photoneo_name));
+    let plane_transform = tv!(&&format!("{}_plane_transform", photoneo_name));
+    let plane_world_transform = tv!(&&format!("{}_plane_world_transform", photoneo_name));
+    let plane_statistics = mv!(&&format!("{}_plane_statistics", photoneo_name));
+
+    let state = state.add(assign!(plane_distance_threshold, SPValue::Float64(FloatOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(plane_iterations, SPValue::Int64(IntOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(plane_transform, SPValue::Transform(TransformOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(plane_world_transform, SPValue::Transform(TransformOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(plane_statistics, SPValue::Map(MapOrUnknown::UNKNOWN)));
+
+    let error_code = v!(&&format!("{}_localization_error_code", photoneo_name));
+    let error_recoverable = bv!(&&format!("{}_localization_error_recoverable", photoneo_name));
+    let raw_info = v!(&&format!("{}_localization_raw_info", photoneo_name));
+
+    let state = state.add(assign!(error_code, "none".to_spvalue()));
+    let state = state.add(assign!(error_recoverable, false.to_spvalue()));
+    let state = state.add(assign!(raw_info, SPValue::String(StringOrUnknown::UNKNOWN)));
+
+    let needs_attention = bv!(&&format!("{}_localization_needs_attention", photoneo_name));
+    let retry_max_attempts = iv!(&&format!("{}_localization_retry_max_attempts", photoneo_name));
+    let retry_backoff = iv!(&&format!("{}_localization_retry_backoff", photoneo_name));
+    let attention_threshold = iv!(&&format!("{}_localization_attention_threshold", photoneo_name));
+
+    let state = state.add(assign!(needs_attention, false.to_spvalue()));
+    let state = state.add(assign!(retry_max_attempts, SPValue::Int64(IntOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(retry_backoff, SPValue::Int64(IntOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(attention_threshold, SPValue::Int64(IntOrUnknown::UNKNOWN)));
 
     state
 }
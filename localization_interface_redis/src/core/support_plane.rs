@@ -0,0 +1,143 @@
+use micro_sp::*;
+use nalgebra::{Isometry3, Matrix3, Rotation3, Translation3, UnitQuaternion, Vector3};
+
+use std::time::SystemTime;
+
+use super::extrinsic::{from_isometry, CameraExtrinsic};
+use super::plane::{fit_plane, PlaneFit, RansacConfig};
+use super::ply::{is_valid_point, PointCloud};
+use super::preprocess::{read_scene, CropBox, SceneError};
+
+// Finds the dominant plane of a captured scene, the table or the floor of
+// the bin, with RANSAC. It needs no PhoLocalization, so it also runs where
+// there is no license. The localization interface runs it for the
+// "fit_plane" command, see LocalizationCommand, on the scene of the
+// request. Only the points in the crop box of the scanner's preprocessing
+// are searched, if it has one.
+// The plane found in a scene, with the points it was searched in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupportPlane {
+    pub fit: PlaneFit,
+    // Valid points in the crop box, the inliers are a part of them.
+    pub points: usize,
+    // On the plane at the inliers' centroid, the z-axis along the normal
+    // and the x-axis along the camera's as far as the plane allows. In
+    // meters, like the other transforms.
+    pub pose: Isometry3<f64>,
+}
+
+impl SupportPlane {
+    // Reads {ply_dir}/{scene_name}.ply, the same scene the localization
+    // would get, and estimates its plane.
+    pub fn from_scene(
+        ply_dir: &str,
+        scene_name: &str,
+        crop: Option<&CropBox>,
+        config: &RansacConfig,
+    ) -> Result<SupportPlane, SceneError> {
+        let scene = read_scene(&format!("{}/{}.ply", ply_dir, scene_name))?;
+        SupportPlane::estimate(&scene, crop, config)
+            .map_err(|reason| SceneError::NoPlane { reason })
+    }
+
+    pub fn estimate(
+        scene: &PointCloud,
+        crop: Option<&CropBox>,
+        config: &RansacConfig,
+    ) -> Result<SupportPlane, String> {
+        let points: Vec<[f32; 3]> = scene
+            .points
+            .iter()
+            .filter(|p| is_valid_point(p) && crop.is_none_or(|crop| crop.contains(p)))
+            .copied()
+            .collect();
+        let fit = fit_plane(&points, config)
+            .ok_or_else(|| format!("No plane found in {} points.", points.len()))?;
+
+        let normal = fit.plane.normal;
+        let centroid = fit
+            .inliers
+            .iter()
+            .map(|i| Vector3::from(points[*i].map(f64::from)))
+            .sum::<Vector3<f64>>()
+            / fit.inliers.len() as f64;
+        let origin = centroid - normal * fit.plane.distance(&centroid);
+        let x_axis = [Vector3::x(), Vector3::y()]
+            .iter()
+            .find_map(|axis| (axis - normal * normal.dot(axis)).try_normalize(1e-6))
+            .unwrap_or_else(Vector3::x);
+        let y_axis = normal.cross(&x_axis);
+        let rotation =
+            Rotation3::from_matrix_unchecked(Matrix3::from_columns(&[x_axis, y_axis, normal]));
+        let pose = Isometry3::from_parts(
+            Translation3::from(origin / 1000.0),
+            UnitQuaternion::from_rotation_matrix(&rotation),
+        );
+
+        Ok(SupportPlane {
+            points: points.len(),
+            fit,
+            pose,
+        })
+    }
+
+    // Published as {id}_plane_statistics, lengths in millimeters and
+    // angles in degrees:
+    //
+    //   points, inliers, inlier_fraction: how much of the scene is the plane.
+    //   rms_distance: of the inliers to the plane, how flat it is.
+    //   distance:     from the camera to the plane.
+    //   tilt:         between the normal and the optical axis of the camera.
+    //   world_tilt:   between the normal and the z-axis of the extrinsic's
+    //                 parent frame, only with an extrinsic.
+    pub fn statistics(&self, extrinsic: Option<&CameraExtrinsic>) -> MapOrUnknown {
+        let normal = self.fit.plane.normal;
+        let inlier_fraction = match self.points {
+            0 => 0.0,
+            points => self.fit.inliers.len() as f64 / points as f64,
+        };
+        let mut statistics = vec![
+            ("points".to_spvalue(), (self.points as i64).to_spvalue()),
+            (
+                "inliers".to_spvalue(),
+                (self.fit.inliers.len() as i64).to_spvalue(),
+            ),
+            ("inlier_fraction".to_spvalue(), inlier_fraction.to_spvalue()),
+            (
+                "rms_distance".to_spvalue(),
+                self.fit.rms_distance.to_spvalue(),
+            ),
+            (
+                "distance".to_spvalue(),
+                (-self.fit.plane.offset).to_spvalue(),
+            ),
+            ("tilt".to_spvalue(), angle_to_axis(&normal).to_spvalue()),
+        ];
+        if let Some(extrinsic) = extrinsic {
+            let world_normal = extrinsic.pose.rotation * normal;
+            statistics.push((
+                "world_tilt".to_spvalue(),
+                angle_to_axis(&world_normal).to_spvalue(),
+            ));
+        }
+        MapOrUnknown::Map(statistics)
+    }
+
+    pub fn to_transform(&self, parent_frame_id: &str, child_frame_id: &str) -> SPTransformStamped {
+        SPTransformStamped {
+            active_transform: true,
+            enable_transform: true,
+            time_stamp: SystemTime::now(),
+            parent_frame_id: parent_frame_id.to_string(),
+            child_frame_id: child_frame_id.to_string(),
+            transform: from_isometry(&self.pose),
+            metadata: self.statistics(None),
+        }
+    }
+}
+
+// Angle between the line of the normal and the z-axis, whichever way
+// either points.
+fn angle_to_axis(normal: &Vector3<f64>) -> f64 {
+    normal.z.abs().min(1.0).acos().to_degrees()
+}
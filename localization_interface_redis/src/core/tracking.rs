@@ -0,0 +1,255 @@
+use micro_sp::*;
+use ordered_float::OrderedFloat;
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+use super::naming::metadata_target_name;
+
+// Associates the detections of consecutive localizations of the same scene
+// with each other, so that a physical part keeps its frame id and its pose
+// can be smoothed over the last few scans.
+
+pub const DEFAULT_TRACKING_TOLERANCE: f64 = 0.02;
+pub const DEFAULT_TRACKING_WINDOW: usize = 5;
+pub const DEFAULT_TRACKING_MAX_MISSED: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackingConfig {
+    // Maximum distance in meters between a track and a detection of it.
+    pub tolerance: f64,
+    // Number of recent poses that are averaged.
+    pub window: usize,
+    // Number of scans a track survives without being detected.
+    pub max_missed: usize,
+}
+
+impl TrackingConfig {
+    // Zero or negative values select the defaults.
+    pub fn from_request(tolerance: f64, window: i64, max_missed: i64) -> TrackingConfig {
+        TrackingConfig {
+            tolerance: if tolerance > 0.0 {
+                tolerance
+            } else {
+                DEFAULT_TRACKING_TOLERANCE
+            },
+            window: if window > 0 {
+                window as usize
+            } else {
+                DEFAULT_TRACKING_WINDOW
+            },
+            max_missed: if max_missed > 0 {
+                max_missed as usize
+            } else {
+                DEFAULT_TRACKING_MAX_MISSED
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Track {
+    id: String,
+    parent_frame_id: String,
+    target_name: Option<String>,
+    poses: VecDeque<SPTransform>,
+    metadata: MapOrUnknown,
+    observations: usize,
+    missed: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TrackingUpdate {
+    // One smoothed transform per live track. Tracks that were not
+    // detected in this scan are published with active_transform false.
+    pub transforms: Vec<SPTransformStamped>,
+    pub appeared: Vec<String>,
+    pub disappeared: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Tracker {
+    tracks: Vec<Track>,
+}
+
+impl Tracker {
+    pub fn new() -> Tracker {
+        Tracker::default()
+    }
+
+    pub fn update(
+        &mut self,
+        detections: &[SPTransformStamped],
+        config: &TrackingConfig,
+    ) -> TrackingUpdate {
+        let mut update = TrackingUpdate::default();
+
+        // Closest pairs first, every track and detection is used at most once.
+        let mut pairs: Vec<(f64, usize, usize)> = Vec::new();
+        for (i, track) in self.tracks.iter().enumerate() {
+            for (j, detection) in detections.iter().enumerate() {
+                if track.parent_frame_id != detection.parent_frame_id
+                    || track.target_name != metadata_target_name(detection)
+                {
+                    continue;
+                }
+                let distance = translation_distance(track.latest(), &detection.transform);
+                if distance <= config.tolerance {
+                    pairs.push((distance, i, j));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut track_matched = vec![false; self.tracks.len()];
+        let mut detection_matched = vec![false; detections.len()];
+        for (_, i, j) in pairs {
+            if track_matched[i] || detection_matched[j] {
+                continue;
+            }
+            track_matched[i] = true;
+            detection_matched[j] = true;
+            self.tracks[i].observe(&detections[j], config.window);
+        }
+
+        for (track, matched) in self.tracks.iter_mut().zip(&track_matched) {
+            if !matched {
+                track.missed += 1;
+                if track.missed == 1 {
+                    update.disappeared.push(track.id.clone());
+                }
+            }
+        }
+        self.tracks.retain(|track| track.missed <= config.max_missed);
+
+        for (detection, matched) in detections.iter().zip(detection_matched) {
+            if matched {
+                continue;
+            }
+            let id = self.unique_id(&detection.child_frame_id);
+            update.appeared.push(id.clone());
+            let mut track = Track {
+                id,
+                parent_frame_id: detection.parent_frame_id.clone(),
+                target_name: metadata_target_name(detection),
+                poses: VecDeque::new(),
+                metadata: MapOrUnknown::UNKNOWN,
+                observations: 0,
+                missed: 0,
+            };
+            track.observe(detection, config.window);
+            self.tracks.push(track);
+        }
+
+        update.transforms = self.tracks.iter().map(Track::smoothed).collect();
+        update
+    }
+
+    // Random or indexed names can collide with a track that is currently
+    // missing, which must not be merged with a new part.
+    fn unique_id(&self, child_frame_id: &str) -> String {
+        let taken = |id: &str| self.tracks.iter().any(|track| track.id == id);
+        if !taken(child_frame_id) {
+            return child_frame_id.to_string();
+        }
+        (1..)
+            .map(|k| format!("{}_{}", child_frame_id, k))
+            .find(|id| !taken(id))
+            .unwrap_or_default()
+    }
+}
+
+impl Track {
+    fn observe(&mut self, detection: &SPTransformStamped, window: usize) {
+        self.poses.push_back(detection.transform.clone());
+        while self.poses.len() > window.max(1) {
+            self.poses.pop_front();
+        }
+        self.metadata = detection.metadata.clone();
+        self.observations += 1;
+        self.missed = 0;
+    }
+
+    fn latest(&self) -> &SPTransform {
+        self.poses.back().expect("a track always has at least one pose")
+    }
+
+    fn smoothed(&self) -> SPTransformStamped {
+        let mut metadata = match &self.metadata {
+            MapOrUnknown::Map(entries) => entries.clone(),
+            MapOrUnknown::UNKNOWN => Vec::new(),
+        };
+        metadata.push(("observations".to_spvalue(), (self.observations as i64).to_spvalue()));
+        metadata.push(("missed".to_spvalue(), (self.missed as i64).to_spvalue()));
+
+        SPTransformStamped {
+            active_transform: self.missed == 0,
+            enable_transform: true,
+            time_stamp: SystemTime::now(),
+            parent_frame_id: self.parent_frame_id.clone(),
+            child_frame_id: self.id.clone(),
+            transform: average_transform(&self.poses),
+            metadata: MapOrUnknown::Map(metadata),
+        }
+    }
+}
+
+// Mean translation and normalized mean of the sign aligned quaternions.
+// For the small spread between scans of a static part this is a close
+// approximation of the rotation mean on SO(3).
+fn average_transform(poses: &VecDeque<SPTransform>) -> SPTransform {
+    let n = poses.len() as f64;
+    let reference = quaternion(poses.back().expect("a track always has at least one pose"));
+
+    let mut translation = [0.0; 3];
+    let mut rotation = [0.0; 4];
+    for pose in poses {
+        translation[0] += pose.translation.x.into_inner() / n;
+        translation[1] += pose.translation.y.into_inner() / n;
+        translation[2] += pose.translation.z.into_inner() / n;
+
+        let q = quaternion(pose);
+        let dot: f64 = (0..4).map(|k| q[k] * reference[k]).sum();
+        let sign = if dot < 0.0 { -1.0 } else { 1.0 };
+        for k in 0..4 {
+            rotation[k] += sign * q[k];
+        }
+    }
+
+    let norm = rotation.iter().map(|v| v * v).sum::<f64>().sqrt();
+    let rotation = if norm > f64::EPSILON {
+        rotation.map(|v| v / norm)
+    } else {
+        reference
+    };
+
+    SPTransform {
+        translation: SPTranslation {
+            x: OrderedFloat(translation[0]),
+            y: OrderedFloat(translation[1]),
+            z: OrderedFloat(translation[2]),
+        },
+        rotation: SPRotation {
+            x: OrderedFloat(rotation[0]),
+            y: OrderedFloat(rotation[1]),
+            z: OrderedFloat(rotation[2]),
+            w: OrderedFloat(rotation[3]),
+        },
+    }
+}
+
+fn quaternion(transform: &SPTransform) -> [f64; 4] {
+    let r = &transform.rotation;
+    [
+        r.x.into_inner(),
+        r.y.into_inner(),
+        r.z.into_inner(),
+        r.w.into_inner(),
+    ]
+}
+
+fn translation_distance(a: &SPTransform, b: &SPTransform) -> f64 {
+    let dx = a.translation.x.into_inner() - b.translation.x.into_inner();
+    let dy = a.translation.y.into_inner() - b.translation.y.into_inner();
+    let dz = a.translation.z.into_inner() - b.translation.z.into_inner();
+    (dx * dx + dy * dy + dz * dz).sqrt()
+}
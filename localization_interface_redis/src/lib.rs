@@ -0,0 +1,9 @@
+use micro_sp::*;
+use std::{fs::File, io::BufReader};
+
+pub mod core;
+pub use core::backend::{backend_from_env, LocalizationBackend};
+pub use core::interface::photoneo_localization_interface;
+pub use core::request_file::RequestProtocol;
+pub use core::scanners::scanner_ids;
+pub use core::state;
@@ -3,27 +3,11 @@ use std::{error::Error, sync::Arc};
 use tokio::time::{interval, Duration};
 
 use micro_sp::*;
-use std::{fs::File, io::BufReader};
-
-use std::io::BufRead;
-use std::process::{Command, Stdio};
-use std::thread;
-
-mod core;
-pub use core::interface::photoneo_localization_interface;
-pub use core::state;
+use localization_interface_redis::*;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     initialize_env_logger();
-    let photoneo_id = match std::env::var("PHOTONEO_ID") {
-        Ok(id) => format!("phoxi_{id}"),
-        Err(e) => {
-            log::error!(target: &&format!("phoxi_localization_interface"), "Failed to read PHOTONEO_ID with error: '{}'.", e);
-            log::warn!(target: &&format!("phoxi_localization_interface"), "Setting default PHOTONEO_ID 1708011.");
-            "photoneo_1708011".to_string()
-        }
-    };
     let phoxi_scans_path = match std::env::var("PHOXI_SCANS_PATH") {
         Ok(dir) => dir,
         Err(e) => {
@@ -57,29 +41,49 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     };
 
+    let request_protocol = RequestProtocol::from_env();
+    log::info!(target: "phoxi_localization_interface",
+        "Passing requests to the executable using the '{:?}' protocol.", request_protocol);
+
+    let photoneo_ids = scanner_ids(&localization_interface_path, "phoxi_localization_interface");
+    log::info!(target: "phoxi_localization_interface",
+        "Serving the scanners: {}.", photoneo_ids.join(", "));
+
     let mut interval = interval(Duration::from_millis(100));
-    let state = state::generate_photoneo_localization_interface_state(&photoneo_id);
 
-    log::info!(target: &&format!("phoxi_control_interface"), "Starting.");
+    log::info!(target: &&format!("phoxi_localization_interface"), "Starting.");
 
     let connection_manager = ConnectionManager::new().await;
-    StateManager::set_state(&mut connection_manager.get_connection().await, &state).await;
     let con_arc = Arc::new(connection_manager);
 
-    tokio::task::spawn(async move {
-        match photoneo_localization_interface(
-            &photoneo_id,
-            &phoxi_scans_path,
-            &plcfs_path,
-            &localization_interface_path,
-            &con_arc,
-        )
-        .await
-        {
-            Ok(()) => (),
-            Err(e) => log::error!(target: &&format!("phoxi_localization_interface"), "{}", e),
-        }
-    });
+    // Every scanner has its own interface, so a long localization on one
+    // does not hold up the others.
+    for photoneo_id in photoneo_ids {
+        let backend =
+            backend_from_env(&photoneo_id, &localization_interface_path, request_protocol);
+        let state = state::generate_photoneo_localization_interface_state(&photoneo_id);
+        StateManager::set_state(&mut con_arc.get_connection().await, &state).await;
+
+        let phoxi_scans_path = phoxi_scans_path.clone();
+        let plcfs_path = plcfs_path.clone();
+        let localization_interface_path = localization_interface_path.clone();
+        let con_arc = con_arc.clone();
+        tokio::task::spawn(async move {
+            match photoneo_localization_interface(
+                &photoneo_id,
+                &phoxi_scans_path,
+                &plcfs_path,
+                &localization_interface_path,
+                &backend,
+                &con_arc,
+            )
+            .await
+            {
+                Ok(()) => (),
+                Err(e) => log::error!(target: &&format!("phoxi_localization_interface"), "{}", e),
+            }
+        });
+    }
 
     loop {
         interval.tick().await;
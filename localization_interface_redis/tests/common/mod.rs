@@ -0,0 +1,231 @@
+// Shared by the integration tests. Not every test uses every helper.
+#![allow(dead_code)]
+
+use localization_interface_redis::core::parser::Detection;
+use localization_interface_redis::core::settings::{LocalizationSettings, SettingsError};
+use localization_interface_redis::*;
+use micro_sp::*;
+pub use photoneo_common::redis_server::redis_server;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub async fn connect() -> Arc<ConnectionManager> {
+    redis_server();
+    Arc::new(ConnectionManager::new().await)
+}
+
+pub struct Interface {
+    pub photoneo_id: String,
+    pub scans: tempfile::TempDir,
+    pub installation: tempfile::TempDir,
+    pub connection_manager: Arc<ConnectionManager>,
+}
+
+// Publishes the initial state of a scanner with its own id, so tests can
+// share a server, and starts the localization interface for it. The
+// backend is made for the empty installation directory of the interface.
+pub async fn start_interface(
+    test_name: &str,
+    backend: impl FnOnce(&str, &str) -> Arc<dyn LocalizationBackend>,
+) -> Interface {
+    let photoneo_id = format!("pholoc_test_{}", test_name);
+    let scans = tempfile::tempdir().expect("scan directory");
+    let installation = tempfile::tempdir().expect("installation directory");
+    let connection_manager = connect().await;
+
+    let state = state::generate_photoneo_localization_interface_state(&photoneo_id);
+    StateManager::set_state(&mut connection_manager.get_connection().await, &state).await;
+
+    let id = photoneo_id.clone();
+    let scans_path = scans.path().to_string_lossy().to_string();
+    let installation_path = installation.path().to_string_lossy().to_string();
+    let backend = backend(&id, &installation_path);
+    let interface_connection = connection_manager.clone();
+    tokio::task::spawn(async move {
+        if let Err(e) = photoneo_localization_interface(
+            &id,
+            &scans_path,
+            &format!("{}/plcfs", installation_path),
+            &installation_path,
+            &backend,
+            &interface_connection,
+        )
+        .await
+        {
+            panic!("Interface stopped: {}", e);
+        }
+    });
+
+    Interface {
+        photoneo_id,
+        scans,
+        installation,
+        connection_manager,
+    }
+}
+
+impl Interface {
+    pub fn key(&self, name: &str) -> String {
+        format!("{}_localization_{}", self.photoneo_id, name)
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        [
+            "request_trigger",
+            "request_state",
+            "request_abort",
+            "command",
+            "scene_name",
+            "target_name",
+            "source_format",
+            "stop_at_timeout",
+            "stop_at_number",
+            "settings",
+            "scanning_frame",
+            "plane_distance_threshold",
+            "plane_iterations",
+            "success",
+            "stop_criteria_met",
+            "count",
+            "transforms",
+            "error_code",
+            "error_recoverable",
+            "raw_info",
+            "total_fail_counter",
+            "subsequent_fail_counter",
+            "needs_attention",
+        ]
+        .iter()
+        .map(|name| self.key(name))
+        .collect()
+    }
+
+    pub async fn state(&self) -> State {
+        let mut con = self.connection_manager.get_connection().await;
+        StateManager::get_state_for_keys(&mut con, &self.keys())
+            .await
+            .expect("state of the interface")
+    }
+
+    // Sets the request and triggers it from the initial state.
+    pub async fn request(&self, values: Vec<(&str, SPValue)>) {
+        let state = self.state().await;
+        let mut new_state = state
+            .update(&self.key("request_state"), "initial".to_spvalue())
+            .update(&self.key("request_trigger"), true.to_spvalue());
+        for (name, value) in values {
+            new_state = new_state.update(&self.key(name), value);
+        }
+        let mut con = self.connection_manager.get_connection().await;
+        StateManager::set_state(&mut con, &state.get_diff_partial_state(&new_state)).await;
+    }
+
+    // Waits until the request left the initial state and returns the state.
+    pub async fn response(&self, timeout: Duration) -> State {
+        let start = Instant::now();
+        loop {
+            let state = self.state().await;
+            if self.request_state(&state) != ServiceRequestState::Initial.to_string() {
+                return state;
+            }
+            assert!(
+                start.elapsed() < timeout,
+                "No response from {} within {:?}.",
+                self.photoneo_id,
+                timeout
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    pub async fn abort(&self) {
+        let state = self.state().await;
+        let new_state = state.update(&self.key("request_abort"), true.to_spvalue());
+        let mut con = self.connection_manager.get_connection().await;
+        StateManager::set_state(&mut con, &state.get_diff_partial_state(&new_state)).await;
+    }
+
+    pub fn request_state(&self, state: &State) -> String {
+        state.get_string_or_default_to_unknown(&self.key("request_state"), "test")
+    }
+
+    pub fn error_code(&self, state: &State) -> String {
+        state.get_string_or_default_to_unknown(&self.key("error_code"), "test")
+    }
+
+    pub fn raw_info(&self, state: &State) -> String {
+        state.get_string_or_default_to_unknown(&self.key("raw_info"), "test")
+    }
+
+    pub fn transforms(&self, state: &State) -> Vec<SPTransformStamped> {
+        state
+            .get_array_or_default_to_empty(&self.key("transforms"), "test")
+            .into_iter()
+            .filter_map(|value| match value {
+                SPValue::Transform(TransformOrUnknown::Transform(transform)) => Some(transform),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Published by a fit_plane request, next to the localization keys.
+    pub async fn plane_transform(&self) -> Option<SPTransformStamped> {
+        self.published_transform("plane_transform").await
+    }
+
+    pub async fn plane_world_transform(&self) -> Option<SPTransformStamped> {
+        self.published_transform("plane_world_transform").await
+    }
+
+    async fn published_transform(&self, name: &str) -> Option<SPTransformStamped> {
+        let key = format!("{}_{}", self.photoneo_id, name);
+        let mut con = self.connection_manager.get_connection().await;
+        let state = StateManager::get_state_for_keys(&mut con, &vec![key.clone()])
+            .await
+            .expect("state of the plane");
+        match state.get_value(&key, "test") {
+            Some(SPValue::Transform(TransformOrUnknown::Transform(transform))) => Some(transform),
+            _ => None,
+        }
+    }
+}
+
+// A detection of the target at the position in meters, the engine reports
+// millimeters.
+pub fn detection(target_name: &str, index: usize, position: [f64; 3]) -> Detection {
+    let [x, y, z] = position.map(|v| v * 1000.0);
+    Detection {
+        index,
+        matrix: [
+            [1.0, 0.0, 0.0, x],
+            [0.0, 1.0, 0.0, y],
+            [0.0, 0.0, 1.0, z],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+        target_name: target_name.to_string(),
+        score: None,
+        overlap: None,
+    }
+}
+
+pub const TOLERANCE: f64 = 1e-9;
+
+pub fn assert_close(actual: [f64; 3], expected: [f64; 3]) {
+    let close = actual
+        .iter()
+        .zip(expected)
+        .all(|(a, e)| (a - e).abs() < TOLERANCE);
+    assert!(close, "expected {:?}, got {:?}", expected, actual);
+}
+
+// The fields of the violations a preset is rejected with.
+pub fn violations(preset: &serde_json::Value) -> Vec<String> {
+    match LocalizationSettings::from_value(preset, "test.json") {
+        Err(SettingsError::Invalid { file, violations }) => {
+            assert_eq!(file, "test.json");
+            violations.into_iter().map(|v| v.field).collect()
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("the preset was accepted"),
+    }
+}
@@ -0,0 +1,90 @@
+// Failures of the engine are classified by the lines it printed, those of
+// the interface itself by their kind.
+
+use localization_interface_redis::core::error::ErrorCode;
+use localization_interface_redis::core::executor::CommandFailure;
+use localization_interface_redis::core::preprocess::SceneError;
+use std::io;
+
+fn printed(kind: io::ErrorKind, stdout: &[&str], stderr: &[&str]) -> io::Error {
+    io::Error::new(
+        kind,
+        CommandFailure {
+            reason: stderr.last().unwrap_or(&"").to_string(),
+            exit_code: Some(1),
+            stdout: stdout.iter().map(|line| line.to_string()).collect(),
+            stderr: stderr.iter().map(|line| line.to_string()).collect(),
+        },
+    )
+}
+
+#[test]
+fn latest_line_of_the_engine_is_the_cause() {
+    let error = printed(
+        io::ErrorKind::Other,
+        &["SceneSource Error: no scene bin.ply"],
+        &["Error loading plcf file: part.plcf"],
+    );
+    assert_eq!(ErrorCode::classify(&error), ErrorCode::PlcfMissing);
+
+    // What the engine printed tells more than that it ran out of time.
+    let error = printed(
+        io::ErrorKind::TimedOut,
+        &["SceneSource Error: no scene bin.ply"],
+        &[],
+    );
+    assert_eq!(ErrorCode::classify(&error), ErrorCode::SceneMissing);
+}
+
+#[test]
+fn messages_of_the_interface_are_not_matched() {
+    let error = io::Error::other("Failed to read settings file 'a.json' of the scene bin.");
+    assert_eq!(ErrorCode::classify(&error), ErrorCode::Unknown);
+}
+
+#[test]
+fn error_kind_is_the_fallback() {
+    let error = printed(io::ErrorKind::TimedOut, &["Searching for part."], &[]);
+    assert_eq!(ErrorCode::classify(&error), ErrorCode::LocalizationTimeout);
+
+    let error = io::Error::new(io::ErrorKind::InvalidInput, "Settings are invalid.");
+    assert_eq!(ErrorCode::classify(&error), ErrorCode::SettingsInvalid);
+
+    let error = io::Error::new(
+        io::ErrorKind::Interrupted,
+        "Command execution was cancelled.",
+    );
+    assert_eq!(ErrorCode::classify(&error), ErrorCode::Cancelled);
+
+    let error = printed(io::ErrorKind::Other, &["Searching for part."], &[]);
+    assert_eq!(ErrorCode::classify(&error), ErrorCode::Unknown);
+}
+
+#[test]
+fn scene_errors_map_to_their_code() {
+    let empty = io::Error::from(SceneError::Empty {
+        scene_name: "bin".to_string(),
+    });
+    assert_eq!(ErrorCode::classify(&empty), ErrorCode::SceneMissing);
+    assert_eq!(
+        empty.to_string(),
+        "Preprocessing left no points of scene bin."
+    );
+
+    let no_plane = io::Error::from(SceneError::NoPlane {
+        reason: "No plane found in 3 points.".to_string(),
+    });
+    assert_eq!(ErrorCode::classify(&no_plane), ErrorCode::Unknown);
+}
+
+#[test]
+fn only_transient_failures_are_recoverable() {
+    assert!(ErrorCode::SceneMissing.is_recoverable());
+    assert!(ErrorCode::LocalizationTimeout.is_recoverable());
+    assert!(!ErrorCode::SettingsInvalid.is_recoverable());
+    assert!(!ErrorCode::PlcfMissing.is_recoverable());
+    assert_eq!(
+        ErrorCode::LocalizationTimeout.as_str(),
+        "localization_timeout"
+    );
+}
@@ -0,0 +1,211 @@
+// The camera extrinsic: expressing detections in its parent frame and
+// where it is taken from.
+
+mod common;
+
+use common::{assert_close, TOLERANCE};
+use localization_interface_redis::core::extrinsic::{from_isometry, to_isometry, CameraExtrinsic};
+use localization_interface_redis::core::interface::{load_extrinsic, load_parameters};
+use localization_interface_redis::core::parameters::save_shared_parameters;
+use micro_sp::*;
+use nalgebra::{Isometry3, Point3, Translation3, UnitQuaternion, Vector3};
+use ordered_float::OrderedFloat;
+use std::f64::consts::{FRAC_PI_2, PI};
+use std::time::SystemTime;
+
+fn transform(parent: &str, child: &str, pose: &Isometry3<f64>) -> SPTransformStamped {
+    SPTransformStamped {
+        active_transform: true,
+        enable_transform: true,
+        time_stamp: SystemTime::now(),
+        parent_frame_id: parent.to_string(),
+        child_frame_id: child.to_string(),
+        transform: from_isometry(pose),
+        metadata: MapOrUnknown::UNKNOWN,
+    }
+}
+
+fn translation(transform: &SPTransformStamped) -> [f64; 3] {
+    let t = &transform.transform.translation;
+    [t.x.0, t.y.0, t.z.0]
+}
+
+// A camera 1.5 m above the world origin looking straight down, its x-axis
+// along the world's y-axis.
+fn overhead_camera() -> CameraExtrinsic {
+    let looking_down = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), PI);
+    let turned = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), FRAC_PI_2);
+    CameraExtrinsic {
+        parent_frame_id: "world".to_string(),
+        child_frame_id: "photoneo_1708011".to_string(),
+        pose: Isometry3::from_parts(Translation3::new(0.0, 0.0, 1.5), turned * looking_down),
+    }
+}
+
+#[test]
+fn detections_are_expressed_in_the_parent_frame() {
+    let extrinsic = overhead_camera();
+    let detections = [
+        transform(
+            "photoneo_1708011",
+            "part_0",
+            &Isometry3::translation(0.0, 0.0, 1.0),
+        ),
+        transform(
+            "photoneo_1708011",
+            "part_1",
+            &Isometry3::from_parts(
+                Translation3::new(0.1, 0.2, 1.2),
+                UnitQuaternion::from_axis_angle(&Vector3::z_axis(), 0.3),
+            ),
+        ),
+    ];
+    let in_world = extrinsic.to_parent_frame(&detections);
+
+    assert_eq!(in_world.len(), 2);
+    assert_eq!(in_world[0].parent_frame_id, "world");
+    assert_eq!(in_world[0].child_frame_id, "part_0");
+    assert_eq!(in_world[1].child_frame_id, "part_1");
+
+    // 1 m in front of the camera is 0.5 m above the floor.
+    assert_close(translation(&in_world[0]), [0.0, 0.0, 0.5]);
+    // Camera x is world y, camera y is world x and camera z is world -z.
+    assert_close(translation(&in_world[1]), [0.2, 0.1, 0.3]);
+
+    // The rotation is composed as well: the part's z-axis points down the
+    // optical axis, so it points down in the world.
+    let pose = to_isometry(&in_world[1].transform);
+    assert!((pose.rotation * Vector3::z() + Vector3::z()).norm() < TOLERANCE);
+    let expected = extrinsic.pose * to_isometry(&detections[1].transform);
+    let point = Point3::new(0.05, -0.02, 0.01);
+    assert!((pose * point - expected * point).norm() < TOLERANCE);
+}
+
+#[test]
+fn isometries_survive_the_round_trip() {
+    let pose = Isometry3::from_parts(
+        Translation3::new(-0.4, 0.25, 0.9),
+        UnitQuaternion::from_euler_angles(0.1, -0.2, 0.3),
+    );
+    let round_trip = to_isometry(&from_isometry(&pose));
+    assert!((round_trip.translation.vector - pose.translation.vector).norm() < TOLERANCE);
+    assert!(round_trip.rotation.angle_to(&pose.rotation) < TOLERANCE);
+
+    // Quaternions that are not quite unit length are normalized.
+    let mut scaled = from_isometry(&pose);
+    scaled.rotation.w = OrderedFloat(scaled.rotation.w.0 * 2.0);
+    scaled.rotation.x = OrderedFloat(scaled.rotation.x.0 * 2.0);
+    scaled.rotation.y = OrderedFloat(scaled.rotation.y.0 * 2.0);
+    scaled.rotation.z = OrderedFloat(scaled.rotation.z.0 * 2.0);
+    assert!(to_isometry(&scaled).rotation.angle_to(&pose.rotation) < TOLERANCE);
+}
+
+#[test]
+fn extrinsic_is_read_from_the_parameters() {
+    let parameters = serde_json::json!({
+        "extrinsic": {
+            "parent_frame_id": "world",
+            "child_frame_id": "photoneo_1708011",
+            "translation": {"x": 0.0, "y": 0.0, "z": 1.5},
+            "rotation": {"x": 1.0, "y": 0.0, "z": 0.0, "w": 0.0}
+        }
+    });
+    let extrinsic = CameraExtrinsic::from_parameters(&parameters)
+        .unwrap()
+        .unwrap();
+    assert_eq!(extrinsic.parent_frame_id, "world");
+    let point = extrinsic.pose * Point3::new(0.0, 0.1, 1.0);
+    assert!((point - Point3::new(0.0, -0.1, 0.5)).norm() < TOLERANCE);
+
+    assert!(CameraExtrinsic::from_parameters(&serde_json::json!({})).is_none());
+    assert!(CameraExtrinsic::from_parameters(&serde_json::json!({"extrinsic": null})).is_none());
+    let error = CameraExtrinsic::from_parameters(&serde_json::json!({
+        "extrinsic": {"parent_frame_id": "world", "child_frame_id": "camera",
+                      "translation": {"x": 0.0, "y": 0.0}, "rotation": {"x": 0.0, "y": 0.0, "z": 0.0, "w": 1.0}}
+    }))
+    .unwrap()
+    .unwrap_err();
+    assert!(error.contains("missing field `z`"), "{}", error);
+}
+
+#[test]
+fn published_extrinsic_takes_precedence_over_the_calibration_and_the_parameters() {
+    let installation = tempfile::tempdir().unwrap();
+    let scans = tempfile::tempdir().unwrap();
+    let scans_path = scans.path().to_string_lossy().to_string();
+    let scanners = installation.path().join("parameters/scanners");
+    std::fs::create_dir_all(&scanners).unwrap();
+    std::fs::write(
+        scanners.join("photoneo_test.json"),
+        serde_json::json!({
+            "extrinsic": {
+                "parent_frame_id": "world",
+                "child_frame_id": "photoneo_test",
+                "translation": {"x": 0.0, "y": 0.0, "z": 1.5},
+                "rotation": {"x": 1.0, "y": 0.0, "z": 0.0, "w": 0.0}
+            }
+        })
+        .to_string(),
+    )
+    .unwrap();
+    let path = installation.path().to_string_lossy().to_string();
+    let parameters = load_parameters(&path, "photoneo_test");
+    let key = tv!("photoneo_test_camera_extrinsic");
+
+    let unpublished = State::new().add(assign!(
+        key,
+        SPValue::Transform(TransformOrUnknown::UNKNOWN)
+    ));
+    let from_parameters =
+        load_extrinsic(&unpublished, &scans_path, &parameters, "photoneo_test").unwrap();
+    assert_eq!(from_parameters.parent_frame_id, "world");
+    assert!((from_parameters.pose.translation.z - 1.5).abs() < TOLERANCE);
+
+    // Saved by a calibration of the control interface.
+    save_shared_parameters(
+        &scans_path,
+        "photoneo_test",
+        serde_json::Map::from_iter([(
+            "extrinsic".to_string(),
+            serde_json::json!({
+                "parent_frame_id": "calibrated_base",
+                "child_frame_id": "photoneo_test",
+                "translation": {"x": 0.0, "y": 0.0, "z": 1.2},
+                "rotation": {"x": 1.0, "y": 0.0, "z": 0.0, "w": 0.0}
+            }),
+        )]),
+        "test",
+    )
+    .unwrap();
+    let calibrated =
+        load_extrinsic(&unpublished, &scans_path, &parameters, "photoneo_test").unwrap();
+    assert_eq!(calibrated.parent_frame_id, "calibrated_base");
+    assert!((calibrated.pose.translation.z - 1.2).abs() < TOLERANCE);
+
+    let published = overhead_camera();
+    let state = unpublished.update(
+        "photoneo_test_camera_extrinsic",
+        SPValue::Transform(TransformOrUnknown::Transform(transform(
+            "robot_base",
+            "photoneo_test",
+            &published.pose,
+        ))),
+    );
+    let extrinsic = load_extrinsic(&state, &scans_path, &parameters, "photoneo_test").unwrap();
+    assert_eq!(extrinsic.parent_frame_id, "robot_base");
+    assert!(extrinsic.pose.rotation.angle_to(&published.pose.rotation) < TOLERANCE);
+    assert!(
+        (extrinsic.pose.translation.vector - published.pose.translation.vector).norm() < TOLERANCE
+    );
+
+    // Neither published, calibrated nor configured.
+    assert_eq!(
+        load_extrinsic(
+            &unpublished,
+            &scans_path,
+            &load_parameters(&path, "photoneo_other"),
+            "photoneo_other"
+        ),
+        None
+    );
+}
@@ -0,0 +1,157 @@
+// Post-filtering and sorting of the detections before they are published.
+
+mod common;
+
+use localization_interface_redis::core::filter::{ResultFilter, SortOrder, Workspace};
+use localization_interface_redis::core::parser::Detection;
+
+// A part at the position in meters, with what the engine reported of it.
+fn detection(
+    index: usize,
+    position: [f64; 3],
+    score: Option<f64>,
+    overlap: Option<f64>,
+) -> Detection {
+    Detection {
+        score,
+        overlap,
+        ..common::detection("part", index, position)
+    }
+}
+
+fn indices(detections: &[Detection]) -> Vec<usize> {
+    detections.iter().map(|d| d.index).collect()
+}
+
+const WORKSPACE: Workspace = Workspace {
+    min: [-0.2, -0.2, 0.5],
+    max: [0.2, 0.2, 1.0],
+};
+
+#[test]
+fn default_filter_keeps_the_engine_order() {
+    let detections = vec![
+        detection(0, [0.0, 0.0, 0.9], Some(0.2), Some(0.1)),
+        detection(1, [5.0, 0.0, 0.1], None, None),
+        detection(2, [0.0, 0.0, 0.6], Some(0.9), Some(0.9)),
+    ];
+    assert_eq!(
+        ResultFilter::default().apply(detections.clone()),
+        detections
+    );
+}
+
+#[test]
+fn detections_outside_of_the_workspace_are_rejected() {
+    let filter = ResultFilter {
+        workspace: Some(WORKSPACE),
+        ..Default::default()
+    };
+    let kept = filter.apply(vec![
+        detection(0, [0.0, 0.0, 0.75], None, None),
+        detection(1, [0.3, 0.0, 0.75], None, None),
+        detection(2, [0.0, -0.25, 0.75], None, None),
+        detection(3, [0.0, 0.0, 0.4], None, None),
+        detection(4, [0.1, 0.1, 1.1], None, None),
+    ]);
+    assert_eq!(indices(&kept), [0]);
+}
+
+#[test]
+fn workspace_bounds_are_inside() {
+    // Every corner and face of the box counts as inside.
+    for x in [-0.2, 0.2] {
+        for y in [-0.2, 0.2] {
+            for z in [0.5, 1.0] {
+                assert!(WORKSPACE.contains([x, y, z]), "{:?}", [x, y, z]);
+            }
+        }
+    }
+    assert!(WORKSPACE.contains([0.0, 0.2, 0.75]));
+    assert!(!WORKSPACE.contains([0.0, 0.2 + 1e-9, 0.75]));
+    assert!(!WORKSPACE.contains([0.0, 0.0, 0.5 - 1e-9]));
+
+    let filter = ResultFilter {
+        workspace: Some(WORKSPACE),
+        ..Default::default()
+    };
+    let kept = filter.apply(vec![
+        detection(0, [0.2, -0.2, 1.0], None, None),
+        detection(1, [0.2, -0.2, 1.001], None, None),
+    ]);
+    assert_eq!(indices(&kept), [0]);
+}
+
+#[test]
+fn low_overlaps_are_rejected_and_missing_ones_kept() {
+    let filter = ResultFilter {
+        minimum_overlap: 0.5,
+        ..Default::default()
+    };
+    let kept = filter.apply(vec![
+        detection(0, [0.0, 0.0, 0.8], None, Some(0.49)),
+        detection(1, [0.0, 0.0, 0.8], None, Some(0.5)),
+        detection(2, [0.0, 0.0, 0.8], None, None),
+        detection(3, [0.0, 0.0, 0.8], None, Some(0.9)),
+    ]);
+    assert_eq!(indices(&kept), [1, 2, 3]);
+}
+
+#[test]
+fn score_order_is_descending_with_unscored_last() {
+    let filter = ResultFilter {
+        sort_by: SortOrder::Score,
+        ..Default::default()
+    };
+    let kept = filter.apply(vec![
+        detection(0, [0.0, 0.0, 0.8], None, Some(0.9)),
+        detection(1, [0.0, 0.0, 0.8], Some(0.4), None),
+        detection(2, [0.0, 0.0, 0.8], Some(0.8), None),
+        detection(3, [0.0, 0.0, 0.8], None, None),
+        detection(4, [0.0, 0.0, 0.8], Some(0.4), None),
+    ]);
+    // Equal scores and the unscored keep the engine order among themselves.
+    assert_eq!(indices(&kept), [2, 1, 4, 0, 3]);
+}
+
+#[test]
+fn distance_order_starts_nearest_to_the_camera() {
+    let filter = ResultFilter {
+        sort_by: SortOrder::Distance,
+        ..Default::default()
+    };
+    let kept = filter.apply(vec![
+        detection(0, [0.0, 0.0, 0.9], None, None),
+        detection(1, [0.3, 0.4, 0.0], None, None),
+        detection(2, [0.0, 0.0, 0.7], None, None),
+        detection(3, [0.0, 0.5, 0.0], None, None),
+    ]);
+    assert_eq!(indices(&kept), [1, 3, 2, 0]);
+}
+
+#[test]
+fn maximum_applies_after_filtering_and_sorting() {
+    let filter = ResultFilter {
+        minimum_overlap: 0.5,
+        maximum_detections: 2,
+        sort_by: SortOrder::Distance,
+        workspace: Some(WORKSPACE),
+    };
+    let kept = filter.apply(vec![
+        detection(0, [0.0, 0.0, 0.9], None, Some(0.8)),
+        detection(1, [0.0, 0.0, 0.55], None, Some(0.3)),
+        detection(2, [0.0, 0.0, 0.8], None, Some(0.8)),
+        detection(3, [0.0, 0.0, 0.45], None, Some(0.8)),
+        detection(4, [0.0, 0.0, 0.6], None, Some(0.7)),
+    ]);
+    assert_eq!(indices(&kept), [4, 2]);
+}
+
+#[test]
+fn sort_order_is_read_from_the_request() {
+    assert_eq!(SortOrder::from_request("Score"), SortOrder::Score);
+    assert_eq!(SortOrder::from_request("distance"), SortOrder::Distance);
+    assert_eq!(SortOrder::from_request(""), SortOrder::Engine);
+    assert_eq!(SortOrder::from_request("unknown"), SortOrder::Engine);
+    assert_eq!(SortOrder::from_request("size"), SortOrder::Engine);
+}
@@ -0,0 +1,446 @@
+// Drives {id}_localization_request_trigger and
+// {id}_localization_request_state through Redis, with the simulated engine.
+// Set TEST_REDIS_EXTERNAL to run against the Redis in REDIS_HOST and
+// REDIS_PORT instead of the in-process stand-in.
+
+mod common;
+
+use common::start_interface;
+use localization_interface_redis::core::backend::SubprocessBackend;
+use localization_interface_redis::core::ply::PointCloud;
+use localization_interface_redis::core::simulation::{
+    Fixture, FixtureDetection, SimulatedEngine, SimulationConfig,
+};
+use localization_interface_redis::*;
+use micro_sp::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn detection(x: f64, y: f64, z: f64) -> FixtureDetection {
+    FixtureDetection {
+        matrix: [
+            [1.0, 0.0, 0.0, x],
+            [0.0, 1.0, 0.0, y],
+            [0.0, 0.0, 1.0, z],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+        score: Some(0.9),
+        overlap: Some(0.8),
+    }
+}
+
+// The engine finds three parts in "bin", one every 100 ms, and never
+// returns for "stuck".
+fn engine() -> SimulationConfig {
+    let mut targets = HashMap::new();
+    targets.insert(
+        "part".to_string(),
+        Fixture {
+            detections: vec![
+                detection(0.0, 0.0, 900.0),
+                detection(100.0, 0.0, 900.0),
+                detection(200.0, 0.0, 900.0),
+            ],
+            hang: false,
+        },
+    );
+    targets.insert(
+        "stuck".to_string(),
+        Fixture {
+            detections: Vec::new(),
+            hang: true,
+        },
+    );
+    SimulationConfig {
+        latency_ms: 50,
+        result_interval_ms: 100,
+        scenes: HashMap::from([("bin".to_string(), targets)]),
+        fit_scenes: false,
+        ..Default::default()
+    }
+}
+
+fn simulated(config: SimulationConfig) -> impl FnOnce(&str, &str) -> Arc<dyn LocalizationBackend> {
+    move |_, _| Arc::new(SimulatedEngine::new(config))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn all_results_meet_the_stop_criteria() {
+    let interface = start_interface("all_results", simulated(engine())).await;
+    interface
+        .request(vec![
+            ("scene_name", "bin".to_spvalue()),
+            ("target_name", "part".to_spvalue()),
+            ("scanning_frame", "camera".to_spvalue()),
+            ("stop_at_timeout", 3000.to_spvalue()),
+            ("stop_at_number", 3.to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "succeeded");
+    assert!(state.get_bool_or_default_to_false(&interface.key("success"), "test"));
+    assert!(state.get_bool_or_default_to_false(&interface.key("stop_criteria_met"), "test"));
+    assert_eq!(state.get_int_or_default_to_zero(&interface.key("count"), "test"), 3);
+
+    let transforms = interface.transforms(&state);
+    assert_eq!(transforms.len(), 3);
+    for transform in &transforms {
+        assert_eq!(transform.parent_frame_id, "camera");
+        assert!((transform.transform.translation.z.0 - 0.9).abs() < 1e-9);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn results_after_the_timeout_are_not_reported() {
+    let interface = start_interface("partial_results", simulated(engine())).await;
+    interface
+        .request(vec![
+            ("scene_name", "bin".to_spvalue()),
+            ("target_name", "part".to_spvalue()),
+            ("stop_at_timeout", 200.to_spvalue()),
+            ("stop_at_number", 3.to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "succeeded");
+    assert!(state.get_bool_or_default_to_false(&interface.key("success"), "test"));
+    assert!(!state.get_bool_or_default_to_false(&interface.key("stop_criteria_met"), "test"));
+    assert_eq!(state.get_int_or_default_to_zero(&interface.key("count"), "test"), 1);
+    assert_eq!(interface.transforms(&state).len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn nothing_found_is_not_a_success() {
+    let interface = start_interface("no_results", simulated(engine())).await;
+    interface
+        .request(vec![
+            ("scene_name", "empty_bin".to_spvalue()),
+            ("target_name", "part".to_spvalue()),
+            ("stop_at_timeout", 3000.to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "succeeded");
+    assert!(!state.get_bool_or_default_to_false(&interface.key("success"), "test"));
+    assert_eq!(state.get_int_or_default_to_zero(&interface.key("count"), "test"), 0);
+    assert!(interface.transforms(&state).is_empty());
+    assert_eq!(interface.error_code(&state), "none");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hanging_engine_fails() {
+    let interface = start_interface("hanging_engine", simulated(engine())).await;
+    interface
+        .request(vec![
+            ("scene_name", "bin".to_spvalue()),
+            ("target_name", "stuck".to_spvalue()),
+            ("stop_at_timeout", 100.to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "failed");
+    assert!(!state.get_bool_or_default_to_false(&interface.key("success"), "test"));
+    assert_eq!(interface.error_code(&state), "localization_timeout");
+    assert!(state.get_bool_or_default_to_false(&interface.key("error_recoverable"), "test"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failures_in_a_row_need_attention_until_a_success() {
+    let interface = start_interface("attention", simulated(engine())).await;
+    let counters = |state: &State| {
+        (
+            state.get_int_or_default_to_zero(&interface.key("total_fail_counter"), "test"),
+            state.get_int_or_default_to_zero(&interface.key("subsequent_fail_counter"), "test"),
+            state.get_bool_or_default_to_false(&interface.key("needs_attention"), "test"),
+        )
+    };
+
+    for (target_name, request_state, expected_counters) in [
+        ("stuck", "failed", (1, 1, false)),
+        ("stuck", "failed", (2, 2, true)),
+        ("part", "succeeded", (2, 0, false)),
+    ] {
+        interface
+            .request(vec![
+                ("scene_name", "bin".to_spvalue()),
+                ("target_name", target_name.to_spvalue()),
+                ("stop_at_timeout", 100.to_spvalue()),
+                ("attention_threshold", 2.to_spvalue()),
+            ])
+            .await;
+        let state = interface.response(RESPONSE_TIMEOUT).await;
+        assert_eq!(interface.request_state(&state), request_state);
+        assert_eq!(counters(&state), expected_counters);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn abort_cancels_the_localization() {
+    let interface = start_interface("abort", simulated(engine())).await;
+    interface
+        .request(vec![
+            ("scene_name", "bin".to_spvalue()),
+            ("target_name", "stuck".to_spvalue()),
+            ("stop_at_timeout", 10000.to_spvalue()),
+        ])
+        .await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(interface.request_state(&interface.state().await), "initial");
+
+    let start = Instant::now();
+    interface.abort().await;
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "cancelled");
+    assert!(!state.get_bool_or_default_to_false(&interface.key("request_abort"), "test"));
+    assert!(!state.get_bool_or_default_to_false(&interface.key("success"), "test"));
+    assert_eq!(interface.error_code(&state), "cancelled");
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn missing_settings_file_fails() {
+    let interface = start_interface("missing_settings", |id, installation| {
+        Arc::new(SubprocessBackend::new(id, installation, RequestProtocol::File))
+    })
+    .await;
+    interface
+        .request(vec![
+            ("scene_name", "bin".to_spvalue()),
+            ("target_name", "part".to_spvalue()),
+            ("settings", "does_not_exist".to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "failed");
+    assert_eq!(state.get_int_or_default_to_zero(&interface.key("count"), "test"), 0);
+    assert_eq!(interface.error_code(&state), "settings_invalid");
+    assert!(interface.raw_info(&state).contains("does_not_exist.json"));
+}
+
+// Stands in for the Localize executable of the installation.
+#[cfg(unix)]
+fn install_engine(installation: &std::path::Path, script: &str) {
+    use std::os::unix::fs::PermissionsExt;
+    let bin = installation.join("cpp_executables/dev/Localize/bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    let executable = bin.join("Localize_Release");
+    std::fs::write(&executable, format!("#!/bin/sh\n{}\n", script)).unwrap();
+    std::fs::set_permissions(&executable, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_engine_publishes_what_it_printed() {
+    let interface = start_interface("failed_engine", |id, installation| {
+        Arc::new(SubprocessBackend::new(id, installation, RequestProtocol::File))
+    })
+    .await;
+    let request = vec![
+        ("scene_name", "bin".to_spvalue()),
+        ("target_name", "part".to_spvalue()),
+        ("settings", "default".to_spvalue()),
+        ("stop_at_timeout", 10000.to_spvalue()),
+    ];
+
+    install_engine(
+        interface.installation.path(),
+        "echo 'Loading part.plcf'
+         echo 'Error loading plcf file: part.plcf' >&2
+         echo 'Releasing the license' >&2
+         exit 3",
+    );
+    interface.request(request.clone()).await;
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "failed");
+    assert_eq!(interface.error_code(&state), "plcf_missing");
+    assert_eq!(
+        interface.raw_info(&state),
+        "Localize_Release exited with code 3: Releasing the license\n\
+         Error loading plcf file: part.plcf\n\
+         Releasing the license"
+    );
+
+    // An aborted engine leaves what it printed until then.
+    install_engine(
+        interface.installation.path(),
+        "echo 'Searching for part'
+         sleep 10",
+    );
+    interface.request(request).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    interface.abort().await;
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "cancelled");
+    assert_eq!(interface.raw_info(&state), "Searching for part");
+}
+
+// A 20 by 20 mm grid of background at 1 m with a box at 0.9 m in its middle.
+fn write_scene(path: &std::path::Path) {
+    let mut points = Vec::new();
+    for i in 0..20 {
+        for j in 0..20 {
+            let in_box = (8..12).contains(&i) && (8..12).contains(&j);
+            let z = if in_box { 900.0 } else { 1000.0 };
+            points.push(format!("{} {} {}", i * 20, j * 20, z));
+        }
+    }
+    let header = format!(
+        "ply\nformat ascii 1.0\nelement vertex {}\nproperty float x\nproperty float y\nproperty float z\nend_header\n",
+        points.len()
+    );
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, header + &points.join("\n")).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scene_without_fixture_is_fitted() {
+    let config = SimulationConfig {
+        fit_scenes: true,
+        ..engine()
+    };
+    let interface = start_interface("fit_scene", simulated(config)).await;
+    write_scene(&interface.scans.path().join("ply/table.ply"));
+    interface
+        .request(vec![
+            ("scene_name", "table".to_spvalue()),
+            ("target_name", "box".to_spvalue()),
+            ("stop_at_timeout", 3000.to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "succeeded");
+    let transforms = interface.transforms(&state);
+    assert_eq!(transforms.len(), 1);
+    let translation = &transforms[0].transform.translation;
+    assert!((translation.x.0 - 0.19).abs() < 1e-9);
+    assert!((translation.y.0 - 0.19).abs() < 1e-9);
+    assert!((translation.z.0 - 0.9).abs() < 1e-9);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fit_plane_command_finds_the_background() {
+    let interface = start_interface("fit_plane", simulated(engine())).await;
+    write_scene(&interface.scans.path().join("ply/table.ply"));
+    interface
+        .request(vec![
+            ("scene_name", "bin".to_spvalue()),
+            ("target_name", "part".to_spvalue()),
+            ("stop_at_timeout", 3000.to_spvalue()),
+        ])
+        .await;
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.transforms(&state).len(), 3);
+
+    interface
+        .request(vec![
+            ("command", "fit_plane".to_spvalue()),
+            ("scene_name", "table".to_spvalue()),
+        ])
+        .await;
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "succeeded");
+    assert_eq!(interface.error_code(&state), "none");
+    let plane = interface.plane_transform().await.expect("plane transform");
+    assert_eq!(plane.child_frame_id, format!("{}_plane", interface.photoneo_id));
+    assert!((plane.transform.translation.z.0 - 1.0).abs() < 1e-6);
+    // The detections of the localization before are still there.
+    assert_eq!(interface.transforms(&state).len(), 3);
+
+    // A missing scene fails like it does for a localization.
+    interface
+        .request(vec![("scene_name", "missing".to_spvalue())])
+        .await;
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "failed");
+    assert_eq!(interface.error_code(&state), "scene_missing");
+    assert!(interface.plane_transform().await.is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn plane_is_moved_by_the_extrinsic_of_its_scanning_frame_only() {
+    let interface = start_interface("plane_frames", simulated(engine())).await;
+    write_scene(&interface.scans.path().join("ply/table.ply"));
+    let parameters = interface
+        .installation
+        .path()
+        .join(format!("parameters/scanners/{}.json", interface.photoneo_id));
+    std::fs::create_dir_all(parameters.parent().unwrap()).unwrap();
+    let extrinsic = serde_json::json!({
+        "extrinsic": {
+            "parent_frame_id": "world",
+            "child_frame_id": "photoneo",
+            "translation": {"x": 0.0, "y": 0.0, "z": 1.5},
+            "rotation": {"x": 1.0, "y": 0.0, "z": 0.0, "w": 0.0}
+        }
+    });
+    std::fs::write(&parameters, extrinsic.to_string()).unwrap();
+
+    interface
+        .request(vec![
+            ("command", "fit_plane".to_spvalue()),
+            ("scene_name", "table".to_spvalue()),
+            ("scanning_frame", "other_camera".to_spvalue()),
+        ])
+        .await;
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "succeeded");
+    assert!(interface.plane_transform().await.is_some());
+    assert!(interface.plane_world_transform().await.is_none());
+
+    interface
+        .request(vec![("scanning_frame", "photoneo".to_spvalue())])
+        .await;
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "succeeded");
+    let world = interface
+        .plane_world_transform()
+        .await
+        .expect("plane in the world frame");
+    assert_eq!(world.parent_frame_id, "world");
+    // The table 1 m in front of a camera 1.5 m up, looking down.
+    assert!((world.transform.translation.z.0 - 0.5).abs() < 1e-6);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ply_scene_is_preprocessed_for_the_engine() {
+    let config = SimulationConfig {
+        fit_scenes: true,
+        ..engine()
+    };
+    let interface = start_interface("preprocess", simulated(config)).await;
+    write_scene(&interface.scans.path().join("ply/table.ply"));
+    let parameters = interface.installation.path().join("parameters/scanners");
+    std::fs::create_dir_all(&parameters).unwrap();
+    std::fs::write(
+        parameters.join(format!("{}.json", interface.photoneo_id)),
+        r#"{"preprocessing": {"crop": {"min": [100, 100, 0], "max": [280, 280, 2000]}}}"#,
+    )
+    .unwrap();
+    interface
+        .request(vec![
+            ("scene_name", "table".to_spvalue()),
+            ("target_name", "box".to_spvalue()),
+            ("source_format", "ply".to_spvalue()),
+            ("stop_at_timeout", 3000.to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "succeeded");
+    let derived = interface.scans.path().join("ply/preprocessed/table.ply");
+    assert_eq!(PointCloud::read(&derived).unwrap().len(), 10 * 10);
+    let transforms = interface.transforms(&state);
+    assert_eq!(transforms.len(), 1);
+    let translation = &transforms[0].transform.translation;
+    assert!((translation.x.0 - 0.19).abs() < 1e-9);
+    assert!((translation.z.0 - 0.9).abs() < 1e-9);
+}
@@ -0,0 +1,199 @@
+// Naming of the published frames, and how the names carry over from one
+// localization to the next.
+
+mod common;
+
+use common::detection;
+use localization_interface_redis::core::interface::make_transforms;
+use localization_interface_redis::core::naming::{NamingStrategy, DEFAULT_NEAREST_TOLERANCE};
+use localization_interface_redis::core::parser::Detection;
+use micro_sp::*;
+
+const TOLERANCE: f64 = 0.02;
+
+fn parts(positions: &[[f64; 3]]) -> Vec<Detection> {
+    positions
+        .iter()
+        .enumerate()
+        .map(|(i, position)| detection("part", i, *position))
+        .collect()
+}
+
+fn names(transforms: &[SPTransformStamped]) -> Vec<&str> {
+    transforms
+        .iter()
+        .map(|t| t.child_frame_id.as_str())
+        .collect()
+}
+
+#[test]
+fn naming_is_read_from_the_request() {
+    assert_eq!(
+        NamingStrategy::from_request("Indexed", 0.0),
+        NamingStrategy::Indexed
+    );
+    assert_eq!(
+        NamingStrategy::from_request("nearest", 0.0),
+        NamingStrategy::Nearest {
+            tolerance: DEFAULT_NEAREST_TOLERANCE
+        }
+    );
+    assert_eq!(
+        NamingStrategy::from_request("nearest", 0.05),
+        NamingStrategy::Nearest { tolerance: 0.05 }
+    );
+    assert_eq!(
+        NamingStrategy::from_request("", 0.0),
+        NamingStrategy::Random
+    );
+    assert_eq!(
+        NamingStrategy::from_request("sequential", 0.0),
+        NamingStrategy::Random
+    );
+}
+
+#[test]
+fn indexed_names_follow_the_published_order() {
+    let naming = NamingStrategy::Indexed;
+    let first = make_transforms(
+        &parts(&[[0.0, 0.0, 0.8], [0.1, 0.0, 0.8]]),
+        "camera",
+        &naming,
+        &[],
+    );
+    assert_eq!(names(&first), ["part_0", "part_1"]);
+
+    // The same parts in the same order keep their names, whatever the
+    // previous frames were called.
+    let second = make_transforms(
+        &parts(&[[0.001, 0.0, 0.8], [0.1, 0.001, 0.8]]),
+        "camera",
+        &naming,
+        &first,
+    );
+    assert_eq!(names(&second), ["part_0", "part_1"]);
+
+    // Reported the other way around, the names swap parts.
+    let third = make_transforms(
+        &parts(&[[0.1, 0.0, 0.8], [0.0, 0.0, 0.8]]),
+        "camera",
+        &naming,
+        &second,
+    );
+    assert_eq!(names(&third), ["part_0", "part_1"]);
+    assert_eq!(third[0].transform.translation.x.0, 0.1);
+}
+
+#[test]
+fn random_names_change_on_every_run() {
+    let naming = NamingStrategy::Random;
+    let detections = parts(&[[0.0, 0.0, 0.8]]);
+    let first = make_transforms(&detections, "camera", &naming, &[]);
+    let second = make_transforms(&detections, "camera", &naming, &first);
+    assert!(first[0].child_frame_id.starts_with("part_"));
+    assert_ne!(first[0].child_frame_id, second[0].child_frame_id);
+}
+
+#[test]
+fn nearest_names_stay_with_their_parts() {
+    let naming = NamingStrategy::Nearest {
+        tolerance: TOLERANCE,
+    };
+    let first = make_transforms(
+        &parts(&[[0.0, 0.0, 0.8], [0.1, 0.0, 0.8], [0.2, 0.0, 0.8]]),
+        "camera",
+        &naming,
+        &[],
+    );
+    assert_eq!(names(&first), ["part_0", "part_1", "part_2"]);
+
+    // Moved a little and reported in another order.
+    let second = make_transforms(
+        &parts(&[[0.205, 0.0, 0.8], [0.0, 0.01, 0.8], [0.1, 0.0, 0.79]]),
+        "camera",
+        &naming,
+        &first,
+    );
+    assert_eq!(names(&second), ["part_2", "part_0", "part_1"]);
+
+    // part_0 is gone and a new part appears. The name of the missing part
+    // is not handed out, the new one gets the next free index.
+    let third = make_transforms(
+        &parts(&[[0.1, 0.0, 0.79], [0.3, 0.0, 0.8], [0.205, 0.0, 0.8]]),
+        "camera",
+        &naming,
+        &second,
+    );
+    assert_eq!(names(&third), ["part_1", "part_3", "part_2"]);
+
+    // Moved further than the tolerance, a part is a new one.
+    let fourth = make_transforms(&parts(&[[0.1, 0.05, 0.79]]), "camera", &naming, &third);
+    assert_eq!(names(&fourth), ["part_0"]);
+}
+
+#[test]
+fn nearest_ties_go_to_the_first_detection_and_frame() {
+    let naming = NamingStrategy::Nearest {
+        tolerance: TOLERANCE,
+    };
+    let previous = make_transforms(&parts(&[[0.0, 0.0, 0.8]]), "camera", &naming, &[]);
+
+    // Two detections exactly as far from part_0, the first one reported
+    // keeps the name.
+    let tied = make_transforms(
+        &parts(&[[0.01, 0.0, 0.8], [-0.01, 0.0, 0.8]]),
+        "camera",
+        &naming,
+        &previous,
+    );
+    assert_eq!(names(&tied), ["part_0", "part_1"]);
+    let swapped = make_transforms(
+        &parts(&[[-0.01, 0.0, 0.8], [0.01, 0.0, 0.8]]),
+        "camera",
+        &naming,
+        &previous,
+    );
+    assert_eq!(names(&swapped), ["part_0", "part_1"]);
+
+    // One detection exactly between two frames takes the first one's name.
+    let previous = make_transforms(
+        &parts(&[[0.0, 0.0, 0.8], [0.02, 0.0, 0.8]]),
+        "camera",
+        &naming,
+        &[],
+    );
+    let between = make_transforms(&parts(&[[0.01, 0.0, 0.8]]), "camera", &naming, &previous);
+    assert_eq!(names(&between), ["part_0"]);
+
+    // The closer of two detections gets the name, wherever it is reported.
+    let closer = make_transforms(
+        &parts(&[[0.015, 0.0, 0.8], [0.002, 0.0, 0.8]]),
+        "camera",
+        &naming,
+        &previous[..1],
+    );
+    assert_eq!(names(&closer), ["part_1", "part_0"]);
+}
+
+#[test]
+fn nearest_names_are_kept_per_target_and_scanning_frame() {
+    let naming = NamingStrategy::Nearest {
+        tolerance: TOLERANCE,
+    };
+    let previous = make_transforms(&parts(&[[0.0, 0.0, 0.8]]), "camera", &naming, &[]);
+
+    // Another target at the same spot does not take over the name.
+    let other_target = make_transforms(
+        &[detection("lid", 0, [0.0, 0.0, 0.8])],
+        "camera",
+        &naming,
+        &previous,
+    );
+    assert_eq!(names(&other_target), ["lid_0"]);
+
+    // Neither does the same target seen from another frame.
+    let other_frame = make_transforms(&parts(&[[0.3, 0.0, 0.8]]), "camera_2", &naming, &[]);
+    let seen_from_camera =
+        make_transforms(&parts(&[[0.3, 0.0, 0.8]]), "camera", &naming, &other_frame);
+    assert_eq!(names(&seen_from_camera), ["part_1"]);
+}
@@ -0,0 +1,186 @@
+// Golden tests for the parsing of the Localize output. Every transcript in
+// tests/data/localize is stdout as captured from the executable, the json
+// next to it holds the transforms the interface has to publish for it.
+
+use localization_interface_redis::core::filter::ResultFilter;
+use localization_interface_redis::core::interface::make_transforms;
+use localization_interface_redis::core::naming::NamingStrategy;
+use localization_interface_redis::core::parser::{
+    is_rigid, parse_float, parse_result, read_lines, rotation_matrix_to_quaternion,
+};
+use localization_interface_redis::core::state::LocalizeRequest;
+use serde_json::Value;
+use std::fs::File;
+use std::io::BufReader;
+
+const TOLERANCE: f64 = 1e-6;
+
+fn request() -> LocalizeRequest {
+    LocalizeRequest {
+        scene_name: "scene".to_string(),
+        target_name: "part".to_string(),
+        source_format: "ply".to_string(),
+        stop_at_timeout: 10000,
+        stop_at_number: 2,
+        praw_dir: String::new(),
+        ply_dir: String::new(),
+        plcf_dir: String::new(),
+        settings: "default".to_string(),
+    }
+}
+
+fn data_path(file: &str) -> String {
+    format!("{}/tests/data/localize/{}", env!("CARGO_MANIFEST_DIR"), file)
+}
+
+fn assert_close(actual: &[f64], expected: &[f64], what: &str) {
+    assert_eq!(actual.len(), expected.len());
+    let close = actual
+        .iter()
+        .zip(expected)
+        .all(|(a, e)| (a - e).abs() < TOLERANCE);
+    assert!(close, "{}: expected {:?}, got {:?}", what, expected, actual);
+}
+
+fn check_golden(name: &str) {
+    let transcript = File::open(data_path(&format!("{}.txt", name))).unwrap();
+    let lines = read_lines(BufReader::new(transcript));
+    let golden: Value =
+        serde_json::from_reader(File::open(data_path(&format!("{}.json", name))).unwrap())
+            .unwrap();
+
+    let parsed = parse_result(&request(), &ResultFilter::default(), &lines);
+    assert_eq!(parsed.success, golden["success"], "{}: success", name);
+    assert_eq!(
+        parsed.stop_criteria_met, golden["stop_criteria_met"],
+        "{}: stop_criteria_met",
+        name
+    );
+    assert_eq!(parsed.discarded, golden["discarded"], "{}: discarded", name);
+
+    let expected = golden["transforms"].as_array().unwrap();
+    assert_eq!(parsed.count, expected.len(), "{}: count", name);
+    let transforms = make_transforms(&parsed.results, "camera", &NamingStrategy::Indexed, &[]);
+    assert_eq!(transforms.len(), expected.len(), "{}: transforms", name);
+
+    for ((transform, detection), expected) in transforms.iter().zip(&parsed.results).zip(expected)
+    {
+        assert_eq!(transform.parent_frame_id, "camera");
+        assert_eq!(transform.child_frame_id, expected["child_frame_id"]);
+        let numbers = |key: &str| -> Vec<f64> {
+            expected[key]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|v| v.as_f64().unwrap())
+                .collect()
+        };
+
+        let t = &transform.transform.translation;
+        assert_close(
+            &[t.x.0, t.y.0, t.z.0],
+            &numbers("translation"),
+            &format!("{} {} translation", name, transform.child_frame_id),
+        );
+
+        // q and -q are the same rotation.
+        let r = &transform.transform.rotation;
+        let mut rotation = [r.x.0, r.y.0, r.z.0, r.w.0];
+        let expected_rotation = numbers("rotation");
+        let dot: f64 = rotation.iter().zip(&expected_rotation).map(|(a, b)| a * b).sum();
+        if dot < 0.0 {
+            rotation.iter_mut().for_each(|v| *v = -*v);
+        }
+        assert_close(
+            &rotation,
+            &expected_rotation,
+            &format!("{} {} rotation", name, transform.child_frame_id),
+        );
+
+        assert_eq!(detection.score, expected["score"].as_f64());
+        assert_eq!(detection.overlap, expected["overlap"].as_f64());
+    }
+}
+
+#[test]
+fn records() {
+    check_golden("records");
+}
+
+// Only the engines that print a score have one, the overlap is not a
+// stand-in for it.
+#[test]
+fn scores() {
+    check_golden("scores");
+}
+
+#[test]
+fn zero_results() {
+    check_golden("zero_results");
+}
+
+#[test]
+fn truncated_matrix() {
+    check_golden("truncated_matrix");
+}
+
+#[test]
+fn integer_tokens() {
+    check_golden("integer_tokens");
+}
+
+#[test]
+fn crlf_line_endings() {
+    check_golden("crlf");
+}
+
+#[test]
+fn matrices_that_are_not_rigid_are_rejected() {
+    check_golden("not_rigid");
+}
+
+#[test]
+fn numbers_are_parsed_like_the_executable_prints_them() {
+    assert_eq!(parse_float(b"0"), Some(0.0));
+    assert_eq!(parse_float(b"-12"), Some(-12.0));
+    assert_eq!(parse_float(b"1.5e+02"), Some(150.0));
+    assert_eq!(parse_float(b"RESULT"), None);
+    assert_eq!(parse_float(b"0:"), None);
+    assert_eq!(parse_float(&[0xff, 0x30]), None);
+}
+
+#[test]
+fn quaternions_have_unit_length() {
+    // A rotation of 120 degrees about (1, 1, 1), printed to six digits.
+    let rounded = [
+        [0.000001, 0.0, 1.0],
+        [1.0, 0.000001, 0.0],
+        [0.0, 1.0, 0.000001],
+    ];
+    let (w, x, y, z) = rotation_matrix_to_quaternion(&rounded);
+    assert!(((w * w + x * x + y * y + z * z).sqrt() - 1.0).abs() < 1e-12);
+    assert!([w, x, y, z].iter().all(|v| (v - 0.5).abs() < 1e-5));
+}
+
+#[test]
+fn rigidity() {
+    let identity = [
+        [1.0, 0.0, 0.0, 1.0],
+        [0.0, 1.0, 0.0, 2.0],
+        [0.0, 0.0, 1.0, 3.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ];
+    assert!(is_rigid(&identity));
+
+    let mut reflection = identity;
+    reflection[2][2] = -1.0;
+    assert!(!is_rigid(&reflection));
+
+    let mut sheared = identity;
+    sheared[0][1] = 0.1;
+    assert!(!is_rigid(&sheared));
+
+    let mut infinite = identity;
+    infinite[1][3] = f64::INFINITY;
+    assert!(!is_rigid(&infinite));
+}
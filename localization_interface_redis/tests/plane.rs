@@ -0,0 +1,153 @@
+// RANSAC plane fitting and the support plane published from it.
+
+use localization_interface_redis::core::extrinsic::CameraExtrinsic;
+use localization_interface_redis::core::plane::{fit_plane, Plane, RansacConfig};
+use localization_interface_redis::core::ply::PointCloud;
+use localization_interface_redis::core::preprocess::{AlignedBox, CropBox};
+use localization_interface_redis::core::state::LocalizationCommand;
+use localization_interface_redis::core::support_plane::SupportPlane;
+use micro_sp::*;
+use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector3};
+
+const TOLERANCE: f64 = 1e-6;
+
+// A floor 1 m from the camera tilted by 10 degrees about x, 10 mm between
+// the points, with a box standing on it and unmeasured pixels.
+fn tilted_floor() -> Vec<[f32; 3]> {
+    let tilt = 10f64.to_radians();
+    let mut points = Vec::new();
+    for i in -20..20 {
+        for j in -20..20 {
+            let (x, y) = (i as f64 * 10.0, j as f64 * 10.0);
+            points.push([x as f32, y as f32, (1000.0 + y * tilt.tan()) as f32]);
+        }
+    }
+    for i in 0..6 {
+        for j in 0..6 {
+            points.push([i as f32 * 10.0, j as f32 * 10.0, 900.0]);
+        }
+    }
+    points.extend([[0.0, 0.0, 0.0], [f32::NAN, 1.0, 1.0]]);
+    points
+}
+
+#[test]
+fn dominant_plane_faces_the_camera() {
+    let fit = fit_plane(&tilted_floor(), &RansacConfig::default()).unwrap();
+    assert_eq!(fit.inliers.len(), 40 * 40);
+    assert!(fit.inliers.iter().all(|i| *i < 40 * 40));
+    assert!(fit.rms_distance < 1e-3);
+
+    let tilt = 10f64.to_radians();
+    let expected = Vector3::new(0.0, tilt.sin(), -tilt.cos());
+    assert!((fit.plane.normal - expected).norm() < 1e-4);
+    assert!((fit.plane.offset + 1000.0 * tilt.cos()).abs() < 1e-2);
+
+    let line: Vec<[f32; 3]> = (1..10).map(|i| [i as f32, 0.0, 1000.0]).collect();
+    assert_eq!(fit_plane(&line, &RansacConfig::default()), None);
+    let a = Vector3::new(1.0, 0.0, 500.0);
+    assert_eq!(Plane::through(&a, &(a * 2.0), &(a * 3.0)), None);
+}
+
+#[test]
+fn request_keys_select_the_defaults() {
+    assert_eq!(RansacConfig::from_request(0.0, 0), RansacConfig::default());
+    let config = RansacConfig::from_request(2.5, 100);
+    assert_eq!(config.distance_threshold, 2.5);
+    assert_eq!(config.iterations, 100);
+}
+
+#[test]
+fn command_is_read_from_the_request() {
+    assert_eq!(
+        LocalizationCommand::from_request("fit_plane"),
+        LocalizationCommand::FitPlane
+    );
+    assert_eq!(
+        LocalizationCommand::from_request("Fit_Plane"),
+        LocalizationCommand::FitPlane
+    );
+    assert_eq!(
+        LocalizationCommand::from_request("localize"),
+        LocalizationCommand::Localize
+    );
+    assert_eq!(
+        LocalizationCommand::from_request(""),
+        LocalizationCommand::Localize
+    );
+    assert_eq!(
+        LocalizationCommand::from_request("plane"),
+        LocalizationCommand::Localize
+    );
+}
+
+#[test]
+fn support_plane_is_published_with_its_statistics() {
+    let scene = PointCloud {
+        points: tilted_floor(),
+        ..Default::default()
+    };
+    let plane = SupportPlane::estimate(&scene, None, &RansacConfig::default()).unwrap();
+    assert_eq!(plane.points, 40 * 40 + 36);
+
+    // The pose is on the plane with its z-axis along the normal.
+    let z_axis = plane.pose.rotation * Vector3::z();
+    assert!((z_axis - plane.fit.plane.normal).norm() < TOLERANCE);
+    let origin = plane.pose.translation.vector * 1000.0;
+    assert!(plane.fit.plane.distance(&origin).abs() < 1e-3);
+    let x_axis = plane.pose.rotation * Vector3::x();
+    assert!((x_axis - Vector3::x()).norm() < 1e-4);
+
+    // Looking straight down from 1 m above the world origin.
+    let extrinsic = CameraExtrinsic {
+        parent_frame_id: "world".to_string(),
+        child_frame_id: "camera".to_string(),
+        pose: Isometry3::from_parts(
+            Translation3::new(0.0, 0.0, 1.0),
+            UnitQuaternion::from_axis_angle(&Vector3::x_axis(), std::f64::consts::PI),
+        ),
+    };
+    let MapOrUnknown::Map(statistics) = plane.statistics(Some(&extrinsic)) else {
+        panic!("no statistics");
+    };
+    let value = |name: &str| -> f64 {
+        match statistics.iter().find(|(key, _)| *key == name.to_spvalue()) {
+            Some((_, SPValue::Float64(FloatOrUnknown::Float64(value)))) => value.0,
+            Some((_, SPValue::Int64(IntOrUnknown::Int64(value)))) => *value as f64,
+            other => panic!("{} is {:?}", name, other),
+        }
+    };
+    assert_eq!(value("inliers"), 1600.0);
+    assert!((value("inlier_fraction") - 1600.0 / 1636.0).abs() < TOLERANCE);
+    assert!((value("tilt") - 10.0).abs() < 1e-3);
+    assert!((value("world_tilt") - 10.0).abs() < 1e-3);
+    assert!((value("distance") - 1000.0 * 10f64.to_radians().cos()).abs() < 1e-2);
+
+    let transform = plane.to_transform("camera", "photoneo_plane");
+    assert_eq!(transform.parent_frame_id, "camera");
+    assert_eq!(transform.child_frame_id, "photoneo_plane");
+    assert!((transform.transform.translation.z.0 - origin.z / 1000.0).abs() < TOLERANCE);
+}
+
+#[test]
+fn crop_box_limits_the_search() {
+    let scene = PointCloud {
+        points: tilted_floor(),
+        ..Default::default()
+    };
+    let on_the_box = CropBox::Aligned(AlignedBox {
+        min: [0.0, 0.0, 850.0],
+        max: [50.0, 50.0, 950.0],
+    });
+    let plane =
+        SupportPlane::estimate(&scene, Some(&on_the_box), &RansacConfig::default()).unwrap();
+    assert_eq!(plane.points, 36);
+    assert!((plane.fit.plane.offset + 900.0).abs() < 1e-3);
+
+    let empty = CropBox::Aligned(AlignedBox {
+        min: [0.0, 0.0, 0.0],
+        max: [1.0, 1.0, 1.0],
+    });
+    let error = SupportPlane::estimate(&scene, Some(&empty), &RansacConfig::default()).unwrap_err();
+    assert_eq!(error, "No plane found in 0 points.");
+}
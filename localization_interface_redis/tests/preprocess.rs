@@ -0,0 +1,165 @@
+// Cropping, plane and outlier removal and downsampling of scenes before
+// they go to the engine.
+
+use localization_interface_redis::core::plane::RansacConfig;
+use localization_interface_redis::core::ply::{PlyFormat, PointCloud};
+use localization_interface_redis::core::preprocess::{
+    preprocess_scene, CropBox, OutlierRemoval, Preprocessing,
+};
+
+// A table at 1 m, 10 mm between the points, with a 40 mm cube on it and a
+// stray point in front of the camera.
+fn table_with_cube() -> PointCloud {
+    let mut points = Vec::new();
+    for i in 0..40 {
+        for j in 0..40 {
+            points.push([i as f32 * 10.0, j as f32 * 10.0, 1000.0]);
+        }
+    }
+    for i in 0..5 {
+        for j in 0..5 {
+            points.push([180.0 + i as f32 * 10.0, 180.0 + j as f32 * 10.0, 960.0]);
+        }
+    }
+    points.push([200.0, 200.0, 500.0]);
+    points.push([0.0, 0.0, 0.0]);
+    PointCloud {
+        points,
+        ..Default::default()
+    }
+}
+
+fn parse(preprocessing: serde_json::Value) -> Result<Preprocessing, String> {
+    serde_json::from_value::<Preprocessing>(preprocessing)
+        .map_err(|e| e.to_string())
+        .and_then(|preprocessing| preprocessing.validate().map(|_| preprocessing))
+}
+
+#[test]
+fn crop_boxes_keep_what_is_inside() {
+    let aligned = parse(serde_json::json!({
+        "crop": {"min": [100, 100, 900], "max": [300, 300, 1100]}
+    }))
+    .unwrap();
+    assert!(matches!(aligned.crop, Some(CropBox::Aligned(_))));
+    let cropped = aligned.apply(&table_with_cube());
+    assert_eq!(cropped.len(), 21 * 21 + 25);
+    assert!(cropped
+        .points
+        .iter()
+        .all(|p| p[0] >= 100.0 && p[0] <= 300.0));
+
+    // The same box around its center, turned 45 degrees about z.
+    let oriented = parse(serde_json::json!({
+        "crop": {"center": [200, 200, 1000], "size": [200, 200, 200],
+                 "rotation": {"x": 0.0, "y": 0.0, "z": 0.38268343, "w": 0.92387953}}
+    }))
+    .unwrap();
+    let crop = oriented.crop.as_ref().unwrap();
+    assert!(matches!(crop, CropBox::Oriented(_)));
+    assert!(crop.contains(&[200.0, 340.0, 1000.0]));
+    assert!(!crop.contains(&[300.0, 300.0, 1000.0]));
+}
+
+#[test]
+fn plane_and_stray_points_are_removed() {
+    let preprocessing = Preprocessing {
+        remove_plane: Some(RansacConfig {
+            distance_threshold: 2.0,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let cube = preprocessing.apply(&table_with_cube());
+    assert_eq!(cube.len(), 26);
+    assert!(cube.points.iter().all(|p| p[2] < 1000.0));
+
+    let preprocessing = Preprocessing {
+        outlier_removal: Some(OutlierRemoval::default()),
+        ..preprocessing
+    };
+    let cube = preprocessing.apply(&table_with_cube());
+    assert_eq!(cube.len(), 25);
+    assert!(cube.points.iter().all(|p| p[2] == 960.0));
+}
+
+#[test]
+fn voxels_keep_one_measured_point_each() {
+    let preprocessing = Preprocessing {
+        crop: Some(CropBox::Aligned(
+            serde_json::from_value(
+                serde_json::json!({"min": [0, 0, 990], "max": [390, 390, 1010]}),
+            )
+            .unwrap(),
+        )),
+        voxel_size: Some(20.0),
+        ..Default::default()
+    };
+    let mut scene = table_with_cube();
+    scene.texture = Some((0..scene.len()).map(|i| i as f32).collect());
+    let downsampled = preprocessing.apply(&scene);
+    assert_eq!(downsampled.len(), 20 * 20);
+    let texture = downsampled.texture.as_ref().unwrap();
+    for (point, texture) in downsampled.points.iter().zip(texture) {
+        assert_eq!(scene.points[*texture as usize], *point);
+    }
+}
+
+#[test]
+fn derived_scene_is_written_next_to_the_original() {
+    let dir = tempfile::tempdir().unwrap();
+    let ply_dir = dir.path().to_string_lossy().to_string();
+    table_with_cube()
+        .write(dir.path().join("bin.ply"), PlyFormat::Ascii)
+        .unwrap();
+    let preprocessing = parse(serde_json::json!({
+        "crop": {"min": [150, 150, 900], "max": [250, 250, 990]}
+    }))
+    .unwrap();
+
+    let preprocessed_dir = preprocess_scene(&ply_dir, "bin", &preprocessing).unwrap();
+    assert_eq!(preprocessed_dir, format!("{}/preprocessed", ply_dir));
+    let derived = PointCloud::read(format!("{}/bin.ply", preprocessed_dir)).unwrap();
+    assert_eq!(derived.len(), 25);
+
+    let error = preprocess_scene(&ply_dir, "missing", &preprocessing)
+        .unwrap_err()
+        .to_string();
+    assert!(error.starts_with("Failed to read scene"), "{}", error);
+    let empty = parse(serde_json::json!({
+        "crop": {"min": [0, 0, 0], "max": [1, 1, 1]}
+    }))
+    .unwrap();
+    let error = preprocess_scene(&ply_dir, "bin", &empty)
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("left no points of scene bin"), "{}", error);
+}
+
+#[test]
+fn invalid_preprocessing_is_rejected() {
+    for (preprocessing, reason) in [
+        (
+            serde_json::json!({"crop": {"min": [1, 0, 0], "max": [0, 1, 1]}}),
+            "is above max",
+        ),
+        (
+            serde_json::json!({"crop": {"center": [0, 0, 0], "size": [1, 0, 1],
+                                        "rotation": {"x": 0, "y": 0, "z": 0, "w": 1}}}),
+            "not positive",
+        ),
+        (serde_json::json!({"voxel_size": 0.0}), "voxel_size"),
+        (
+            serde_json::json!({"remove_plane": {"iterations": 0}}),
+            "remove_plane",
+        ),
+        (
+            serde_json::json!({"outlier_removal": {"neighbours": 0}}),
+            "outlier_removal",
+        ),
+        (serde_json::json!({"downsample": 2.0}), "unknown field"),
+    ] {
+        let error = parse(preprocessing).unwrap_err();
+        assert!(error.contains(reason), "{}", error);
+    }
+}
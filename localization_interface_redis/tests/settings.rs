@@ -0,0 +1,221 @@
+// Loading the localization_settings section of the presets and checking
+// every value against its bounds.
+
+mod common;
+
+use common::violations;
+use localization_interface_redis::core::interface::{
+    execution_timeout, DEFAULT_LOCALIZATION_TIMEOUT_MS, LOCALIZATION_TIMEOUT_MARGIN_MS,
+};
+use localization_interface_redis::core::settings::{
+    LocalizationSettings, SceneClusteringLevel, Timeout,
+};
+
+fn default_preset() -> serde_json::Value {
+    serde_json::from_str(
+        &std::fs::read_to_string(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/parameters/settings/default.json"
+        ))
+        .unwrap(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn presets_and_builtin_settings_are_valid() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/parameters/settings");
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path().to_string_lossy().to_string();
+        if let Err(e) = LocalizationSettings::load(&path) {
+            panic!("{}", e);
+        }
+    }
+    let settings = LocalizationSettings::builtin().unwrap();
+    assert_eq!(settings.timeout_criterion.max, Timeout(0));
+    assert_eq!(
+        settings.scene_clustering_level.value,
+        SceneClusteringLevel::Normal
+    );
+}
+
+#[test]
+fn values_outside_of_their_bounds_are_rejected() {
+    let mut preset = default_preset();
+    let section = &mut preset["localization_settings"];
+    section["number_of_results_criterion"]["value"] = 0.into();
+    section["projection_tolerance"]["value"] = 101.into();
+    section["overlap"]["value"] = 100.5.into();
+    assert_eq!(
+        violations(&preset),
+        [
+            "localization_settings.number_of_results_criterion",
+            "localization_settings.projection_tolerance",
+            "localization_settings.overlap",
+        ]
+    );
+
+    // Values on the bounds are allowed.
+    let mut preset = default_preset();
+    let section = &mut preset["localization_settings"];
+    section["number_of_results_criterion"]["value"] = 999.into();
+    section["projection_tolerance"]["value"] = 0.into();
+    section["overlap"]["value"] = 100.0.into();
+    assert!(LocalizationSettings::from_value(&preset, "test.json").is_ok());
+}
+
+#[test]
+fn enum_bounds_follow_the_declaration_order() {
+    // Low < Normal < High < Very high
+    let mut preset = default_preset();
+    let section = &mut preset["localization_settings"];
+    section["scene_clustering_level"]["max"] = "High".into();
+    section["scene_clustering_level"]["value"] = "Very high".into();
+    // Sparse < Medium < Dense
+    section["model_keypoints_sampling"]["min"] = "Dense".into();
+    assert_eq!(
+        violations(&preset),
+        [
+            "localization_settings.scene_clustering_level",
+            "localization_settings.model_keypoints_sampling",
+        ]
+    );
+
+    let mut preset = default_preset();
+    preset["localization_settings"]["scene_clustering_level"]["value"] = "Very High".into();
+    let error = LocalizationSettings::from_value(&preset, "test.json")
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("scene_clustering_level: unknown variant `Very High`"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn minimal_cluster_size_can_not_exceed_the_maximal() {
+    let mut preset = default_preset();
+    let section = &mut preset["localization_settings"];
+    section["scene_minimal_cluster_size"]["value"] = 5000.into();
+    section["scene_maximal_cluster_size"]["value"] = 4000.into();
+    let error = LocalizationSettings::from_value(&preset, "test.json")
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains(
+            "localization_settings.scene_minimal_cluster_size: \
+             value 5000 is larger than scene_maximal_cluster_size 4000"
+        ),
+        "{}",
+        error
+    );
+
+    preset["localization_settings"]["scene_maximal_cluster_size"]["value"] = 5000.into();
+    assert!(LocalizationSettings::from_value(&preset, "test.json").is_ok());
+}
+
+#[test]
+fn no_timeout_orders_above_every_timeout() {
+    assert!(Timeout(0) > Timeout(u64::MAX - 1));
+    assert!(Timeout(1) < Timeout(10000));
+    assert_eq!(Timeout(0).to_string(), "0 (no timeout)");
+
+    // The presets allow any timeout with "max": 0, including none.
+    let mut preset = default_preset();
+    preset["localization_settings"]["timeout_criterion"]["value"] = 0.into();
+    assert!(LocalizationSettings::from_value(&preset, "test.json").is_ok());
+    preset["localization_settings"]["timeout_criterion"]["value"] = 3_600_000.into();
+    assert!(LocalizationSettings::from_value(&preset, "test.json").is_ok());
+
+    // A bounded maximum does not allow running without a timeout.
+    preset["localization_settings"]["timeout_criterion"]["max"] = 20000.into();
+    preset["localization_settings"]["timeout_criterion"]["value"] = 0.into();
+    let error = LocalizationSettings::from_value(&preset, "test.json")
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("value 0 (no timeout) is outside of the allowed range [1, 20000]"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn executable_is_killed_even_without_a_timeout() {
+    let millis = |stop_at_timeout, settings_timeout| {
+        execution_timeout(stop_at_timeout, Timeout(settings_timeout))
+            .map(|timeout| timeout.as_millis() as u64)
+    };
+    let margin = LOCALIZATION_TIMEOUT_MARGIN_MS;
+
+    // The request takes precedence over the settings.
+    assert_eq!(millis(2000, 10000), Some(2000 + margin));
+    // Without a timeout in the request, the one of the settings applies.
+    assert_eq!(millis(0, 10000), Some(10000 + margin));
+    // Without one in the settings either, the default one does.
+    assert_eq!(millis(0, 0), Some(DEFAULT_LOCALIZATION_TIMEOUT_MS + margin));
+    // Running without a limit has to be asked for.
+    assert_eq!(millis(-1, 10000), None);
+    assert_eq!(millis(-1, 0), None);
+}
+
+#[test]
+fn every_violation_is_reported_with_the_file() {
+    let mut preset = default_preset();
+    let section = &mut preset["localization_settings"];
+    section["timeout_criterion"]["max"] = 20000.into();
+    section["timeout_criterion"]["value"] = 30000.into();
+    section["matching_algorithm"]["value"] = "Points".into();
+    section["fine_alignment_iterations"]["deafult"] = 30.into();
+    section["scene_minimal_cluster_size"]["value"] = 9999999.into();
+    section["scene_maximal_cluster_size"]["value"] = 10000000.into();
+    section.as_object_mut().unwrap().remove("smart_memory");
+    section["icp_iterations"] = 10.into();
+
+    let error = LocalizationSettings::from_value(&preset, "big_plate.json")
+        .unwrap_err()
+        .to_string();
+    let (header, report) = error.split_once(": ").unwrap();
+    assert_eq!(
+        header,
+        "Settings file 'big_plate.json' has 6 invalid localization setting(s)"
+    );
+    let fields: Vec<&str> = report
+        .split("; ")
+        .map(|violation| violation.split_once(": ").unwrap().0)
+        .collect();
+    assert_eq!(
+        fields,
+        [
+            "localization_settings.timeout_criterion",
+            "localization_settings.smart_memory",
+            "localization_settings.scene_maximal_cluster_size",
+            "localization_settings.matching_algorithm",
+            "localization_settings.fine_alignment_iterations",
+            "localization_settings.icp_iterations",
+        ]
+    );
+    assert!(report.contains("unknown field `deafult`"), "{}", report);
+    assert!(
+        report.contains("icp_iterations: unknown field"),
+        "{}",
+        report
+    );
+    assert!(report.contains("smart_memory: missing field"), "{}", report);
+
+    // The cross-field rule is only checked once every setting could be
+    // read, next to the range violations.
+    let mut preset = default_preset();
+    let section = &mut preset["localization_settings"];
+    section["overlap"]["value"] = (-1.0).into();
+    section["scene_minimal_cluster_size"]["value"] = 10000.into();
+    section["scene_maximal_cluster_size"]["value"] = 100.into();
+    assert_eq!(
+        violations(&preset),
+        [
+            "localization_settings.overlap",
+            "localization_settings.scene_minimal_cluster_size",
+        ]
+    );
+}
@@ -0,0 +1,275 @@
+// Tracking parts over consecutive localizations: association of the
+// detections with the tracks, smoothing of their poses and expiry.
+
+mod common;
+
+use common::{assert_close, TOLERANCE};
+use localization_interface_redis::core::tracking::{
+    Tracker, TrackingConfig, DEFAULT_TRACKING_MAX_MISSED, DEFAULT_TRACKING_TOLERANCE,
+    DEFAULT_TRACKING_WINDOW,
+};
+use micro_sp::*;
+use ordered_float::OrderedFloat;
+use std::time::SystemTime;
+
+const CONFIG: TrackingConfig = TrackingConfig {
+    tolerance: 0.02,
+    window: 3,
+    max_missed: 2,
+};
+
+// A detection of the target in the camera frame, turned by yaw radians
+// about z.
+fn detection(name: &str, target_name: &str, position: [f64; 3], yaw: f64) -> SPTransformStamped {
+    SPTransformStamped {
+        active_transform: true,
+        enable_transform: true,
+        time_stamp: SystemTime::now(),
+        parent_frame_id: "camera".to_string(),
+        child_frame_id: name.to_string(),
+        transform: SPTransform {
+            translation: SPTranslation {
+                x: OrderedFloat(position[0]),
+                y: OrderedFloat(position[1]),
+                z: OrderedFloat(position[2]),
+            },
+            rotation: SPRotation {
+                x: OrderedFloat(0.0),
+                y: OrderedFloat(0.0),
+                z: OrderedFloat((yaw / 2.0).sin()),
+                w: OrderedFloat((yaw / 2.0).cos()),
+            },
+        },
+        metadata: MapOrUnknown::Map(vec![("target_name".to_spvalue(), target_name.to_spvalue())]),
+    }
+}
+
+fn part(name: &str, position: [f64; 3]) -> SPTransformStamped {
+    detection(name, "part", position, 0.0)
+}
+
+fn position(transform: &SPTransformStamped) -> [f64; 3] {
+    let t = &transform.transform.translation;
+    [t.x.0, t.y.0, t.z.0]
+}
+
+fn yaw(transform: &SPTransformStamped) -> f64 {
+    let r = &transform.transform.rotation;
+    2.0 * r.z.0.atan2(r.w.0)
+}
+
+fn ids(transforms: &[SPTransformStamped]) -> Vec<&str> {
+    transforms
+        .iter()
+        .map(|t| t.child_frame_id.as_str())
+        .collect()
+}
+
+fn metadata_int(transform: &SPTransformStamped, key: &str) -> Option<i64> {
+    let MapOrUnknown::Map(entries) = &transform.metadata else {
+        return None;
+    };
+    entries.iter().find_map(|(k, v)| match v {
+        SPValue::Int64(IntOrUnknown::Int64(value)) if *k == key.to_spvalue() => Some(*value),
+        _ => None,
+    })
+}
+
+#[test]
+fn request_keys_select_the_defaults() {
+    assert_eq!(
+        TrackingConfig::from_request(0.0, 0, -1),
+        TrackingConfig {
+            tolerance: DEFAULT_TRACKING_TOLERANCE,
+            window: DEFAULT_TRACKING_WINDOW,
+            max_missed: DEFAULT_TRACKING_MAX_MISSED,
+        }
+    );
+    assert_eq!(
+        TrackingConfig::from_request(0.05, 2, 1),
+        TrackingConfig {
+            tolerance: 0.05,
+            window: 2,
+            max_missed: 1,
+        }
+    );
+}
+
+#[test]
+fn poses_are_averaged_over_the_window() {
+    let mut tracker = Tracker::new();
+    let scans = [
+        [0.100, 0.0, 0.8],
+        [0.103, 0.0, 0.8],
+        [0.106, 0.0, 0.8],
+        [0.112, 0.0, 0.8],
+    ];
+    let smoothed: Vec<[f64; 3]> = scans
+        .iter()
+        .map(|p| {
+            let update = tracker.update(&[part("part_0", *p)], &CONFIG);
+            assert_eq!(ids(&update.transforms), ["part_0"]);
+            position(&update.transforms[0])
+        })
+        .collect();
+
+    assert_close(smoothed[0], [0.100, 0.0, 0.8]);
+    assert_close(smoothed[1], [0.1015, 0.0, 0.8]);
+    assert_close(smoothed[2], [0.103, 0.0, 0.8]);
+    // The first scan has left the window of three.
+    assert_close(smoothed[3], [0.107, 0.0, 0.8]);
+}
+
+#[test]
+fn rotations_are_averaged_on_the_same_hemisphere() {
+    let mut tracker = Tracker::new();
+    tracker.update(
+        &[detection("part_0", "part", [0.0, 0.0, 0.8], 0.1)],
+        &CONFIG,
+    );
+    let update = tracker.update(
+        &[detection("part_0", "part", [0.0, 0.0, 0.8], -0.1)],
+        &CONFIG,
+    );
+    assert!(yaw(&update.transforms[0]).abs() < TOLERANCE);
+
+    // Turned by almost half a turn either way, the quaternions are on
+    // opposite hemispheres but the poses only 0.2 rad apart.
+    let mut tracker = Tracker::new();
+    let half_turn = std::f64::consts::PI;
+    tracker.update(
+        &[detection(
+            "part_0",
+            "part",
+            [0.0, 0.0, 0.8],
+            half_turn - 0.1,
+        )],
+        &CONFIG,
+    );
+    let update = tracker.update(
+        &[detection(
+            "part_0",
+            "part",
+            [0.0, 0.0, 0.8],
+            -half_turn + 0.1,
+        )],
+        &CONFIG,
+    );
+    let r = &update.transforms[0].transform.rotation;
+    let norm = (r.x.0.powi(2) + r.y.0.powi(2) + r.z.0.powi(2) + r.w.0.powi(2)).sqrt();
+    assert!((norm - 1.0).abs() < TOLERANCE);
+    assert!((yaw(&update.transforms[0]).abs() - half_turn).abs() < TOLERANCE);
+}
+
+#[test]
+fn detections_are_associated_within_the_tolerance() {
+    let mut tracker = Tracker::new();
+    let update = tracker.update(
+        &[
+            part("part_0", [0.0, 0.0, 0.8]),
+            part("part_1", [0.1, 0.0, 0.8]),
+        ],
+        &CONFIG,
+    );
+    assert_eq!(update.appeared, ["part_0", "part_1"]);
+
+    // Names follow the tracks, not the detections: part_1 was detected
+    // where part_0 is and the other way around.
+    let update = tracker.update(
+        &[
+            part("part_1", [0.01, 0.0, 0.8]),
+            part("part_0", [0.1, 0.015, 0.8]),
+        ],
+        &CONFIG,
+    );
+    assert!(update.appeared.is_empty() && update.disappeared.is_empty());
+    assert_eq!(ids(&update.transforms), ["part_0", "part_1"]);
+    assert_close(position(&update.transforms[0]), [0.005, 0.0, 0.8]);
+    assert_close(position(&update.transforms[1]), [0.1, 0.0075, 0.8]);
+
+    // Measured from the latest pose, not the smoothed one. 0.025 away is
+    // beyond the gate, the part is a new one and the track is missed.
+    let update = tracker.update(
+        &[
+            part("part_0", [0.035, 0.0, 0.8]),
+            part("part_1", [0.1, 0.015, 0.8]),
+        ],
+        &CONFIG,
+    );
+    assert_eq!(update.appeared, ["part_0_1"]);
+    assert_eq!(update.disappeared, ["part_0"]);
+    assert_eq!(ids(&update.transforms), ["part_0", "part_1", "part_0_1"]);
+    assert!(!update.transforms[0].active_transform);
+    assert!(update.transforms[1].active_transform);
+}
+
+#[test]
+fn closest_pairs_are_associated_first() {
+    let mut tracker = Tracker::new();
+    tracker.update(&[part("part_0", [0.0, 0.0, 0.8])], &CONFIG);
+
+    // Both are within the gate, the closer one continues the track.
+    let update = tracker.update(
+        &[part("a", [0.015, 0.0, 0.8]), part("b", [0.005, 0.0, 0.8])],
+        &CONFIG,
+    );
+    assert_eq!(update.appeared, ["a"]);
+    assert_close(position(&update.transforms[0]), [0.0025, 0.0, 0.8]);
+    assert_eq!(metadata_int(&update.transforms[0], "observations"), Some(2));
+}
+
+#[test]
+fn tracks_are_kept_per_target_and_frame() {
+    let mut tracker = Tracker::new();
+    tracker.update(&[part("part_0", [0.0, 0.0, 0.8])], &CONFIG);
+
+    let mut other_frame = part("part_0", [0.0, 0.0, 0.8]);
+    other_frame.parent_frame_id = "camera_2".to_string();
+    let update = tracker.update(
+        &[detection("lid_0", "lid", [0.0, 0.0, 0.8], 0.0), other_frame],
+        &CONFIG,
+    );
+    assert_eq!(update.appeared, ["lid_0", "part_0_1"]);
+    assert_eq!(update.disappeared, ["part_0"]);
+}
+
+#[test]
+fn missing_tracks_expire_after_max_missed_scans() {
+    let mut tracker = Tracker::new();
+    tracker.update(&[part("part_0", [0.0, 0.0, 0.8])], &CONFIG);
+
+    // Missing for max_missed scans, the track is kept inactive and only
+    // reported as disappeared the first time.
+    for missed in 1..=CONFIG.max_missed {
+        let update = tracker.update(&[], &CONFIG);
+        assert_eq!(ids(&update.transforms), ["part_0"]);
+        assert!(!update.transforms[0].active_transform);
+        assert_eq!(
+            metadata_int(&update.transforms[0], "missed"),
+            Some(missed as i64)
+        );
+        let expected: &[&str] = if missed == 1 { &["part_0"] } else { &[] };
+        assert_eq!(update.disappeared, expected);
+    }
+    let update = tracker.update(&[], &CONFIG);
+    assert!(update.transforms.is_empty());
+
+    // Once expired, its name is free again.
+    let update = tracker.update(&[part("part_0", [0.0, 0.0, 0.8])], &CONFIG);
+    assert_eq!(update.appeared, ["part_0"]);
+    assert_eq!(metadata_int(&update.transforms[0], "observations"), Some(1));
+}
+
+#[test]
+fn track_found_again_before_expiry_continues() {
+    let mut tracker = Tracker::new();
+    tracker.update(&[part("part_0", [0.0, 0.0, 0.8])], &CONFIG);
+    tracker.update(&[], &CONFIG);
+    let update = tracker.update(&[part("part_3", [0.01, 0.0, 0.8])], &CONFIG);
+    assert!(update.appeared.is_empty());
+    assert_eq!(ids(&update.transforms), ["part_0"]);
+    assert!(update.transforms[0].active_transform);
+    assert_eq!(metadata_int(&update.transforms[0], "missed"), Some(0));
+    assert_eq!(metadata_int(&update.transforms[0], "observations"), Some(2));
+    assert_close(position(&update.transforms[0]), [0.005, 0.0, 0.8]);
+}
@@ -1,15 +1,25 @@
 use futures::{Stream, StreamExt};
-use r2r::{localization_msgs::srv::Localize, ServiceRequest};
+use r2r::builtin_interfaces::msg::Time;
+use r2r::geometry_msgs::msg::{Quaternion, Transform, TransformStamped, Vector3};
+use r2r::std_msgs::msg::Header;
+use r2r::{localization_msgs::srv::Localize, std_srvs::srv::Trigger, ServiceRequest};
 use serde_json::Value;
 use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::{fs::File, io::BufReader};
 
 use std::io::{self, BufRead};
 use std::process::{Command, Stdio};
-use std::sync::mpsc;
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// How often a running executable checks whether it was aborted.
+const ABORT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// The detections are in the frame of the camera the scene was taken with.
+const CAMERA_FRAME_ID: &str = "photoneo";
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -45,9 +55,20 @@ pub async fn spawn_localization_interface(
         .lock()
         .unwrap()
         .create_service::<Localize::Service>("/localization_interface")?;
+    let abort_service = arc_node
+        .lock()
+        .unwrap()
+        .create_service::<Trigger::Service>("/localization_interface/abort")?;
+
+    let running = Arc::new(RunningRequest::default());
 
+    let running_clone = running.clone();
     tokio::task::spawn(async move {
-        let result = localization_interface(service).await;
+        abort_interface(abort_service, running_clone).await;
+    });
+
+    tokio::task::spawn(async move {
+        let result = localization_interface(service, running).await;
         match result {
             Ok(()) => r2r::log_info!("localization_interface", "Service call succeeded."),
             Err(e) => r2r::log_error!(
@@ -62,6 +83,7 @@ pub async fn spawn_localization_interface(
 
 async fn localization_interface(
     mut service: impl Stream<Item = ServiceRequest<Localize::Service>> + Unpin,
+    running: Arc<RunningRequest>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     r2r::log_info!("localization_interface", "Server task spawned.");
 
@@ -70,25 +92,51 @@ async fn localization_interface(
             Some(request) => {
                 r2r::log_info!("localization_interface", "Got request.");
 
-                let response = match call_blocking_exec(request.message.clone()) {
-                    Ok(val) => {
+                running.start();
+                let message = request.message.clone();
+                let running_clone = running.clone();
+                let outcome = tokio::task::spawn_blocking(move || {
+                    call_blocking_exec(message, &running_clone.abort)
+                })
+                .await
+                .unwrap_or_else(|e| Err(io::Error::new(io::ErrorKind::Other, e.to_string())));
+                running.finish();
+
+                let response = match outcome {
+                    Ok(ExecOutcome::Finished(val)) => {
                         r2r::log_info!("localization_interface", "Succeeded.");
+                        let data: Vec<Vec<u8>> =
+                            val.iter().map(|line| line.as_bytes().to_vec()).collect();
+                        let parsed = parse_result(&request.message, &data);
+                        Localize::Response {
+                            req_success: parsed.success,
+                            any_success: parsed.success || parsed.count > 0,
+                            nr_of_items: parsed.count as u32,
+                            transforms: make_transforms(&parsed),
+                            raw_data: val.join("\n"),
+                            cancelled: false,
+                        }
+                    }
+                    Ok(ExecOutcome::Cancelled(partial)) => {
+                        r2r::log_warn!("localization_interface", "Cancelled.");
                         Localize::Response {
-                            req_success: todo!(),
-                            any_success: todo!(),
-                            nr_of_items: todo!(),
-                            transforms: todo!(),
-                            raw_data: todo!(),
+                            req_success: false,
+                            any_success: false,
+                            nr_of_items: 0,
+                            transforms: vec![],
+                            raw_data: partial.join("\n"),
+                            cancelled: true,
                         }
                     }
                     Err(e) => {
                         r2r::log_info!("localization_interface", "Failed.");
                         Localize::Response {
-                            req_success: todo!(),
-                            any_success: todo!(),
-                            nr_of_items: todo!(),
-                            transforms: todo!(),
-                            raw_data: todo!(),
+                            req_success: false,
+                            any_success: false,
+                            nr_of_items: 0,
+                            transforms: vec![],
+                            raw_data: e.to_string(),
+                            cancelled: false,
                         }
                     }
                 };
@@ -103,7 +151,62 @@ async fn localization_interface(
     }
 }
 
-fn call_blocking_exec(request: Localize::Request) -> Result<Vec<String>, io::Error> {
+// The request that is being executed, if any, and whether it should be
+// aborted. Shared between the localization service and the abort service.
+#[derive(Default)]
+struct RunningRequest {
+    busy: AtomicBool,
+    abort: AtomicBool,
+}
+
+impl RunningRequest {
+    fn start(&self) {
+        self.abort.store(false, Ordering::SeqCst);
+        self.busy.store(true, Ordering::SeqCst);
+    }
+
+    fn finish(&self) {
+        self.busy.store(false, Ordering::SeqCst);
+        self.abort.store(false, Ordering::SeqCst);
+    }
+}
+
+// A call to /localization_interface/abort kills the executable of the
+// running request, which is then answered with cancelled set.
+async fn abort_interface(
+    mut service: impl Stream<Item = ServiceRequest<Trigger::Service>> + Unpin,
+    running: Arc<RunningRequest>,
+) {
+    while let Some(request) = service.next().await {
+        let response = if running.busy.load(Ordering::SeqCst) {
+            running.abort.store(true, Ordering::SeqCst);
+            r2r::log_warn!("localization_interface", "Aborting the running request.");
+            Trigger::Response {
+                success: true,
+                message: "Aborting the running request.".to_string(),
+            }
+        } else {
+            Trigger::Response {
+                success: false,
+                message: "No request is running.".to_string(),
+            }
+        };
+        request
+            .respond(response)
+            .expect("Could not send service response.");
+    }
+}
+
+enum ExecOutcome {
+    Finished(Vec<String>),
+    // Aborted, with what the executable printed until then.
+    Cancelled(Vec<String>),
+}
+
+fn call_blocking_exec(
+    request: Localize::Request,
+    abort: &AtomicBool,
+) -> Result<ExecOutcome, io::Error> {
     let args = prepare_arguments(&request);
     let mut child = Command::new(&args[0])
         .args(&args[1..])
@@ -123,14 +226,32 @@ fn call_blocking_exec(request: Localize::Request) -> Result<Vec<String>, io::Err
         tx.send(lines).unwrap();
     });
 
-    let timeout_duration = Duration::from_millis(request.stop_at_timeout as u64 + 3000);
+    let deadline = Instant::now() + Duration::from_millis(request.stop_at_timeout as u64 + 3000);
 
-    match rx.recv_timeout(timeout_duration) {
-        Ok(output_lines) => Ok(output_lines),
-        Err(_) => {
-            child.kill()?;
-            let _ = child.wait(); // Clean up the process if it's still running
-            Ok(vec!["Timeout Expired".to_string()])
+    // Waits in short steps, so that an abort does not wait for the timeout.
+    loop {
+        match rx.recv_timeout(ABORT_POLL_INTERVAL) {
+            Ok(output_lines) => return Ok(ExecOutcome::Finished(output_lines)),
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "Lost the output of the executable.",
+                ))
+            }
+            Err(RecvTimeoutError::Timeout) if abort.load(Ordering::SeqCst) => {
+                child.kill()?;
+                let _ = child.wait();
+                // The output ends with the process, unless it left children
+                // behind that still hold it.
+                let partial = rx.recv_timeout(Duration::from_secs(1)).unwrap_or_default();
+                return Ok(ExecOutcome::Cancelled(partial));
+            }
+            Err(RecvTimeoutError::Timeout) if Instant::now() >= deadline => {
+                child.kill()?;
+                let _ = child.wait(); // Clean up the process if it's still running
+                return Ok(ExecOutcome::Finished(vec!["Timeout Expired".to_string()]));
+            }
+            Err(RecvTimeoutError::Timeout) => (),
         }
     }
 }
@@ -225,6 +346,82 @@ fn parse_result(request: &Localize::Request, data: &[Vec<u8>]) -> ParsedResult {
     parsed
 }
 
+// The detections in meters, as the Redis interface publishes them, named
+// {target_name}_{index}.
+fn make_transforms(parsed: &ParsedResult) -> Vec<TransformStamped> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    parsed
+        .results
+        .iter()
+        .enumerate()
+        .map(|(index, (matrix, target_name))| {
+            let rotation_matrix = [
+                [matrix[0][0], matrix[0][1], matrix[0][2]],
+                [matrix[1][0], matrix[1][1], matrix[1][2]],
+                [matrix[2][0], matrix[2][1], matrix[2][2]],
+            ];
+            let (w, x, y, z) = rotation_matrix_to_quaternion(&rotation_matrix);
+            TransformStamped {
+                header: Header {
+                    stamp: Time {
+                        sec: now.as_secs() as i32,
+                        nanosec: now.subsec_nanos(),
+                    },
+                    frame_id: CAMERA_FRAME_ID.to_string(),
+                },
+                child_frame_id: format!("{}_{}", target_name, index),
+                transform: Transform {
+                    translation: Vector3 {
+                        x: matrix[0][3] / 1000.0,
+                        y: matrix[1][3] / 1000.0,
+                        z: matrix[2][3] / 1000.0,
+                    },
+                    rotation: Quaternion { x, y, z, w },
+                },
+            }
+        })
+        .collect()
+}
+
+// The same conversion as in the parser of the Redis interface.
+fn rotation_matrix_to_quaternion(m: &[[f64; 3]; 3]) -> (f64, f64, f64, f64) {
+    let trace = m[0][0] + m[1][1] + m[2][2];
+    let (w, x, y, z);
+
+    if trace > 0.0 {
+        let s = (trace + 1.0).sqrt() * 2.0;
+        w = 0.25 * s;
+        x = (m[2][1] - m[1][2]) / s;
+        y = (m[0][2] - m[2][0]) / s;
+        z = (m[1][0] - m[0][1]) / s;
+    } else if (m[0][0] > m[1][1]) && (m[0][0] > m[2][2]) {
+        let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+        w = (m[2][1] - m[1][2]) / s;
+        x = 0.25 * s;
+        y = (m[0][1] + m[1][0]) / s;
+        z = (m[0][2] + m[2][0]) / s;
+    } else if m[1][1] > m[2][2] {
+        let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+        w = (m[0][2] - m[2][0]) / s;
+        x = (m[0][1] + m[1][0]) / s;
+        y = 0.25 * s;
+        z = (m[1][2] + m[2][1]) / s;
+    } else {
+        let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+        w = (m[1][0] - m[0][1]) / s;
+        x = (m[0][2] + m[2][0]) / s;
+        y = (m[1][2] + m[2][1]) / s;
+        z = 0.25 * s;
+    }
+
+    // The printed matrix is rounded, so the quaternion is off unit length
+    // by about the same amount.
+    let norm = (w * w + x * x + y * y + z * z).sqrt();
+    (w / norm, x / norm, y / norm, z / norm)
+}
+
 fn parse_float(data: &[u8]) -> Option<f64> {
     if let Ok(string) = std::str::from_utf8(data) {
         if string.contains('.') {
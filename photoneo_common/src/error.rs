@@ -0,0 +1,4 @@
+// Published as {id}_error_code ({id}_localization_error_code) when a
+// request did not fail. The failures are told apart by the ErrorCode of
+// each interface.
+pub const NO_ERROR: &str = "none";
@@ -0,0 +1,205 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+// What an executable printed, line by line, when it exited with 0.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandOutput {
+    pub stdout: Vec<String>,
+    pub stderr: Vec<String>,
+}
+
+// Runs an executable and collects the lines it prints. The process is
+// killed when the timeout expires or the token is cancelled, and when the
+// returned future is dropped, so it never outlives its request. Nothing
+// here blocks the runtime. A non-zero exit, a timeout or a cancellation is
+// an error that keeps what was printed until then, see command_failure.
+// What goes wrong is logged under log_target.
+pub async fn run_executable(
+    args: &[String],
+    timeout: Option<Duration>,
+    cancel: &CancellationToken,
+    log_target: &str,
+) -> Result<CommandOutput, io::Error> {
+    let (program, arguments) = args
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No executable given."))?;
+    let mut child = Command::new(program)
+        .args(arguments)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "Failed to capture stdout."))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "Failed to capture stderr."))?;
+
+    let deadline = async {
+        match timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    let mut output = CommandOutput::default();
+    let outcome = tokio::select! {
+        status = async {
+            tokio::join!(
+                for_each_line(stdout, log_target, |line| output.stdout.push(line)),
+                for_each_line(stderr, log_target, |line| {
+                    log::debug!(target: log_target,
+                        "{} stderr: {}", program, line);
+                    output.stderr.push(line);
+                }),
+            );
+            child.wait().await
+        } => status.map(Ok),
+        _ = deadline => Ok(Err((io::ErrorKind::TimedOut, "Command execution timed out."))),
+        _ = cancel.cancelled() => Ok(Err((
+            io::ErrorKind::Interrupted,
+            "Command execution was cancelled.",
+        ))),
+    };
+
+    let failure = match outcome {
+        Ok(Ok(status)) if status.success() => {
+            if !output.stderr.is_empty() {
+                log::warn!(target: log_target,
+                    "{} succeeded, but printed to stderr: {}", program, output.stderr.join(" | "));
+            }
+            return Ok(output);
+        }
+        Ok(Ok(status)) => {
+            let failure = CommandFailure::exited(program, status, output);
+            log::warn!(target: log_target,
+                "{} stderr: {}", failure.reason, failure.stderr.join(" | "));
+            return Err(io::Error::other(failure));
+        }
+        Ok(Err((kind, reason))) => io::Error::new(
+            kind,
+            CommandFailure {
+                reason: reason.to_string(),
+                exit_code: None,
+                stdout: output.stdout,
+                stderr: output.stderr,
+            },
+        ),
+        Err(e) => e,
+    };
+    if let Err(e) = child.kill().await {
+        log::error!(target: log_target,
+            "Failed to kill {}: {}", program, e);
+    }
+    Err(failure)
+}
+
+// The error of a command that exited with a non-zero code, timed out or
+// was cancelled, with everything it printed until then.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandFailure {
+    pub reason: String,
+    // None if the command did not exit by itself.
+    pub exit_code: Option<i32>,
+    pub stdout: Vec<String>,
+    pub stderr: Vec<String>,
+}
+
+impl CommandFailure {
+    // The executables explain what went wrong in the last line they print,
+    // to stderr or, mostly, to stdout.
+    fn exited(program: &str, status: ExitStatus, output: CommandOutput) -> CommandFailure {
+        let name = Path::new(program)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| program.to_string());
+        let exit = match status.code() {
+            Some(code) => format!("{} exited with code {}", name, code),
+            None => format!("{} was terminated by a signal", name),
+        };
+        let explanation = [&output.stderr, &output.stdout]
+            .iter()
+            .find_map(|lines| lines.iter().rev().find(|line| !line.trim().is_empty()));
+        let reason = match explanation {
+            Some(explanation) => format!("{}: {}", exit, explanation.trim()),
+            None => format!("{}.", exit),
+        };
+        CommandFailure {
+            reason,
+            exit_code: status.code(),
+            stdout: output.stdout,
+            stderr: output.stderr,
+        }
+    }
+}
+
+impl fmt::Display for CommandFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.reason)
+    }
+}
+
+impl std::error::Error for CommandFailure {}
+
+pub fn command_failure(error: &io::Error) -> Option<&CommandFailure> {
+    error.get_ref()?.downcast_ref::<CommandFailure>()
+}
+
+// What the command printed to stdout before it failed, nothing for
+// errors that are not a CommandFailure.
+pub fn partial_output(error: &io::Error) -> &[String] {
+    command_failure(error)
+        .map(|failure| failure.stdout.as_slice())
+        .unwrap_or_default()
+}
+
+// The error, with the exit code if the command exited by itself, followed
+// by what the executable printed to stderr, unless that is the single line
+// the error already quotes.
+pub fn failure_info(error: &io::Error) -> String {
+    match command_failure(error).map(|failure| (failure, failure.stderr.as_slice())) {
+        Some((failure, [line])) if failure.reason.ends_with(line.trim()) => error.to_string(),
+        Some((failure, lines)) if !lines.is_empty() => {
+            format!("{}\n{}", failure.reason, lines.join("\n"))
+        }
+        _ => error.to_string(),
+    }
+}
+
+// Hands every line to on_line as it arrives, without its line ending.
+// Output that is not UTF-8 is kept lossily rather than dropped.
+async fn for_each_line(
+    pipe: impl AsyncRead + Unpin,
+    log_target: &str,
+    mut on_line: impl FnMut(String),
+) {
+    let mut segments = BufReader::new(pipe).split(b'\n');
+    loop {
+        match segments.next_segment().await {
+            Ok(Some(mut line)) => {
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                on_line(String::from_utf8_lossy(&line).into_owned());
+            }
+            Ok(None) => break,
+            Err(e) => {
+                log::warn!(target: log_target,
+                    "Error reading the output of the executable: {}", e);
+                break;
+            }
+        }
+    }
+}
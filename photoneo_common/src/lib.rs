@@ -0,0 +1,12 @@
+// Code shared by the control and the localization interface. Whatever
+// logs here does so under the log target of the interface it runs for.
+
+pub mod error;
+pub mod executor;
+pub mod parameters;
+pub mod ply;
+pub mod retry;
+pub mod scanners;
+
+#[cfg(feature = "test-support")]
+pub mod redis_server;
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// Scanner parameters both interfaces read, in the scans folder they share:
+// {phoxi_scans_path}/parameters/scanners/{id}.json. The calibration of the
+// control interface saves the camera extrinsic there,
+//
+//     "extrinsic": {
+//         "parent_frame_id": "world",
+//         "child_frame_id": "photoneo_1708011",
+//         "translation": {"x": 0.0, "y": 0.0, "z": 1.5},
+//         "rotation": {"x": 1.0, "y": 0.0, "z": 0.0, "w": 0.0}
+//     }
+//
+// with the translation in meters, next to the "calibration" it came from.
+// The "extrinsic" of the parameters of an interface has the same format.
+pub fn shared_parameters_path(phoxi_scans_path: &str, photoneo_id: &str) -> String {
+    format!(
+        "{}/parameters/scanners/{}.json",
+        phoxi_scans_path, photoneo_id
+    )
+}
+
+// Null if the scanner has none yet.
+pub fn load_shared_parameters(
+    phoxi_scans_path: &str,
+    photoneo_id: &str,
+    log_target: &str,
+) -> Value {
+    let path = shared_parameters_path(phoxi_scans_path, photoneo_id);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Value::Null,
+        Err(e) => {
+            log::warn!(target: log_target, "Failed to read {}: {}", path, e);
+            return Value::Null;
+        }
+    };
+    serde_json::from_str(&contents).unwrap_or_else(|e| {
+        log::warn!(target: log_target, "Ignoring {}, it is not valid json: {}", path, e);
+        Value::Null
+    })
+}
+
+// Sets the entries, keeping everything else, and returns the path written.
+pub fn save_shared_parameters(
+    phoxi_scans_path: &str,
+    photoneo_id: &str,
+    entries: Map<String, Value>,
+    log_target: &str,
+) -> Result<String, String> {
+    let mut parameters = match load_shared_parameters(phoxi_scans_path, photoneo_id, log_target) {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    };
+    parameters.extend(entries);
+
+    let path = shared_parameters_path(phoxi_scans_path, photoneo_id);
+    let contents =
+        serde_json::to_string_pretty(&Value::Object(parameters)).map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(format!("{}/parameters/scanners", phoxi_scans_path))
+        .and_then(|()| std::fs::write(&path, contents + "\n"))
+        .map_err(|e| format!("Writing {} failed with: {}", path, e))?;
+    Ok(path)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExtrinsicParameters {
+    pub parent_frame_id: String,
+    pub child_frame_id: String,
+    pub translation: TranslationParameters,
+    pub rotation: RotationParameters,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TranslationParameters {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RotationParameters {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub w: f64,
+}
+
+impl ExtrinsicParameters {
+    // None if the parameters have no extrinsic.
+    pub fn from_parameters(parameters: &Value) -> Option<Result<ExtrinsicParameters, String>> {
+        let extrinsic = parameters.get("extrinsic").filter(|e| !e.is_null())?;
+        Some(serde_json::from_value(extrinsic.clone()).map_err(|e| e.to_string()))
+    }
+
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+}
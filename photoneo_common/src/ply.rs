@@ -0,0 +1,475 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Scans as PhoXiControl saves them to ply, ascii or binary little endian.
+// Every pixel of the camera is a vertex, in millimeters, and pixels without
+// a measurement are 0 0 0 or NaN. Depending on the output settings a scan
+// also has the normals, the texture, as red green blue and/or as a float
+// intensity, and the confidence of every point.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PointCloud {
+    pub points: Vec<[f32; 3]>,
+    // The optional properties have one entry per point.
+    pub normals: Option<Vec<[f32; 3]>>,
+    pub colors: Option<Vec<[u8; 3]>>,
+    pub texture: Option<Vec<f32>>,
+    pub confidence: Option<Vec<f32>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+#[derive(Debug)]
+pub enum PlyError {
+    Io(io::Error),
+    Invalid(String),
+    Unsupported(String),
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlyError::Io(e) => write!(f, "{}", e),
+            PlyError::Invalid(reason) => write!(f, "Invalid ply: {}", reason),
+            PlyError::Unsupported(reason) => write!(f, "Unsupported ply: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for PlyError {}
+
+impl From<io::Error> for PlyError {
+    fn from(e: io::Error) -> Self {
+        PlyError::Io(e)
+    }
+}
+
+// A point the scanner measured, pixels without a measurement are not.
+pub fn is_valid_point(point: &[f32; 3]) -> bool {
+    point.iter().all(|v| v.is_finite()) && point.iter().any(|v| *v != 0.0)
+}
+
+impl PointCloud {
+    pub fn read(path: impl AsRef<Path>) -> Result<PointCloud, PlyError> {
+        PointCloud::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<PointCloud, PlyError> {
+        let (header, body) = Header::parse(bytes)?;
+        let mut reader = match header.format {
+            PlyFormat::Ascii => Body::Ascii(
+                std::str::from_utf8(body)
+                    .map_err(|e| PlyError::Invalid(e.to_string()))?
+                    .split_ascii_whitespace(),
+            ),
+            PlyFormat::BinaryLittleEndian => Body::Binary(body),
+        };
+
+        let mut cloud = PointCloud::default();
+        for element in &header.elements {
+            if element.name != "vertex" {
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        reader.skip(property)?;
+                    }
+                }
+                continue;
+            }
+            let columns = VertexColumns::new(&element.properties)?;
+            cloud = columns.cloud(element.count);
+            let mut row = vec![0.0; element.properties.len()];
+            for _ in 0..element.count {
+                for (value, property) in row.iter_mut().zip(&element.properties) {
+                    *value = reader.value(property)?;
+                }
+                columns.push(&mut cloud, &row);
+            }
+        }
+        Ok(cloud)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>, format: PlyFormat) -> Result<(), PlyError> {
+        fs::write(path, self.to_bytes(format)?)?;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self, format: PlyFormat) -> Result<Vec<u8>, PlyError> {
+        self.check_lengths()?;
+        let mut header = format!(
+            "ply\nformat {} 1.0\nelement vertex {}\n\
+             property float x\nproperty float y\nproperty float z\n",
+            match format {
+                PlyFormat::Ascii => "ascii",
+                PlyFormat::BinaryLittleEndian => "binary_little_endian",
+            },
+            self.len()
+        );
+        if self.normals.is_some() {
+            header += "property float nx\nproperty float ny\nproperty float nz\n";
+        }
+        if self.colors.is_some() {
+            header += "property uchar red\nproperty uchar green\nproperty uchar blue\n";
+        }
+        if self.texture.is_some() {
+            header += "property float texture\n";
+        }
+        if self.confidence.is_some() {
+            header += "property float confidence\n";
+        }
+        header += "end_header\n";
+
+        let mut bytes = header.into_bytes();
+        for i in 0..self.len() {
+            let mut floats: Vec<f32> = self.points[i].to_vec();
+            if let Some(normals) = &self.normals {
+                floats.extend(normals[i]);
+            }
+            let color = self.colors.as_ref().map(|colors| colors[i]);
+            let extra: Vec<f32> = [&self.texture, &self.confidence]
+                .iter()
+                .filter_map(|values| values.as_ref().map(|values| values[i]))
+                .collect();
+
+            match format {
+                PlyFormat::Ascii => {
+                    let mut words: Vec<String> = floats.iter().map(|v| v.to_string()).collect();
+                    words.extend(color.iter().flatten().map(|v| v.to_string()));
+                    words.extend(extra.iter().map(|v| v.to_string()));
+                    bytes.extend(words.join(" ").as_bytes());
+                    bytes.push(b'\n');
+                }
+                PlyFormat::BinaryLittleEndian => {
+                    floats.iter().for_each(|v| bytes.extend(v.to_le_bytes()));
+                    color.iter().for_each(|rgb| bytes.extend(rgb));
+                    extra.iter().for_each(|v| bytes.extend(v.to_le_bytes()));
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    // The points at the given indices, with their properties, for writing
+    // a cropped or filtered scan.
+    pub fn select(&self, indices: &[usize]) -> PointCloud {
+        fn pick<T: Copy>(values: &Option<Vec<T>>, indices: &[usize]) -> Option<Vec<T>> {
+            values
+                .as_ref()
+                .map(|values| indices.iter().map(|i| values[*i]).collect())
+        }
+        PointCloud {
+            points: indices.iter().map(|i| self.points[*i]).collect(),
+            normals: pick(&self.normals, indices),
+            colors: pick(&self.colors, indices),
+            texture: pick(&self.texture, indices),
+            confidence: pick(&self.confidence, indices),
+        }
+    }
+
+    // The points for which keep is true.
+    pub fn filter(&self, keep: impl Fn(usize, &[f32; 3]) -> bool) -> PointCloud {
+        let indices: Vec<usize> = (0..self.len())
+            .filter(|i| keep(*i, &self.points[*i]))
+            .collect();
+        self.select(&indices)
+    }
+
+    fn check_lengths(&self) -> Result<(), PlyError> {
+        let lengths = [
+            ("normals", self.normals.as_ref().map(Vec::len)),
+            ("colors", self.colors.as_ref().map(Vec::len)),
+            ("texture", self.texture.as_ref().map(Vec::len)),
+            ("confidence", self.confidence.as_ref().map(Vec::len)),
+        ];
+        for (name, length) in lengths {
+            if let Some(length) = length.filter(|length| *length != self.len()) {
+                return Err(PlyError::Invalid(format!(
+                    "{} {} for {} points",
+                    length,
+                    name,
+                    self.len()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scalar {
+    Char,
+    Uchar,
+    Short,
+    Ushort,
+    Int,
+    Uint,
+    Float,
+    Double,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Result<Scalar, PlyError> {
+        Ok(match name {
+            "char" | "int8" => Scalar::Char,
+            "uchar" | "uint8" => Scalar::Uchar,
+            "short" | "int16" => Scalar::Short,
+            "ushort" | "uint16" => Scalar::Ushort,
+            "int" | "int32" => Scalar::Int,
+            "uint" | "uint32" => Scalar::Uint,
+            "float" | "float32" => Scalar::Float,
+            "double" | "float64" => Scalar::Double,
+            _ => return Err(PlyError::Invalid(format!("unknown type '{}'", name))),
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Scalar::Char | Scalar::Uchar => 1,
+            Scalar::Short | Scalar::Ushort => 2,
+            Scalar::Int | Scalar::Uint | Scalar::Float => 4,
+            Scalar::Double => 8,
+        }
+    }
+
+    // Reads the little endian value at the start of bytes, which holds
+    // at least size bytes.
+    fn read(&self, bytes: &[u8]) -> f64 {
+        let four = || bytes[..4].try_into().unwrap();
+        match self {
+            Scalar::Char => bytes[0] as i8 as f64,
+            Scalar::Uchar => bytes[0] as f64,
+            Scalar::Short => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Scalar::Ushort => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            Scalar::Int => i32::from_le_bytes(four()) as f64,
+            Scalar::Uint => u32::from_le_bytes(four()) as f64,
+            Scalar::Float => f32::from_le_bytes(four()) as f64,
+            Scalar::Double => f64::from_le_bytes(bytes[..8].try_into().unwrap()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Property {
+    Scalar { name: String, scalar: Scalar },
+    // Faces and the like, skipped.
+    List { count: Scalar, item: Scalar },
+}
+
+#[derive(Debug, Clone)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+#[derive(Debug)]
+struct Header {
+    format: PlyFormat,
+    elements: Vec<Element>,
+}
+
+impl Header {
+    // The header and the bytes after end_header.
+    fn parse(bytes: &[u8]) -> Result<(Header, &[u8]), PlyError> {
+        let mut format = None;
+        let mut elements: Vec<Element> = Vec::new();
+        let mut position = 0;
+        let mut first = true;
+        loop {
+            let Some(length) = bytes[position..].iter().position(|b| *b == b'\n') else {
+                return Err(PlyError::Invalid("no end_header".to_string()));
+            };
+            let line = String::from_utf8_lossy(&bytes[position..position + length]);
+            position += length + 1;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if first {
+                if words != ["ply"] {
+                    return Err(PlyError::Invalid("does not start with 'ply'".to_string()));
+                }
+                first = false;
+                continue;
+            }
+            match words.as_slice() {
+                ["format", "ascii", ..] => format = Some(PlyFormat::Ascii),
+                ["format", "binary_little_endian", ..] => {
+                    format = Some(PlyFormat::BinaryLittleEndian)
+                }
+                ["format", other, ..] => {
+                    return Err(PlyError::Unsupported(format!("format '{}'", other)))
+                }
+                ["element", name, count] => elements.push(Element {
+                    name: name.to_string(),
+                    count: count
+                        .parse()
+                        .map_err(|_| PlyError::Invalid(format!("element {} {}", name, count)))?,
+                    properties: vec![],
+                }),
+                ["property", "list", count, item, _] => elements
+                    .last_mut()
+                    .ok_or(PlyError::Invalid("property before element".to_string()))?
+                    .properties
+                    .push(Property::List {
+                        count: Scalar::from_name(count)?,
+                        item: Scalar::from_name(item)?,
+                    }),
+                ["property", scalar, name] => elements
+                    .last_mut()
+                    .ok_or(PlyError::Invalid("property before element".to_string()))?
+                    .properties
+                    .push(Property::Scalar {
+                        name: name.to_string(),
+                        scalar: Scalar::from_name(scalar)?,
+                    }),
+                ["end_header"] => break,
+                _ => (),
+            }
+        }
+        let format = format.ok_or(PlyError::Invalid("no format".to_string()))?;
+        Ok((Header { format, elements }, &bytes[position..]))
+    }
+}
+
+enum Body<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary(&'a [u8]),
+}
+
+impl Body<'_> {
+    fn scalar(&mut self, scalar: Scalar) -> Result<f64, PlyError> {
+        match self {
+            Body::Ascii(words) => {
+                let word = words.next().ok_or(PlyError::Invalid(
+                    "fewer values than in the header".to_string(),
+                ))?;
+                word.parse()
+                    .map_err(|_| PlyError::Invalid(format!("'{}' is not a number", word)))
+            }
+            Body::Binary(bytes) => {
+                if bytes.len() < scalar.size() {
+                    return Err(PlyError::Invalid(
+                        "fewer values than in the header".to_string(),
+                    ));
+                }
+                let value = scalar.read(bytes);
+                *bytes = &bytes[scalar.size()..];
+                Ok(value)
+            }
+        }
+    }
+
+    fn value(&mut self, property: &Property) -> Result<f64, PlyError> {
+        match property {
+            Property::Scalar { scalar, .. } => self.scalar(*scalar),
+            Property::List { .. } => {
+                self.skip(property)?;
+                Ok(0.0)
+            }
+        }
+    }
+
+    fn skip(&mut self, property: &Property) -> Result<(), PlyError> {
+        match property {
+            Property::Scalar { scalar, .. } => self.scalar(*scalar).map(|_| ()),
+            Property::List { count, item } => {
+                let count = self.scalar(*count)?;
+                for _ in 0..count.max(0.0) as usize {
+                    self.scalar(*item)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// Where the properties PhoXiControl writes are in a vertex row. Other
+// properties are read and dropped.
+struct VertexColumns {
+    point: [usize; 3],
+    normal: Option<[usize; 3]>,
+    color: Option<[(usize, Scalar); 3]>,
+    texture: Option<usize>,
+    confidence: Option<usize>,
+}
+
+impl VertexColumns {
+    fn new(properties: &[Property]) -> Result<VertexColumns, PlyError> {
+        let find = |matches: &dyn Fn(&str) -> bool| {
+            properties.iter().position(|property| match property {
+                Property::Scalar { name, .. } => matches(&name.to_lowercase()),
+                Property::List { .. } => false,
+            })
+        };
+        let named = |wanted: &str| find(&|name: &str| name == wanted);
+        let scalar = |column: usize| match properties[column] {
+            Property::Scalar { scalar, .. } => scalar,
+            Property::List { item, .. } => item,
+        };
+        let triple = |names: [&str; 3]| -> Option<[usize; 3]> {
+            Some([named(names[0])?, named(names[1])?, named(names[2])?])
+        };
+
+        let point = triple(["x", "y", "z"]).ok_or(PlyError::Invalid(
+            "no x, y and z vertex properties".to_string(),
+        ))?;
+        Ok(VertexColumns {
+            point,
+            normal: triple(["nx", "ny", "nz"]),
+            color: triple(["red", "green", "blue"])
+                .map(|columns| columns.map(|column| (column, scalar(column)))),
+            texture: find(&|name: &str| name.starts_with("texture") || name == "intensity"),
+            confidence: named("confidence"),
+        })
+    }
+
+    fn cloud(&self, count: usize) -> PointCloud {
+        PointCloud {
+            points: Vec::with_capacity(count),
+            normals: self.normal.map(|_| Vec::with_capacity(count)),
+            colors: self.color.map(|_| Vec::with_capacity(count)),
+            texture: self.texture.map(|_| Vec::with_capacity(count)),
+            confidence: self.confidence.map(|_| Vec::with_capacity(count)),
+        }
+    }
+
+    fn push(&self, cloud: &mut PointCloud, row: &[f64]) {
+        cloud
+            .points
+            .push(self.point.map(|column| row[column] as f32));
+        if let (Some(normal), Some(normals)) = (self.normal, cloud.normals.as_mut()) {
+            normals.push(normal.map(|column| row[column] as f32));
+        }
+        if let (Some(color), Some(colors)) = (self.color, cloud.colors.as_mut()) {
+            colors.push(color.map(|(column, scalar)| to_u8(row[column], scalar)));
+        }
+        if let (Some(column), Some(texture)) = (self.texture, cloud.texture.as_mut()) {
+            texture.push(row[column] as f32);
+        }
+        if let (Some(column), Some(confidence)) = (self.confidence, cloud.confidence.as_mut()) {
+            confidence.push(row[column] as f32);
+        }
+    }
+}
+
+// Colors are stored as uchar, other types are scaled: 16 bit values
+// down to 8 bit and floats from 0 to 1 up to 0 to 255.
+fn to_u8(value: f64, scalar: Scalar) -> u8 {
+    let value = match scalar {
+        Scalar::Char | Scalar::Uchar => value,
+        Scalar::Short | Scalar::Ushort => value / 257.0,
+        Scalar::Int | Scalar::Uint => value / 16843009.0,
+        Scalar::Float | Scalar::Double => value * 255.0,
+    };
+    value.round().clamp(0.0, 255.0) as u8
+}
@@ -0,0 +1,271 @@
+// A small in-process stand-in for Redis, so the interfaces can be tested
+// without a server. It speaks RESP2 and implements the handful of commands
+// the interfaces and micro_sp use, on a single keyspace. Only built with the
+// test-support feature, which the interfaces enable for their tests.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+type Store = Arc<Mutex<HashMap<Vec<u8>, Vec<u8>>>>;
+
+enum Reply {
+    Status(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+static SERVER: OnceLock<Option<RedisServer>> = OnceLock::new();
+
+// Runs against the Redis in REDIS_HOST and REDIS_PORT if TEST_REDIS_EXTERNAL
+// is set, and otherwise against a stand-in started once per test binary.
+pub fn redis_server() -> Option<&'static RedisServer> {
+    SERVER
+        .get_or_init(|| {
+            if std::env::var("TEST_REDIS_EXTERNAL").is_ok() {
+                return None;
+            }
+            let server = RedisServer::start();
+            std::env::set_var("REDIS_HOST", "127.0.0.1");
+            std::env::set_var("REDIS_PORT", server.port.to_string());
+            Some(server)
+        })
+        .as_ref()
+}
+
+pub struct RedisServer {
+    pub port: u16,
+    store: Store,
+    offline: Arc<AtomicBool>,
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl RedisServer {
+    pub fn start() -> RedisServer {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind the Redis stand-in");
+        let server = RedisServer {
+            port: listener.local_addr().expect("local address").port(),
+            store: Store::default(),
+            offline: Arc::new(AtomicBool::new(false)),
+            clients: Arc::new(Mutex::new(Vec::new())),
+        };
+
+        let store = server.store.clone();
+        let offline = server.offline.clone();
+        let clients = server.clients.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // Refuse connections while offline, like a stopped server.
+                if offline.load(Ordering::SeqCst) {
+                    let _ = stream.shutdown(Shutdown::Both);
+                    continue;
+                }
+                if let Ok(clone) = stream.try_clone() {
+                    clients.lock().unwrap().push(clone);
+                }
+                let store = store.clone();
+                thread::spawn(move || {
+                    let _ = serve(stream, store);
+                });
+            }
+        });
+        server
+    }
+
+    // Drops every client and refuses new ones until set_online.
+    pub fn set_offline(&self) {
+        self.offline.store(true, Ordering::SeqCst);
+        for client in self.clients.lock().unwrap().drain(..) {
+            let _ = client.shutdown(Shutdown::Both);
+        }
+    }
+
+    pub fn set_online(&self) {
+        self.offline.store(false, Ordering::SeqCst);
+    }
+}
+
+fn serve(stream: TcpStream, store: Store) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut transaction: Option<Vec<Vec<Vec<u8>>>> = None;
+
+    while let Some(command) = read_command(&mut reader)? {
+        if command.is_empty() {
+            continue;
+        }
+        let name = command
+            .first()
+            .map(|n| String::from_utf8_lossy(n).to_uppercase())
+            .unwrap_or_default();
+        let reply = match (name.as_str(), transaction.as_mut()) {
+            ("MULTI", None) => {
+                transaction = Some(Vec::new());
+                Reply::Status("OK")
+            }
+            ("EXEC", Some(_)) => Reply::Array(
+                transaction
+                    .take()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|queued| execute(queued, &store))
+                    .collect(),
+            ),
+            ("DISCARD", Some(_)) => {
+                transaction = None;
+                Reply::Status("OK")
+            }
+            (_, Some(queued)) => {
+                queued.push(command);
+                Reply::Status("QUEUED")
+            }
+            _ => execute(&command, &store),
+        };
+        let mut buffer = Vec::new();
+        encode(&reply, &mut buffer);
+        writer.write_all(&buffer)?;
+    }
+    Ok(())
+}
+
+fn execute(command: &[Vec<u8>], store: &Store) -> Reply {
+    let name = String::from_utf8_lossy(&command[0]).to_uppercase();
+    let args = &command[1..];
+    let mut store = store.lock().unwrap();
+    match (name.as_str(), args.len()) {
+        ("PING", 0) => Reply::Status("PONG"),
+        ("PING", _) | ("ECHO", 1) => Reply::Bulk(Some(args[0].clone())),
+        ("CLIENT", _) | ("SELECT", 1) => Reply::Status("OK"),
+        ("GET", 1) => Reply::Bulk(store.get(&args[0]).cloned()),
+        ("SET", n) if n >= 2 => {
+            store.insert(args[0].clone(), args[1].clone());
+            Reply::Status("OK")
+        }
+        ("MGET", n) if n >= 1 => Reply::Array(
+            args.iter()
+                .map(|key| Reply::Bulk(store.get(key).cloned()))
+                .collect(),
+        ),
+        ("MSET", n) if n >= 2 && n % 2 == 0 => {
+            for pair in args.chunks(2) {
+                store.insert(pair[0].clone(), pair[1].clone());
+            }
+            Reply::Status("OK")
+        }
+        ("DEL", _) => Reply::Integer(args.iter().filter(|k| store.remove(*k).is_some()).count() as i64),
+        ("EXISTS", _) => Reply::Integer(args.iter().filter(|k| store.contains_key(*k)).count() as i64),
+        ("KEYS", 1) => Reply::Array(
+            store
+                .keys()
+                .filter(|key| matches(&args[0], key))
+                .map(|key| Reply::Bulk(Some(key.clone())))
+                .collect(),
+        ),
+        // Everything in one batch, cursor 0 ends the iteration.
+        ("SCAN", _) => {
+            let pattern = args
+                .windows(2)
+                .find(|w| w[0].eq_ignore_ascii_case(b"MATCH"))
+                .map(|w| w[1].clone())
+                .unwrap_or_else(|| b"*".to_vec());
+            Reply::Array(vec![
+                Reply::Bulk(Some(b"0".to_vec())),
+                Reply::Array(
+                    store
+                        .keys()
+                        .filter(|key| matches(&pattern, key))
+                        .map(|key| Reply::Bulk(Some(key.clone())))
+                        .collect(),
+                ),
+            ])
+        }
+        ("FLUSHDB", _) | ("FLUSHALL", _) => {
+            store.clear();
+            Reply::Status("OK")
+        }
+        _ => Reply::Error(format!("ERR unknown command '{}'", name)),
+    }
+}
+
+// Glob patterns with '*' wildcards only, which is all that is used.
+fn matches(pattern: &[u8], key: &[u8]) -> bool {
+    match pattern.iter().position(|c| *c == b'*') {
+        None => pattern == key,
+        Some(star) => {
+            let (prefix, rest) = (&pattern[..star], &pattern[star + 1..]);
+            key.starts_with(prefix)
+                && (prefix.len()..=key.len()).any(|i| matches(rest, &key[i..]))
+        }
+    }
+}
+
+fn read_command(reader: &mut impl BufRead) -> io::Result<Option<Vec<Vec<u8>>>> {
+    let line = match read_line(reader)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    if line.first() != Some(&b'*') {
+        // Inline command, as typed into redis-cli or telnet.
+        return Ok(Some(
+            line.split(|c| c.is_ascii_whitespace())
+                .filter(|w| !w.is_empty())
+                .map(|w| w.to_vec())
+                .collect(),
+        ));
+    }
+
+    let count = parse_length(&line[1..])?;
+    let mut command = Vec::with_capacity(count);
+    for _ in 0..count {
+        let header = read_line(reader)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+        let length = parse_length(header.get(1..).unwrap_or_default())?;
+        let mut data = vec![0; length + 2];
+        reader.read_exact(&mut data)?;
+        data.truncate(length);
+        command.push(data);
+    }
+    Ok(Some(command))
+}
+
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    while line.last().is_some_and(|c| *c == b'\n' || *c == b'\r') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn parse_length(data: &[u8]) -> io::Result<usize> {
+    std::str::from_utf8(data)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid length"))
+}
+
+fn encode(reply: &Reply, buffer: &mut Vec<u8>) {
+    match reply {
+        Reply::Status(status) => buffer.extend_from_slice(format!("+{}\r\n", status).as_bytes()),
+        Reply::Error(error) => buffer.extend_from_slice(format!("-{}\r\n", error).as_bytes()),
+        Reply::Integer(value) => buffer.extend_from_slice(format!(":{}\r\n", value).as_bytes()),
+        Reply::Bulk(None) => buffer.extend_from_slice(b"$-1\r\n"),
+        Reply::Bulk(Some(data)) => {
+            buffer.extend_from_slice(format!("${}\r\n", data.len()).as_bytes());
+            buffer.extend_from_slice(data);
+            buffer.extend_from_slice(b"\r\n");
+        }
+        Reply::Array(items) => {
+            buffer.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
+            for item in items {
+                encode(item, buffer);
+            }
+        }
+    }
+}
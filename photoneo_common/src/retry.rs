@@ -0,0 +1,110 @@
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+pub const DEFAULT_RETRY_MAX_ATTEMPTS: u32 = 1;
+pub const DEFAULT_RETRY_BACKOFF_MS: u64 = 500;
+pub const DEFAULT_ATTENTION_THRESHOLD: i64 = 3;
+
+// The backoff doubles with every retry, up to this.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+// How a request that failed with a recoverable error is retried, from
+// {id}_retry_max_attempts, {id}_retry_backoff and {id}_retry_reconnect,
+// and after how many failed requests in a row {id}_needs_attention is set,
+// from {id}_attention_threshold. The localization interface reads the same
+// keys with the {id}_localization_ prefix, and does not reconnect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    // Attempts per request, 1 does not retry.
+    pub max_attempts: u32,
+    // Wait before the first retry.
+    pub backoff: Duration,
+    // Connect to the scanner again before every retry.
+    pub reconnect: bool,
+    pub attention_threshold: i64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: DEFAULT_RETRY_MAX_ATTEMPTS,
+            backoff: Duration::from_millis(DEFAULT_RETRY_BACKOFF_MS),
+            reconnect: false,
+            attention_threshold: DEFAULT_ATTENTION_THRESHOLD,
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Zero or negative values select the defaults.
+    pub fn from_request(
+        max_attempts: i64,
+        backoff_ms: i64,
+        reconnect: bool,
+        attention_threshold: i64,
+    ) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: if max_attempts > 0 {
+                max_attempts.min(u32::MAX as i64) as u32
+            } else {
+                DEFAULT_RETRY_MAX_ATTEMPTS
+            },
+            backoff: Duration::from_millis(if backoff_ms > 0 {
+                backoff_ms as u64
+            } else {
+                DEFAULT_RETRY_BACKOFF_MS
+            }),
+            reconnect,
+            attention_threshold: if attention_threshold > 0 {
+                attention_threshold
+            } else {
+                DEFAULT_ATTENTION_THRESHOLD
+            },
+        }
+    }
+
+    // The wait before the given retry, the first one being 1.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.backoff.saturating_mul(factor).min(MAX_RETRY_BACKOFF)
+    }
+
+    // Waits before the given retry, false if the request was cancelled
+    // in the meantime.
+    pub async fn wait(&self, retry: u32, cancel: &CancellationToken) -> bool {
+        tokio::select! {
+            _ = tokio::time::sleep(self.delay(retry)) => true,
+            _ = cancel.cancelled() => false,
+        }
+    }
+}
+
+// {id}_total_fail_counter and {id}_subsequent_fail_counter, with the
+// {id}_localization_ prefix for the localization interface. A request
+// counts once, however often it was retried, and cancelled requests do
+// not count at all.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FailCounters {
+    pub total: i64,
+    pub subsequent: i64,
+}
+
+impl FailCounters {
+    pub fn succeeded(self) -> FailCounters {
+        FailCounters {
+            total: self.total,
+            subsequent: 0,
+        }
+    }
+
+    pub fn failed(self) -> FailCounters {
+        FailCounters {
+            total: self.total + 1,
+            subsequent: self.subsequent + 1,
+        }
+    }
+
+    pub fn needs_attention(&self, policy: &RetryPolicy) -> bool {
+        self.subsequent >= policy.attention_threshold
+    }
+}
@@ -0,0 +1,81 @@
+pub const DEFAULT_PHOTONEO_ID: &str = "photoneo_1708011";
+
+// The scanners one interface process serves, each with its own request
+// loop under its own {id}_ keys:
+//
+//   PHOTONEO_ID=1708011   only phoxi_1708011, as before.
+//   PHOTONEO_IDS=a,b      the scanners a and b.
+//   neither               every scanner with a parameters/scanners/{id}.json.
+//
+// Every source gives the ids the keys and the parameter files are named
+// by, see scanner_id. If that leaves no scanner, photoneo_1708011 is
+// served.
+pub fn scanner_ids(interface_path: &str, log_target: &str) -> Vec<String> {
+    let ids = if let Ok(id) = std::env::var("PHOTONEO_ID") {
+        vec![scanner_id(id.trim())]
+    } else if let Ok(ids) = std::env::var("PHOTONEO_IDS") {
+        ids.split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(scanner_id)
+            .collect()
+    } else {
+        parameter_files(
+            &format!("{}/parameters/scanners", interface_path),
+            log_target,
+        )
+    };
+
+    if ids.is_empty() {
+        log::warn!(target: log_target,
+            "No scanners configured, serving the default {}.", DEFAULT_PHOTONEO_ID);
+        return vec![DEFAULT_PHOTONEO_ID.to_string()];
+    }
+    ids
+}
+
+// The id of a scanner given by its serial number, as PHOTONEO_ID always
+// was, is phoxi_{serial}. Any other name already is the id.
+pub fn scanner_id(name: &str) -> String {
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_digit()) {
+        format!("phoxi_{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+// The ids of the {id}.json files in the directory, sorted. A file named by
+// the serial number alone would be served under another id than its name,
+// so it is skipped.
+fn parameter_files(dir: &str, log_target: &str) -> Vec<String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!(target: log_target,
+                "Failed to read the scanner parameters in {}: {}", dir, e);
+            return vec![];
+        }
+    };
+    let mut ids: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .filter_map(|path| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+        })
+        .filter(|id| {
+            let named_by_id = scanner_id(id) == *id;
+            if !named_by_id {
+                log::warn!(target: log_target,
+                    "Skipping {}/{}.json, name it {}.json to serve that scanner.", dir, id, scanner_id(id));
+            }
+            named_by_id
+        })
+        .collect();
+    ids.sort();
+    ids
+}
@@ -0,0 +1,146 @@
+// The executor runs the C++ executables, here stood in for by sh.
+#![cfg(unix)]
+
+use photoneo_common::executor::{command_failure, failure_info, partial_output, run_executable};
+use std::io;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+fn sh(script: &str) -> Vec<String> {
+    vec!["/bin/sh".to_string(), "-c".to_string(), script.to_string()]
+}
+
+#[tokio::test]
+async fn collects_stdout_and_stderr_without_line_endings() {
+    let output = run_executable(
+        &sh("printf 'first\\r\\nsecond\\n\\nthird'; echo 'not stdout' >&2"),
+        Some(Duration::from_secs(5)),
+        &CancellationToken::new(),
+        "test",
+    )
+    .await
+    .unwrap();
+    assert_eq!(output.stdout, vec!["first", "second", "", "third"]);
+    assert_eq!(output.stderr, vec!["not stdout"]);
+}
+
+#[tokio::test]
+async fn non_zero_exit_fails_with_the_last_line_printed() {
+    let error = run_executable(
+        &sh("echo 'Error loading plcf file: part.plcf'; exit 3"),
+        Some(Duration::from_secs(5)),
+        &CancellationToken::new(),
+        "test",
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "sh exited with code 3: Error loading plcf file: part.plcf"
+    );
+    let failure = command_failure(&error).unwrap();
+    assert_eq!(failure.exit_code, Some(3));
+    assert_eq!(failure.stdout, ["Error loading plcf file: part.plcf"]);
+}
+
+#[tokio::test]
+async fn stderr_explains_a_failure_before_stdout() {
+    let error = run_executable(
+        &sh("echo 'Connecting'; echo 'PhoXi Control is not running' >&2; exit 1"),
+        Some(Duration::from_secs(5)),
+        &CancellationToken::new(),
+        "test",
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "sh exited with code 1: PhoXi Control is not running"
+    );
+    assert_eq!(
+        command_failure(&error).unwrap().stderr,
+        ["PhoXi Control is not running"]
+    );
+    // The line is quoted already.
+    assert_eq!(failure_info(&error), error.to_string());
+}
+
+#[tokio::test]
+async fn failure_info_adds_the_rest_of_stderr() {
+    let error = run_executable(
+        &sh("echo 'Loading' >&2; echo 'SceneSource Error' >&2; exit 2"),
+        Some(Duration::from_secs(5)),
+        &CancellationToken::new(),
+        "test",
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(
+        failure_info(&error),
+        "sh exited with code 2: SceneSource Error\nLoading\nSceneSource Error"
+    );
+}
+
+#[tokio::test]
+async fn kills_the_process_on_timeout() {
+    let start = Instant::now();
+    let error = run_executable(
+        &sh("echo started; exec sleep 30"),
+        Some(Duration::from_millis(200)),
+        &CancellationToken::new(),
+        "test",
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    assert_eq!(error.to_string(), "Command execution timed out.");
+    assert_eq!(partial_output(&error), ["started"]);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn kills_the_process_when_cancelled() {
+    let cancel = CancellationToken::new();
+    let canceller = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        canceller.cancel();
+    });
+
+    let start = Instant::now();
+    let error = run_executable(&sh("echo started; exec sleep 30"), None, &cancel, "test")
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::Interrupted);
+    assert_eq!(partial_output(&error), ["started"]);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+// A child that outlives its stdout must still be waited for, and killed
+// at the timeout.
+#[tokio::test]
+async fn waits_for_the_process_after_stdout_closes() {
+    let error = run_executable(
+        &sh("exec >&-; sleep 30"),
+        Some(Duration::from_millis(200)),
+        &CancellationToken::new(),
+        "test",
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+}
+
+#[tokio::test]
+async fn missing_executable_fails_to_spawn() {
+    let error = run_executable(
+        &["/does/not/exist/Capture_Release".to_string()],
+        Some(Duration::from_secs(1)),
+        &CancellationToken::new(),
+        "test",
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    assert!(partial_output(&error).is_empty());
+}
@@ -0,0 +1,75 @@
+// The scanner parameters both interfaces read from the shared scans folder.
+
+use photoneo_common::parameters::{
+    load_shared_parameters, save_shared_parameters, shared_parameters_path, ExtrinsicParameters,
+    RotationParameters, TranslationParameters,
+};
+use serde_json::{json, Map, Value};
+
+fn extrinsic() -> ExtrinsicParameters {
+    ExtrinsicParameters {
+        parent_frame_id: "world".to_string(),
+        child_frame_id: "photoneo_test".to_string(),
+        translation: TranslationParameters {
+            x: 0.1,
+            y: -0.2,
+            z: 1.5,
+        },
+        rotation: RotationParameters {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+            w: 0.0,
+        },
+    }
+}
+
+#[test]
+fn saved_extrinsic_is_read_back_and_other_entries_are_kept() {
+    let scans = tempfile::tempdir().unwrap();
+    let scans_path = scans.path().to_string_lossy().to_string();
+    assert_eq!(
+        load_shared_parameters(&scans_path, "photoneo_test", "test"),
+        Value::Null
+    );
+
+    let path = save_shared_parameters(
+        &scans_path,
+        "photoneo_test",
+        Map::from_iter([("workspace".to_string(), json!({"min": [0, 0, 0]}))]),
+        "test",
+    )
+    .unwrap();
+    assert_eq!(path, shared_parameters_path(&scans_path, "photoneo_test"));
+
+    save_shared_parameters(
+        &scans_path,
+        "photoneo_test",
+        Map::from_iter([("extrinsic".to_string(), extrinsic().to_value())]),
+        "test",
+    )
+    .unwrap();
+    let parameters = load_shared_parameters(&scans_path, "photoneo_test", "test");
+    assert_eq!(parameters["workspace"], json!({"min": [0, 0, 0]}));
+    assert_eq!(
+        ExtrinsicParameters::from_parameters(&parameters),
+        Some(Ok(extrinsic()))
+    );
+}
+
+#[test]
+fn missing_or_invalid_extrinsics_are_told_apart() {
+    assert_eq!(ExtrinsicParameters::from_parameters(&json!({})), None);
+    assert_eq!(
+        ExtrinsicParameters::from_parameters(&json!({"extrinsic": null})),
+        None
+    );
+    let error = ExtrinsicParameters::from_parameters(&json!({
+        "extrinsic": {"parent_frame_id": "world", "child_frame_id": "camera",
+                      "translation": {"x": 0.0, "y": 0.0},
+                      "rotation": {"x": 0.0, "y": 0.0, "z": 0.0, "w": 1.0}}
+    }))
+    .unwrap()
+    .unwrap_err();
+    assert!(error.contains("missing field `z`"), "{}", error);
+}
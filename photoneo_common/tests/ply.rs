@@ -0,0 +1,122 @@
+// Reading and writing scans, in the layout PhoXiControl saves them.
+
+use photoneo_common::ply::{is_valid_point, PlyError, PlyFormat, PointCloud};
+
+fn scan() -> PointCloud {
+    PointCloud {
+        points: vec![
+            [1.5, -2.0, 900.0],
+            [0.0, 0.0, 0.0],
+            [f32::NAN, f32::NAN, f32::NAN],
+        ],
+        normals: Some(vec![[0.0, 0.0, -1.0], [0.0, 0.0, 0.0], [0.0, 0.0, 0.0]]),
+        colors: Some(vec![[255, 128, 0], [0, 0, 0], [1, 2, 3]]),
+        texture: Some(vec![1023.5, 0.0, 12.0]),
+        confidence: Some(vec![3.25, 0.0, 0.0]),
+    }
+}
+
+// NaN != NaN, so the points are compared by their bits.
+fn assert_same(read: &PointCloud, written: &PointCloud) {
+    let bits = |cloud: &PointCloud| -> Vec<[u32; 3]> {
+        cloud.points.iter().map(|p| p.map(f32::to_bits)).collect()
+    };
+    assert_eq!(bits(read), bits(written));
+    assert_eq!(read.normals, written.normals);
+    assert_eq!(read.colors, written.colors);
+    assert_eq!(read.texture, written.texture);
+    assert_eq!(read.confidence, written.confidence);
+}
+
+#[test]
+fn scans_survive_a_round_trip_in_both_formats() {
+    let dir = tempfile::tempdir().unwrap();
+    for format in [PlyFormat::Ascii, PlyFormat::BinaryLittleEndian] {
+        let path = dir.path().join(format!("{:?}.ply", format));
+        scan().write(&path, format).unwrap();
+        assert_same(&PointCloud::read(&path).unwrap(), &scan());
+    }
+
+    let bare = PointCloud {
+        points: vec![[1.0, 2.0, 3.0]],
+        ..Default::default()
+    };
+    let bytes = bare.to_bytes(PlyFormat::BinaryLittleEndian).unwrap();
+    assert_eq!(PointCloud::parse(&bytes).unwrap(), bare);
+}
+
+#[test]
+fn binary_scan_with_other_elements_and_property_names() {
+    let mut bytes = b"ply\r\nformat binary_little_endian 1.0\r\n\
+        comment PhoXi scan\r\n\
+        element camera 1\r\n\
+        property double view_px\r\n\
+        element vertex 2\r\n\
+        property float x\r\nproperty float y\r\nproperty float z\r\n\
+        property ushort red\r\nproperty ushort green\r\nproperty ushort blue\r\n\
+        property float Texture32\r\n\
+        property int id\r\n\
+        property float Confidence\r\n\
+        element face 1\r\n\
+        property list uchar int vertex_indices\r\n\
+        end_header\r\n"
+        .to_vec();
+    bytes.extend(1.0f64.to_le_bytes());
+    for (i, z) in [1000.0f32, 1001.0].iter().enumerate() {
+        [i as f32, 2.0, *z]
+            .iter()
+            .for_each(|v| bytes.extend(v.to_le_bytes()));
+        [65535u16, 257, 0]
+            .iter()
+            .for_each(|v| bytes.extend(v.to_le_bytes()));
+        bytes.extend(0.5f32.to_le_bytes());
+        bytes.extend((-7i32).to_le_bytes());
+        bytes.extend(2.0f32.to_le_bytes());
+    }
+    bytes.push(3);
+    [0i32, 1, 0]
+        .iter()
+        .for_each(|v| bytes.extend(v.to_le_bytes()));
+
+    let cloud = PointCloud::parse(&bytes).unwrap();
+    assert_eq!(cloud.points, vec![[0.0, 2.0, 1000.0], [1.0, 2.0, 1001.0]]);
+    assert_eq!(cloud.colors, Some(vec![[255, 1, 0]; 2]));
+    assert_eq!(cloud.texture, Some(vec![0.5; 2]));
+    assert_eq!(cloud.confidence, Some(vec![2.0; 2]));
+    assert_eq!(cloud.normals, None);
+
+    bytes.truncate(bytes.len() - 4);
+    assert!(matches!(
+        PointCloud::parse(&bytes),
+        Err(PlyError::Invalid(_))
+    ));
+}
+
+#[test]
+fn unmeasured_pixels_are_not_valid_points() {
+    let cloud = scan();
+    let valid: Vec<bool> = cloud.points.iter().map(is_valid_point).collect();
+    assert_eq!(valid, vec![true, false, false]);
+
+    let cropped = cloud.filter(|_, point| is_valid_point(point));
+    assert_eq!(cropped.len(), 1);
+    assert_eq!(cropped.colors, Some(vec![[255, 128, 0]]));
+    assert_eq!(cropped.confidence, Some(vec![3.25]));
+    assert_eq!(cloud.select(&[2, 0]).texture, Some(vec![12.0, 1023.5]));
+}
+
+#[test]
+fn unsupported_and_inconsistent_clouds_are_errors() {
+    let big_endian = b"ply\nformat binary_big_endian 1.0\nelement vertex 0\nend_header\n";
+    assert!(matches!(
+        PointCloud::parse(big_endian),
+        Err(PlyError::Unsupported(_))
+    ));
+    let no_z = b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\n\
+        property float y\nend_header\n1 2\n";
+    assert!(matches!(PointCloud::parse(no_z), Err(PlyError::Invalid(_))));
+
+    let mut cloud = scan();
+    cloud.confidence = Some(vec![1.0]);
+    assert!(cloud.to_bytes(PlyFormat::Ascii).is_err());
+}
@@ -0,0 +1,54 @@
+use photoneo_common::retry::{
+    FailCounters, RetryPolicy, DEFAULT_ATTENTION_THRESHOLD, DEFAULT_RETRY_MAX_ATTEMPTS,
+};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+#[test]
+fn unset_values_select_the_defaults() {
+    let policy = RetryPolicy::from_request(0, -1, false, 0);
+    assert_eq!(policy, RetryPolicy::default());
+    assert_eq!(policy.max_attempts, DEFAULT_RETRY_MAX_ATTEMPTS);
+    assert_eq!(policy.attention_threshold, DEFAULT_ATTENTION_THRESHOLD);
+}
+
+#[test]
+fn backoff_doubles_up_to_its_maximum() {
+    let policy = RetryPolicy::from_request(10, 400, true, 3);
+    assert_eq!(policy.delay(1), Duration::from_millis(400));
+    assert_eq!(policy.delay(2), Duration::from_millis(800));
+    assert_eq!(policy.delay(4), Duration::from_millis(3200));
+    assert_eq!(policy.delay(40), Duration::from_secs(30));
+}
+
+#[test]
+fn success_resets_the_subsequent_failures_only() {
+    let policy = RetryPolicy::from_request(1, 0, false, 2);
+    let counters = FailCounters::default().failed();
+    assert!(!counters.needs_attention(&policy));
+    let counters = counters.failed();
+    assert!(counters.needs_attention(&policy));
+    let counters = counters.succeeded();
+    assert_eq!(
+        counters,
+        FailCounters {
+            total: 2,
+            subsequent: 0
+        }
+    );
+    assert!(!counters.needs_attention(&policy));
+}
+
+#[tokio::test]
+async fn cancellation_ends_the_wait() {
+    let policy = RetryPolicy::from_request(3, 10_000, false, 0);
+    let cancel = CancellationToken::new();
+    let canceller = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        canceller.cancel();
+    });
+    let start = Instant::now();
+    assert!(!policy.wait(1, &cancel).await);
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
@@ -0,0 +1,81 @@
+// One process serves several scanners, see scanner_ids.
+
+use photoneo_common::scanners::{scanner_id, scanner_ids, DEFAULT_PHOTONEO_ID};
+use std::sync::Mutex;
+
+// The environment is shared by the whole test binary, the tests take turns
+// with it.
+static ENVIRONMENT: Mutex<()> = Mutex::new(());
+
+#[test]
+fn scanners_are_taken_from_the_environment_or_the_parameters() {
+    let _environment = ENVIRONMENT.lock().unwrap_or_else(|e| e.into_inner());
+    let installation = tempfile::tempdir().unwrap();
+    let interface_path = installation.path().to_string_lossy().to_string();
+    std::env::remove_var("PHOTONEO_ID");
+    std::env::remove_var("PHOTONEO_IDS");
+
+    assert_eq!(
+        scanner_ids(&interface_path, "test"),
+        vec![DEFAULT_PHOTONEO_ID]
+    );
+
+    let scanners = installation.path().join("parameters/scanners");
+    std::fs::create_dir_all(&scanners).unwrap();
+    for file in [
+        "photoneo_volvo.json",
+        "photoneo_chalmers.json",
+        "notes.txt",
+        "1708011.json",
+    ] {
+        std::fs::write(scanners.join(file), "{}").unwrap();
+    }
+    assert_eq!(
+        scanner_ids(&interface_path, "test"),
+        vec!["photoneo_chalmers", "photoneo_volvo"]
+    );
+
+    std::env::set_var("PHOTONEO_IDS", "photoneo_a, photoneo_b,");
+    assert_eq!(
+        scanner_ids(&interface_path, "test"),
+        vec!["photoneo_a", "photoneo_b"]
+    );
+
+    std::env::set_var("PHOTONEO_ID", "1708011");
+    assert_eq!(scanner_ids(&interface_path, "test"), vec!["phoxi_1708011"]);
+
+    std::env::remove_var("PHOTONEO_ID");
+    std::env::remove_var("PHOTONEO_IDS");
+}
+
+#[test]
+fn every_source_names_a_scanner_by_the_same_id() {
+    let _environment = ENVIRONMENT.lock().unwrap_or_else(|e| e.into_inner());
+    let installation = tempfile::tempdir().unwrap();
+    let interface_path = installation.path().to_string_lossy().to_string();
+    let scanners = installation.path().join("parameters/scanners");
+    std::fs::create_dir_all(&scanners).unwrap();
+    std::fs::write(scanners.join("phoxi_1708011.json"), "{}").unwrap();
+    std::env::remove_var("PHOTONEO_ID");
+    std::env::remove_var("PHOTONEO_IDS");
+
+    assert_eq!(scanner_id("1708011"), "phoxi_1708011");
+    assert_eq!(scanner_id("phoxi_1708011"), "phoxi_1708011");
+    assert_eq!(scanner_id("photoneo_volvo"), "photoneo_volvo");
+
+    let from_files = scanner_ids(&interface_path, "test");
+    assert_eq!(from_files, vec!["phoxi_1708011"]);
+    for variable in ["PHOTONEO_ID", "PHOTONEO_IDS"] {
+        for name in ["1708011", "phoxi_1708011"] {
+            std::env::set_var(variable, name);
+            assert_eq!(
+                scanner_ids(&interface_path, "test"),
+                from_files,
+                "{}={}",
+                variable,
+                name
+            );
+            std::env::remove_var(variable);
+        }
+    }
+}
@@ -8,6 +8,7 @@
 #endif
 
 #include "PhoXi.h"
+#include "ScanRequest.h"
 
 #if defined(_WIN32)
 #define LOCAL_CROSS_SLEEP(Millis) Sleep(Millis)
@@ -18,6 +19,8 @@
 #endif
 
 // ARGUMENTS:
+// Either a single --request=<path> pointing to a JSON request file
+// (see common/ScanRequest.h), or the legacy positional arguments:
 // 1 - hardware_identification
 // 2 - scene_name
 // 3 - praw
@@ -51,15 +54,18 @@ class Capture
 private:
     pho::api::PhoXiFactory Factory;
     pho::api::PPhoXi PhoXiDevice;
+    ScanRequest Request;
     pho::api::PFrame LastFrame;
 
     std::string PrawsOutputFolder = "C:\\Users\\photoneo\\Desktop\\scans\\praws";
     std::string PlysOutputFolder = "C:\\Users\\photoneo\\Desktop\\scans\\plys";
     std::string TifsOutputFolder = "C:\\Users\\photoneo\\Desktop\\scans\\tifs";
 
-    void ConnectPhoXiDeviceBySerial(int argc, char *argv[]);
-    void ChangeSettings(int argc, char* argv[]);
-    void CaptureAndSaveFrame(int argc, char *argv[]);
+    void ConnectPhoXiDeviceBySerial();
+    void ChangeSettings();
+    void SelectCapturingMode();
+    void CaptureAndSaveFrame();
+    std::string OutputFolder(const std::string &Requested, const std::string &Fallback) const;
 
     template <class T>
     bool ReadLine(T &Output) const
@@ -81,10 +87,10 @@ public:
     void Run(int argc, char *argv[]);
 };
 
-void Capture::ConnectPhoXiDeviceBySerial(int argc, char *argv[])
+void Capture::ConnectPhoXiDeviceBySerial()
 {
     pho::api::PhoXiTimeout Timeout = pho::api::PhoXiTimeout::ZeroTimeout;
-    std::string hardware_identification = argv[1];
+    std::string hardware_identification = Request.HardwareIdentification;
     PhoXiDevice = Factory.CreateAndConnect(hardware_identification, Timeout);
     if (PhoXiDevice)
     {
@@ -96,40 +102,60 @@ void Capture::ConnectPhoXiDeviceBySerial(int argc, char *argv[])
     }
 }
 
-void Capture::ChangeSettings(int argc, char* argv[])
+void Capture::ChangeSettings()
+{
+    PhoXiDevice->CapturingSettings->ShutterMultiplier = Request.ShutterMultiplier;
+    PhoXiDevice->CapturingSettings->ScanMultiplier = Request.ScanMultiplier;
+    SelectCapturingMode();
+    PhoXiDevice->CapturingSettings->CameraOnlyMode = Request.CameraOnlyMode;
+    PhoXiDevice->CapturingSettings->AmbientLightSuppression = Request.AmbientLightSuppression;
+    PhoXiDevice->CapturingSettings->CodingStrategy = Request.CodingStrategy;
+    PhoXiDevice->CapturingSettings->CodingQuality = Request.CodingQuality;
+    PhoXiDevice->CapturingSettings->TextureSource = Request.TextureSource;
+    PhoXiDevice->CapturingSettings->SinglePatternExposure = Request.SinglePatternExposure;
+    PhoXiDevice->CapturingSettings->MaximumFPS = Request.MaximumFPS;
+    PhoXiDevice->CapturingSettings->LaserPower = Request.LaserPower;
+    // Are the following unsupported or what?
+    //PhoxiDevice->CapturingSettings->ProjectionOffsetLeft = Request.ProjectionOffsetLeft;
+    // PhoxiDevice->CapturingSettings->ProjectionOffsetRight = Request.ProjectionOffsetRight;
+    // PhoXiDevice->CapturingSettings->LedPower = Request.LedPower;
+    PhoXiDevice->ProcessingSettings->Confidence = Request.MaxInaccuracy;
+    PhoXiDevice->ProcessingSettings->SurfaceSmoothness = Request.SurfaceSmoothness;
+    PhoXiDevice->ProcessingSettings->NormalsEstimationRadius = Request.NormalsEstimationRadius;
+    // Are the following unsupported or what?
+    // PhoXiDevice->ProcessingSettings->InterReflectionsFilter = Request.InterreflectionsFilter;
+    // PhoxiDevice->ExperimentalSettings->AmbientLightSuppressionCompatibilityMode = Request.AmbientLightSuppressionCompatibilityMode;
+    // PhoxiDevice->ExperimentalSettings->PatternDecompositionReach = Request.PatternDecompositionReach;
+    // PhoxiDevice->ExperimentalSettings->SignalContrastThreshold = Request.SignalContrastThreshold;
+    // PhoxiDevice->ExperimentalSettings->UseExtendedLogging = Request.UseExtendedLogging;
+}
+
+void Capture::SelectCapturingMode()
 {
-    PhoXiDevice->CapturingSettings->ShutterMultiplier = std::stod(argv[6]);
-    PhoXiDevice->CapturingSettings->ScanMultiplier = std::stoi(argv[7]);
     std::vector<pho::api::PhoXiCapturingMode> SupportedCapturingModes = PhoXiDevice->SupportedCapturingModes;
     if (!PhoXiDevice->SupportedCapturingModes.isLastOperationSuccessful())
     {
         throw std::runtime_error(PhoXiDevice->SupportedCapturingModes.GetLastErrorMessage().c_str());
     }
-    PhoXiDevice->CapturingMode = SupportedCapturingModes[std::stoi(argv[8])];
-    PhoXiDevice->CapturingSettings->CameraOnlyMode = std::stoi(argv[9]);
-    PhoXiDevice->CapturingSettings->AmbientLightSuppression = std::stoi(argv[10]);
-    PhoXiDevice->CapturingSettings->CodingStrategy = argv[11];
-    PhoXiDevice->CapturingSettings->CodingQuality = argv[12];
-    PhoXiDevice->CapturingSettings->TextureSource = argv[13];
-    PhoXiDevice->CapturingSettings->SinglePatternExposure = std::stod(argv[14]);
-    PhoXiDevice->CapturingSettings->MaximumFPS = std::stod(argv[15]);
-    PhoXiDevice->CapturingSettings->LaserPower = std::stoi(argv[16]);
-    // Are the following unsupported or what?
-    //PhoxiDevice->CapturingSettings->ProjectionOffsetLeft = std::stoi(argv[17]);
-    // PhoxiDevice->CapturingSettings->ProjectionOffsetRight = std::stoi(argv[18]);
-    // PhoXiDevice->CapturingSettings->LedPower = std::stoi(argv[19]);
-    PhoXiDevice->ProcessingSettings->Confidence = std::stod(argv[20]);
-    PhoXiDevice->ProcessingSettings->SurfaceSmoothness = argv[21];
-    PhoXiDevice->ProcessingSettings->NormalsEstimationRadius = std::stoi(argv[22]);
-    // Are the following unsupported or what?
-    // PhoXiDevice->ProcessingSettings->InterReflectionsFilter = argv[23];
-    // PhoxiDevice->ExperimentalSettings->AmbientLightSuppressionCompatibilityMode = std::stoi(argv[24]);
-    // PhoxiDevice->ExperimentalSettings->PatternDecompositionReach = argv[25];
-    // PhoxiDevice->ExperimentalSettings->SignalContrastThreshold = std::stod(argv[26]);
-    // PhoxiDevice->ExperimentalSettings->UseExtendedLogging = std::stoi(argv[24]);
+    for (const auto &Mode : SupportedCapturingModes)
+    {
+        if (Mode.Resolution.Width == Request.ResolutionWidth && Mode.Resolution.Height == Request.ResolutionHeight)
+        {
+            PhoXiDevice->CapturingMode = Mode;
+            return;
+        }
+    }
+    throw std::runtime_error("Resolution " + std::to_string(Request.ResolutionWidth) + "x" +
+                             std::to_string(Request.ResolutionHeight) + " is not supported by the device");
+}
+
+std::string Capture::OutputFolder(const std::string &Requested, const std::string &Fallback) const
+{
+    const std::string &Folder = Requested.empty() ? Fallback : Requested;
+    return Folder.empty() ? std::string() : Folder + DELIMITER;
 }
 
-void Capture::CaptureAndSaveFrame(int argc, char *argv[])
+void Capture::CaptureAndSaveFrame()
 {
     if (!PhoXiDevice || !PhoXiDevice->isConnected())
     {
@@ -197,10 +223,10 @@ void Capture::CaptureAndSaveFrame(int argc, char *argv[])
         return;
     }
 
-    if (std::stoi(argv[3]) == 1)
+    if (Request.Praw)
     {
-        const auto prawsOutputFolder = PrawsOutputFolder.empty() ? std::string() : PrawsOutputFolder + DELIMITER;
-        const auto lastFramePraw = prawsOutputFolder + argv[2] + ".praw";
+        const auto prawsOutputFolder = OutputFolder(Request.PrawDir, PrawsOutputFolder);
+        const auto lastFramePraw = prawsOutputFolder + Request.SceneName + ".praw";
         if (PhoXiDevice->SaveLastOutput(lastFramePraw))
         {
             std::cout << "Saved frame as praw to: " << lastFramePraw << std::endl;
@@ -211,10 +237,10 @@ void Capture::CaptureAndSaveFrame(int argc, char *argv[])
         }
     }
 
-    if (std::stoi(argv[4]) == 1)
+    if (Request.Ply)
     {
-        const auto plysOutputFolder = PlysOutputFolder.empty() ? std::string() : PlysOutputFolder + DELIMITER;
-        const auto lastFramePly = plysOutputFolder + argv[2] + ".ply";
+        const auto plysOutputFolder = OutputFolder(Request.PlyDir, PlysOutputFolder);
+        const auto lastFramePly = plysOutputFolder + Request.SceneName + ".ply";
         std::cout << "Saving frame as ply'" << std::endl;
         if (LastFrame->SaveAsPly(lastFramePly, true, true))
         {
@@ -226,10 +252,10 @@ void Capture::CaptureAndSaveFrame(int argc, char *argv[])
         }
     }
     
-    if (std::stoi(argv[5]) == 1)
+    if (Request.Tif)
     {
-        const auto tifsOutputFolder = TifsOutputFolder.empty() ? std::string() : TifsOutputFolder + DELIMITER;
-        const auto lastFrameTif = tifsOutputFolder + argv[2] + ".tif";
+        const auto tifsOutputFolder = OutputFolder(Request.TifDir, TifsOutputFolder);
+        const auto lastFrameTif = tifsOutputFolder + Request.SceneName + ".tif";
         if (PhoXiDevice->SaveLastOutput(lastFrameTif))
         {
             std::cout << "Saved frame as tif to: " << lastFrameTif << std::endl;
@@ -245,15 +271,16 @@ void Capture::Run(int argc, char *argv[])
 {
     try
     {
-        ConnectPhoXiDeviceBySerial(argc, argv);
-        ChangeSettings(argc, argv);
-        CaptureAndSaveFrame(argc, argv);
+        Request = LoadScanRequest(argc, argv);
+        ConnectPhoXiDeviceBySerial();
+        ChangeSettings();
+        CaptureAndSaveFrame();
     }
     catch (std::runtime_error &InternalException)
     {
         std::cout << std::endl
                   << "Exception was thrown: " << InternalException.what() << std::endl;
-        if (PhoXiDevice->isConnected())
+        if (PhoXiDevice && PhoXiDevice->isConnected())
         {
             PhoXiDevice->Disconnect(true);
         }
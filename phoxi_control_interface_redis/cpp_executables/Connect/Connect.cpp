@@ -8,6 +8,7 @@
 #endif
 
 #include "PhoXi.h"
+#include "ScanRequest.h"
 
 #if defined(_WIN32)
     #define LOCAL_CROSS_SLEEP(Millis) Sleep(Millis)
@@ -22,8 +23,9 @@ class Connect
   private:
     pho::api::PhoXiFactory Factory;
     pho::api::PPhoXi PhoXiDevice;
+    ScanRequest Request;
 
-    void ConnectPhoXiDeviceBySerial(int argc, char* argv[]);
+    void ConnectPhoXiDeviceBySerial();
 
     template<class T>
     bool ReadLine(T &Output) const
@@ -45,10 +47,10 @@ class Connect
     void Run(int argc, char* argv[]);
 };
 
-void Connect::ConnectPhoXiDeviceBySerial(int argc, char* argv[])
+void Connect::ConnectPhoXiDeviceBySerial()
 {
     pho::api::PhoXiTimeout Timeout = pho::api::PhoXiTimeout::ZeroTimeout;
-    std::string hardware_identification = argv[1];
+    std::string hardware_identification = Request.HardwareIdentification;
     PhoXiDevice = Factory.CreateAndConnect(hardware_identification, Timeout);
     if (PhoXiDevice)
     {
@@ -64,12 +66,13 @@ void Connect::Run(int argc, char* argv[])
 {
     try
     {
-        ConnectPhoXiDeviceBySerial(argc, argv);
+        Request = LoadScanRequest(argc, argv);
+        ConnectPhoXiDeviceBySerial();
     }
     catch (std::runtime_error &InternalException)
     {
         std::cout << std::endl << "Exception was thrown: " << InternalException.what() << std::endl;
-        if (PhoXiDevice->isConnected())
+        if (PhoXiDevice && PhoXiDevice->isConnected())
         {
             PhoXiDevice->Disconnect(true);
         }
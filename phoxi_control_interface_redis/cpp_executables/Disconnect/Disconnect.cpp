@@ -0,0 +1,110 @@
+#include <string>
+#include <iostream>
+#include <sstream>
+#if defined(_WIN32)
+    #include <windows.h>
+#elif defined (__linux__)
+    #include <unistd.h>
+#endif
+
+#include "PhoXi.h"
+#include "ScanRequest.h"
+
+#if defined(_WIN32)
+    #define LOCAL_CROSS_SLEEP(Millis) Sleep(Millis)
+    #define DELIMITER "\\"
+#elif defined (__linux__) || defined(__APPLE__)
+    #define LOCAL_CROSS_SLEEP(Millis) usleep(Millis * 1000)
+    #define DELIMITER "/"
+#endif
+
+class Disconnect
+{
+  private:
+    pho::api::PhoXiFactory Factory;
+    pho::api::PPhoXi PhoXiDevice;
+    ScanRequest Request;
+
+    void ConnectPhoXiDeviceBySerial();
+	void DisconnectDevice();
+
+    template<class T>
+    bool ReadLine(T &Output) const
+    {
+        std::string Input;
+        std::getline(std::cin, Input);
+        std::stringstream InputSteam(Input);
+        return (InputSteam >> Output) ? true : false;
+    }
+    bool ReadLine(std::string &Output) const
+    {
+        std::getline(std::cin, Output);
+        return true;
+    }
+
+  public:
+	  Disconnect() {};
+	  ~Disconnect() {};
+    void Run(int argc, char* argv[]);
+};
+
+void Disconnect::ConnectPhoXiDeviceBySerial()
+{
+    pho::api::PhoXiTimeout Timeout = pho::api::PhoXiTimeout::ZeroTimeout;
+    std::string hardware_identification = Request.HardwareIdentification;
+    PhoXiDevice = Factory.CreateAndConnect(hardware_identification, Timeout);
+    if (PhoXiDevice)
+    {
+        std::cout << "Connection to the device " << hardware_identification << " was Successful!" << std::endl;
+    }
+    else
+    {
+        std::cout << "Connection to the device " << hardware_identification << " was Unsuccessful!" << std::endl;
+    }
+}
+
+// Stops a running acquisition and logs out of the device, so that it is
+// released in PhoXi Control until the next Connect.
+void Disconnect::DisconnectDevice()
+{
+	if (!PhoXiDevice || !PhoXiDevice->isConnected())
+	{
+		throw std::runtime_error("Device is not created, or not connected!");
+	}
+	if (PhoXiDevice->isAcquiring())
+	{
+		std::cout << "Stopping acquisition" << std::endl;
+		if (!PhoXiDevice->StopAcquisition())
+		{
+			throw std::runtime_error("Error in StopAcquistion");
+		}
+	}
+	PhoXiDevice->Disconnect(true);
+	std::cout << "Disconnected from the device " << Request.HardwareIdentification << std::endl;
+}
+
+void Disconnect::Run(int argc, char* argv[])
+{
+    try
+    {
+        Request = LoadScanRequest(argc, argv);
+        ConnectPhoXiDeviceBySerial();
+        DisconnectDevice();
+    }
+    catch (std::runtime_error &InternalException)
+    {
+        std::cout << std::endl << "Exception was thrown: " << InternalException.what() << std::endl;
+        if (PhoXiDevice && PhoXiDevice->isConnected())
+        {
+            PhoXiDevice->Disconnect(true);
+        }
+    }
+}
+
+int main(int argc, char *argv[])
+{
+    Disconnect Example;
+    Example.Run(argc, argv);
+    return 0;
+}
+
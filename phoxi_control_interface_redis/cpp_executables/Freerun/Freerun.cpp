@@ -8,6 +8,7 @@
 #endif
 
 #include "PhoXi.h"
+#include "ScanRequest.h"
 
 #if defined(_WIN32)
 #define LOCAL_CROSS_SLEEP(Millis) Sleep(Millis)
@@ -18,6 +19,8 @@
 #endif
 
 // ARGUMENTS:
+// Either a single --request=<path> pointing to a JSON request file
+// (see common/ScanRequest.h), or the legacy positional arguments:
 // 1 - hardware_identification
 // 2 - scene_name
 // 3 - praw
@@ -51,6 +54,7 @@ class Freerun
 private:
     pho::api::PhoXiFactory Factory;
     pho::api::PPhoXi PhoXiDevice;
+    ScanRequest Request;
     pho::api::PFrame LastFrame;
 
     std::string trueString = "true";
@@ -58,8 +62,9 @@ private:
 
     std::string FreerunOutputFolder = "C:\\Users\\photoneo\\Desktop\\scans\\freerun";
 
-    void ConnectPhoXiDeviceBySerial(int argc, char *argv[]);
-    void ChangeSettings(int argc, char* argv[]);
+    void ConnectPhoXiDeviceBySerial();
+    void ChangeSettings();
+    void SelectCapturingMode();
     void StartFreerun();
 
     template <class T>
@@ -82,10 +87,10 @@ public:
     void Run(int argc, char *argv[]);
 };
 
-void Freerun::ConnectPhoXiDeviceBySerial(int argc, char *argv[])
+void Freerun::ConnectPhoXiDeviceBySerial()
 {
     pho::api::PhoXiTimeout Timeout = pho::api::PhoXiTimeout::ZeroTimeout;
-    std::string hardware_identification = argv[1];
+    std::string hardware_identification = Request.HardwareIdentification;
     PhoXiDevice = Factory.CreateAndConnect(hardware_identification, Timeout);
     if (PhoXiDevice)
     {
@@ -97,37 +102,51 @@ void Freerun::ConnectPhoXiDeviceBySerial(int argc, char *argv[])
     }
 }
 
-void Freerun::ChangeSettings(int argc, char* argv[])
+void Freerun::ChangeSettings()
+{
+    PhoXiDevice->CapturingSettings->ShutterMultiplier = Request.ShutterMultiplier;
+    PhoXiDevice->CapturingSettings->ScanMultiplier = Request.ScanMultiplier;
+    SelectCapturingMode();
+    PhoXiDevice->CapturingSettings->CameraOnlyMode = Request.CameraOnlyMode;
+    PhoXiDevice->CapturingSettings->AmbientLightSuppression = Request.AmbientLightSuppression;
+    PhoXiDevice->CapturingSettings->CodingStrategy = Request.CodingStrategy;
+    PhoXiDevice->CapturingSettings->CodingQuality = Request.CodingQuality;
+    PhoXiDevice->CapturingSettings->TextureSource = Request.TextureSource;
+    PhoXiDevice->CapturingSettings->SinglePatternExposure = Request.SinglePatternExposure;
+    PhoXiDevice->CapturingSettings->MaximumFPS = Request.MaximumFPS;
+    PhoXiDevice->CapturingSettings->LaserPower = Request.LaserPower;
+    // Are the following unsupported or what?
+    //PhoxiDevice->CapturingSettings->ProjectionOffsetLeft = Request.ProjectionOffsetLeft;
+    // PhoxiDevice->CapturingSettings->ProjectionOffsetRight = Request.ProjectionOffsetRight;
+    // PhoXiDevice->CapturingSettings->LedPower = Request.LedPower;
+    PhoXiDevice->ProcessingSettings->Confidence = Request.MaxInaccuracy;
+    PhoXiDevice->ProcessingSettings->SurfaceSmoothness = Request.SurfaceSmoothness;
+    PhoXiDevice->ProcessingSettings->NormalsEstimationRadius = Request.NormalsEstimationRadius;
+    // Are the following unsupported or what?
+    // PhoXiDevice->ProcessingSettings->InterReflectionsFilter = Request.InterreflectionsFilter;
+    // PhoxiDevice->ExperimentalSettings->AmbientLightSuppressionCompatibilityMode = Request.AmbientLightSuppressionCompatibilityMode;
+    // PhoxiDevice->ExperimentalSettings->PatternDecompositionReach = Request.PatternDecompositionReach;
+    // PhoxiDevice->ExperimentalSettings->SignalContrastThreshold = Request.SignalContrastThreshold;
+    // PhoxiDevice->ExperimentalSettings->UseExtendedLogging = Request.UseExtendedLogging;
+}
+
+void Freerun::SelectCapturingMode()
 {
-    PhoXiDevice->CapturingSettings->ShutterMultiplier = std::stod(argv[6]);
-    PhoXiDevice->CapturingSettings->ScanMultiplier = std::stoi(argv[7]);
     std::vector<pho::api::PhoXiCapturingMode> SupportedCapturingModes = PhoXiDevice->SupportedCapturingModes;
     if (!PhoXiDevice->SupportedCapturingModes.isLastOperationSuccessful())
     {
         throw std::runtime_error(PhoXiDevice->SupportedCapturingModes.GetLastErrorMessage().c_str());
     }
-    PhoXiDevice->CapturingMode = SupportedCapturingModes[std::stoi(argv[8])];
-    PhoXiDevice->CapturingSettings->CameraOnlyMode = std::stoi(argv[9]);
-    PhoXiDevice->CapturingSettings->AmbientLightSuppression = std::stoi(argv[10]);
-    PhoXiDevice->CapturingSettings->CodingStrategy = argv[11];
-    PhoXiDevice->CapturingSettings->CodingQuality = argv[12];
-    PhoXiDevice->CapturingSettings->TextureSource = argv[13];
-    PhoXiDevice->CapturingSettings->SinglePatternExposure = std::stod(argv[14]);
-    PhoXiDevice->CapturingSettings->MaximumFPS = std::stod(argv[15]);
-    PhoXiDevice->CapturingSettings->LaserPower = std::stoi(argv[16]);
-    // Are the following unsupported or what?
-    //PhoxiDevice->CapturingSettings->ProjectionOffsetLeft = std::stoi(argv[17]);
-    // PhoxiDevice->CapturingSettings->ProjectionOffsetRight = std::stoi(argv[18]);
-    // PhoXiDevice->CapturingSettings->LedPower = std::stoi(argv[19]);
-    PhoXiDevice->ProcessingSettings->Confidence = std::stod(argv[20]);
-    PhoXiDevice->ProcessingSettings->SurfaceSmoothness = argv[21];
-    PhoXiDevice->ProcessingSettings->NormalsEstimationRadius = std::stoi(argv[22]);
-    // Are the following unsupported or what?
-    // PhoXiDevice->ProcessingSettings->InterReflectionsFilter = argv[23];
-    // PhoxiDevice->ExperimentalSettings->AmbientLightSuppressionCompatibilityMode = std::stoi(argv[24]);
-    // PhoxiDevice->ExperimentalSettings->PatternDecompositionReach = argv[25];
-    // PhoxiDevice->ExperimentalSettings->SignalContrastThreshold = std::stod(argv[26]);
-    // PhoxiDevice->ExperimentalSettings->UseExtendedLogging = std::stoi(argv[24]);
+    for (const auto &Mode : SupportedCapturingModes)
+    {
+        if (Mode.Resolution.Width == Request.ResolutionWidth && Mode.Resolution.Height == Request.ResolutionHeight)
+        {
+            PhoXiDevice->CapturingMode = Mode;
+            return;
+        }
+    }
+    throw std::runtime_error("Resolution " + std::to_string(Request.ResolutionWidth) + "x" +
+                             std::to_string(Request.ResolutionHeight) + " is not supported by the device");
 }
 
 void Freerun::StartFreerun()
@@ -203,15 +222,16 @@ void Freerun::Run(int argc, char *argv[])
 {
     try
     {
-        ConnectPhoXiDeviceBySerial(argc, argv);
-        ChangeSettings(argc, argv);
+        Request = LoadScanRequest(argc, argv);
+        ConnectPhoXiDeviceBySerial();
+        ChangeSettings();
         StartFreerun();
     }
     catch (std::runtime_error &InternalException)
     {
         std::cout << std::endl
                   << "Exception was thrown: " << InternalException.what() << std::endl;
-        if (PhoXiDevice->isConnected())
+        if (PhoXiDevice && PhoXiDevice->isConnected())
         {
             PhoXiDevice->Disconnect(true);
         }
@@ -8,6 +8,7 @@
 #endif
 
 #include "PhoXi.h"
+#include "ScanRequest.h"
 
 #if defined(_WIN32)
     #define LOCAL_CROSS_SLEEP(Millis) Sleep(Millis)
@@ -22,8 +23,9 @@ class Stop
   private:
     pho::api::PhoXiFactory Factory;
     pho::api::PPhoXi PhoXiDevice;
+    ScanRequest Request;
 
-    void ConnectPhoXiDeviceBySerial(int argc, char* argv[]);
+    void ConnectPhoXiDeviceBySerial();
 	void StopAcquisition();
 
     template<class T>
@@ -46,10 +48,10 @@ class Stop
     void Run(int argc, char* argv[]);
 };
 
-void Stop::ConnectPhoXiDeviceBySerial(int argc, char* argv[])
+void Stop::ConnectPhoXiDeviceBySerial()
 {
     pho::api::PhoXiTimeout Timeout = pho::api::PhoXiTimeout::ZeroTimeout;
-    std::string hardware_identification = argv[1];
+    std::string hardware_identification = Request.HardwareIdentification;
     PhoXiDevice = Factory.CreateAndConnect(hardware_identification, Timeout);
     if (PhoXiDevice)
     {
@@ -77,13 +79,14 @@ void Stop::Run(int argc, char* argv[])
 {
     try
     {
-        ConnectPhoXiDeviceBySerial(argc, argv);
+        Request = LoadScanRequest(argc, argv);
+        ConnectPhoXiDeviceBySerial();
         StopAcquisition();
     }
     catch (std::runtime_error &InternalException)
     {
         std::cout << std::endl << "Exception was thrown: " << InternalException.what() << std::endl;
-        if (PhoXiDevice->isConnected())
+        if (PhoXiDevice && PhoXiDevice->isConnected())
         {
             PhoXiDevice->Disconnect(true);
         }
@@ -0,0 +1,201 @@
+#pragma once
+
+#include <fstream>
+#include <stdexcept>
+#include <string>
+
+#include <nlohmann/json.hpp>
+
+// Bump together with REQUEST_SCHEMA_VERSION in src/core/request_file.rs
+// whenever the request document changes shape.
+#define SCAN_REQUEST_SCHEMA_VERSION 1
+
+// A scan request as handed over by the redis interface. It is read either
+// from a JSON request file (--request=<path>) or, for older interfaces,
+// from the positional arguments listed in the executables.
+struct ScanRequest
+{
+    std::string HardwareIdentification;
+    std::string IpIdentification;
+    std::string SceneName;
+
+    bool Praw = false;
+    bool Ply = false;
+    bool Tif = false;
+    std::string PrawDir;
+    std::string PlyDir;
+    std::string TifDir;
+
+    double ShutterMultiplier = 1;
+    int ScanMultiplier = 1;
+    int ResolutionWidth = 2064;
+    int ResolutionHeight = 1544;
+    bool CameraOnlyMode = false;
+    bool AmbientLightSuppression = false;
+    std::string CodingStrategy = "Interreflections";
+    std::string CodingQuality = "High";
+    std::string TextureSource = "LED";
+    double SinglePatternExposure = 10.24;
+    double MaximumFPS = 0;
+    int LaserPower = 4095;
+    int ProjectionOffsetLeft = 0;
+    int ProjectionOffsetRight = 0;
+    int LedPower = 4095;
+    double MaxInaccuracy = 2;
+    std::string SurfaceSmoothness = "Normal";
+    int NormalsEstimationRadius = 2;
+    bool InterreflectionsFilter = false;
+    bool AmbientLightSuppressionCompatibilityMode = false;
+    std::string PatternDecompositionReach = "Small";
+    double SignalContrastThreshold = 0.032;
+    bool UseExtendedLogging = false;
+};
+
+inline ScanRequest LoadScanRequestFile(const std::string &Path)
+{
+    std::ifstream File(Path);
+    if (!File)
+    {
+        throw std::runtime_error("Could not open request file " + Path);
+    }
+
+    nlohmann::json Document;
+    try
+    {
+        File >> Document;
+    }
+    catch (nlohmann::json::exception &Exception)
+    {
+        throw std::runtime_error("Request file " + Path + " is not valid JSON: " + Exception.what());
+    }
+
+    const int SchemaVersion = Document.value("schema_version", 0);
+    if (SchemaVersion != SCAN_REQUEST_SCHEMA_VERSION)
+    {
+        throw std::runtime_error("Request file schema version " + std::to_string(SchemaVersion) +
+                                 " is not supported, expected " +
+                                 std::to_string(SCAN_REQUEST_SCHEMA_VERSION));
+    }
+
+    ScanRequest Request;
+    try
+    {
+        const auto &Scanner = Document.at("scanner");
+        Request.HardwareIdentification = Scanner.at("hardware_identification").get<std::string>();
+        Request.IpIdentification = Scanner.at("ip_identification").get<std::string>();
+        Request.SceneName = Document.at("scene_name").get<std::string>();
+
+        const auto &Outputs = Document.at("outputs");
+        Request.Praw = Outputs.at("praw").get<bool>();
+        Request.Ply = Outputs.at("ply").get<bool>();
+        Request.Tif = Outputs.at("tif").get<bool>();
+
+        const auto &Directories = Document.at("directories");
+        Request.PrawDir = Directories.at("praw_dir").get<std::string>();
+        Request.PlyDir = Directories.at("ply_dir").get<std::string>();
+        Request.TifDir = Directories.at("tif_dir").get<std::string>();
+
+        const auto &Capturing = Document.at("settings").at("capturing_settings");
+        Request.ShutterMultiplier = Capturing.at("shutter_multiplier").get<double>();
+        Request.ScanMultiplier = Capturing.at("scan_multiplier").get<int>();
+        Request.ResolutionWidth = Capturing.at("resolution").at("width").get<int>();
+        Request.ResolutionHeight = Capturing.at("resolution").at("height").get<int>();
+        Request.CameraOnlyMode = Capturing.at("camera_only_mode").get<bool>();
+        Request.AmbientLightSuppression = Capturing.at("ambient_light_suppression").get<bool>();
+        Request.CodingStrategy = Capturing.at("coding_strategy").get<std::string>();
+        Request.CodingQuality = Capturing.at("coding_quality").get<std::string>();
+        Request.TextureSource = Capturing.at("texture_source").get<std::string>();
+        Request.SinglePatternExposure = Capturing.at("single_pattern_exposure").get<double>();
+        Request.MaximumFPS = Capturing.at("maximum_fps").get<double>();
+        Request.LaserPower = Capturing.at("laser_power").get<int>();
+        Request.ProjectionOffsetLeft = Capturing.at("projection_offset_left").get<int>();
+        Request.ProjectionOffsetRight = Capturing.at("projection_offset_right").get<int>();
+        Request.LedPower = Capturing.at("led_power").get<int>();
+
+        const auto &Processing = Document.at("settings").at("processing_settings");
+        Request.MaxInaccuracy = Processing.at("max_inaccuracy").get<double>();
+        Request.SurfaceSmoothness = Processing.at("surface_smoothness").get<std::string>();
+        Request.NormalsEstimationRadius = Processing.at("normals_estimation_radius").get<int>();
+        Request.InterreflectionsFilter = Processing.at("interreflections_filter").get<bool>();
+
+        const auto &Experimental = Document.at("settings").at("experimental_settings");
+        Request.AmbientLightSuppressionCompatibilityMode =
+            Experimental.at("ambient_light_suppression_compatibility_mode").get<bool>();
+        Request.PatternDecompositionReach = Experimental.at("pattern_decomposition_reach").get<std::string>();
+        Request.SignalContrastThreshold = Experimental.at("signal_contrast_threshold").get<double>();
+        Request.UseExtendedLogging = Experimental.at("use_extended_logging").get<bool>();
+    }
+    catch (nlohmann::json::exception &Exception)
+    {
+        throw std::runtime_error("Request file " + Path + " is incomplete: " + Exception.what());
+    }
+
+    return Request;
+}
+
+// Positional protocol, see the ARGUMENTS list in the executables.
+inline ScanRequest LoadLegacyScanRequest(int argc, char *argv[])
+{
+    if (argc < 2)
+    {
+        throw std::runtime_error("Missing hardware identification argument");
+    }
+
+    ScanRequest Request;
+    Request.HardwareIdentification = argv[1];
+    if (argc < 28)
+    {
+        // Connect and Stop only get the hardware identification.
+        return Request;
+    }
+
+    Request.SceneName = argv[2];
+    Request.Praw = std::stoi(argv[3]) == 1;
+    Request.Ply = std::stoi(argv[4]) == 1;
+    Request.Tif = std::stoi(argv[5]) == 1;
+    Request.ShutterMultiplier = std::stod(argv[6]);
+    Request.ScanMultiplier = std::stoi(argv[7]);
+    // "0" is 2064x1544 and "1" is 1032x772.
+    const bool HalfResolution = std::stoi(argv[8]) == 1;
+    Request.ResolutionWidth = HalfResolution ? 1032 : 2064;
+    Request.ResolutionHeight = HalfResolution ? 772 : 1544;
+    Request.CameraOnlyMode = std::stoi(argv[9]) == 1;
+    Request.AmbientLightSuppression = std::stoi(argv[10]) == 1;
+    Request.CodingStrategy = argv[11];
+    Request.CodingQuality = argv[12];
+    Request.TextureSource = argv[13];
+    Request.SinglePatternExposure = std::stod(argv[14]);
+    Request.MaximumFPS = std::stod(argv[15]);
+    Request.LaserPower = std::stoi(argv[16]);
+    Request.ProjectionOffsetLeft = std::stoi(argv[17]);
+    Request.ProjectionOffsetRight = std::stoi(argv[18]);
+    Request.LedPower = std::stoi(argv[19]);
+    Request.MaxInaccuracy = std::stod(argv[20]);
+    Request.SurfaceSmoothness = argv[21];
+    Request.NormalsEstimationRadius = std::stoi(argv[22]);
+    Request.InterreflectionsFilter = std::stoi(argv[23]) == 1;
+    Request.AmbientLightSuppressionCompatibilityMode = std::stoi(argv[24]) == 1;
+    Request.PatternDecompositionReach = argv[25];
+    Request.SignalContrastThreshold = std::stod(argv[26]);
+    Request.UseExtendedLogging = std::stoi(argv[27]) == 1;
+
+    if (argc >= 32)
+    {
+        Request.PrawDir = argv[28];
+        Request.PlyDir = argv[29];
+        Request.TifDir = argv[30];
+        Request.IpIdentification = argv[31];
+    }
+
+    return Request;
+}
+
+inline ScanRequest LoadScanRequest(int argc, char *argv[])
+{
+    const std::string RequestFlag = "--request=";
+    if (argc == 2 && std::string(argv[1]).rfind(RequestFlag, 0) == 0)
+    {
+        return LoadScanRequestFile(std::string(argv[1]).substr(RequestFlag.size()));
+    }
+    return LoadLegacyScanRequest(argc, argv);
+}
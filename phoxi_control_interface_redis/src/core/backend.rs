@@ -0,0 +1,353 @@
+use async_trait::async_trait;
+use std::io;
+use std::ops::Deref;
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tokio_util::sync::CancellationToken;
+
+use super::health::{probe_scanner, HealthConfig, ScannerHealth};
+use super::interface::call_exec;
+use super::request_file::RequestProtocol;
+use super::simulation::SimulatedBackend;
+use super::state::ScanRequest;
+
+// Everything the interface asks of a scanner. Every command returns the
+// lines the scanner reported, the first of which is published as
+// {id}_phoxi_raw_info. Commands give up with io::ErrorKind::Interrupted
+// once `cancel` is cancelled.
+#[async_trait]
+pub trait ScannerBackend: Send + Sync {
+    async fn connect(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error>;
+    async fn capture(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error>;
+    async fn freerun(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error>;
+    async fn stop(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error>;
+    async fn disconnect(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error>;
+
+    // Commands outside of the ones above, such as connectIPv4.
+    async fn other(
+        &self,
+        request: &ScanRequest,
+        _cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Unknown command type '{}'.", request.command_type),
+        ))
+    }
+
+    // Whether the scanner can be used, found out without sending it a
+    // command, see photoneo_health_interface.
+    async fn health(&self, ip_identification: &str, config: &HealthConfig) -> ScannerHealth {
+        probe_scanner(ip_identification, config).await
+    }
+
+    // Reserves the scanner for a request of more than one command, see
+    // reserve. Backends that do not take turns return None.
+    async fn take_turn(
+        &self,
+        _cancel: &CancellationToken,
+    ) -> Result<Option<ScannerSession>, io::Error> {
+        Ok(None)
+    }
+}
+
+// The scanner reserved for one request. Its retries, the reconnects before
+// them and the Stop after an abort run back to back, without a command of
+// another interface in between. The turn is given back on drop.
+pub struct ScannerSession {
+    backend: Arc<dyn ScannerBackend>,
+    _turn: Option<OwnedMutexGuard<()>>,
+}
+
+impl Deref for ScannerSession {
+    type Target = dyn ScannerBackend;
+
+    fn deref(&self) -> &Self::Target {
+        self.backend.as_ref()
+    }
+}
+
+// Waits for the turn of the backend, giving up when `cancel` is cancelled.
+// The commands of the request are sent to the returned session.
+pub async fn reserve(
+    backend: &Arc<dyn ScannerBackend>,
+    cancel: &CancellationToken,
+) -> Result<ScannerSession, io::Error> {
+    Ok(match backend.take_turn(cancel).await? {
+        Some(session) => session,
+        None => ScannerSession {
+            backend: backend.clone(),
+            _turn: None,
+        },
+    })
+}
+
+// Calls the backend method for the command_type of the request.
+pub async fn execute(
+    backend: &dyn ScannerBackend,
+    request: &ScanRequest,
+    cancel: &CancellationToken,
+) -> Result<Vec<String>, io::Error> {
+    match request.command_type.to_lowercase().as_str() {
+        "connect" => backend.connect(request, cancel).await,
+        "capture" => backend.capture(request, cancel).await,
+        "freerun" => backend.freerun(request, cancel).await,
+        "stop" => backend.stop(request, cancel).await,
+        "disconnect" => backend.disconnect(request, cancel).await,
+        _ => backend.other(request, cancel).await,
+    }
+}
+
+// PHOXI_BACKEND selects the backend, "subprocess" (the default) runs the
+// C++ executables, "simulated" runs without hardware, see SimulatedBackend.
+pub fn backend_from_env(
+    photoneo_id: &str,
+    phoxi_interface_path: &str,
+    request_protocol: RequestProtocol,
+) -> Arc<dyn ScannerBackend> {
+    let subprocess = || -> Arc<dyn ScannerBackend> {
+        Arc::new(SubprocessBackend::new(
+            photoneo_id,
+            phoxi_interface_path,
+            request_protocol,
+        ))
+    };
+    match std::env::var("PHOXI_BACKEND") {
+        Ok(backend) if backend.eq_ignore_ascii_case("simulated") => {
+            Arc::new(SimulatedBackend::load(phoxi_interface_path, photoneo_id))
+        }
+        Ok(backend) if backend.eq_ignore_ascii_case("subprocess") => subprocess(),
+        Ok(backend) => {
+            log::warn!(target: "phoxi_control_interface",
+                "Unknown PHOXI_BACKEND '{}', using 'subprocess'.", backend);
+            subprocess()
+        }
+        Err(_) => subprocess(),
+    }
+}
+
+// Runs cpp_executables/dev/{Command}/{Command}_Release for every command.
+// These only work with a PhoXi device and PhoXi Control installed.
+#[derive(Debug, Clone)]
+pub struct SubprocessBackend {
+    photoneo_id: String,
+    phoxi_interface_path: String,
+    request_protocol: RequestProtocol,
+}
+
+impl SubprocessBackend {
+    pub fn new(
+        photoneo_id: &str,
+        phoxi_interface_path: &str,
+        request_protocol: RequestProtocol,
+    ) -> SubprocessBackend {
+        SubprocessBackend {
+            photoneo_id: photoneo_id.to_string(),
+            phoxi_interface_path: phoxi_interface_path.to_string(),
+            request_protocol,
+        }
+    }
+
+    async fn run(
+        &self,
+        request: &ScanRequest,
+        command: &str,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        call_exec(
+            request,
+            command,
+            &self.phoxi_interface_path,
+            &self.photoneo_id,
+            self.request_protocol,
+            cancel,
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl ScannerBackend for SubprocessBackend {
+    async fn connect(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.run(request, "Connect", cancel).await
+    }
+
+    async fn capture(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.run(request, "Capture", cancel).await
+    }
+
+    async fn freerun(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.run(request, "Freerun", cancel).await
+    }
+
+    async fn stop(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.run(request, "Stop", cancel).await
+    }
+
+    async fn disconnect(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.run(request, "Disconnect", cancel).await
+    }
+
+    // Any other executable in cpp_executables/dev, named after the command.
+    async fn other(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.run(request, &capitalize_first(&request.command_type), cancel)
+            .await
+    }
+}
+
+fn capitalize_first(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        None => String::new(),
+        Some(f) => f.to_uppercase().collect::<String>() + chars.as_str(),
+    }
+}
+
+// Runs one command at a time on a scanner that more than one interface
+// talks to, such as the control and the calibration interface. A command
+// waiting for its turn gives up when it is cancelled. A request of several
+// commands holds the turn for all of them, see reserve.
+pub struct SerializedBackend {
+    backend: Arc<dyn ScannerBackend>,
+    turn: Arc<Mutex<()>>,
+}
+
+impl SerializedBackend {
+    pub fn new(backend: Arc<dyn ScannerBackend>) -> SerializedBackend {
+        SerializedBackend {
+            backend,
+            turn: Arc::new(Mutex::new(())),
+        }
+    }
+
+    async fn wait_for_turn(
+        &self,
+        cancel: &CancellationToken,
+    ) -> Result<OwnedMutexGuard<()>, io::Error> {
+        tokio::select! {
+            turn = self.turn.clone().lock_owned() => Ok(turn),
+            _ = cancel.cancelled() => Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "Command execution was cancelled.",
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl ScannerBackend for SerializedBackend {
+    async fn connect(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        let _turn = self.wait_for_turn(cancel).await?;
+        self.backend.connect(request, cancel).await
+    }
+
+    async fn capture(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        let _turn = self.wait_for_turn(cancel).await?;
+        self.backend.capture(request, cancel).await
+    }
+
+    async fn freerun(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        let _turn = self.wait_for_turn(cancel).await?;
+        self.backend.freerun(request, cancel).await
+    }
+
+    async fn stop(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        let _turn = self.wait_for_turn(cancel).await?;
+        self.backend.stop(request, cancel).await
+    }
+
+    async fn disconnect(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        let _turn = self.wait_for_turn(cancel).await?;
+        self.backend.disconnect(request, cancel).await
+    }
+
+    async fn other(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        let _turn = self.wait_for_turn(cancel).await?;
+        self.backend.other(request, cancel).await
+    }
+
+    async fn health(&self, ip_identification: &str, config: &HealthConfig) -> ScannerHealth {
+        self.backend.health(ip_identification, config).await
+    }
+
+    // The session talks to the wrapped backend directly, the turn is
+    // already taken.
+    async fn take_turn(
+        &self,
+        cancel: &CancellationToken,
+    ) -> Result<Option<ScannerSession>, io::Error> {
+        let turn = self.wait_for_turn(cancel).await?;
+        Ok(Some(ScannerSession {
+            backend: self.backend.clone(),
+            _turn: Some(turn),
+        }))
+    }
+}
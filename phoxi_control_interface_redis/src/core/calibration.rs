@@ -0,0 +1,467 @@
+use micro_sp::*;
+use nalgebra::{Isometry3, Quaternion, Translation3, UnitQuaternion};
+use ordered_float::OrderedFloat;
+use serde_json::{json, Map};
+use tokio::time::{interval, Duration};
+
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio_util::sync::CancellationToken;
+
+use super::hand_eye::{self, CalibrationSample, HandEyeMode, HandEyeSolution};
+use super::interface::DEFAULT_SCAN_TIMEOUT_MS;
+use super::backend::ScannerBackend;
+use super::parameters::{
+    load_shared_parameters, save_shared_parameters, ExtrinsicParameters, RotationParameters,
+    TranslationParameters,
+};
+use super::state::ScanRequest;
+
+// Hand-eye calibration on top of the capture path. A sample is taken in
+// two steps, since the calibration target is found in the scan by the
+// localization interface:
+//
+//   capture_sample: reads {id}_calibration_flange_pose and captures the
+//                   scene published in {id}_calibration_scene_name.
+//   observe_sample: reads {id}_calibration_target_pose, the pose of the
+//                   target in that scene, and completes the sample.
+//   solve:          solves AX = XB for {id}_calibration_mode, publishes
+//                   {id}_camera_extrinsic and saves the extrinsic and
+//                   the residuals in the shared parameters, see
+//                   photoneo_common::parameters.
+//   reset:          drops all samples.
+//
+// Poses are in meters. Scans triggered on the same scanner from the
+// control interface while a sample is being captured wait for it when
+// the two share a SerializedBackend, as they do in the interface binary.
+pub async fn photoneo_calibration_interface(
+    photoneo_id: &str,
+    phoxi_scans_path: &str,
+    backend: &Arc<dyn ScannerBackend>,
+    connection_manager: &Arc<ConnectionManager>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut interval = interval(Duration::from_millis(250));
+    let log_target = "phoxi_calibration_interface";
+    log::info!(target: log_target, "Online.");
+
+    let keys: Vec<String> = vec![
+        &format!("{}_calibration_trigger", photoneo_id),
+        &format!("{}_calibration_state", photoneo_id),
+        &format!("{}_calibration_command", photoneo_id),
+        &format!("{}_calibration_mode", photoneo_id),
+        &format!("{}_calibration_flange_pose", photoneo_id),
+        &format!("{}_calibration_target_pose", photoneo_id),
+        &format!("{}_calibration_scene_name", photoneo_id),
+        &format!("{}_calibration_sample_count", photoneo_id),
+        &format!("{}_calibration_rotation_error", photoneo_id),
+        &format!("{}_calibration_translation_error", photoneo_id),
+        &format!("{}_calibration_info", photoneo_id),
+        &format!("{}_camera_extrinsic", photoneo_id),
+        &format!("{}_name_identification", photoneo_id),
+        &format!("{}_hardware_identification", photoneo_id),
+        &format!("{}_ip_identification", photoneo_id),
+        &format!("{}_timeout", photoneo_id),
+        &format!("{}_settings", photoneo_id),
+    ]
+    .iter()
+    .map(|k| k.to_string())
+    .collect();
+
+    let mut session = CalibrationSession::default();
+
+    let mut con = connection_manager.get_connection().await;
+    let mut reconnect = false;
+    loop {
+        interval.tick().await;
+        if connection_manager.check_redis_health(log_target).await.is_err() {
+            reconnect = true;
+            continue;
+        }
+        if reconnect {
+            con = connection_manager.get_connection().await;
+            reconnect = false;
+        }
+        let state = match StateManager::get_state_for_keys(&mut con, &keys).await {
+            Some(s) => s,
+            None => continue,
+        };
+
+        let mut calibration_trigger = state.get_bool_or_default_to_false(
+            &format!("{}_calibration_trigger", photoneo_id),
+            log_target,
+        );
+
+        let mut calibration_state = state.get_string_or_default_to_unknown(
+            &format!("{}_calibration_state", photoneo_id),
+            log_target,
+        );
+
+        if calibration_trigger {
+            calibration_trigger = false;
+            if calibration_state == ServiceRequestState::Initial.to_string() {
+                let command = state.get_string_or_default_to_unknown(
+                    &format!("{}_calibration_command", photoneo_id),
+                    log_target,
+                );
+
+                let mut new_state = state.clone();
+                let result = match command.as_str() {
+                    "capture_sample" => {
+                        let scene_name = format!("calibration_{}", session.samples.len());
+                        new_state = new_state.update(
+                            &format!("{photoneo_id}_calibration_scene_name"),
+                            scene_name.to_spvalue(),
+                        );
+                        session.capture_sample(
+                            &state,
+                            &scene_name,
+                            photoneo_id,
+                            phoxi_scans_path,
+                            backend.as_ref(),
+                        )
+                        .await
+                    }
+                    "observe_sample" => session.observe_sample(&state, photoneo_id),
+                    "solve" => session.solve(&state, photoneo_id).and_then(|extrinsic| {
+                        new_state = new_state
+                            .update(
+                                &format!("{photoneo_id}_camera_extrinsic"),
+                                SPValue::Transform(TransformOrUnknown::Transform(
+                                    extrinsic.clone(),
+                                )),
+                            )
+                            .update(
+                                &format!("{photoneo_id}_calibration_rotation_error"),
+                                session.rotation_error().to_spvalue(),
+                            )
+                            .update(
+                                &format!("{photoneo_id}_calibration_translation_error"),
+                                session.translation_error().to_spvalue(),
+                            );
+                        save_calibration(phoxi_scans_path, photoneo_id, &extrinsic, &session)
+                    }),
+                    "reset" => {
+                        session = CalibrationSession::default();
+                        Ok(format!("Dropped all calibration samples of {photoneo_id}."))
+                    }
+                    other => Err(format!("Unknown calibration command '{}'.", other)),
+                };
+
+                let calibration_info = match result {
+                    Ok(info) => {
+                        log::info!(target: log_target, "{}", info);
+                        calibration_state = ServiceRequestState::Succeeded.to_string();
+                        info
+                    }
+                    Err(e) => {
+                        log::error!(target: log_target, "Calibration failed with error: {}", e);
+                        calibration_state = ServiceRequestState::Failed.to_string();
+                        e
+                    }
+                };
+
+                let new_state = new_state
+                    .update(
+                        &format!("{photoneo_id}_calibration_trigger"),
+                        calibration_trigger.to_spvalue(),
+                    )
+                    .update(
+                        &format!("{photoneo_id}_calibration_state"),
+                        calibration_state.to_spvalue(),
+                    )
+                    .update(
+                        &format!("{photoneo_id}_calibration_sample_count"),
+                        (session.samples.len() as i64).to_spvalue(),
+                    )
+                    .update(
+                        &format!("{photoneo_id}_calibration_info"),
+                        calibration_info.to_spvalue(),
+                    );
+
+                let modified_state = state.get_diff_partial_state(&new_state);
+                StateManager::set_state(&mut con, &modified_state).await;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct CalibrationSession {
+    samples: Vec<CalibrationSample>,
+    // Flange pose of a captured scene whose observation is still missing.
+    pending: Option<SPTransformStamped>,
+    // Frames of the last sample, the extrinsic is published between them.
+    flange: Option<SPTransformStamped>,
+    camera_frame_id: Option<String>,
+    solution: Option<(HandEyeMode, HandEyeSolution)>,
+}
+
+impl CalibrationSession {
+    async fn capture_sample(
+        &mut self,
+        state: &State,
+        scene_name: &str,
+        photoneo_id: &str,
+        phoxi_scans_path: &str,
+        backend: &dyn ScannerBackend,
+    ) -> Result<String, String> {
+        let flange = read_transform(state, &format!("{photoneo_id}_calibration_flange_pose"))?;
+
+        let scan_request = ScanRequest {
+            name_identification: state.get_string_or_default_to_unknown(
+                &format!("{}_name_identification", photoneo_id),
+                "phoxi_calibration_interface",
+            ),
+            hardware_identification: state.get_string_or_default_to_unknown(
+                &format!("{}_hardware_identification", photoneo_id),
+                "phoxi_calibration_interface",
+            ),
+            ip_identification: state.get_string_or_default_to_unknown(
+                &format!("{}_ip_identification", photoneo_id),
+                "phoxi_calibration_interface",
+            ),
+            command_type: "capture".to_string(),
+            scene_name: scene_name.to_string(),
+            praw: true,
+            ply: true,
+            tif: false,
+            praw_dir: format!("{phoxi_scans_path}/praw"),
+            ply_dir: format!("{phoxi_scans_path}/ply"),
+            tif_dir: format!("{phoxi_scans_path}/tif"),
+            timeout: match state.get_int_or_unknown(
+                &format!("{}_timeout", photoneo_id),
+                "phoxi_calibration_interface",
+            ) {
+                IntOrUnknown::UNKNOWN => DEFAULT_SCAN_TIMEOUT_MS as i64,
+                IntOrUnknown::Int64(int) => int,
+            },
+            settings: match state.get_string_or_unknown(
+                &format!("{}_settings", photoneo_id),
+                "phoxi_calibration_interface",
+            ) {
+                StringOrUnknown::UNKNOWN => "default".to_string(),
+                StringOrUnknown::String(val) => val,
+            },
+        };
+
+        backend
+            .capture(&scan_request, &CancellationToken::new())
+            .await
+            .map_err(|e| format!("Capturing '{}' failed with: {}", scene_name, e))?;
+
+        if self.pending.replace(flange).is_some() {
+            log::warn!(target: "phoxi_calibration_interface",
+                "Replacing the previous capture, which was never observed.");
+        }
+        Ok(format!(
+            "Captured '{}', localize the calibration target in it and observe the sample.",
+            scene_name
+        ))
+    }
+
+    fn observe_sample(&mut self, state: &State, photoneo_id: &str) -> Result<String, String> {
+        let target = read_transform(state, &format!("{photoneo_id}_calibration_target_pose"))?;
+        let flange = self
+            .pending
+            .take()
+            .ok_or("There is no captured sample to observe.".to_string())?;
+
+        match &self.camera_frame_id {
+            Some(frame) if *frame != target.parent_frame_id => {
+                self.pending = Some(flange);
+                return Err(format!(
+                    "The target is observed in '{}', earlier samples in '{}'.",
+                    target.parent_frame_id, frame
+                ));
+            }
+            _ => self.camera_frame_id = Some(target.parent_frame_id.clone()),
+        }
+
+        self.samples.push(CalibrationSample {
+            flange: to_isometry(&flange.transform),
+            target: to_isometry(&target.transform),
+        });
+        self.flange = Some(flange);
+        self.solution = None;
+        Ok(format!("Added calibration sample {}.", self.samples.len()))
+    }
+
+    fn solve(&mut self, state: &State, photoneo_id: &str) -> Result<SPTransformStamped, String> {
+        let mode_name = state.get_string_or_default_to_unknown(
+            &format!("{}_calibration_mode", photoneo_id),
+            "phoxi_calibration_interface",
+        );
+        let mode = HandEyeMode::from_request(&mode_name)
+            .ok_or(format!("Unknown calibration mode '{}'.", mode_name))?;
+        let solution = hand_eye::solve(mode, &self.samples).map_err(|e| e.to_string())?;
+
+        // Eye-in-hand the camera hangs on the flange, eye-to-hand it is
+        // fixed in the frame the flange poses are expressed in.
+        let flange = self.flange.as_ref().ok_or("No samples.".to_string())?;
+        let parent_frame_id = match mode {
+            HandEyeMode::EyeInHand => flange.child_frame_id.clone(),
+            HandEyeMode::EyeToHand => flange.parent_frame_id.clone(),
+        };
+        let child_frame_id = self.camera_frame_id.clone().unwrap_or_default();
+
+        self.solution = Some((mode, solution));
+        Ok(SPTransformStamped {
+            active_transform: true,
+            enable_transform: true,
+            time_stamp: SystemTime::now(),
+            parent_frame_id,
+            child_frame_id,
+            transform: from_isometry(&solution.extrinsic),
+            metadata: MapOrUnknown::UNKNOWN,
+        })
+    }
+
+    fn rotation_error(&self) -> f64 {
+        self.solution
+            .map(|(_, s)| s.residuals.rotation_rms_deg)
+            .unwrap_or_default()
+    }
+
+    fn translation_error(&self) -> f64 {
+        self.solution
+            .map(|(_, s)| s.residuals.translation_rms)
+            .unwrap_or_default()
+    }
+}
+
+// Writes "extrinsic" and "calibration" into the shared parameters, where
+// the localization interface reads it and the control interface publishes
+// it again when it starts, see saved_extrinsic.
+fn save_calibration(
+    phoxi_scans_path: &str,
+    photoneo_id: &str,
+    extrinsic: &SPTransformStamped,
+    session: &CalibrationSession,
+) -> Result<String, String> {
+    let (mode, solution) = session.solution.ok_or("Nothing was solved.".to_string())?;
+    let t = &extrinsic.transform.translation;
+    let r = &extrinsic.transform.rotation;
+    let residuals = &solution.residuals;
+
+    let saved = ExtrinsicParameters {
+        parent_frame_id: extrinsic.parent_frame_id.clone(),
+        child_frame_id: extrinsic.child_frame_id.clone(),
+        translation: TranslationParameters {
+            x: t.x.into_inner(),
+            y: t.y.into_inner(),
+            z: t.z.into_inner(),
+        },
+        rotation: RotationParameters {
+            x: r.x.into_inner(),
+            y: r.y.into_inner(),
+            z: r.z.into_inner(),
+            w: r.w.into_inner(),
+        },
+    };
+    let entries = Map::from_iter([
+        ("extrinsic".to_string(), saved.to_value()),
+        (
+            "calibration".to_string(),
+            json!({
+                "mode": mode.to_string(),
+                "samples": session.samples.len(),
+                "motions": residuals.motions,
+                "rotation_rms_deg": residuals.rotation_rms_deg,
+                "rotation_max_deg": residuals.rotation_max_deg,
+                "translation_rms": residuals.translation_rms,
+                "translation_max": residuals.translation_max
+            }),
+        ),
+    ]);
+    let parameters_path = save_shared_parameters(
+        phoxi_scans_path,
+        photoneo_id,
+        entries,
+        "phoxi_calibration_interface",
+    )?;
+
+    Ok(format!(
+        "Calibrated {} from {} samples, rotation error {:.3} deg, translation error {:.4} m. Saved to {}.",
+        mode,
+        session.samples.len(),
+        residuals.rotation_rms_deg,
+        residuals.translation_rms,
+        parameters_path
+    ))
+}
+
+// The extrinsic of the last calibration, published as {id}_camera_extrinsic
+// when the interface starts so a restart does not lose it. Unknown if the
+// scanner was never calibrated.
+pub fn saved_extrinsic(phoxi_scans_path: &str, photoneo_id: &str) -> TransformOrUnknown {
+    let parameters =
+        load_shared_parameters(phoxi_scans_path, photoneo_id, "phoxi_calibration_interface");
+    match ExtrinsicParameters::from_parameters(&parameters) {
+        Some(Ok(saved)) => TransformOrUnknown::Transform(SPTransformStamped {
+            active_transform: true,
+            enable_transform: true,
+            time_stamp: SystemTime::now(),
+            parent_frame_id: saved.parent_frame_id,
+            child_frame_id: saved.child_frame_id,
+            transform: SPTransform {
+                translation: SPTranslation {
+                    x: OrderedFloat(saved.translation.x),
+                    y: OrderedFloat(saved.translation.y),
+                    z: OrderedFloat(saved.translation.z),
+                },
+                rotation: SPRotation {
+                    x: OrderedFloat(saved.rotation.x),
+                    y: OrderedFloat(saved.rotation.y),
+                    z: OrderedFloat(saved.rotation.z),
+                    w: OrderedFloat(saved.rotation.w),
+                },
+            },
+            metadata: MapOrUnknown::UNKNOWN,
+        }),
+        Some(Err(e)) => {
+            log::warn!(target: "phoxi_calibration_interface",
+                "Ignoring the invalid saved extrinsic of {}: {}", photoneo_id, e);
+            TransformOrUnknown::UNKNOWN
+        }
+        None => TransformOrUnknown::UNKNOWN,
+    }
+}
+
+fn read_transform(state: &State, key: &str) -> Result<SPTransformStamped, String> {
+    match state.get_value(key, "phoxi_calibration_interface") {
+        Some(SPValue::Transform(TransformOrUnknown::Transform(transform))) => Ok(transform),
+        _ => Err(format!("{} is not set.", key)),
+    }
+}
+
+fn to_isometry(transform: &SPTransform) -> Isometry3<f64> {
+    let t = &transform.translation;
+    let r = &transform.rotation;
+    Isometry3::from_parts(
+        Translation3::new(t.x.into_inner(), t.y.into_inner(), t.z.into_inner()),
+        UnitQuaternion::from_quaternion(Quaternion::new(
+            r.w.into_inner(),
+            r.x.into_inner(),
+            r.y.into_inner(),
+            r.z.into_inner(),
+        )),
+    )
+}
+
+fn from_isometry(pose: &Isometry3<f64>) -> SPTransform {
+    let t = &pose.translation.vector;
+    let r = pose.rotation.quaternion();
+    SPTransform {
+        translation: SPTranslation {
+            x: OrderedFloat(t.x),
+            y: OrderedFloat(t.y),
+            z: OrderedFloat(t.z),
+        },
+        rotation: SPRotation {
+            x: OrderedFloat(r.i),
+            y: OrderedFloat(r.j),
+            z: OrderedFloat(r.k),
+            w: OrderedFloat(r.w),
+        },
+    }
+}
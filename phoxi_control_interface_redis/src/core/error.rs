@@ -0,0 +1,124 @@
+use std::fmt;
+use std::io;
+
+use super::executor::command_failure;
+
+pub use photoneo_common::error::NO_ERROR;
+
+// Why a scanner request failed, published as {id}_error_code next to
+// {id}_error_recoverable so that planners can branch on it. The codes are
+// part of the interface, do not rename them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    DeviceNotFound,
+    ConnectionRefused,
+    NotInSoftwareTriggerMode,
+    TriggerFailed,
+    FrameTimeout,
+    SaveFailed,
+    SettingsInvalid,
+    // The saved scan fell below the thresholds of the settings preset.
+    PoorScanQuality,
+    Cancelled,
+    Unknown,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::DeviceNotFound => "device_not_found",
+            ErrorCode::ConnectionRefused => "connection_refused",
+            ErrorCode::NotInSoftwareTriggerMode => "not_in_software_trigger_mode",
+            ErrorCode::TriggerFailed => "trigger_failed",
+            ErrorCode::FrameTimeout => "frame_timeout",
+            ErrorCode::SaveFailed => "save_failed",
+            ErrorCode::SettingsInvalid => "settings_invalid",
+            ErrorCode::PoorScanQuality => "poor_scan_quality",
+            ErrorCode::Cancelled => "cancelled",
+            ErrorCode::Unknown => "unknown",
+        }
+    }
+
+    // Whether the same request can succeed when it is sent again, possibly
+    // after a reconnect. The others need someone to look at the setup.
+    pub fn is_recoverable(&self) -> bool {
+        match self {
+            ErrorCode::ConnectionRefused
+            | ErrorCode::NotInSoftwareTriggerMode
+            | ErrorCode::TriggerFailed
+            | ErrorCode::FrameTimeout
+            | ErrorCode::Cancelled => true,
+            ErrorCode::DeviceNotFound
+            | ErrorCode::SaveFailed
+            | ErrorCode::SettingsInvalid
+            | ErrorCode::PoorScanQuality
+            | ErrorCode::Unknown => false,
+        }
+    }
+
+    // The executables print what went wrong and mostly exit with 0, so the
+    // output of a failed command is searched for the first error reported
+    // after the last step that worked, later errors follow from it. What
+    // the error itself says is the fallback.
+    pub fn classify(error: &io::Error) -> ErrorCode {
+        if let Some(failure) = command_failure(error) {
+            let lines: Vec<&String> = failure.stdout.iter().chain(&failure.stderr).collect();
+            let start = lines
+                .iter()
+                .rposition(|line| is_progress(line))
+                .map_or(0, |last| last + 1);
+            if let Some(code) = lines[start..]
+                .iter()
+                .find_map(|line| ErrorCode::from_line(line))
+            {
+                return code;
+            }
+        }
+        match error.kind() {
+            io::ErrorKind::NotConnected | io::ErrorKind::ConnectionRefused => {
+                ErrorCode::ConnectionRefused
+            }
+            io::ErrorKind::TimedOut => ErrorCode::FrameTimeout,
+            io::ErrorKind::Interrupted => ErrorCode::Cancelled,
+            io::ErrorKind::InvalidInput => ErrorCode::SettingsInvalid,
+            _ => ErrorCode::Unknown,
+        }
+    }
+
+    // See the std::cout lines in cpp_executables.
+    fn from_line(line: &str) -> Option<ErrorCode> {
+        if line.contains("Device is not created, or not connected!") {
+            Some(ErrorCode::DeviceNotFound)
+        } else if line.contains("was Unsuccessful!") && line.contains("Connection to the device") {
+            Some(ErrorCode::ConnectionRefused)
+        } else if line.contains("Device is not in Software trigger mode") {
+            Some(ErrorCode::NotInSoftwareTriggerMode)
+        } else if line.contains("Trigger was unsuccessful!")
+            || line.contains("Device is not acquiring")
+        {
+            Some(ErrorCode::TriggerFailed)
+        } else if line.contains("Failed to retrieve the frame!")
+            || line.contains("Frame does not exist, or has no content!")
+        {
+            Some(ErrorCode::FrameTimeout)
+        } else if line.contains("Could not save frame") {
+            Some(ErrorCode::SaveFailed)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Lines printed once a step worked, errors before them are dealt with.
+fn is_progress(line: &str) -> bool {
+    line.contains("was Successful!")
+        || line.contains("Switching to Software trigger mode")
+        || line.contains("Frame was triggered")
+        || line.contains("Saved frame as")
+}
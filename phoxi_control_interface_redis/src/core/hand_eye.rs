@@ -0,0 +1,230 @@
+use nalgebra::{DMatrix, DVector, Isometry3, Matrix3, Rotation3, Translation3, UnitQuaternion, Vector3};
+use std::fmt;
+
+// Hand-eye calibration, solving AX = XB for the camera extrinsic X from
+// pairs of robot flange poses and camera observations of a calibration
+// target. The rotation is found as in Park and Martin, by aligning the
+// rotation axes of the relative motions, and the translation by linear
+// least squares given that rotation.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HandEyeMode {
+    // Camera on the flange, X is the camera pose in the flange frame.
+    EyeInHand,
+    // Camera fixed in the cell, X is the camera pose in the robot base frame.
+    EyeToHand,
+}
+
+impl HandEyeMode {
+    pub fn from_request(mode: &str) -> Option<HandEyeMode> {
+        match mode.to_lowercase().as_str() {
+            "eye_in_hand" => Some(HandEyeMode::EyeInHand),
+            "eye_to_hand" => Some(HandEyeMode::EyeToHand),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for HandEyeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandEyeMode::EyeInHand => write!(f, "eye_in_hand"),
+            HandEyeMode::EyeToHand => write!(f, "eye_to_hand"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CalibrationSample {
+    // Flange pose in the robot base frame.
+    pub flange: Isometry3<f64>,
+    // Calibration target pose in the camera frame.
+    pub target: Isometry3<f64>,
+}
+
+// Errors of AX = XB over all motion pairs, evaluated with the solution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Residuals {
+    pub motions: usize,
+    pub rotation_rms_deg: f64,
+    pub rotation_max_deg: f64,
+    pub translation_rms: f64,
+    pub translation_max: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HandEyeSolution {
+    pub extrinsic: Isometry3<f64>,
+    pub residuals: Residuals,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalibrationError {
+    TooFewSamples { required: usize, given: usize },
+    Degenerate(String),
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalibrationError::TooFewSamples { required, given } => write!(
+                f,
+                "Hand-eye calibration needs at least {} samples, got {}.",
+                required, given
+            ),
+            CalibrationError::Degenerate(reason) => {
+                write!(f, "Hand-eye calibration is degenerate: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CalibrationError {}
+
+pub const MINIMUM_SAMPLES: usize = 3;
+
+// Motions with a smaller rotation carry no information about the rotation axis.
+const MINIMUM_MOTION_ANGLE: f64 = 1e-6;
+
+pub fn solve(
+    mode: HandEyeMode,
+    samples: &[CalibrationSample],
+) -> Result<HandEyeSolution, CalibrationError> {
+    if samples.len() < MINIMUM_SAMPLES {
+        return Err(CalibrationError::TooFewSamples {
+            required: MINIMUM_SAMPLES,
+            given: samples.len(),
+        });
+    }
+
+    let motions = relative_motions(mode, samples);
+    let rotation = solve_rotation(&motions)?;
+    let translation = solve_translation(&motions, &rotation)?;
+    let extrinsic = Isometry3::from_parts(
+        Translation3::from(translation),
+        UnitQuaternion::from_rotation_matrix(&rotation),
+    );
+
+    Ok(HandEyeSolution {
+        extrinsic,
+        residuals: residuals(&motions, &extrinsic),
+    })
+}
+
+// Every pair of samples gives one motion (A, B) with AX = XB.
+//
+// Eye-in-hand, the target is fixed in the base frame:
+//     F_i X C_i = F_j X C_j  =>  (F_i^-1 F_j) X = X (C_i C_j^-1)
+// Eye-to-hand, the target is fixed on the flange:
+//     F_i^-1 X C_i = F_j^-1 X C_j  =>  (F_i F_j^-1) X = X (C_i C_j^-1)
+fn relative_motions(
+    mode: HandEyeMode,
+    samples: &[CalibrationSample],
+) -> Vec<(Isometry3<f64>, Isometry3<f64>)> {
+    let mut motions = Vec::new();
+    for (i, a) in samples.iter().enumerate() {
+        for b in samples.iter().skip(i + 1) {
+            let flange_motion = match mode {
+                HandEyeMode::EyeInHand => a.flange.inverse() * b.flange,
+                HandEyeMode::EyeToHand => a.flange * b.flange.inverse(),
+            };
+            let camera_motion = a.target * b.target.inverse();
+            motions.push((flange_motion, camera_motion));
+        }
+    }
+    motions
+}
+
+// R_A R_X = R_X R_B means the rotation axis of A is R_X times the axis of B,
+// so R_X is the rotation that best aligns all the axis-angle vectors.
+fn solve_rotation(
+    motions: &[(Isometry3<f64>, Isometry3<f64>)],
+) -> Result<Rotation3<f64>, CalibrationError> {
+    let mut correlation = Matrix3::zeros();
+    let mut used = 0;
+    for (a, b) in motions {
+        let alpha = a.rotation.scaled_axis();
+        let beta = b.rotation.scaled_axis();
+        if alpha.norm() < MINIMUM_MOTION_ANGLE || beta.norm() < MINIMUM_MOTION_ANGLE {
+            continue;
+        }
+        correlation += alpha * beta.transpose();
+        used += 1;
+    }
+    if used < 2 {
+        return Err(CalibrationError::Degenerate(
+            "less than two motions with a rotation".to_string(),
+        ));
+    }
+
+    let svd = correlation.svd(true, true);
+    let (u, v_t) = match (svd.u, svd.v_t) {
+        (Some(u), Some(v_t)) => (u, v_t),
+        _ => {
+            return Err(CalibrationError::Degenerate(
+                "rotation decomposition failed".to_string(),
+            ))
+        }
+    };
+    // Two parallel axes only constrain the rotation about that axis.
+    let mut singular_values = svd.singular_values.iter().copied().collect::<Vec<f64>>();
+    singular_values.sort_by(|a, b| b.total_cmp(a));
+    if singular_values[1] < 1e-9 * singular_values[0].max(1.0) {
+        return Err(CalibrationError::Degenerate(
+            "all motions rotate about parallel axes".to_string(),
+        ));
+    }
+
+    let mut correction = Matrix3::identity();
+    correction[(2, 2)] = (u * v_t).determinant().signum();
+    Ok(Rotation3::from_matrix_unchecked(u * correction * v_t))
+}
+
+// (R_A - I) t_X = R_X t_B - t_A, stacked for all motions.
+fn solve_translation(
+    motions: &[(Isometry3<f64>, Isometry3<f64>)],
+    rotation: &Rotation3<f64>,
+) -> Result<Vector3<f64>, CalibrationError> {
+    let mut lhs = DMatrix::zeros(3 * motions.len(), 3);
+    let mut rhs = DVector::zeros(3 * motions.len());
+    for (k, (a, b)) in motions.iter().enumerate() {
+        let block = a.rotation.to_rotation_matrix().into_inner() - Matrix3::identity();
+        let value = rotation * b.translation.vector - a.translation.vector;
+        lhs.fixed_view_mut::<3, 3>(3 * k, 0).copy_from(&block);
+        rhs.fixed_rows_mut::<3>(3 * k).copy_from(&value);
+    }
+
+    let solution = lhs
+        .svd(true, true)
+        .solve(&rhs, 1e-12)
+        .map_err(|e| CalibrationError::Degenerate(e.to_string()))?;
+    Ok(Vector3::new(solution[0], solution[1], solution[2]))
+}
+
+fn residuals(motions: &[(Isometry3<f64>, Isometry3<f64>)], x: &Isometry3<f64>) -> Residuals {
+    let mut rotation_sum = 0.0;
+    let mut rotation_max: f64 = 0.0;
+    let mut translation_sum = 0.0;
+    let mut translation_max: f64 = 0.0;
+
+    for (a, b) in motions {
+        let error = (a * x).inverse() * (x * b);
+        // acos of the real part loses precision near zero, atan2 does not.
+        let q = error.rotation.quaternion();
+        let angle = (2.0 * q.imag().norm().atan2(q.w.abs())).to_degrees();
+        let distance = error.translation.vector.norm();
+        rotation_sum += angle * angle;
+        rotation_max = rotation_max.max(angle);
+        translation_sum += distance * distance;
+        translation_max = translation_max.max(distance);
+    }
+
+    let n = motions.len().max(1) as f64;
+    Residuals {
+        motions: motions.len(),
+        rotation_rms_deg: (rotation_sum / n).sqrt(),
+        rotation_max_deg: rotation_max,
+        translation_rms: (translation_sum / n).sqrt(),
+        translation_max,
+    }
+}
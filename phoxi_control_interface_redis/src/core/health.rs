@@ -0,0 +1,228 @@
+use micro_sp::*;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::net::TcpStream;
+use tokio::time::{interval, timeout, Duration};
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::backend::ScannerBackend;
+use super::interface::load_parameters;
+
+// Watches the scanner between requests, so that a dead camera is noticed
+// before a scan is scheduled on it. Every period it publishes:
+//
+//   {id}_interface_heartbeat: now, in milliseconds since the Unix epoch.
+//   {id}_scanner_online:      whether the scanner answers on one of its
+//                             ports and PhoXiControl is running.
+//   {id}_phoxi_control_running
+//   {id}_last_seen:           when the scanner was last online.
+//
+// The scanner is looked up at {id}_ip_identification, or else at the
+// ip_identification in parameters/scanners/{id}.json. The check only opens
+// and closes a TCP connection, it never runs a command that could get in
+// the way of a request.
+pub async fn photoneo_health_interface(
+    photoneo_id: &str,
+    phoxi_interface_path: &str,
+    backend: &Arc<dyn ScannerBackend>,
+    connection_manager: &Arc<ConnectionManager>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let log_target = "phoxi_health_interface";
+    let parameters = load_parameters(phoxi_interface_path, photoneo_id);
+    let config = HealthConfig::from_parameters(&parameters, photoneo_id);
+    let mut interval = interval(Duration::from_millis(config.period_ms.max(1)));
+    log::info!(target: log_target, "Online.");
+
+    let keys: Vec<String> = vec![
+        &format!("{}_ip_identification", photoneo_id),
+        &format!("{}_scanner_online", photoneo_id),
+        &format!("{}_phoxi_control_running", photoneo_id),
+        &format!("{}_last_seen", photoneo_id),
+        &format!("{}_interface_heartbeat", photoneo_id),
+    ]
+    .iter()
+    .map(|k| k.to_string())
+    .collect();
+
+    let mut con = connection_manager.get_connection().await;
+    let mut reconnect = false;
+    let mut was_online: Option<bool> = None;
+    loop {
+        interval.tick().await;
+        if connection_manager
+            .check_redis_health(log_target)
+            .await
+            .is_err()
+        {
+            reconnect = true;
+            continue;
+        }
+        if reconnect {
+            con = connection_manager.get_connection().await;
+            reconnect = false;
+        }
+        let state = match StateManager::get_state_for_keys(&mut con, &keys).await {
+            Some(s) => s,
+            None => continue,
+        };
+
+        let ip_identification = match state
+            .get_string_or_unknown(&format!("{}_ip_identification", photoneo_id), log_target)
+        {
+            StringOrUnknown::String(ip) => ip,
+            StringOrUnknown::UNKNOWN => parameters["ip_identification"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+        };
+
+        let health = backend.health(&ip_identification, &config).await;
+        let online = health.online();
+        if was_online != Some(online) {
+            match online {
+                true => log::info!(target: log_target,
+                    "Scanner at '{}' is online.", ip_identification),
+                false => log::warn!(target: log_target,
+                    "Scanner at '{}' is offline: {}.", ip_identification, health),
+            }
+            was_online = Some(online);
+        }
+
+        let now = unix_millis();
+        let mut new_state = state
+            .update(
+                &format!("{photoneo_id}_interface_heartbeat"),
+                now.to_spvalue(),
+            )
+            .update(
+                &format!("{photoneo_id}_scanner_online"),
+                online.to_spvalue(),
+            );
+        if let Some(running) = health.phoxi_control_running {
+            new_state = new_state.update(
+                &format!("{photoneo_id}_phoxi_control_running"),
+                running.to_spvalue(),
+            );
+        }
+        if online {
+            new_state = new_state.update(&format!("{photoneo_id}_last_seen"), now.to_spvalue());
+        }
+
+        let modified_state = state.get_diff_partial_state(&new_state);
+        StateManager::set_state(&mut con, &modified_state).await;
+    }
+}
+
+// "health" in parameters/scanners/{id}.json, all entries are optional:
+//
+//     "health": {
+//         "period_ms": 2000,
+//         "ports": [6100],
+//         "connect_timeout_ms": 1000,
+//         "phoxi_control": true
+//     }
+//
+// Set phoxi_control to false when PhoXiControl runs in another container
+// than the interface, its process cannot be seen from here then.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    pub period_ms: u64,
+    // The scanner is reachable if any of these accepts a connection.
+    pub ports: Vec<u16>,
+    pub connect_timeout_ms: u64,
+    pub phoxi_control: bool,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            period_ms: 2000,
+            ports: vec![6100],
+            connect_timeout_ms: 1000,
+            phoxi_control: true,
+        }
+    }
+}
+
+impl HealthConfig {
+    pub fn from_parameters(parameters: &Value, photoneo_id: &str) -> HealthConfig {
+        match parameters.get("health") {
+            None | Some(Value::Null) => HealthConfig::default(),
+            Some(health) => serde_json::from_value(health.clone()).unwrap_or_else(|e| {
+                log::warn!(target: "phoxi_health_interface",
+                    "Ignoring invalid health settings in the parameters of {}: {}", photoneo_id, e);
+                HealthConfig::default()
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScannerHealth {
+    pub reachable: bool,
+    // None if it is not checked.
+    pub phoxi_control_running: Option<bool>,
+}
+
+impl ScannerHealth {
+    pub fn online(&self) -> bool {
+        self.reachable && self.phoxi_control_running != Some(false)
+    }
+}
+
+impl std::fmt::Display for ScannerHealth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.reachable, self.phoxi_control_running) {
+            (false, Some(false)) => write!(f, "not reachable and PhoXiControl is not running"),
+            (false, _) => write!(f, "not reachable"),
+            (true, Some(false)) => write!(f, "PhoXiControl is not running"),
+            (true, _) => write!(f, "online"),
+        }
+    }
+}
+
+// What the subprocess backend reports, see ScannerBackend::health.
+pub async fn probe_scanner(ip_identification: &str, config: &HealthConfig) -> ScannerHealth {
+    ScannerHealth {
+        reachable: is_reachable(ip_identification, config).await,
+        phoxi_control_running: if config.phoxi_control {
+            Some(is_phoxi_control_running())
+        } else {
+            None
+        },
+    }
+}
+
+async fn is_reachable(ip_identification: &str, config: &HealthConfig) -> bool {
+    if ip_identification.is_empty() {
+        return false;
+    }
+    let connect_timeout = Duration::from_millis(config.connect_timeout_ms);
+    for port in &config.ports {
+        let address = (ip_identification, *port);
+        if let Ok(Ok(_)) = timeout(connect_timeout, TcpStream::connect(address)).await {
+            return true;
+        }
+    }
+    false
+}
+
+// Looks for the process in /proc, PhoXiControl only runs on Linux here.
+fn is_phoxi_control_running() -> bool {
+    let Ok(processes) = std::fs::read_dir("/proc") else {
+        return false;
+    };
+    processes.flatten().any(|process| {
+        std::fs::read_to_string(process.path().join("comm"))
+            .is_ok_and(|name| name.trim() == "PhoXiControl")
+    })
+}
+
+fn unix_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64)
+}
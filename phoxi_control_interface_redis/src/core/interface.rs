@@ -2,14 +2,27 @@ use crate::*;
 use serde_json::Value;
 use tokio::time::{interval, Duration};
 
-use std::sync::{mpsc as sync_mpsc, Arc};
-
-use super::state::ScanRequest;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use super::backend::{execute, reserve, ScannerBackend};
+use super::error::{ErrorCode, NO_ERROR};
+use super::executor::{failure_info, partial_output, run_executable, CommandFailure};
+use super::request_file::{RequestFile, RequestProtocol, ScanRequestDocument};
+use super::retry::{FailCounters, RetryPolicy};
+use super::scan_quality::{check_scan, ScanCheck, ScanVerdict};
+use super::settings::{Resolution, ScannerSettings, SettingsError};
+use super::state::{ScanRequest, REQUEST_CANCELLED};
+
+pub const DEFAULT_SCAN_TIMEOUT_MS: u64 = 5000;
 
 pub async fn photoneo_control_interface(
     photoneo_id: &str,
     phoxi_scans_path: &str,
     phoxi_interface_path: &str,
+    backend: &Arc<dyn ScannerBackend>,
     connection_manager: &Arc<ConnectionManager>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut interval = interval(Duration::from_millis(250));
@@ -19,6 +32,7 @@ pub async fn photoneo_control_interface(
     let keys: Vec<String> = vec![
         &format!("{}_request_trigger", photoneo_id),
         &format!("{}_request_state", photoneo_id),
+        &format!("{}_request_abort", photoneo_id),
         &format!("{}_name_identification", photoneo_id),
         &format!("{}_hardware_identification", photoneo_id),
         &format!("{}_ip_identification", photoneo_id),
@@ -30,251 +44,596 @@ pub async fn photoneo_control_interface(
         &format!("{}_timeout", photoneo_id),
         &format!("{}_settings", photoneo_id),
         &format!("{}_phoxi_raw_info", photoneo_id),
+        &format!("{}_error_code", photoneo_id),
+        &format!("{}_error_recoverable", photoneo_id),
+        &format!("{}_total_fail_counter", photoneo_id),
+        &format!("{}_subsequent_fail_counter", photoneo_id),
+        &format!("{}_needs_attention", photoneo_id),
+        &format!("{}_retry_max_attempts", photoneo_id),
+        &format!("{}_retry_backoff", photoneo_id),
+        &format!("{}_retry_reconnect", photoneo_id),
+        &format!("{}_attention_threshold", photoneo_id),
+        &format!("{}_scan_quality", photoneo_id),
     ]
     .iter()
     .map(|k| k.to_string())
     .collect();
 
     let mut con = connection_manager.get_connection().await;
+    // Set while Redis is unreachable, the old connection does not survive it.
+    let mut reconnect = false;
+    let mut running: Option<RunningRequest> = None;
     loop {
         interval.tick().await;
         if let Err(_) = connection_manager.check_redis_health(&log_target).await {
+            reconnect = true;
             continue;
         }
+        if reconnect {
+            con = connection_manager.get_connection().await;
+            reconnect = false;
+        }
         let state = match StateManager::get_state_for_keys(&mut con, &keys).await {
             Some(s) => s,
             None => continue,
         };
 
-        let mut request_trigger = state
+        let request_trigger = state
             .get_bool_or_default_to_false(&format!("{}_request_trigger", photoneo_id), &log_target);
 
-        let mut request_state = state.get_string_or_default_to_unknown(
+        let request_state = state.get_string_or_default_to_unknown(
             &format!("{}_request_state", photoneo_id),
             &log_target,
         );
 
-        if request_trigger {
-            request_trigger = false;
-            if request_state == ServiceRequestState::Initial.to_string() {
-                let name_identification = state.get_string_or_default_to_unknown(
-                    &format!("{}_name_identification", photoneo_id),
-                    &log_target,
-                );
+        let request_abort = state
+            .get_bool_or_default_to_false(&format!("{}_request_abort", photoneo_id), &log_target);
 
-                let hardware_identification = state.get_string_or_default_to_unknown(
-                    &format!("{}_hardware_identification", photoneo_id),
-                    &log_target,
-                );
+        if let Some(mut finished) = running.take_if(|r| r.handle.is_finished()) {
+            let (result, scan_check) = (&mut finished.handle).await.unwrap_or_else(|e| {
+                (
+                    Err(io::Error::other(format!("The command task failed: {}", e))),
+                    None,
+                )
+            });
 
-                let ip_identification = state.get_string_or_default_to_unknown(
-                    &format!("{}_ip_identification", photoneo_id),
-                    &log_target,
+            // A capture whose scan failed the quality check fails as well.
+            let rejection = scan_check
+                .as_ref()
+                .filter(|check| check.verdict == ScanVerdict::Failed)
+                .map(|check| check.violations.join("; "));
+            let (request_state, phoxi_raw_info, error_code) = match (result, rejection) {
+                (Ok(_), Some(violations)) => {
+                    log::error!(target: &log_target,
+                        "Photoneo scan was rejected: {}.", violations
+                    );
+                    (
+                        ServiceRequestState::Failed.to_string(),
+                        format!("Scan quality is too low: {}", violations),
+                        Some(ErrorCode::PoorScanQuality),
+                    )
+                }
+                (Ok(val), None) => {
+                    log::info!(target: &log_target,
+                        "Photoneo request succeeded with output: {}", val.join(" | ")
+                    );
+                    (
+                        ServiceRequestState::Succeeded.to_string(),
+                        val.first().cloned().unwrap_or_default(),
+                        None,
+                    )
+                }
+                (Err(e), _)
+                    if e.kind() == io::ErrorKind::Interrupted && finished.cancel.is_cancelled() =>
+                {
+                    log::warn!(target: &log_target, "Photoneo request was aborted.");
+                    let output = partial_output(&e);
+                    let phoxi_raw_info = if output.is_empty() {
+                        e.to_string()
+                    } else {
+                        output.join("\n")
+                    };
+                    (
+                        REQUEST_CANCELLED.to_string(),
+                        phoxi_raw_info,
+                        Some(ErrorCode::Cancelled),
+                    )
+                }
+                (Err(e), _) => {
+                    let error_code = ErrorCode::classify(&e);
+                    log::error!(target: &log_target,
+                        "Photoneo failed with error ({}): {}.", error_code, e
+                    );
+                    (
+                        ServiceRequestState::Failed.to_string(),
+                        failure_info(&e),
+                        Some(error_code),
+                    )
+                }
+            };
+
+            let new_state = state
+                .update(
+                    &format!("{photoneo_id}_request_trigger"),
+                    false.to_spvalue(),
+                )
+                .update(&format!("{photoneo_id}_request_abort"), false.to_spvalue())
+                .update(
+                    &format!("{photoneo_id}_request_state"),
+                    request_state.to_spvalue(),
+                )
+                .update(
+                    &format!("{photoneo_id}_phoxi_raw_info"),
+                    phoxi_raw_info.to_spvalue(),
                 );
-
-                let phoxi_raw_info;
-
-                let command_type = state.get_string_or_default_to_unknown(
-                    &format!("{}_command_type", photoneo_id),
-                    &log_target,
+            let mut new_state = with_error_code(new_state, photoneo_id, error_code);
+            if let Some(check) = &scan_check {
+                if check.verdict == ScanVerdict::Degraded {
+                    log::warn!(target: &log_target,
+                        "Photoneo scan is degraded: {}.", check.violations.join("; "));
+                }
+                new_state = new_state.update(
+                    &format!("{photoneo_id}_scan_quality"),
+                    SPValue::Map(check.to_map()),
                 );
+            }
 
-                let scene_name = state.get_string_or_default_to_unknown(
-                    &format!("{}_scene_name", photoneo_id),
+            let counters = FailCounters {
+                total: state.get_int_or_default_to_zero(
+                    &format!("{}_total_fail_counter", photoneo_id),
                     &log_target,
-                );
-
-                let praw = match state
-                    .get_bool_or_unknown(&format!("{}_praw", photoneo_id), &log_target)
-                {
-                    BoolOrUnknown::UNKNOWN => true,
-                    BoolOrUnknown::Bool(val) => val,
-                };
+                ),
+                subsequent: state.get_int_or_default_to_zero(
+                    &format!("{}_subsequent_fail_counter", photoneo_id),
+                    &log_target,
+                ),
+            };
+            let counters = match error_code {
+                None => counters.succeeded(),
+                Some(ErrorCode::Cancelled) => counters,
+                Some(_) => {
+                    let counters = counters.failed();
+                    if counters.needs_attention(&finished.retry) {
+                        log::error!(target: &log_target,
+                            "{} requests failed in a row, the scanner needs attention.",
+                            counters.subsequent);
+                    }
+                    counters
+                }
+            };
+            let new_state = with_fail_counters(new_state, photoneo_id, counters, &finished.retry);
+
+            let modified_state = state.get_diff_partial_state(&new_state);
+            StateManager::set_state(&mut con, &modified_state).await;
+        } else if request_abort {
+            match &running {
+                Some(running) => {
+                    if !running.cancel.is_cancelled() {
+                        log::info!(target: &log_target, "Aborting the running request.");
+                        running.cancel.cancel();
+                    }
+                }
+                // Nothing to abort, unless the request was triggered but
+                // did not start yet, which is then never started.
+                None => {
+                    let mut new_state =
+                        state.update(&format!("{photoneo_id}_request_abort"), false.to_spvalue());
+                    if request_trigger && request_state == ServiceRequestState::Initial.to_string()
+                    {
+                        new_state = new_state
+                            .update(
+                                &format!("{photoneo_id}_request_trigger"),
+                                false.to_spvalue(),
+                            )
+                            .update(
+                                &format!("{photoneo_id}_request_state"),
+                                REQUEST_CANCELLED.to_spvalue(),
+                            )
+                            .update(
+                                &format!("{photoneo_id}_phoxi_raw_info"),
+                                "Request was aborted before it started.".to_spvalue(),
+                            );
+                        new_state =
+                            with_error_code(new_state, photoneo_id, Some(ErrorCode::Cancelled));
+                    }
+                    let modified_state = state.get_diff_partial_state(&new_state);
+                    StateManager::set_state(&mut con, &modified_state).await;
+                }
+            }
+        } else if running.is_none()
+            && request_trigger
+            && request_state == ServiceRequestState::Initial.to_string()
+        {
+            let name_identification = state.get_string_or_default_to_unknown(
+                &format!("{}_name_identification", photoneo_id),
+                &log_target,
+            );
 
-                let ply = state
-                    .get_bool_or_default_to_false(&format!("{}_ply", photoneo_id), &log_target);
+            let hardware_identification = state.get_string_or_default_to_unknown(
+                &format!("{}_hardware_identification", photoneo_id),
+                &log_target,
+            );
 
-                let tif = state
-                    .get_bool_or_default_to_false(&format!("{}_tif", photoneo_id), &log_target);
+            let ip_identification = state.get_string_or_default_to_unknown(
+                &format!("{}_ip_identification", photoneo_id),
+                &log_target,
+            );
 
-                let praw_dir = format!("{phoxi_scans_path}/praw");
-                let ply_dir = format!("{phoxi_scans_path}/ply");
-                let tif_dir = format!("{phoxi_scans_path}/tif");
+            let command_type = state.get_string_or_default_to_unknown(
+                &format!("{}_command_type", photoneo_id),
+                &log_target,
+            );
 
-                let timeout = match state
-                    .get_int_or_unknown(&format!("{}_timeout", photoneo_id), &log_target)
-                {
-                    IntOrUnknown::UNKNOWN => 5000,
-                    IntOrUnknown::Int64(int) => int,
-                };
+            let scene_name = state.get_string_or_default_to_unknown(
+                &format!("{}_scene_name", photoneo_id),
+                &log_target,
+            );
 
-                let settings = match state
-                    .get_string_or_unknown(&format!("{}_settings", photoneo_id), &log_target)
-                {
-                    StringOrUnknown::UNKNOWN => "default".to_string(),
-                    StringOrUnknown::String(val) => val,
-                };
+            let praw = match state
+                .get_bool_or_unknown(&format!("{}_praw", photoneo_id), &log_target)
+            {
+                BoolOrUnknown::UNKNOWN => true,
+                BoolOrUnknown::Bool(val) => val,
+            };
+
+            let ply = state
+                .get_bool_or_default_to_false(&format!("{}_ply", photoneo_id), &log_target);
+
+            let tif = state
+                .get_bool_or_default_to_false(&format!("{}_tif", photoneo_id), &log_target);
+
+            let praw_dir = format!("{phoxi_scans_path}/praw");
+            let ply_dir = format!("{phoxi_scans_path}/ply");
+            let tif_dir = format!("{phoxi_scans_path}/tif");
+
+            let timeout = match state
+                .get_int_or_unknown(&format!("{}_timeout", photoneo_id), &log_target)
+            {
+                IntOrUnknown::UNKNOWN => DEFAULT_SCAN_TIMEOUT_MS as i64,
+                IntOrUnknown::Int64(int) => int,
+            };
+
+            let settings = match state
+                .get_string_or_unknown(&format!("{}_settings", photoneo_id), &log_target)
+            {
+                StringOrUnknown::UNKNOWN => "default".to_string(),
+                StringOrUnknown::String(val) => val,
+            };
+
+            let scan_request = ScanRequest {
+                name_identification,
+                hardware_identification,
+                ip_identification,
+                command_type,
+                scene_name,
+                praw,
+                ply,
+                tif,
+                praw_dir,
+                ply_dir,
+                tif_dir,
+                timeout,
+                settings,
+            };
+
+            let retry = RetryPolicy::from_request(
+                state.get_int_or_default_to_zero(
+                    &format!("{}_retry_max_attempts", photoneo_id),
+                    &log_target,
+                ),
+                state.get_int_or_default_to_zero(
+                    &format!("{}_retry_backoff", photoneo_id),
+                    &log_target,
+                ),
+                state.get_bool_or_default_to_false(
+                    &format!("{}_retry_reconnect", photoneo_id),
+                    &log_target,
+                ),
+                state.get_int_or_default_to_zero(
+                    &format!("{}_attention_threshold", photoneo_id),
+                    &log_target,
+                ),
+            );
 
-                let scan_request = ScanRequest {
-                    name_identification,
-                    hardware_identification,
-                    ip_identification,
-                    command_type,
-                    scene_name,
-                    praw,
-                    ply,
-                    tif,
-                    praw_dir,
-                    ply_dir,
-                    tif_dir,
-                    timeout,
-                    settings,
+            // The command runs in its own task, so the loop keeps serving
+            // Redis while a scan takes its time.
+            let cancel = CancellationToken::new();
+            let task_backend = backend.clone();
+            let task_cancel = cancel.clone();
+            let task_interface_path = phoxi_interface_path.to_string();
+            let handle = tokio::task::spawn(async move {
+                let session = match reserve(&task_backend, &task_cancel).await {
+                    Ok(session) => session,
+                    Err(e) => return (Err(e), None),
                 };
-
-                match call_blocking_exec(scan_request, phoxi_interface_path, &photoneo_id) {
-                    Ok(val) => {
-                        log::info!(target: &log_target,
-                            "Photoneo request succeeded. Check {photoneo_id}_phoxi_raw_info for feedback from the scanner."
-                        );
-                        request_state = ServiceRequestState::Succeeded.to_string();
-                        phoxi_raw_info = val[0].clone();
-                    }
-                    Err(e) => {
-                        log::error!(target: &log_target,
-                            "Photoneo failed with error: {}.", e
-                        );
-                        request_state = ServiceRequestState::Failed.to_string();
-                        phoxi_raw_info = e.to_string();
+                let result =
+                    execute_with_retry(&*session, &scan_request, &retry, &task_cancel).await;
+                if task_cancel.is_cancelled() && leaves_scanner_acquiring(&scan_request) {
+                    stop_acquisition(&*session, &scan_request).await;
+                }
+                // The scan check does not need the scanner.
+                drop(session);
+                let scan_check = match result {
+                    Ok(_) if saves_a_ply(&scan_request) => {
+                        Some(check_saved_scan(scan_request, task_interface_path).await)
                     }
+                    _ => None,
                 };
+                (result, scan_check)
+            });
+            running = Some(RunningRequest {
+                cancel,
+                handle,
+                retry,
+            });
+        }
+    }
+}
 
-                let new_state = state
-                    .update(
-                        &format!("{photoneo_id}_request_trigger"),
-                        request_trigger.to_spvalue(),
-                    )
-                    .update(
-                        &format!("{photoneo_id}_request_state"),
-                        request_state.to_spvalue(),
-                    )
-                    .update(
-                        &format!("{photoneo_id}_phoxi_raw_info"),
-                        phoxi_raw_info.to_spvalue(),
-                    );
+// A request whose command is still running. Dropping it, e.g. when the
+// interface stops, cancels the command.
+struct RunningRequest {
+    cancel: CancellationToken,
+    handle: JoinHandle<(Result<Vec<String>, io::Error>, Option<ScanCheck>)>,
+    retry: RetryPolicy,
+}
 
-                let modified_state = state.get_diff_partial_state(&new_state);
-                StateManager::set_state(&mut con, &modified_state).await;
+impl Drop for RunningRequest {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+// Every finished request publishes its error code, NO_ERROR on success.
+fn with_error_code(state: State, photoneo_id: &str, error_code: Option<ErrorCode>) -> State {
+    state
+        .update(
+            &format!("{photoneo_id}_error_code"),
+            error_code
+                .map_or(NO_ERROR, |code| code.as_str())
+                .to_spvalue(),
+        )
+        .update(
+            &format!("{photoneo_id}_error_recoverable"),
+            error_code
+                .is_some_and(|code| code.is_recoverable())
+                .to_spvalue(),
+        )
+}
+
+fn with_fail_counters(
+    state: State,
+    photoneo_id: &str,
+    counters: FailCounters,
+    retry: &RetryPolicy,
+) -> State {
+    state
+        .update(
+            &format!("{photoneo_id}_total_fail_counter"),
+            counters.total.to_spvalue(),
+        )
+        .update(
+            &format!("{photoneo_id}_subsequent_fail_counter"),
+            counters.subsequent.to_spvalue(),
+        )
+        .update(
+            &format!("{photoneo_id}_needs_attention"),
+            counters.needs_attention(retry).to_spvalue(),
+        )
+}
+
+// Runs the command again while it fails with a recoverable error, as the
+// policy allows. The error of the last attempt is the one reported.
+async fn execute_with_retry(
+    backend: &dyn ScannerBackend,
+    request: &ScanRequest,
+    retry: &RetryPolicy,
+    cancel: &CancellationToken,
+) -> Result<Vec<String>, io::Error> {
+    let mut attempt = 1;
+    loop {
+        let error = match execute(backend, request, cancel).await {
+            Ok(output) => return Ok(output),
+            Err(e) => e,
+        };
+        let error_code = ErrorCode::classify(&error);
+        if attempt >= retry.max_attempts || cancel.is_cancelled() || !error_code.is_recoverable()
+        {
+            return Err(error);
+        }
+        log::warn!(target: "phoxi_control_interface",
+            "Attempt {} of {} failed ({}): {}. Retrying in {:?}.",
+            attempt, retry.max_attempts, error_code, error, retry.delay(attempt));
+        if !retry.wait(attempt, cancel).await {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "Command execution was cancelled.",
+            ));
+        }
+        if retry.reconnect && !request.command_type.eq_ignore_ascii_case("connect") {
+            if let Err(e) = backend.connect(request, cancel).await {
+                log::warn!(target: "phoxi_control_interface",
+                    "Failed to reconnect before the retry: {}", e);
             }
         }
+        attempt += 1;
     }
 }
 
-fn call_blocking_exec(
-    request: ScanRequest,
+fn saves_a_ply(request: &ScanRequest) -> bool {
+    request.ply && request.command_type.eq_ignore_ascii_case("capture")
+}
+
+// Checks the ply of a capture against the scan_quality section of its
+// settings preset. Without the section the scan is only measured.
+async fn check_saved_scan(request: ScanRequest, phoxi_interface_path: String) -> ScanCheck {
+    let thresholds = match load_settings(&request, &phoxi_interface_path) {
+        Ok(settings) => settings.scan_quality,
+        Err(e) => {
+            log::warn!(target: "phoxi_control_interface",
+                "Checking the scan without thresholds: {}", e);
+            None
+        }
+    };
+    let path = Path::new(&request.ply_dir).join(format!("{}.ply", request.scene_name));
+    // A full resolution scan has three million points.
+    tokio::task::spawn_blocking(move || check_scan(&path, thresholds.as_ref()))
+        .await
+        .unwrap_or_else(|e| ScanCheck {
+            quality: None,
+            violations: vec![format!("the scan check failed: {}", e)],
+            verdict: ScanVerdict::Degraded,
+        })
+}
+
+// An aborted capture or freerun can leave the scanner acquiring.
+fn leaves_scanner_acquiring(request: &ScanRequest) -> bool {
+    matches!(
+        request.command_type.to_lowercase().as_str(),
+        "capture" | "freerun"
+    )
+}
+
+async fn stop_acquisition(backend: &dyn ScannerBackend, request: &ScanRequest) {
+    match backend.stop(request, &CancellationToken::new()).await {
+        Ok(_) => log::info!(target: "phoxi_control_interface",
+            "Stopped the acquisition of the aborted request."),
+        Err(e) => log::warn!(target: "phoxi_control_interface",
+            "Failed to stop the acquisition of the aborted request: {}", e),
+    }
+}
+
+// How long a command may run before it is killed. A timeout of 0 means
+// DEFAULT_SCAN_TIMEOUT_MS, only a negative one lets the command run until
+// it finishes or is aborted.
+pub fn execution_timeout(timeout: i64) -> Option<Duration> {
+    match timeout {
+        t if t < 0 => None,
+        0 => Some(Duration::from_millis(DEFAULT_SCAN_TIMEOUT_MS)),
+        t => Some(Duration::from_millis(t as u64)),
+    }
+}
+
+// Runs cpp_executables/dev/{command}/{command}_Release, see SubprocessBackend.
+pub async fn call_exec(
+    request: &ScanRequest,
+    command: &str,
     phoxi_interface_path: &str,
     photoneo_id: &str,
+    request_protocol: RequestProtocol,
+    cancel: &CancellationToken,
 ) -> Result<Vec<String>, io::Error> {
-    let args = prepare_arguments(&request, phoxi_interface_path, photoneo_id);
-    let mut child = Command::new(&args[0])
-        .args(&args[1..])
-        .stdout(Stdio::piped())
-        .spawn()?;
-
-    match child.stdout.take() {
-        Some(stdout) => {
-            let reader = BufReader::new(stdout);
-
-            let (tx, rx) = sync_mpsc::channel();
-
-            thread::spawn(move || {
-                let lines: Vec<String> = reader
-                    .lines()
-                    .map(|line| line.unwrap_or_else(|_| "".to_string()))
-                    .collect();
-                if let Err(e) = tx.send(lines) {
-                    log::warn!(
-                        target: "phoxi_control_interface",
-                        "Failed to send captured stdout lines to main thread (receiver dropped) with : {}",
-                        e
-                    );
-                }
-            });
+    let settings = load_settings(request, phoxi_interface_path)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    let parameters = load_parameters(phoxi_interface_path, photoneo_id);
+    let executable = format!(
+        "{}/cpp_executables/dev/{}/{}_Release",
+        phoxi_interface_path, command, command
+    );
 
-            let timeout_duration = Duration::from_millis(request.timeout as u64);
-
-            match rx.recv_timeout(timeout_duration) {
-                Ok(output_lines) => Ok(output_lines),
-                Err(_) => {
-                    child.kill()?;
-                    let _ = child.wait();
-                    Err(io::Error::new(
-                        io::ErrorKind::TimedOut,
-                        "Command execution timed out.",
-                    ))
-                }
-            }
-        }
-        None => {
-            log::error!(target: &&format!(
-                "phoxi_control_interface"),
-                "Failed to capture stdout."
+    // Has to outlive the child process, the file is removed on drop.
+    let request_file;
+    let args = match request_protocol.for_command(command) {
+        RequestProtocol::File => {
+            let document = ScanRequestDocument::new(
+                request,
+                &settings,
+                effective_resolution(&settings, &parameters),
+                &parameters,
             );
-            Err(io::Error::new(
-                io::ErrorKind::BrokenPipe,
-                "Failed to capture stdout.",
-            ))
+            request_file = RequestFile::write(&document, photoneo_id)?;
+            vec![executable, request_file.argument()]
         }
+        RequestProtocol::Argv => prepare_arguments(request, &executable, &settings, &parameters),
+    };
+
+    let timeout = execution_timeout(request.timeout);
+    let output = run_executable(&args, timeout, cancel, "phoxi_control_interface").await?;
+
+    // The executables exit with 0 when they cannot connect to the device.
+    if let Some(line) = output
+        .stdout
+        .iter()
+        .find(|line| line.contains("was Unsuccessful!"))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::NotConnected,
+            CommandFailure {
+                reason: line.clone(),
+                exit_code: Some(0),
+                stdout: output.stdout.clone(),
+                stderr: output.stderr,
+            },
+        ));
     }
+    Ok(output.stdout)
 }
 
-fn prepare_arguments(
+fn load_settings(
     request: &ScanRequest,
     phoxi_interface_path: &str,
-    photoneo_id: &str,
-) -> Vec<String> {
-    let capcom = capitalize_first(&request.command_type);
-
+) -> Result<ScannerSettings, SettingsError> {
     let settings_path = format!(
         "{}/parameters/settings/{}.json",
         phoxi_interface_path, request.settings
     );
-    let parameters_path = format!(
-        "{}/parameters/scanners/{}.json",
-        phoxi_interface_path, photoneo_id
-    );
 
-    let settings: Value = load_json_from_file(&settings_path).unwrap_or_else(|| {
+    if request.settings == "default" && !Path::new(&settings_path).exists() {
         log::warn!(
-            "Failed to load settings from {}. Using built-in default settings.",
+            target: "phoxi_control_interface",
+            "Settings file {} not found. Using built-in default settings.",
             settings_path
         );
-        serde_json::from_str(crate::core::DEFAULT_SETTINGS_JSON).unwrap_or_else(|parse_err| {
-            log::error!(
-                "CRITICAL: Failed to parse built-in default settings JSON: {}. 
-                        This indicates a bug in the default JSON string. 
-                        Using Value::Null as ultimate fallback.",
-                parse_err
-            );
-            Value::Null
-        })
-    });
+        ScannerSettings::builtin()
+    } else {
+        ScannerSettings::load(&settings_path)
+    }
+}
 
-    // For parameters, you'd have similar logic or a different default/handling:
-    let parameters: Value = load_json_from_file(&parameters_path).unwrap_or_else(|| {
+pub fn load_parameters(phoxi_interface_path: &str, photoneo_id: &str) -> Value {
+    let parameters_path = format!(
+        "{}/parameters/scanners/{}.json",
+        phoxi_interface_path, photoneo_id
+    );
+
+    load_json_from_file(&parameters_path).unwrap_or_else(|| {
         log::warn!(
             "Failed to load parameters from {}. Using null as default.",
             parameters_path,
         );
         Value::Null
-    });
+    })
+}
+
+// The 1708011 scanner is always run in its low resolution mode.
+fn effective_resolution(settings: &ScannerSettings, parameters: &Value) -> Resolution {
+    let resolution = &settings.capturing_settings.resolution;
+    if parameters["name_identification"]
+        .as_str()
+        .unwrap_or("photoneo_1708011")
+        == "photoneo_1708011"
+    {
+        resolution.min
+    } else {
+        resolution.value
+    }
+}
+
+// Legacy positional protocol, see RequestProtocol::Argv.
+fn prepare_arguments(
+    request: &ScanRequest,
+    executable: &str,
+    settings: &ScannerSettings,
+    parameters: &Value,
+) -> Vec<String> {
+    let cap = &settings.capturing_settings;
+    let pro = &settings.processing_settings;
+    let exp = &settings.experimental_settings;
 
     let mut args_list: Vec<String> = Vec::new();
 
     // 0 - executable photoneo_id
-    args_list.push(format!(
-        "{}/cpp_executables/dev/{}/{}_Release",
-        phoxi_interface_path, capcom, capcom
-    ));
+    args_list.push(executable.to_string());
 
     // 1 - scanner hardware identification
     args_list.push(request.hardware_identification.to_string());
@@ -292,183 +651,72 @@ fn prepare_arguments(
     args_list.push(bool_to_arg(request.tif));
 
     // 6 - capturing_settings::shutter_multiplier
-    args_list.push(
-        settings["capturing_settings"]["shutter_multiplier"]["value"]
-            .as_i64()
-            .unwrap_or(1)
-            .to_string(),
-    );
+    args_list.push(cap.shutter_multiplier.value.to_string());
 
     // 7 - capturing_settings::scan_multiplier
-    args_list.push(
-        settings["capturing_settings"]["scan_multiplier"]["value"]
-            .as_i64()
-            .unwrap_or(1)
-            .to_string(),
-    );
+    args_list.push(cap.scan_multiplier.value.to_string());
 
     // 8 - capturing_settings::resolution
-    if parameters["name_identification"]
-        .as_str()
-        .unwrap_or("photoneo_1708011")
-        .to_string()
-        == "photoneo_1708011"
-    {
-        args_list.push(resolution_to_arg(
-            &settings["capturing_settings"]["resolution"]["min"],
-        ));
-    } else {
-        args_list.push(resolution_to_arg(
-            &settings["capturing_settings"]["resolution"]["value"],
-        ));
-    }
+    args_list.push(resolution_to_arg(&effective_resolution(settings, parameters)));
 
     // 9 - capturing_settings::camera_only_mode
-    args_list.push(bool_to_arg(
-        settings["capturing_settings"]["camera_only_mode"]["value"]
-            .as_bool()
-            .unwrap_or(false),
-    ));
+    args_list.push(bool_to_arg(cap.camera_only_mode.value));
 
     // 10 - capturing_settings::ambient_light_suppression
-    args_list.push(bool_to_arg(
-        settings["capturing_settings"]["ambient_light_suppression"]["value"]
-            .as_bool()
-            .unwrap_or(false),
-    ));
+    args_list.push(bool_to_arg(cap.ambient_light_suppression.value));
 
     // 11 - capturing_settings::coding_strategy
-    args_list.push(
-        settings["capturing_settings"]["coding_strategy"]["value"]
-            .as_str()
-            .unwrap_or("Interreflections")
-            .to_string(),
-    );
+    args_list.push(cap.coding_strategy.value.to_string());
 
     // 12 - capturing_settings::coding_quality
-    args_list.push(
-        settings["capturing_settings"]["coding_quality"]["value"]
-            .as_str()
-            .unwrap_or("High")
-            .to_string(),
-    );
+    args_list.push(cap.coding_quality.value.to_string());
 
     // 13 - capturing_settings::texture_source
-    args_list.push(
-        settings["capturing_settings"]["texture_source"]["value"]
-            .as_str()
-            .unwrap_or("LED")
-            .to_string(),
-    );
+    args_list.push(cap.texture_source.value.to_string());
 
     // 14 - capturing_settings::single_pattern_exposure
-    args_list.push(
-        settings["capturing_settings"]["single_pattern_exposure"]["value"]
-            .as_f64()
-            .unwrap_or(10.24)
-            .to_string(),
-    );
+    args_list.push(cap.single_pattern_exposure.value.to_string());
 
     // 15 - capturing_settings::maximum_fps
-    args_list.push(
-        settings["capturing_settings"]["maximum_fps"]["value"]
-            .as_f64()
-            .unwrap_or(0.0)
-            .to_string(),
-    );
+    args_list.push(cap.maximum_fps.value.to_string());
 
     // 16 - capturing_settings::laser_power
-    args_list.push(
-        settings["capturing_settings"]["laser_power"]["value"]
-            .as_i64()
-            .unwrap_or(4095)
-            .to_string(),
-    );
+    args_list.push(cap.laser_power.value.to_string());
 
     // 17 - capturing_settings::projection_offset_left
-    args_list.push(
-        settings["capturing_settings"]["projection_offset_left"]["value"]
-            .as_i64()
-            .unwrap_or(0)
-            .to_string(),
-    );
+    args_list.push(cap.projection_offset_left.value.to_string());
 
     // 18 - capturing_settings::projection_offset_right
-    args_list.push(
-        settings["capturing_settings"]["projection_offset_right"]["value"]
-            .as_i64()
-            .unwrap_or(0)
-            .to_string(),
-    );
+    args_list.push(cap.projection_offset_right.value.to_string());
 
     // 19 - capturing_settings::led_power
-    args_list.push(
-        settings["capturing_settings"]["led_power"]["value"]
-            .as_i64()
-            .unwrap_or(4095)
-            .to_string(),
-    );
+    args_list.push(cap.led_power.value.to_string());
 
     // 20 - processing_settings::max_inaccuracy
-    args_list.push(
-        settings["processing_settings"]["max_inaccuracy"]["value"]
-            .as_f64()
-            .unwrap_or(2.0)
-            .to_string(),
-    );
+    args_list.push(pro.max_inaccuracy.value.to_string());
 
     // 21 - processing_settings::surface_smoothness
-    args_list.push(
-        settings["processing_settings"]["surface_smoothness"]["value"]
-            .as_str()
-            .unwrap_or("Normal")
-            .to_string(),
-    );
+    args_list.push(pro.surface_smoothness.value.to_string());
 
     // 22 - processing_settings::normals_estimation_radius
-    args_list.push(
-        settings["processing_settings"]["normals_estimation_radius"]["value"]
-            .as_i64()
-            .unwrap_or(2)
-            .to_string(),
-    );
+    args_list.push(pro.normals_estimation_radius.value.to_string());
 
     // 23 - processing_settings::interreflections_filter
-    args_list.push(bool_to_arg(
-        settings["processing_settings"]["interreflections_filter"]["value"]
-            .as_bool()
-            .unwrap_or(false),
-    ));
+    args_list.push(bool_to_arg(pro.interreflections_filter.value));
 
     // 24 - experimental_settings::ambient_light_suppression_compatibility_mode
     args_list.push(bool_to_arg(
-        settings["experimental_settings"]["ambient_light_suppression_compatibility_mode"]["value"]
-            .as_bool()
-            .unwrap_or(false),
+        exp.ambient_light_suppression_compatibility_mode.value,
     ));
 
     // 25 - experimental_settings::pattern_decomposition_reach
-    args_list.push(
-        settings["experimental_settings"]["pattern_decomposition_reach"]["value"]
-            .as_str()
-            .unwrap_or("Local")
-            .to_string(),
-    );
+    args_list.push(exp.pattern_decomposition_reach.value.to_string());
 
     // 26 - experimental_settings::signal_contrast_threshold
-    args_list.push(
-        settings["experimental_settings"]["signal_contrast_threshold"]["value"]
-            .as_f64()
-            .unwrap_or(0.032)
-            .to_string(),
-    );
+    args_list.push(exp.signal_contrast_threshold.value.to_string());
 
     // 27 - experimental_settings::use_extended_logging
-    args_list.push(bool_to_arg(
-        settings["experimental_settings"]["use_extended_logging"]["value"]
-            .as_bool()
-            .unwrap_or(false),
-    ));
+    args_list.push(bool_to_arg(exp.use_extended_logging.value));
 
     // 28 - Where to save the praw files
     args_list.push(request.praw_dir.clone());
@@ -490,14 +738,6 @@ fn prepare_arguments(
     args_list
 }
 
-fn capitalize_first(s: &str) -> String {
-    let mut chars = s.chars();
-    match chars.next() {
-        None => String::new(),
-        Some(f) => f.to_uppercase().collect::<String>() + chars.as_str(),
-    }
-}
-
 fn bool_to_arg(value: bool) -> String {
     if value {
         "1".to_string()
@@ -506,23 +746,9 @@ fn bool_to_arg(value: bool) -> String {
     }
 }
 
-fn resolution_to_arg(value: &Value) -> String {
-    // "0" is 2064x1544 and "1" is 1032x772
-    if value["width"] == 2064 && value["height"] == 1544 {
-        "0".to_string()
-    } else if value["width"] == 1032 && value["height"] == 772 {
-        "1".to_string()
-    } else {
-        log::error!(target: &&format!(
-            "phoxi_control_interface"),
-            "Unsupported Photoneo resolution."
-        );
-        log::error!(target: &&format!(
-            "phoxi_control_interface"),
-            "Resolution defaulting to 2064x1544."
-        );
-        "0".to_string()
-    }
+fn resolution_to_arg(value: &Resolution) -> String {
+    // Settings are validated on load, so only supported resolutions get here.
+    value.capturing_mode().unwrap_or(0).to_string()
 }
 
 fn load_json_from_file(path: &str) -> Option<Value> {
@@ -1,6 +1,18 @@
 pub mod state;
 // pub mod state_manager;
+pub mod backend;
+pub mod calibration;
+pub mod error;
+pub mod hand_eye;
+pub mod health;
 pub mod interface;
+pub mod request_file;
+pub mod scan_quality;
+pub mod settings;
+pub mod simulation;
+
+// Shared with the other interface, see photoneo_common.
+pub use photoneo_common::{executor, parameters, ply, retry, scanners};
 
 pub const DEFAULT_SETTINGS_JSON: &str = r#"
 {
@@ -285,7 +297,7 @@ pub const DEFAULT_SETTINGS_JSON: &str = r#"
         },
         "fine_alignment_point_set": {
             "value": "Surface",
-            "deafult": "Surface",
+            "default": "Surface",
             "min": "Surface",
             "max": "Edges",
             "info": "Values: Surface, Edges. This refers to iterative closest point (ICP) algorithm implementation which refines the object's pose in the scene. This defines which points from model and scene will be used in the algorithm. Surface points - default. ICP algorithm will use only those points that are not on the edges. Edge points - useful for objects which are completely described by their edges only (boxes, etc.)."
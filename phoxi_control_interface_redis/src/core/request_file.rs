@@ -0,0 +1,179 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::settings::{Resolution, ScannerSettings};
+use super::state::ScanRequest;
+
+// Bump together with SCAN_REQUEST_SCHEMA_VERSION in
+// cpp_executables/common/ScanRequest.h whenever the document changes shape.
+pub const REQUEST_SCHEMA_VERSION: u32 = 1;
+
+// How a request is handed over to the C++ executables. The positional
+// argv protocol is kept around until all deployed executables read files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RequestProtocol {
+    File,
+    Argv,
+}
+
+// The executables that read their request with LoadScanRequest from
+// cpp_executables/common/ScanRequest.h. The others, ConnectIPv4,
+// CaptureNew and CaptureNewIPv4, only know the positional arguments.
+pub const REQUEST_FILE_COMMANDS: [&str; 5] =
+    ["Connect", "Capture", "Freerun", "Stop", "Disconnect"];
+
+impl RequestProtocol {
+    pub fn from_env() -> RequestProtocol {
+        match std::env::var("PHOXI_REQUEST_PROTOCOL") {
+            Ok(protocol) if protocol.eq_ignore_ascii_case("argv") => RequestProtocol::Argv,
+            Ok(protocol) if protocol.eq_ignore_ascii_case("file") => RequestProtocol::File,
+            Ok(protocol) => {
+                log::warn!(target: "phoxi_control_interface",
+                    "Unknown PHOXI_REQUEST_PROTOCOL '{}', using 'file'.", protocol);
+                RequestProtocol::File
+            }
+            Err(_) => RequestProtocol::File,
+        }
+    }
+
+    // The protocol the executable of the command understands, argv for
+    // the ones that do not read request files yet.
+    pub fn for_command(self, command: &str) -> RequestProtocol {
+        match self {
+            RequestProtocol::File if REQUEST_FILE_COMMANDS.contains(&command) => {
+                RequestProtocol::File
+            }
+            _ => RequestProtocol::Argv,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScannerIdentity {
+    pub name_identification: String,
+    pub hardware_identification: String,
+    pub ip_identification: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputFormats {
+    pub praw: bool,
+    pub ply: bool,
+    pub tif: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputDirectories {
+    pub praw_dir: String,
+    pub ply_dir: String,
+    pub tif_dir: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanRequestDocument {
+    pub schema_version: u32,
+    pub command: String,
+    pub scene_name: String,
+    pub scanner: ScannerIdentity,
+    pub outputs: OutputFormats,
+    pub directories: OutputDirectories,
+    // Only the effective values of the preset, without default/min/max/info.
+    pub settings: Value,
+}
+
+impl ScanRequestDocument {
+    pub fn new(
+        request: &ScanRequest,
+        settings: &ScannerSettings,
+        resolution: Resolution,
+        parameters: &Value,
+    ) -> ScanRequestDocument {
+        let mut settings = setting_values(serde_json::to_value(settings).unwrap_or(Value::Null));
+        settings["capturing_settings"]["resolution"] =
+            serde_json::to_value(resolution).unwrap_or(Value::Null);
+
+        ScanRequestDocument {
+            schema_version: REQUEST_SCHEMA_VERSION,
+            command: request.command_type.clone(),
+            scene_name: request.scene_name.clone(),
+            scanner: ScannerIdentity {
+                name_identification: parameters["name_identification"]
+                    .as_str()
+                    .unwrap_or(&request.name_identification)
+                    .to_string(),
+                hardware_identification: request.hardware_identification.clone(),
+                ip_identification: parameters["ip_identification"]
+                    .as_str()
+                    .unwrap_or("192.168.1.27")
+                    .to_string(),
+            },
+            outputs: OutputFormats {
+                praw: request.praw,
+                ply: request.ply,
+                tif: request.tif,
+            },
+            directories: OutputDirectories {
+                praw_dir: request.praw_dir.clone(),
+                ply_dir: request.ply_dir.clone(),
+                tif_dir: request.tif_dir.clone(),
+            },
+            settings,
+        }
+    }
+}
+
+// Replaces every {value, default, min, max, info} entry with its value.
+fn setting_values(value: Value) -> Value {
+    match value {
+        Value::Object(mut map) if map.contains_key("value") && map.contains_key("info") => {
+            map.remove("value").unwrap_or(Value::Null)
+        }
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| (key, setting_values(value)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+// A request document on disk, removed again when dropped.
+pub struct RequestFile {
+    path: PathBuf,
+}
+
+impl RequestFile {
+    pub fn write<T: Serialize>(document: &T, prefix: &str) -> io::Result<RequestFile> {
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let path = std::env::temp_dir().join(format!(
+            "{}_{}_{}.json",
+            prefix,
+            std::process::id(),
+            stamp
+        ));
+        let contents = serde_json::to_vec_pretty(document)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut file = std::fs::File::create(&path)?;
+        file.write_all(&contents)?;
+        Ok(RequestFile { path })
+    }
+
+    pub fn argument(&self) -> String {
+        format!("--request={}", self.path.display())
+    }
+}
+
+impl Drop for RequestFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            log::warn!(target: "phoxi_control_interface",
+                "Failed to remove request file {}: {}", self.path.display(), e);
+        }
+    }
+}
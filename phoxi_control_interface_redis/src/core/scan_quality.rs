@@ -0,0 +1,213 @@
+use micro_sp::*;
+use std::path::Path;
+
+use super::ply::{is_valid_point, PointCloud};
+use super::settings::{ImageRegion, OnViolation, Resolution, ScanQualitySettings};
+
+// What a saved scan looks like, measured on its ply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanQuality {
+    pub points: usize,
+    pub valid_points: usize,
+    // Points without a measurement, 0 0 0 or NaN.
+    pub invalid_fraction: f64,
+    // Nearest and farthest valid point, None if there is none.
+    pub depth_range: Option<(f64, f64)>,
+    // None without a roi, or if the scan is not a full camera image.
+    pub roi_coverage: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScanVerdict {
+    Passed,
+    Degraded,
+    Failed,
+}
+
+impl ScanVerdict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScanVerdict::Passed => "passed",
+            ScanVerdict::Degraded => "degraded",
+            ScanVerdict::Failed => "failed",
+        }
+    }
+}
+
+// Published as {id}_scan_quality after every capture that saved a ply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanCheck {
+    // None if the ply could not be read.
+    pub quality: Option<ScanQuality>,
+    pub violations: Vec<String>,
+    pub verdict: ScanVerdict,
+}
+
+impl ScanQuality {
+    pub fn measure(cloud: &PointCloud, roi: Option<&ImageRegion>) -> ScanQuality {
+        let mut valid_points = 0;
+        let mut depth_range: Option<(f64, f64)> = None;
+        for point in cloud.points.iter().filter(|p| is_valid_point(p)) {
+            valid_points += 1;
+            let depth = point[2] as f64;
+            depth_range = Some(match depth_range {
+                Some((near, far)) => (near.min(depth), far.max(depth)),
+                None => (depth, depth),
+            });
+        }
+        let invalid_fraction = match cloud.len() {
+            0 => 1.0,
+            points => (points - valid_points) as f64 / points as f64,
+        };
+        ScanQuality {
+            points: cloud.len(),
+            valid_points,
+            invalid_fraction,
+            depth_range,
+            roi_coverage: roi.and_then(|roi| roi_coverage(cloud, roi)),
+        }
+    }
+
+    pub fn violations(&self, settings: &ScanQualitySettings) -> Vec<String> {
+        let mut violations = Vec::new();
+        if let Some(min) = settings.min_valid_points {
+            if (self.valid_points as u64) < min {
+                violations.push(format!(
+                    "{} valid points, expected at least {}",
+                    self.valid_points, min
+                ));
+            }
+        }
+        if let Some(max) = settings.max_invalid_fraction {
+            if self.invalid_fraction > max {
+                violations.push(format!(
+                    "{:.3} of the points are invalid, expected at most {}",
+                    self.invalid_fraction, max
+                ));
+            }
+        }
+        let depth_limits = settings.min_depth.is_some() || settings.max_depth.is_some();
+        match (self.depth_range, settings.min_depth, settings.max_depth) {
+            (None, _, _) if depth_limits => {
+                violations.push("no valid points to measure the depth of".to_string())
+            }
+            (Some((near, far)), min, max) => {
+                if let Some(min) = min.filter(|min| near < *min) {
+                    violations.push(format!(
+                        "nearest point at {:.1} mm, expected at least {} mm",
+                        near, min
+                    ));
+                }
+                if let Some(max) = max.filter(|max| far > *max) {
+                    violations.push(format!(
+                        "farthest point at {:.1} mm, expected at most {} mm",
+                        far, max
+                    ));
+                }
+            }
+            _ => (),
+        }
+        if let Some(min) = settings.min_roi_coverage {
+            match self.roi_coverage {
+                None => violations.push(format!(
+                    "roi coverage unknown, {} points are not a full camera image",
+                    self.points
+                )),
+                Some(coverage) if coverage < min => violations.push(format!(
+                    "roi coverage {:.3}, expected at least {}",
+                    coverage, min
+                )),
+                Some(_) => (),
+            }
+        }
+        violations
+    }
+}
+
+// PhoXiControl saves one point per pixel, row by row, so the pixels of the
+// roi can be found if the scan has as many points as a camera image.
+fn roi_coverage(cloud: &PointCloud, roi: &ImageRegion) -> Option<f64> {
+    let (width, height) = [Resolution::FULL, Resolution::HALF]
+        .iter()
+        .map(|r| (r.width as usize, r.height as usize))
+        .find(|(width, height)| width * height == cloud.len())?;
+
+    let columns =
+        (roi.left * width as f64).floor() as usize..(roi.right * width as f64).ceil() as usize;
+    let rows =
+        (roi.top * height as f64).floor() as usize..(roi.bottom * height as f64).ceil() as usize;
+    let columns = columns.start.min(width)..columns.end.min(width);
+    let rows = rows.start.min(height)..rows.end.min(height);
+
+    let pixels = columns.len() * rows.len();
+    if pixels == 0 {
+        return None;
+    }
+    let valid = rows
+        .flat_map(|row| columns.clone().map(move |column| row * width + column))
+        .filter(|i| is_valid_point(&cloud.points[*i]))
+        .count();
+    Some(valid as f64 / pixels as f64)
+}
+
+// Measures the saved scan and checks it against the thresholds of the
+// settings preset. A ply that cannot be read fails the scan if there are
+// thresholds to fail it, and degrades it otherwise.
+pub fn check_scan(path: &Path, settings: Option<&ScanQualitySettings>) -> ScanCheck {
+    let no_thresholds = ScanQualitySettings::default();
+    let settings = settings.unwrap_or(&no_thresholds);
+    let (quality, violations) = match PointCloud::read(path) {
+        Ok(cloud) => {
+            let quality = ScanQuality::measure(&cloud, settings.roi.as_ref());
+            let violations = quality.violations(settings);
+            (Some(quality), violations)
+        }
+        Err(e) => (
+            None,
+            vec![format!("could not read {}: {}", path.display(), e)],
+        ),
+    };
+    let verdict = if violations.is_empty() {
+        ScanVerdict::Passed
+    } else if settings.has_thresholds() && settings.on_violation == OnViolation::Fail {
+        ScanVerdict::Failed
+    } else {
+        ScanVerdict::Degraded
+    };
+    ScanCheck {
+        quality,
+        violations,
+        verdict,
+    }
+}
+
+impl ScanCheck {
+    pub fn to_map(&self) -> MapOrUnknown {
+        let mut map = vec![
+            ("verdict".to_spvalue(), self.verdict.as_str().to_spvalue()),
+            (
+                "violations".to_spvalue(),
+                self.violations.join("; ").to_spvalue(),
+            ),
+        ];
+        if let Some(quality) = &self.quality {
+            map.push(("points".to_spvalue(), (quality.points as i64).to_spvalue()));
+            map.push((
+                "valid_points".to_spvalue(),
+                (quality.valid_points as i64).to_spvalue(),
+            ));
+            map.push((
+                "invalid_fraction".to_spvalue(),
+                quality.invalid_fraction.to_spvalue(),
+            ));
+            if let Some((near, far)) = quality.depth_range {
+                map.push(("min_depth".to_spvalue(), near.to_spvalue()));
+                map.push(("max_depth".to_spvalue(), far.to_spvalue()));
+            }
+            if let Some(coverage) = quality.roi_coverage {
+                map.push(("roi_coverage".to_spvalue(), coverage.to_spvalue()));
+            }
+        }
+        MapOrUnknown::Map(map)
+    }
+}
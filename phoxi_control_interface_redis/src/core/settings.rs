@@ -0,0 +1,559 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+// Typed view of the settings presets in parameters/settings/*.json.
+// Every tunable entry has the shape {value, default, min, max, info}
+// and is checked against its own bounds when the preset is loaded.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Setting<T> {
+    pub value: T,
+    pub default: T,
+    pub min: T,
+    pub max: T,
+    pub info: String,
+}
+
+macro_rules! setting_enum {
+    ($name:ident { $($variant:ident => $label:literal),+ $(,)? }) => {
+        // Variants are declared in the order used by the min/max bounds.
+        #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+        pub enum $name {
+            $(#[serde(rename = $label)] $variant),+
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    $($name::$variant => write!(f, $label)),+
+                }
+            }
+        }
+    };
+}
+
+setting_enum!(CodingStrategy {
+    Normal => "Normal",
+    Interreflections => "Interreflections",
+});
+
+setting_enum!(CodingQuality {
+    Fast => "Fast",
+    High => "High",
+    Ultra => "Ultra",
+});
+
+setting_enum!(TextureSource {
+    Led => "LED",
+    Computed => "Computed",
+    Laser => "Laser",
+    Focus => "Focus",
+});
+
+setting_enum!(SurfaceSmoothness {
+    Sharp => "Sharp",
+    Normal => "Normal",
+    Smooth => "Smooth",
+});
+
+setting_enum!(PatternDecompositionReach {
+    Local => "Local",
+    Small => "Small",
+    Medium => "Medium",
+    Large => "Large",
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Resolution {
+    pub const FULL: Resolution = Resolution { width: 2064, height: 1544 };
+    pub const HALF: Resolution = Resolution { width: 1032, height: 772 };
+
+    // Index into the scanner's supported capturing modes.
+    // "0" is 2064x1544 and "1" is 1032x772.
+    pub fn capturing_mode(&self) -> Option<usize> {
+        if *self == Resolution::FULL {
+            Some(0)
+        } else if *self == Resolution::HALF {
+            Some(1)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+// The PhoXi only accepts these discrete exposures, in milliseconds.
+pub const SINGLE_PATTERN_EXPOSURES: [f64; 12] = [
+    10.24, 14.336, 20.48, 24.576, 30.72, 34.816, 40.96, 49.152, 75.776, 79.872, 90.112, 100.352,
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapturingSettings {
+    pub shutter_multiplier: Setting<u32>,
+    pub scan_multiplier: Setting<u32>,
+    pub resolution: Setting<Resolution>,
+    pub camera_only_mode: Setting<bool>,
+    pub ambient_light_suppression: Setting<bool>,
+    pub coding_strategy: Setting<CodingStrategy>,
+    pub coding_quality: Setting<CodingQuality>,
+    pub texture_source: Setting<TextureSource>,
+    pub single_pattern_exposure: Setting<f64>,
+    pub maximum_fps: Setting<f64>,
+    pub laser_power: Setting<u32>,
+    pub projection_offset_left: Setting<u32>,
+    pub projection_offset_right: Setting<u32>,
+    pub led_power: Setting<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessingSettings {
+    pub max_inaccuracy: Setting<f64>,
+    pub surface_smoothness: Setting<SurfaceSmoothness>,
+    pub normals_estimation_radius: Setting<u32>,
+    pub interreflections_filter: Setting<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExperimentalSettings {
+    pub ambient_light_suppression_compatibility_mode: Setting<bool>,
+    pub pattern_decomposition_reach: Setting<PatternDecompositionReach>,
+    pub signal_contrast_threshold: Setting<f64>,
+    pub use_extended_logging: Setting<bool>,
+}
+
+// Placeholder for the calibration and coordinates sections,
+// which are not forwarded to the scanner yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnimplementedSettings {
+    pub unimplemented: bool,
+    pub comment: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputSettings {
+    pub send_confidence_map: bool,
+    pub send_depth_map: bool,
+    pub send_normal_map: bool,
+    pub send_point_cloud: bool,
+    pub send_texture: bool,
+}
+
+// The optional "scan_quality" section, thresholds every saved ply is
+// checked against, see check_scan. Checks without a threshold are skipped:
+//
+//     "scan_quality": {
+//         "min_valid_points": 200000,
+//         "max_invalid_fraction": 0.6,
+//         "min_depth": 400.0,
+//         "max_depth": 2000.0,
+//         "roi": {"left": 0.25, "top": 0.2, "right": 0.75, "bottom": 0.8},
+//         "min_roi_coverage": 0.8,
+//         "on_violation": "fail"
+//     }
+//
+// Depths are in millimeters. The roi is a part of the camera image, in
+// fractions of its width and height, and its coverage is the fraction of
+// its pixels with a valid point.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScanQualitySettings {
+    pub min_valid_points: Option<u64>,
+    pub max_invalid_fraction: Option<f64>,
+    pub min_depth: Option<f64>,
+    pub max_depth: Option<f64>,
+    pub roi: Option<ImageRegion>,
+    pub min_roi_coverage: Option<f64>,
+    pub on_violation: OnViolation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImageRegion {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+}
+
+// A failed scan fails the request, a degraded one only says so in
+// {id}_scan_quality.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnViolation {
+    #[default]
+    Fail,
+    Degrade,
+}
+
+impl ScanQualitySettings {
+    pub fn has_thresholds(&self) -> bool {
+        self.min_valid_points.is_some()
+            || self.max_invalid_fraction.is_some()
+            || self.min_depth.is_some()
+            || self.max_depth.is_some()
+            || self.min_roi_coverage.is_some()
+    }
+
+    fn validate(&self, violations: &mut Vec<SettingsViolation>) {
+        let mut violation = |field: &str, reason: String| {
+            violations.push(SettingsViolation {
+                field: format!("scan_quality.{}", field),
+                reason,
+            })
+        };
+        for (field, fraction) in [
+            ("max_invalid_fraction", self.max_invalid_fraction),
+            ("min_roi_coverage", self.min_roi_coverage),
+        ] {
+            if let Some(fraction) = fraction.filter(|f| !(0.0..=1.0).contains(f)) {
+                violation(field, format!("value {} is not between 0 and 1", fraction));
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_depth, self.max_depth) {
+            if min > max {
+                violation("min_depth", format!("value {} is above max_depth {}", min, max));
+            }
+        }
+        if let Some(roi) = &self.roi {
+            let inside = [roi.left, roi.top, roi.right, roi.bottom]
+                .iter()
+                .all(|f| (0.0..=1.0).contains(f));
+            if !inside || roi.left >= roi.right || roi.top >= roi.bottom {
+                violation(
+                    "roi",
+                    "expected 0 <= left < right <= 1 and 0 <= top < bottom <= 1".to_string(),
+                );
+            }
+        }
+        if self.min_roi_coverage.is_some() && self.roi.is_none() {
+            violation("min_roi_coverage", "no roi to cover".to_string());
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScannerSettings {
+    pub capturing_settings: CapturingSettings,
+    pub processing_settings: ProcessingSettings,
+    pub experimental_settings: ExperimentalSettings,
+    pub calibration_settings: UnimplementedSettings,
+    pub coordinates_settings: UnimplementedSettings,
+    pub output_settings: OutputSettings,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scan_quality: Option<ScanQualitySettings>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingsViolation {
+    pub field: String,
+    pub reason: String,
+}
+
+impl fmt::Display for SettingsViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.reason)
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Unreadable { file: String, reason: String },
+    Invalid { file: String, violations: Vec<SettingsViolation> },
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Unreadable { file, reason } => {
+                write!(f, "Failed to read settings file '{}': {}", file, reason)
+            }
+            SettingsError::Invalid { file, violations } => {
+                write!(f, "Settings file '{}' is invalid: ", file)?;
+                let report: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+                write!(f, "{}", report.join("; "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+// Reads the fields of one settings section, recording a violation for
+// every field that is missing, has the wrong shape or is not expected.
+struct SectionReader<'a> {
+    section: &'static str,
+    object: Option<&'a serde_json::Map<String, Value>>,
+    violations: &'a mut Vec<SettingsViolation>,
+}
+
+impl<'a> SectionReader<'a> {
+    fn new(
+        root: &'a Value,
+        section: &'static str,
+        violations: &'a mut Vec<SettingsViolation>,
+    ) -> Self {
+        let object = root[section].as_object();
+        if object.is_none() {
+            violations.push(SettingsViolation {
+                field: section.to_string(),
+                reason: "missing section".to_string(),
+            });
+        }
+        SectionReader {
+            section,
+            object,
+            violations,
+        }
+    }
+
+    fn field<T: DeserializeOwned>(&mut self, name: &str) -> Option<T> {
+        let object = self.object?;
+        let reason = match object.get(name) {
+            Some(value) => match T::deserialize(value) {
+                Ok(parsed) => return Some(parsed),
+                Err(e) => e.to_string(),
+            },
+            None => "missing field".to_string(),
+        };
+        self.violations.push(SettingsViolation {
+            field: format!("{}.{}", self.section, name),
+            reason,
+        });
+        None
+    }
+
+    fn finish(self, expected: &[&str]) {
+        if let Some(object) = self.object {
+            for key in object.keys().filter(|k| !expected.contains(&k.as_str())) {
+                self.violations.push(SettingsViolation {
+                    field: format!("{}.{}", self.section, key),
+                    reason: "unknown field".to_string(),
+                });
+            }
+        }
+    }
+}
+
+macro_rules! read_section {
+    ($root:expr, $section:literal, $violations:expr, $ty:ident { $($field:ident),+ $(,)? }) => {{
+        let mut reader = SectionReader::new($root, $section, $violations);
+        $(let $field = reader.field(stringify!($field));)+
+        reader.finish(&[$(stringify!($field)),+]);
+        match ($($field,)+) {
+            ($(Some($field),)+) => Some($ty { $($field),+ }),
+            _ => None,
+        }
+    }};
+}
+
+fn check_range<T: PartialOrd + fmt::Display>(
+    field: &str,
+    setting: &Setting<T>,
+    violations: &mut Vec<SettingsViolation>,
+) {
+    if setting.value < setting.min || setting.value > setting.max {
+        violations.push(SettingsViolation {
+            field: field.to_string(),
+            reason: format!(
+                "value {} is outside of the allowed range [{}, {}]",
+                setting.value, setting.min, setting.max
+            ),
+        });
+    }
+}
+
+impl ScannerSettings {
+    pub fn load(path: &str) -> Result<ScannerSettings, SettingsError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| SettingsError::Unreadable {
+            file: path.to_string(),
+            reason: e.to_string(),
+        })?;
+        let root: Value = serde_json::from_str(&contents).map_err(|e| SettingsError::Unreadable {
+            file: path.to_string(),
+            reason: e.to_string(),
+        })?;
+        ScannerSettings::from_value(&root, path)
+    }
+
+    pub fn builtin() -> Result<ScannerSettings, SettingsError> {
+        let file = "<built-in default settings>";
+        let root: Value = serde_json::from_str(crate::core::DEFAULT_SETTINGS_JSON).map_err(|e| {
+            SettingsError::Unreadable {
+                file: file.to_string(),
+                reason: e.to_string(),
+            }
+        })?;
+        ScannerSettings::from_value(&root, file)
+    }
+
+    pub fn from_value(root: &Value, file: &str) -> Result<ScannerSettings, SettingsError> {
+        let mut violations = Vec::new();
+
+        let capturing_settings = read_section!(root, "capturing_settings", &mut violations, CapturingSettings {
+            shutter_multiplier,
+            scan_multiplier,
+            resolution,
+            camera_only_mode,
+            ambient_light_suppression,
+            coding_strategy,
+            coding_quality,
+            texture_source,
+            single_pattern_exposure,
+            maximum_fps,
+            laser_power,
+            projection_offset_left,
+            projection_offset_right,
+            led_power,
+        });
+
+        let processing_settings = read_section!(root, "processing_settings", &mut violations, ProcessingSettings {
+            max_inaccuracy,
+            surface_smoothness,
+            normals_estimation_radius,
+            interreflections_filter,
+        });
+
+        let experimental_settings = read_section!(root, "experimental_settings", &mut violations, ExperimentalSettings {
+            ambient_light_suppression_compatibility_mode,
+            pattern_decomposition_reach,
+            signal_contrast_threshold,
+            use_extended_logging,
+        });
+
+        let calibration_settings = read_section!(root, "calibration_settings", &mut violations, UnimplementedSettings {
+            unimplemented,
+            comment,
+        });
+
+        let coordinates_settings = read_section!(root, "coordinates_settings", &mut violations, UnimplementedSettings {
+            unimplemented,
+            comment,
+        });
+
+        let output_settings = read_section!(root, "output_settings", &mut violations, OutputSettings {
+            send_confidence_map,
+            send_depth_map,
+            send_normal_map,
+            send_point_cloud,
+            send_texture,
+        });
+
+        let scan_quality = match root.get("scan_quality") {
+            None | Some(Value::Null) => None,
+            Some(section) => ScanQualitySettings::deserialize(section)
+                .map_err(|e| {
+                    violations.push(SettingsViolation {
+                        field: "scan_quality".to_string(),
+                        reason: e.to_string(),
+                    })
+                })
+                .ok(),
+        };
+
+        let settings = match (
+            capturing_settings,
+            processing_settings,
+            experimental_settings,
+            calibration_settings,
+            coordinates_settings,
+            output_settings,
+        ) {
+            (Some(cap), Some(pro), Some(exp), Some(cal), Some(coo), Some(out)) => ScannerSettings {
+                capturing_settings: cap,
+                processing_settings: pro,
+                experimental_settings: exp,
+                calibration_settings: cal,
+                coordinates_settings: coo,
+                output_settings: out,
+                scan_quality,
+            },
+            _ => {
+                return Err(SettingsError::Invalid {
+                    file: file.to_string(),
+                    violations,
+                })
+            }
+        };
+
+        settings.validate(&mut violations);
+        if violations.is_empty() {
+            Ok(settings)
+        } else {
+            Err(SettingsError::Invalid {
+                file: file.to_string(),
+                violations,
+            })
+        }
+    }
+
+    fn validate(&self, violations: &mut Vec<SettingsViolation>) {
+        let cap = &self.capturing_settings;
+        check_range("capturing_settings.shutter_multiplier", &cap.shutter_multiplier, violations);
+        check_range("capturing_settings.scan_multiplier", &cap.scan_multiplier, violations);
+        check_range("capturing_settings.resolution", &cap.resolution, violations);
+        check_range("capturing_settings.coding_strategy", &cap.coding_strategy, violations);
+        check_range("capturing_settings.coding_quality", &cap.coding_quality, violations);
+        check_range("capturing_settings.texture_source", &cap.texture_source, violations);
+        check_range("capturing_settings.single_pattern_exposure", &cap.single_pattern_exposure, violations);
+        check_range("capturing_settings.maximum_fps", &cap.maximum_fps, violations);
+        check_range("capturing_settings.laser_power", &cap.laser_power, violations);
+        check_range("capturing_settings.projection_offset_left", &cap.projection_offset_left, violations);
+        check_range("capturing_settings.projection_offset_right", &cap.projection_offset_right, violations);
+        check_range("capturing_settings.led_power", &cap.led_power, violations);
+
+        for (field, resolution) in [
+            ("capturing_settings.resolution", cap.resolution.value),
+            ("capturing_settings.resolution.min", cap.resolution.min),
+        ] {
+            if resolution.capturing_mode().is_none() {
+                violations.push(SettingsViolation {
+                    field: field.to_string(),
+                    reason: format!(
+                        "unsupported resolution {}, expected {} or {}",
+                        resolution,
+                        Resolution::FULL,
+                        Resolution::HALF
+                    ),
+                });
+            }
+        }
+
+        let exposure = cap.single_pattern_exposure.value;
+        if !SINGLE_PATTERN_EXPOSURES.iter().any(|e| (e - exposure).abs() < 1e-6) {
+            violations.push(SettingsViolation {
+                field: "capturing_settings.single_pattern_exposure".to_string(),
+                reason: format!(
+                    "value {} is not one of the supported exposures {:?}",
+                    exposure, SINGLE_PATTERN_EXPOSURES
+                ),
+            });
+        }
+
+        let pro = &self.processing_settings;
+        check_range("processing_settings.max_inaccuracy", &pro.max_inaccuracy, violations);
+        check_range("processing_settings.surface_smoothness", &pro.surface_smoothness, violations);
+        check_range("processing_settings.normals_estimation_radius", &pro.normals_estimation_radius, violations);
+
+        let exp = &self.experimental_settings;
+        check_range("experimental_settings.pattern_decomposition_reach", &exp.pattern_decomposition_reach, violations);
+        check_range("experimental_settings.signal_contrast_threshold", &exp.signal_contrast_threshold, violations);
+
+        if let Some(scan_quality) = &self.scan_quality {
+            scan_quality.validate(violations);
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use super::backend::ScannerBackend;
+use super::health::{HealthConfig, ScannerHealth};
+use super::interface::execution_timeout;
+use super::state::ScanRequest;
+
+// A scanner that needs no hardware, for development and CI. Configured per
+// scanner in parameters/simulation/{id}.json, all entries are optional:
+//
+//     {
+//         "latency_ms": 300,
+//         "recordings_dir": "/root/shared_folder/recordings",
+//         "fail_commands": ["freerun"],
+//         "fail_every": 0
+//     }
+//
+// Captures copy {scene_name}.{praw,ply,tif}, or else default.{praw,ply,tif},
+// from the recordings directory into the scan directories of the request.
+// Without a recording a synthetic point cloud is written for the ply, the
+// other formats are reported as not saved.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+    // How long every command takes. Commands that take longer than the
+    // request timeout fail with a timeout, like the executables do.
+    pub latency_ms: u64,
+    pub recordings_dir: Option<String>,
+    // Commands that always fail.
+    pub fail_commands: Vec<String>,
+    // Every n-th command fails, 0 disables it.
+    pub fail_every: u64,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        SimulationConfig {
+            latency_ms: 100,
+            recordings_dir: None,
+            fail_commands: Vec::new(),
+            fail_every: 0,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct SimulatedDevice {
+    freerunning: bool,
+    commands: u64,
+    frames: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct SimulatedBackend {
+    config: SimulationConfig,
+    device: Mutex<SimulatedDevice>,
+}
+
+impl SimulatedBackend {
+    pub fn new(config: SimulationConfig) -> SimulatedBackend {
+        SimulatedBackend {
+            config,
+            device: Mutex::new(SimulatedDevice::default()),
+        }
+    }
+
+    // Falls back to the default configuration if there is no file.
+    pub fn load(phoxi_interface_path: &str, photoneo_id: &str) -> SimulatedBackend {
+        let config_path = format!(
+            "{}/parameters/simulation/{}.json",
+            phoxi_interface_path, photoneo_id
+        );
+        let config = match fs::read_to_string(&config_path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                log::warn!(target: "phoxi_control_interface",
+                    "Invalid simulation config {}: {}. Using the defaults.", config_path, e);
+                SimulationConfig::default()
+            }),
+            Err(_) => SimulationConfig::default(),
+        };
+        log::warn!(target: "phoxi_control_interface",
+            "Using a simulated scanner for {}: {:?}.", photoneo_id, config);
+        SimulatedBackend::new(config)
+    }
+
+    // Simulates the latency and injected failures shared by all commands.
+    async fn begin(
+        &self,
+        command: &str,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<(), io::Error> {
+        let count = {
+            let mut device = self.lock();
+            device.commands += 1;
+            device.commands
+        };
+
+        let timeout = execution_timeout(request.timeout).map(|t| t.as_millis() as u64);
+        if let Some(timeout) = timeout.filter(|t| self.config.latency_ms > *t) {
+            wait(timeout, cancel).await?;
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "Command execution timed out.",
+            ));
+        }
+        wait(self.config.latency_ms, cancel).await?;
+
+        let fails_always = self
+            .config
+            .fail_commands
+            .iter()
+            .any(|c| c.eq_ignore_ascii_case(command));
+        let fails_now = self.config.fail_every > 0 && count % self.config.fail_every == 0;
+        if fails_always || fails_now {
+            return Err(io::Error::other(format!(
+                "Simulated failure of command {} ({}).",
+                count, command
+            )));
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SimulatedDevice> {
+        // The device holds no invariants that a panic could break.
+        self.device.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn connection_line(request: &ScanRequest) -> String {
+        format!(
+            "Connection to the device {} was Successful!",
+            request.hardware_identification
+        )
+    }
+
+    fn save(&self, request: &ScanRequest, extension: &str, dir: &str) -> Result<String, io::Error> {
+        fs::create_dir_all(dir)?;
+        let target = Path::new(dir).join(format!("{}.{}", request.scene_name, extension));
+        if let Some(recording) = self.recording(&request.scene_name, extension) {
+            fs::copy(&recording, &target)?;
+        } else if extension == "ply" {
+            fs::write(&target, synthetic_ply())?;
+        } else {
+            return Ok(format!(
+                "Could not save frame as {} to: {} !",
+                extension,
+                target.display()
+            ));
+        }
+        Ok(format!("Saved frame as {} to: {}", extension, target.display()))
+    }
+
+    fn recording(&self, scene_name: &str, extension: &str) -> Option<PathBuf> {
+        let dir = Path::new(self.config.recordings_dir.as_ref()?);
+        [scene_name, "default"]
+            .iter()
+            .map(|name| dir.join(format!("{}.{}", name, extension)))
+            .find(|path| path.is_file())
+    }
+}
+
+#[async_trait]
+impl ScannerBackend for SimulatedBackend {
+    async fn connect(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.begin("connect", request, cancel).await?;
+        Ok(vec![Self::connection_line(request)])
+    }
+
+    async fn capture(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.begin("capture", request, cancel).await?;
+        let mut lines = vec![Self::connection_line(request)];
+        let frame = {
+            let mut device = self.lock();
+            if device.freerunning {
+                lines.push("Switching to Software trigger mode ".to_string());
+                device.freerunning = false;
+            }
+            device.frames += 1;
+            device.frames
+        };
+        lines.push(format!("Frame was triggered, Frame Id: {}", frame));
+
+        if request.praw {
+            lines.push(self.save(request, "praw", &request.praw_dir)?);
+        }
+        if request.ply {
+            lines.push(self.save(request, "ply", &request.ply_dir)?);
+        }
+        if request.tif {
+            lines.push(self.save(request, "tif", &request.tif_dir)?);
+        }
+        Ok(lines)
+    }
+
+    async fn freerun(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.begin("freerun", request, cancel).await?;
+        let mut device = self.lock();
+        device.freerunning = true;
+        Ok(vec![
+            Self::connection_line(request),
+            "Started freerun acquisition".to_string(),
+        ])
+    }
+
+    async fn stop(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.begin("stop", request, cancel).await?;
+        let mut device = self.lock();
+        let mut lines = vec![Self::connection_line(request)];
+        if device.freerunning {
+            lines.push("Stopping acquisition".to_string());
+        }
+        device.freerunning = false;
+        Ok(lines)
+    }
+
+    async fn disconnect(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.begin("disconnect", request, cancel).await?;
+        let mut device = self.lock();
+        device.freerunning = false;
+        Ok(vec![format!(
+            "Disconnected from the device {}",
+            request.hardware_identification
+        )])
+    }
+
+    // The simulated scanner is always there.
+    async fn health(&self, _ip_identification: &str, _config: &HealthConfig) -> ScannerHealth {
+        ScannerHealth {
+            reachable: true,
+            phoxi_control_running: None,
+        }
+    }
+}
+
+async fn wait(milliseconds: u64, cancel: &CancellationToken) -> Result<(), io::Error> {
+    tokio::select! {
+        _ = tokio::time::sleep(Duration::from_millis(milliseconds)) => Ok(()),
+        _ = cancel.cancelled() => Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "Command execution was cancelled.",
+        )),
+    }
+}
+
+// A 600 x 400 mm table one meter in front of the camera, with a
+// 100 mm cube standing on it, sampled every 10 mm. In millimeters,
+// like the scans of the real scanner.
+fn synthetic_ply() -> String {
+    let mut points: Vec<(f64, f64, f64)> = Vec::new();
+    for i in -30..=30 {
+        for j in -20..=20 {
+            let (x, y) = (i as f64 * 10.0, j as f64 * 10.0);
+            if (-5..=5).contains(&i) && (-5..=5).contains(&j) {
+                points.push((x, y, 900.0));
+            } else {
+                points.push((x, y, 1000.0));
+            }
+        }
+    }
+
+    let mut ply = String::new();
+    let _ = write!(
+        ply,
+        "ply\nformat ascii 1.0\ncomment simulated scan\nelement vertex {}\n\
+         property float x\nproperty float y\nproperty float z\nend_header\n",
+        points.len()
+    );
+    for (x, y, z) in points {
+        let _ = writeln!(ply, "{} {} {}", x, y, z);
+    }
+    ply
+}
@@ -1,15 +1,20 @@
 use micro_sp::*;
 
+// Request state of an aborted request, next to the ServiceRequestState ones.
+pub const REQUEST_CANCELLED: &str = "cancelled";
+
 pub fn generate_photoneo_interface_state(photoneo_name: &str) -> State {
     let state = State::new();
 
     let request_trigger = bv!(&&format!("{}_request_trigger", photoneo_name));
     let request_state = v!(&&format!("{}_request_state", photoneo_name));
+    let request_abort = bv!(&&format!("{}_request_abort", photoneo_name));
     let total_fail_counter = iv!(&&format!("{}_total_fail_counter", photoneo_name));
     let subsequent_fail_counter = iv!(&&format!("{}_subsequent_fail_counter", photoneo_name));
 
     let state = state.add(assign!(request_trigger, false.to_spvalue()));
     let state = state.add(assign!(request_state, "initial".to_spvalue()));
+    let state = state.add(assign!(request_abort, false.to_spvalue()));
     let state = state.add(assign!(total_fail_counter, 0.to_spvalue()));
     let state = state.add(assign!(subsequent_fail_counter, 0.to_spvalue()));
 
@@ -45,6 +50,38 @@ pub fn generate_photoneo_interface_state(photoneo_name: &str) -> State {
     let state = state.add(assign!(settings, SPValue::String(StringOrUnknown::UNKNOWN)));
     let state = state.add(assign!(phoxi_raw_info, SPValue::String(StringOrUnknown::UNKNOWN)));
 
+    let error_code = v!(&&format!("{}_error_code", photoneo_name));
+    let error_recoverable = bv!(&&format!("{}_error_recoverable", photoneo_name));
+
+    let state = state.add(assign!(error_code, "none".to_spvalue()));
+    let state = state.add(assign!(error_recoverable, false.to_spvalue()));
+
+    let needs_attention = bv!(&&format!("{}_needs_attention", photoneo_name));
+    let retry_max_attempts = iv!(&&format!("{}_retry_max_attempts", photoneo_name));
+    let retry_backoff = iv!(&&format!("{}_retry_backoff", photoneo_name));
+    let retry_reconnect = bv!(&&format!("{}_retry_reconnect", photoneo_name));
+    let attention_threshold = iv!(&&format!("{}_attention_threshold", photoneo_name));
+
+    let state = state.add(assign!(needs_attention, false.to_spvalue()));
+    let state = state.add(assign!(retry_max_attempts, SPValue::Int64(IntOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(retry_backoff, SPValue::Int64(IntOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(retry_reconnect, SPValue::Bool(BoolOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(attention_threshold, SPValue::Int64(IntOrUnknown::UNKNOWN)));
+
+    let scanner_online = bv!(&&format!("{}_scanner_online", photoneo_name));
+    let phoxi_control_running = bv!(&&format!("{}_phoxi_control_running", photoneo_name));
+    let last_seen = iv!(&&format!("{}_last_seen", photoneo_name));
+    let interface_heartbeat = iv!(&&format!("{}_interface_heartbeat", photoneo_name));
+
+    let state = state.add(assign!(scanner_online, false.to_spvalue()));
+    let state = state.add(assign!(phoxi_control_running, SPValue::Bool(BoolOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(last_seen, SPValue::Int64(IntOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(interface_heartbeat, SPValue::Int64(IntOrUnknown::UNKNOWN)));
+
+    let scan_quality = mv!(&&format!("{}_scan_quality", photoneo_name));
+
+    let state = state.add(assign!(scan_quality, SPValue::Map(MapOrUnknown::UNKNOWN)));
+
     state
 }
 
@@ -62,4 +99,42 @@ pub struct ScanRequest {
     pub tif_dir: String,
     pub timeout: i64,
     pub settings: String
-}
\ No newline at end of file
+}
+// The camera extrinsic starts as the one saved by the last calibration,
+// see calibration::saved_extrinsic.
+pub fn generate_photoneo_calibration_state(
+    photoneo_name: &str,
+    saved_extrinsic: TransformOrUnknown,
+) -> State {
+    let state = State::new();
+
+    let calibration_trigger = bv!(&&format!("{}_calibration_trigger", photoneo_name));
+    let calibration_state = v!(&&format!("{}_calibration_state", photoneo_name));
+    let calibration_command = v!(&&format!("{}_calibration_command", photoneo_name));
+    let calibration_mode = v!(&&format!("{}_calibration_mode", photoneo_name));
+    let flange_pose = tv!(&&format!("{}_calibration_flange_pose", photoneo_name));
+    let target_pose = tv!(&&format!("{}_calibration_target_pose", photoneo_name));
+
+    let state = state.add(assign!(calibration_trigger, false.to_spvalue()));
+    let state = state.add(assign!(calibration_state, "initial".to_spvalue()));
+    let state = state.add(assign!(calibration_command, SPValue::String(StringOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(calibration_mode, "eye_to_hand".to_spvalue()));
+    let state = state.add(assign!(flange_pose, SPValue::Transform(TransformOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(target_pose, SPValue::Transform(TransformOrUnknown::UNKNOWN)));
+
+    let scene_name = v!(&&format!("{}_calibration_scene_name", photoneo_name));
+    let sample_count = iv!(&&format!("{}_calibration_sample_count", photoneo_name));
+    let rotation_error = fv!(&&format!("{}_calibration_rotation_error", photoneo_name));
+    let translation_error = fv!(&&format!("{}_calibration_translation_error", photoneo_name));
+    let calibration_info = v!(&&format!("{}_calibration_info", photoneo_name));
+    let camera_extrinsic = tv!(&&format!("{}_camera_extrinsic", photoneo_name));
+
+    let state = state.add(assign!(scene_name, SPValue::String(StringOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(sample_count, 0.to_spvalue()));
+    let state = state.add(assign!(rotation_error, SPValue::Float64(FloatOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(translation_error, SPValue::Float64(FloatOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(calibration_info, SPValue::String(StringOrUnknown::UNKNOWN)));
+    let state = state.add(assign!(camera_extrinsic, SPValue::Transform(saved_extrinsic)));
+
+    state
+}
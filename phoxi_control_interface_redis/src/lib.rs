@@ -0,0 +1,15 @@
+use micro_sp::*;
+use std::{fs::File, io::BufReader};
+
+use std::io;
+
+pub mod core;
+pub use core::backend::{
+    backend_from_env, reserve, ScannerBackend, ScannerSession, SerializedBackend,
+};
+pub use core::calibration::{photoneo_calibration_interface, saved_extrinsic};
+pub use core::health::photoneo_health_interface;
+pub use core::interface::photoneo_control_interface;
+pub use core::request_file::RequestProtocol;
+pub use core::scanners::scanner_ids;
+pub use core::state;
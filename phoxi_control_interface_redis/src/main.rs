@@ -3,27 +3,11 @@ use std::{error::Error, sync::Arc};
 use tokio::time::{interval, Duration};
 
 use micro_sp::*;
-use std::{fs::File, io::BufReader};
-
-use std::io::{self, BufRead};
-use std::process::{Command, Stdio};
-use std::thread;
-
-mod core;
-pub use core::interface::photoneo_control_interface;
-pub use core::state;
+use phoxi_control_interface_redis::*;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     initialize_env_logger();
-    let photoneo_id = match std::env::var("PHOTONEO_ID") {
-        Ok(id) => format!("phoxi_{id}"),
-        Err(e) => {
-            log::error!(target: &&format!("phoxi_control_interface"), "Failed to read PHOTONEO_ID with error: '{}'.", e);
-            log::warn!(target: &&format!("phoxi_control_interface"), "Setting default PHOTONEO_ID 1708011.");
-            "photoneo_1708011".to_string()
-        }
-    };
     let phoxi_scans_path = match std::env::var("PHOXI_SCANS_PATH") {
         Ok(dir) => dir,
         Err(e) => {
@@ -40,26 +24,108 @@ async fn main() -> Result<(), Box<dyn Error>> {
         Err(e) => {
             log::error!(target: &&format!("phoxi_control_interface"), 
                 "Failed to read PHOXI_INTERFACE_PATH environment variable: {}", e);
-            log::warn!(target: &&format!("phoxi_control_interface"), 
+            log::warn!(target: "phoxi_control_interface", 
                 "Setting PHOXI_INTERFACE_PATH to /usr/local/src/photoneo_campx/phoxi_control_interface_redis.");
             "/usr/local/src/photoneo_campx/phoxi_control_interface_redis".to_string()
         }
     };
 
+    let request_protocol = RequestProtocol::from_env();
+    log::info!(target: "phoxi_control_interface",
+        "Passing requests to the executables using the '{:?}' protocol.", request_protocol);
+
+    let photoneo_ids = scanner_ids(&phoxi_interface_path, "phoxi_control_interface");
+    log::info!(target: "phoxi_control_interface",
+        "Serving the scanners: {}.", photoneo_ids.join(", "));
+
     let mut interval = interval(Duration::from_millis(100));
-    let state = state::generate_photoneo_interface_state(&photoneo_id);
 
     log::info!(target: &&format!("phoxi_control_interface"), "Starting.");
 
     let connection_manager = ConnectionManager::new().await;
-    StateManager::set_state(&mut connection_manager.get_connection().await, &state).await;
     let con_arc = Arc::new(connection_manager);
 
+    // Every scanner has its own interfaces, so a slow scanner does not hold
+    // up the others. The interfaces of one scanner share its backend, which
+    // runs their commands one at a time.
+    for photoneo_id in photoneo_ids {
+        let backend: Arc<dyn ScannerBackend> = Arc::new(SerializedBackend::new(
+            backend_from_env(&photoneo_id, &phoxi_interface_path, request_protocol),
+        ));
+        serve_scanner(
+            photoneo_id,
+            phoxi_scans_path.clone(),
+            phoxi_interface_path.clone(),
+            backend,
+            con_arc.clone(),
+        )
+        .await;
+    }
+
+    loop {
+        interval.tick().await;
+    }
+
+    // Ok(())
+}
+
+async fn serve_scanner(
+    photoneo_id: String,
+    phoxi_scans_path: String,
+    phoxi_interface_path: String,
+    backend: Arc<dyn ScannerBackend>,
+    con_arc: Arc<ConnectionManager>,
+) {
+    let state = state::generate_photoneo_interface_state(&photoneo_id);
+    StateManager::set_state(&mut con_arc.get_connection().await, &state).await;
+    let calibration_state = state::generate_photoneo_calibration_state(
+        &photoneo_id,
+        saved_extrinsic(&phoxi_scans_path, &photoneo_id),
+    );
+    StateManager::set_state(&mut con_arc.get_connection().await, &calibration_state).await;
+
+    let calibration_id = photoneo_id.clone();
+    let calibration_scans_path = phoxi_scans_path.clone();
+    let calibration_backend = backend.clone();
+    let calibration_con_arc = con_arc.clone();
+    tokio::task::spawn(async move {
+        match photoneo_calibration_interface(
+            &calibration_id,
+            &calibration_scans_path,
+            &calibration_backend,
+            &calibration_con_arc,
+        )
+        .await
+        {
+            Ok(()) => (),
+            Err(e) => log::error!(target: "phoxi_calibration_interface", "{}", e),
+        }
+    });
+
+    let health_id = photoneo_id.clone();
+    let health_interface_path = phoxi_interface_path.clone();
+    let health_backend = backend.clone();
+    let health_con_arc = con_arc.clone();
+    tokio::task::spawn(async move {
+        match photoneo_health_interface(
+            &health_id,
+            &health_interface_path,
+            &health_backend,
+            &health_con_arc,
+        )
+        .await
+        {
+            Ok(()) => (),
+            Err(e) => log::error!(target: "phoxi_health_interface", "{}", e),
+        }
+    });
+
     tokio::task::spawn(async move {
         match photoneo_control_interface(
             &photoneo_id,
             &phoxi_scans_path,
             &phoxi_interface_path,
+            &backend,
             &con_arc,
         )
         .await
@@ -68,10 +134,4 @@ async fn main() -> Result<(), Box<dyn Error>> {
             Err(e) => log::error!(target: &&format!("phoxi_control_interface"), "{}", e),
         }
     });
-
-    loop {
-        interval.tick().await;
-    }
-
-    // Ok(())
 }
@@ -0,0 +1,244 @@
+// Drives the calibration interface through Redis with a mock scanner and
+// checks that a solved extrinsic survives a restart of the interface.
+// Set TEST_REDIS_EXTERNAL to run against the Redis in REDIS_HOST and
+// REDIS_PORT instead of the in-process stand-in.
+
+mod common;
+
+use common::{connect, MockScanner};
+use micro_sp::*;
+use nalgebra::{Isometry3, Translation3, UnitQuaternion};
+use ordered_float::OrderedFloat;
+use phoxi_control_interface_redis::*;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn pose(x: f64, y: f64, z: f64, roll: f64, pitch: f64, yaw: f64) -> Isometry3<f64> {
+    Isometry3::from_parts(
+        Translation3::new(x, y, z),
+        UnitQuaternion::from_euler_angles(roll, pitch, yaw),
+    )
+}
+
+fn stamped(parent_frame_id: &str, child_frame_id: &str, pose: &Isometry3<f64>) -> SPValue {
+    let t = &pose.translation.vector;
+    let r = pose.rotation.quaternion();
+    SPValue::Transform(TransformOrUnknown::Transform(SPTransformStamped {
+        active_transform: true,
+        enable_transform: true,
+        time_stamp: SystemTime::now(),
+        parent_frame_id: parent_frame_id.to_string(),
+        child_frame_id: child_frame_id.to_string(),
+        transform: SPTransform {
+            translation: SPTranslation {
+                x: OrderedFloat(t.x),
+                y: OrderedFloat(t.y),
+                z: OrderedFloat(t.z),
+            },
+            rotation: SPRotation {
+                x: OrderedFloat(r.i),
+                y: OrderedFloat(r.j),
+                z: OrderedFloat(r.k),
+                w: OrderedFloat(r.w),
+            },
+        },
+        metadata: MapOrUnknown::UNKNOWN,
+    }))
+}
+
+struct Calibration {
+    photoneo_id: String,
+    scans: tempfile::TempDir,
+    connection_manager: Arc<ConnectionManager>,
+}
+
+impl Calibration {
+    fn key(&self, name: &str) -> String {
+        format!("{}_{}", self.photoneo_id, name)
+    }
+
+    // Publishes the state the interface binary starts a scanner with and
+    // starts the calibration interface for it.
+    async fn start(&self) -> tokio::task::JoinHandle<()> {
+        let scans_path = self.scans.path().to_string_lossy().to_string();
+        let state = state::generate_photoneo_calibration_state(
+            &self.photoneo_id,
+            saved_extrinsic(&scans_path, &self.photoneo_id),
+        );
+        let mut con = self.connection_manager.get_connection().await;
+        StateManager::set_state(&mut con, &state).await;
+
+        let id = self.photoneo_id.clone();
+        let backend: Arc<dyn ScannerBackend> = Arc::new(MockScanner::default());
+        let connection_manager = self.connection_manager.clone();
+        tokio::task::spawn(async move {
+            if let Err(e) =
+                photoneo_calibration_interface(&id, &scans_path, &backend, &connection_manager)
+                    .await
+            {
+                panic!("Calibration interface stopped: {}", e);
+            }
+        })
+    }
+
+    async fn state(&self) -> State {
+        let keys: Vec<String> = [
+            "calibration_trigger",
+            "calibration_state",
+            "calibration_command",
+            "calibration_flange_pose",
+            "calibration_target_pose",
+            "calibration_info",
+            "camera_extrinsic",
+        ]
+        .iter()
+        .map(|name| self.key(name))
+        .collect();
+        let mut con = self.connection_manager.get_connection().await;
+        StateManager::get_state_for_keys(&mut con, &keys)
+            .await
+            .expect("state of the calibration interface")
+    }
+
+    async fn set(&self, values: Vec<(&str, SPValue)>) {
+        let state = self.state().await;
+        let mut new_state = state.clone();
+        for (name, value) in values {
+            new_state = new_state.update(&self.key(name), value);
+        }
+        let mut con = self.connection_manager.get_connection().await;
+        StateManager::set_state(&mut con, &state.get_diff_partial_state(&new_state)).await;
+    }
+
+    // Triggers the command and waits until it succeeded.
+    async fn command(&self, command: &str) {
+        self.set(vec![
+            ("calibration_command", command.to_spvalue()),
+            ("calibration_state", "initial".to_spvalue()),
+            ("calibration_trigger", true.to_spvalue()),
+        ])
+        .await;
+        let start = Instant::now();
+        loop {
+            let state = self.state().await;
+            let calibration_state =
+                state.get_string_or_default_to_unknown(&self.key("calibration_state"), "test");
+            if calibration_state != ServiceRequestState::Initial.to_string() {
+                assert_eq!(
+                    calibration_state,
+                    "succeeded",
+                    "{} failed: {}",
+                    command,
+                    state.get_string_or_default_to_unknown(&self.key("calibration_info"), "test")
+                );
+                return;
+            }
+            assert!(
+                start.elapsed() < RESPONSE_TIMEOUT,
+                "No response to {}.",
+                command
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    async fn camera_extrinsic(&self) -> TransformOrUnknown {
+        match self
+            .state()
+            .await
+            .get_value(&self.key("camera_extrinsic"), "test")
+        {
+            Some(SPValue::Transform(transform)) => transform,
+            other => panic!("unexpected camera extrinsic: {:?}", other),
+        }
+    }
+}
+
+fn translation(extrinsic: &TransformOrUnknown) -> [f64; 3] {
+    match extrinsic {
+        TransformOrUnknown::Transform(t) => {
+            let t = &t.transform.translation;
+            [t.x.into_inner(), t.y.into_inner(), t.z.into_inner()]
+        }
+        TransformOrUnknown::UNKNOWN => panic!("the camera extrinsic is unknown"),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn solved_extrinsic_is_published_again_after_a_restart() {
+    let calibration = Calibration {
+        photoneo_id: "phoxi_test_calibration".to_string(),
+        scans: tempfile::tempdir().expect("scan directory"),
+        connection_manager: connect().await,
+    };
+    let interface = calibration.start().await;
+    assert_eq!(
+        calibration.camera_extrinsic().await,
+        TransformOrUnknown::UNKNOWN
+    );
+
+    // Eye-to-hand, the target on the flange seen by a fixed camera.
+    let extrinsic = pose(0.90, 0.20, 1.40, 3.00, 0.20, -1.20);
+    let fixed_target = pose(0.00, 0.02, 0.10, 0.20, 0.00, 0.00);
+    let flange_poses = [
+        pose(0.50, 0.00, 0.40, 3.10, 0.05, 0.00),
+        pose(0.45, 0.10, 0.45, 2.90, -0.20, 0.30),
+        pose(0.55, -0.12, 0.38, 3.00, 0.25, -0.40),
+        pose(0.40, 0.05, 0.50, -2.95, 0.10, 0.80),
+        pose(0.60, 0.15, 0.42, 2.80, -0.30, -0.70),
+        pose(0.52, -0.05, 0.35, 3.05, 0.35, 0.15),
+    ];
+    for flange in flange_poses {
+        let target = extrinsic.inverse() * flange * fixed_target;
+        calibration
+            .set(vec![(
+                "calibration_flange_pose",
+                stamped("base", "flange", &flange),
+            )])
+            .await;
+        calibration.command("capture_sample").await;
+        calibration
+            .set(vec![(
+                "calibration_target_pose",
+                stamped("camera", "target", &target),
+            )])
+            .await;
+        calibration.command("observe_sample").await;
+    }
+    calibration.command("solve").await;
+
+    let solved = calibration.camera_extrinsic().await;
+    let expected = extrinsic.translation.vector;
+    for (actual, expected) in translation(&solved).iter().zip(expected.iter()) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    // A restart, after Redis lost the published extrinsic.
+    interface.abort();
+    let _ = interface.await;
+    calibration
+        .set(vec![(
+            "camera_extrinsic",
+            SPValue::Transform(TransformOrUnknown::UNKNOWN),
+        )])
+        .await;
+    let _restarted = calibration.start().await;
+
+    let restored = calibration.camera_extrinsic().await;
+    assert_eq!(translation(&restored), translation(&solved));
+    match (&restored, &solved) {
+        (TransformOrUnknown::Transform(restored), TransformOrUnknown::Transform(solved)) => {
+            assert_eq!(restored.parent_frame_id, "base");
+            assert_eq!(restored.child_frame_id, "camera");
+            assert_eq!(restored.transform, solved.transform);
+        }
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,265 @@
+// Shared by the integration tests. Not every test uses every helper.
+#![allow(dead_code)]
+
+use async_trait::async_trait;
+use micro_sp::*;
+pub use photoneo_common::redis_server::redis_server;
+use phoxi_control_interface_redis::core::settings::{ScannerSettings, SettingsError};
+use phoxi_control_interface_redis::core::state::ScanRequest;
+use phoxi_control_interface_redis::*;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+pub async fn connect() -> Arc<ConnectionManager> {
+    redis_server();
+    Arc::new(ConnectionManager::new().await)
+}
+
+pub struct Interface {
+    pub photoneo_id: String,
+    pub scans: tempfile::TempDir,
+    // Where the settings presets of the scan quality check are.
+    pub installation: tempfile::TempDir,
+    pub connection_manager: Arc<ConnectionManager>,
+}
+
+// Publishes the initial state of a scanner with its own id, so tests can
+// share a server, and starts the control interface for it.
+pub async fn start_interface(test_name: &str, backend: Arc<dyn ScannerBackend>) -> Interface {
+    let photoneo_id = format!("phoxi_test_{}", test_name);
+    let scans = tempfile::tempdir().expect("scan directory");
+    let installation = tempfile::tempdir().expect("installation directory");
+    let connection_manager = connect().await;
+
+    let state = state::generate_photoneo_interface_state(&photoneo_id);
+    StateManager::set_state(&mut connection_manager.get_connection().await, &state).await;
+
+    let id = photoneo_id.clone();
+    let scans_path = scans.path().to_string_lossy().to_string();
+    let interface_path = installation.path().to_string_lossy().to_string();
+    let interface_connection = connection_manager.clone();
+    tokio::task::spawn(async move {
+        if let Err(e) = photoneo_control_interface(
+            &id,
+            &scans_path,
+            &interface_path,
+            &backend,
+            &interface_connection,
+        )
+        .await
+        {
+            panic!("Interface stopped: {}", e);
+        }
+    });
+
+    Interface {
+        photoneo_id,
+        scans,
+        installation,
+        connection_manager,
+    }
+}
+
+impl Interface {
+    pub fn key(&self, name: &str) -> String {
+        format!("{}_{}", self.photoneo_id, name)
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        [
+            "request_trigger",
+            "request_state",
+            "request_abort",
+            "command_type",
+            "scene_name",
+            "hardware_identification",
+            "praw",
+            "ply",
+            "tif",
+            "timeout",
+            "settings",
+            "phoxi_raw_info",
+            "error_code",
+            "error_recoverable",
+            "total_fail_counter",
+            "subsequent_fail_counter",
+            "needs_attention",
+            "scan_quality",
+        ]
+        .iter()
+        .map(|name| self.key(name))
+        .collect()
+    }
+
+    pub async fn state(&self) -> State {
+        let mut con = self.connection_manager.get_connection().await;
+        StateManager::get_state_for_keys(&mut con, &self.keys())
+            .await
+            .expect("state of the interface")
+    }
+
+    // Sets the request and triggers it from the initial state.
+    pub async fn request(&self, values: Vec<(&str, SPValue)>) {
+        let state = self.state().await;
+        let mut new_state = state
+            .update(&self.key("request_state"), "initial".to_spvalue())
+            .update(&self.key("request_trigger"), true.to_spvalue());
+        for (name, value) in values {
+            new_state = new_state.update(&self.key(name), value);
+        }
+        let mut con = self.connection_manager.get_connection().await;
+        StateManager::set_state(&mut con, &state.get_diff_partial_state(&new_state)).await;
+    }
+
+    // Waits until the request left the initial state and returns the state.
+    pub async fn response(&self, timeout: Duration) -> State {
+        let start = Instant::now();
+        loop {
+            let state = self.state().await;
+            let request_state =
+                state.get_string_or_default_to_unknown(&self.key("request_state"), "test");
+            if request_state != ServiceRequestState::Initial.to_string() {
+                return state;
+            }
+            assert!(
+                start.elapsed() < timeout,
+                "No response from {} within {:?}.",
+                self.photoneo_id,
+                timeout
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    pub async fn abort(&self) {
+        let state = self.state().await;
+        let new_state = state.update(&self.key("request_abort"), true.to_spvalue());
+        let mut con = self.connection_manager.get_connection().await;
+        StateManager::set_state(&mut con, &state.get_diff_partial_state(&new_state)).await;
+    }
+
+    pub fn request_state(&self, state: &State) -> String {
+        state.get_string_or_default_to_unknown(&self.key("request_state"), "test")
+    }
+
+    pub fn raw_info(&self, state: &State) -> String {
+        state.get_string_or_default_to_unknown(&self.key("phoxi_raw_info"), "test")
+    }
+
+    pub fn error_code(&self, state: &State) -> String {
+        state.get_string_or_default_to_unknown(&self.key("error_code"), "test")
+    }
+
+    // An entry of {id}_scan_quality, None if there is no such entry.
+    pub fn scan_quality(&self, state: &State, name: &str) -> Option<SPValue> {
+        match state.get_value(&self.key("scan_quality"), "test") {
+            Some(SPValue::Map(MapOrUnknown::Map(entries))) => entries
+                .into_iter()
+                .find(|(key, _)| *key == name.to_spvalue())
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+// Answers every command with "{command} {scene_name}", or fails them all,
+// and remembers what it was asked to do. A hanging scanner answers stop
+// only, everything else runs until it is cancelled. The first `refusing`
+// commands fail as if the scanner refused the connection.
+#[derive(Debug, Default)]
+pub struct MockScanner {
+    pub failing: bool,
+    pub hanging: bool,
+    pub refusing: AtomicUsize,
+    pub commands: Mutex<Vec<String>>,
+}
+
+impl MockScanner {
+    async fn answer(
+        &self,
+        command: &str,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.commands.lock().unwrap().push(command.to_string());
+        if self.hanging && command != "stop" {
+            cancel.cancelled().await;
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "Command execution was cancelled.",
+            ));
+        }
+        let refuse = self
+            .refusing
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        if refuse {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "Connection to the device was Unsuccessful!",
+            ));
+        }
+        if self.failing {
+            return Err(io::Error::other(format!("Mock failure of {}.", command)));
+        }
+        Ok(vec![format!("{} {}", command, request.scene_name)])
+    }
+}
+
+#[async_trait]
+impl ScannerBackend for MockScanner {
+    async fn connect(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.answer("connect", request, cancel).await
+    }
+
+    async fn capture(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.answer("capture", request, cancel).await
+    }
+
+    async fn freerun(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.answer("freerun", request, cancel).await
+    }
+
+    async fn stop(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.answer("stop", request, cancel).await
+    }
+
+    async fn disconnect(
+        &self,
+        request: &ScanRequest,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<String>, io::Error> {
+        self.answer("disconnect", request, cancel).await
+    }
+}
+
+// The fields of the violations a preset is rejected with.
+pub fn violations(preset: &serde_json::Value) -> Vec<String> {
+    match ScannerSettings::from_value(preset, "test.json") {
+        Err(SettingsError::Invalid { file, violations }) => {
+            assert_eq!(file, "test.json");
+            violations.into_iter().map(|v| v.field).collect()
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("the preset was accepted"),
+    }
+}
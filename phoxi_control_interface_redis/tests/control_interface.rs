@@ -0,0 +1,558 @@
+// Drives {id}_request_trigger and {id}_request_state of the control
+// interface through Redis, with the simulated scanner or a mock backend.
+// Set TEST_REDIS_EXTERNAL to run against the Redis in REDIS_HOST and
+// REDIS_PORT instead of the in-process stand-in.
+
+mod common;
+
+use common::{start_interface, MockScanner};
+use micro_sp::*;
+use phoxi_control_interface_redis::core::backend::SubprocessBackend;
+use phoxi_control_interface_redis::core::simulation::{SimulatedBackend, SimulationConfig};
+use phoxi_control_interface_redis::*;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn simulated(config: SimulationConfig) -> Arc<dyn ScannerBackend> {
+    Arc::new(SimulatedBackend::new(config))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn capture_succeeds_and_saves_the_ply() {
+    let interface = start_interface("capture", simulated(SimulationConfig::default())).await;
+    interface
+        .request(vec![
+            ("command_type", "capture".to_spvalue()),
+            ("scene_name", "scene_1".to_spvalue()),
+            ("hardware_identification", "2019-09-051-LC3".to_spvalue()),
+            ("praw", false.to_spvalue()),
+            ("ply", true.to_spvalue()),
+            ("timeout", 3000.to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "succeeded");
+    assert_eq!(
+        interface.raw_info(&state),
+        "Connection to the device 2019-09-051-LC3 was Successful!"
+    );
+    assert!(!state.get_bool_or_default_to_false(&interface.key("request_trigger"), "test"));
+    assert!(interface.scans.path().join("ply/scene_1.ply").is_file());
+    assert_eq!(
+        interface.scan_quality(&state, "verdict"),
+        Some("passed".to_spvalue())
+    );
+    assert_eq!(
+        interface.scan_quality(&state, "valid_points"),
+        Some(2501.to_spvalue())
+    );
+}
+
+// The preset big_plate with a scan_quality section.
+fn install_preset(installation: &std::path::Path, name: &str, scan_quality: serde_json::Value) {
+    let mut preset: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/parameters/settings/big_plate.json"
+        ))
+        .unwrap(),
+    )
+    .unwrap();
+    preset["scan_quality"] = scan_quality;
+    let dir = installation.join("parameters/settings");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join(format!("{}.json", name)), preset.to_string()).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scan_below_the_quality_thresholds_fails_or_degrades() {
+    let interface = start_interface("scan_quality", simulated(SimulationConfig::default())).await;
+    let thresholds = |on_violation: &str| {
+        serde_json::json!({
+            "min_valid_points": 100000,
+            "max_depth": 950.0,
+            "on_violation": on_violation
+        })
+    };
+    install_preset(interface.installation.path(), "strict", thresholds("fail"));
+    install_preset(
+        interface.installation.path(),
+        "lenient",
+        thresholds("degrade"),
+    );
+
+    let capture = |settings: &'static str| {
+        vec![
+            ("command_type", "capture".to_spvalue()),
+            ("scene_name", "scene_1".to_spvalue()),
+            ("ply", true.to_spvalue()),
+            ("timeout", 3000.to_spvalue()),
+            ("settings", settings.to_spvalue()),
+        ]
+    };
+
+    interface.request(capture("strict")).await;
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "failed");
+    assert_eq!(interface.error_code(&state), "poor_scan_quality");
+    let raw_info = interface.raw_info(&state);
+    assert!(raw_info.starts_with("Scan quality is too low: 2501 valid points"));
+    assert!(raw_info.contains("farthest point at 1000.0 mm"));
+    assert_eq!(
+        interface.scan_quality(&state, "verdict"),
+        Some("failed".to_spvalue())
+    );
+    assert_eq!(
+        state.get_int_or_default_to_zero(&interface.key("total_fail_counter"), "test"),
+        1
+    );
+
+    interface.request(capture("lenient")).await;
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "succeeded");
+    assert_eq!(interface.error_code(&state), "none");
+    assert_eq!(
+        interface.scan_quality(&state, "verdict"),
+        Some("degraded".to_spvalue())
+    );
+    assert_eq!(
+        interface.scan_quality(&state, "max_depth"),
+        Some(1000.0.to_spvalue())
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn request_reaches_the_backend() {
+    let scanner = Arc::new(MockScanner::default());
+    let interface = start_interface("mock_connect", scanner.clone()).await;
+    interface
+        .request(vec![
+            ("command_type", "connect".to_spvalue()),
+            ("scene_name", "scene_2".to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "succeeded");
+    assert_eq!(interface.raw_info(&state), "connect scene_2");
+    assert_eq!(*scanner.commands.lock().unwrap(), vec!["connect"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn backend_error_fails_the_request() {
+    let scanner = Arc::new(MockScanner {
+        failing: true,
+        ..Default::default()
+    });
+    let interface = start_interface("mock_failure", scanner).await;
+    interface
+        .request(vec![("command_type", "stop".to_spvalue())])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "failed");
+    assert_eq!(interface.raw_info(&state), "Mock failure of stop.");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unknown_command_fails() {
+    let scanner = Arc::new(MockScanner::default());
+    let interface = start_interface("unknown_command", scanner.clone()).await;
+    interface
+        .request(vec![("command_type", "rotate".to_spvalue())])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "failed");
+    assert_eq!(interface.raw_info(&state), "Unknown command type 'rotate'.");
+    assert!(scanner.commands.lock().unwrap().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn simulated_failure_fails_the_request() {
+    let config = SimulationConfig {
+        fail_commands: vec!["capture".to_string()],
+        ..Default::default()
+    };
+    let interface = start_interface("simulated_failure", simulated(config)).await;
+    interface
+        .request(vec![
+            ("command_type", "capture".to_spvalue()),
+            ("timeout", 3000.to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "failed");
+    assert!(interface.raw_info(&state).starts_with("Simulated failure"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn slow_scanner_times_out() {
+    let config = SimulationConfig {
+        latency_ms: 2000,
+        ..Default::default()
+    };
+    let interface = start_interface("timeout", simulated(config)).await;
+    interface
+        .request(vec![
+            ("command_type", "connect".to_spvalue()),
+            ("timeout", 300.to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "failed");
+    assert_eq!(interface.raw_info(&state), "Command execution timed out.");
+    assert_eq!(interface.error_code(&state), "frame_timeout");
+    assert!(state.get_bool_or_default_to_false(&interface.key("error_recoverable"), "test"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn missing_settings_file_fails() {
+    let installation = tempfile::tempdir().unwrap();
+    let backend = Arc::new(SubprocessBackend::new(
+        "phoxi_test_missing_settings",
+        &installation.path().to_string_lossy(),
+        RequestProtocol::File,
+    ));
+    let interface = start_interface("missing_settings", backend).await;
+    interface
+        .request(vec![
+            ("command_type", "capture".to_spvalue()),
+            ("settings", "does_not_exist".to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "failed");
+    let raw_info = interface.raw_info(&state);
+    assert!(raw_info.starts_with("Failed to read settings file"));
+    assert!(raw_info.contains("does_not_exist.json"));
+    assert_eq!(interface.error_code(&state), "settings_invalid");
+    assert!(!state.get_bool_or_default_to_false(&interface.key("error_recoverable"), "test"));
+}
+
+// Executables standing in for the ones in cpp_executables/dev.
+#[cfg(unix)]
+fn install_executable(installation: &std::path::Path, command: &str, script: &str) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = installation.join("cpp_executables/dev").join(command);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}_Release", command));
+    std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn subprocess_output_is_published() {
+    let installation = tempfile::tempdir().unwrap();
+    install_executable(
+        installation.path(),
+        "Connect",
+        "echo 'Connection to the device test was Successful!'",
+    );
+    let backend = Arc::new(SubprocessBackend::new(
+        "phoxi_test_subprocess",
+        &installation.path().to_string_lossy(),
+        RequestProtocol::File,
+    ));
+    let interface = start_interface("subprocess", backend).await;
+    interface
+        .request(vec![
+            ("command_type", "connect".to_spvalue()),
+            ("timeout", 3000.to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "succeeded");
+    assert_eq!(
+        interface.raw_info(&state),
+        "Connection to the device test was Successful!"
+    );
+}
+
+// ConnectIPv4 does not read request files yet and gets the positional
+// arguments, the hardware identification first and the ip last.
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn executables_without_request_files_get_positional_arguments() {
+    let installation = tempfile::tempdir().unwrap();
+    install_executable(
+        installation.path(),
+        "ConnectIPv4",
+        "echo \"Connection to the device $1 at ${31} was Successful! ($# arguments)\"",
+    );
+    let backend = Arc::new(SubprocessBackend::new(
+        "phoxi_test_positional",
+        &installation.path().to_string_lossy(),
+        RequestProtocol::File,
+    ));
+    let interface = start_interface("positional", backend).await;
+    interface
+        .request(vec![
+            ("command_type", "connectIPv4".to_spvalue()),
+            ("hardware_identification", "test".to_spvalue()),
+            ("timeout", 3000.to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "succeeded");
+    assert_eq!(
+        interface.raw_info(&state),
+        "Connection to the device test at 192.168.1.27 was Successful! (31 arguments)"
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn unsuccessful_connection_fails_the_request() {
+    let installation = tempfile::tempdir().unwrap();
+    install_executable(
+        installation.path(),
+        "Capture",
+        "echo 'Connection to the device test was Unsuccessful!'\necho 'Device is not created, or not connected!'",
+    );
+    let backend = Arc::new(SubprocessBackend::new(
+        "phoxi_test_unsuccessful",
+        &installation.path().to_string_lossy(),
+        RequestProtocol::File,
+    ));
+    let interface = start_interface("unsuccessful", backend).await;
+    interface
+        .request(vec![
+            ("command_type", "capture".to_spvalue()),
+            ("timeout", 3000.to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "failed");
+    assert_eq!(
+        interface.raw_info(&state),
+        "Connection to the device test was Unsuccessful!"
+    );
+    assert_eq!(interface.error_code(&state), "connection_refused");
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn exit_code_and_stderr_are_published() {
+    let installation = tempfile::tempdir().unwrap();
+    install_executable(
+        installation.path(),
+        "Connect",
+        "echo 'Starting'\necho 'Device not found' >&2\necho 'PhoXi Control is not running' >&2\nexit 2",
+    );
+    let backend = Arc::new(SubprocessBackend::new(
+        "phoxi_test_exit_code",
+        &installation.path().to_string_lossy(),
+        RequestProtocol::File,
+    ));
+    let interface = start_interface("exit_code", backend).await;
+    interface
+        .request(vec![
+            ("command_type", "connect".to_spvalue()),
+            ("timeout", 3000.to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "failed");
+    assert_eq!(
+        interface.raw_info(&state),
+        "Connect_Release exited with code 2: PhoXi Control is not running\n\
+         Device not found\nPhoXi Control is not running"
+    );
+    assert_eq!(interface.error_code(&state), "unknown");
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn hanging_subprocess_is_killed() {
+    let installation = tempfile::tempdir().unwrap();
+    install_executable(installation.path(), "Capture", "exec sleep 30");
+    let backend = Arc::new(SubprocessBackend::new(
+        "phoxi_test_hanging_subprocess",
+        &installation.path().to_string_lossy(),
+        RequestProtocol::File,
+    ));
+    let interface = start_interface("hanging_subprocess", backend).await;
+    interface
+        .request(vec![
+            ("command_type", "capture".to_spvalue()),
+            ("timeout", 500.to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "failed");
+    assert_eq!(interface.raw_info(&state), "Command execution timed out.");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn abort_cancels_the_capture_and_stops_the_scanner() {
+    let scanner = Arc::new(MockScanner {
+        hanging: true,
+        ..Default::default()
+    });
+    let interface = start_interface("abort", scanner.clone()).await;
+    interface
+        .request(vec![
+            ("command_type", "capture".to_spvalue()),
+            ("scene_name", "scene_3".to_spvalue()),
+        ])
+        .await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(*scanner.commands.lock().unwrap(), vec!["capture"]);
+
+    interface.abort().await;
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "cancelled");
+    assert_eq!(
+        interface.raw_info(&state),
+        "Command execution was cancelled."
+    );
+    assert!(!state.get_bool_or_default_to_false(&interface.key("request_abort"), "test"));
+    assert!(!state.get_bool_or_default_to_false(&interface.key("request_trigger"), "test"));
+    assert_eq!(*scanner.commands.lock().unwrap(), vec!["capture", "stop"]);
+    assert_eq!(interface.error_code(&state), "cancelled");
+}
+
+#[cfg(unix)]
+#[tokio::test(flavor = "multi_thread")]
+async fn aborted_subprocess_keeps_its_output() {
+    let installation = tempfile::tempdir().unwrap();
+    install_executable(
+        installation.path(),
+        "Connect",
+        "echo 'Connecting to the device test.'; exec sleep 30",
+    );
+    let backend = Arc::new(SubprocessBackend::new(
+        "phoxi_test_aborted_subprocess",
+        &installation.path().to_string_lossy(),
+        RequestProtocol::File,
+    ));
+    let interface = start_interface("aborted_subprocess", backend).await;
+    interface
+        .request(vec![
+            ("command_type", "connect".to_spvalue()),
+            ("timeout", 20000.to_spvalue()),
+        ])
+        .await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let start = Instant::now();
+    interface.abort().await;
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "cancelled");
+    assert_eq!(interface.raw_info(&state), "Connecting to the device test.");
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+// On a single threaded runtime, a command that blocked the interface
+// would stall every other task until it is done.
+#[tokio::test]
+async fn interface_stays_responsive_during_a_long_command() {
+    let config = SimulationConfig {
+        latency_ms: 1500,
+        ..Default::default()
+    };
+    let interface = start_interface("responsive", simulated(config)).await;
+    interface
+        .request(vec![
+            ("command_type", "connect".to_spvalue()),
+            ("timeout", 5000.to_spvalue()),
+        ])
+        .await;
+
+    let start = Instant::now();
+    let mut longest_gap = Duration::ZERO;
+    loop {
+        let before = Instant::now();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        longest_gap = longest_gap.max(before.elapsed());
+        let state = interface.state().await;
+        if interface.request_state(&state) != "initial" {
+            assert_eq!(interface.request_state(&state), "succeeded");
+            break;
+        }
+        assert!(start.elapsed() < RESPONSE_TIMEOUT, "No response.");
+    }
+    assert!(start.elapsed() >= Duration::from_millis(1500));
+    assert!(
+        longest_gap < Duration::from_millis(500),
+        "The runtime stalled for {:?}.",
+        longest_gap
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refused_connection_is_retried_after_a_reconnect() {
+    let scanner = Arc::new(MockScanner {
+        refusing: 1.into(),
+        ..Default::default()
+    });
+    let interface = start_interface("retry", scanner.clone()).await;
+    interface
+        .request(vec![
+            ("command_type", "capture".to_spvalue()),
+            ("scene_name", "scene_3".to_spvalue()),
+            ("retry_max_attempts", 3.to_spvalue()),
+            ("retry_backoff", 100.to_spvalue()),
+            ("retry_reconnect", true.to_spvalue()),
+        ])
+        .await;
+
+    let state = interface.response(RESPONSE_TIMEOUT).await;
+    assert_eq!(interface.request_state(&state), "succeeded");
+    assert_eq!(interface.raw_info(&state), "capture scene_3");
+    assert_eq!(
+        *scanner.commands.lock().unwrap(),
+        vec!["capture", "connect", "capture"]
+    );
+    assert_eq!(
+        state.get_int_or_default_to_zero(&interface.key("total_fail_counter"), "test"),
+        0
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failures_in_a_row_need_attention_until_a_success() {
+    let scanner = Arc::new(MockScanner {
+        refusing: 2.into(),
+        ..Default::default()
+    });
+    let interface = start_interface("attention", scanner.clone()).await;
+    let counters = |state: &State| {
+        (
+            state.get_int_or_default_to_zero(&interface.key("total_fail_counter"), "test"),
+            state.get_int_or_default_to_zero(&interface.key("subsequent_fail_counter"), "test"),
+            state.get_bool_or_default_to_false(&interface.key("needs_attention"), "test"),
+        )
+    };
+
+    for (request_state, expected_counters) in [
+        ("failed", (1, 1, false)),
+        ("failed", (2, 2, true)),
+        ("succeeded", (2, 0, false)),
+    ] {
+        interface
+            .request(vec![
+                ("command_type", "connect".to_spvalue()),
+                ("attention_threshold", 2.to_spvalue()),
+            ])
+            .await;
+        let state = interface.response(RESPONSE_TIMEOUT).await;
+        assert_eq!(interface.request_state(&state), request_state);
+        assert_eq!(counters(&state), expected_counters);
+    }
+    assert_eq!(scanner.commands.lock().unwrap().len(), 3);
+}
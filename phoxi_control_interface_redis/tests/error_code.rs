@@ -0,0 +1,86 @@
+// Failures are classified by what the executables printed, see the
+// std::cout lines in cpp_executables.
+
+use phoxi_control_interface_redis::core::error::ErrorCode;
+use phoxi_control_interface_redis::core::executor::CommandFailure;
+use std::io;
+
+fn printed(kind: io::ErrorKind, stdout: &[&str]) -> io::Error {
+    io::Error::new(
+        kind,
+        CommandFailure {
+            reason: stdout.last().unwrap_or(&"").to_string(),
+            exit_code: Some(0),
+            stdout: stdout.iter().map(|line| line.to_string()).collect(),
+            stderr: vec![],
+        },
+    )
+}
+
+#[test]
+fn first_error_after_the_last_step_that_worked_is_the_cause() {
+    let error = printed(
+        io::ErrorKind::NotConnected,
+        &[
+            "Connection to the device test was Unsuccessful!",
+            "Device is not created, or not connected!",
+        ],
+    );
+    assert_eq!(ErrorCode::classify(&error), ErrorCode::ConnectionRefused);
+
+    let error = printed(
+        io::ErrorKind::Other,
+        &[
+            "Connection to the device test was Successful!",
+            "Switching to Software trigger mode.",
+            "Trigger was unsuccessful!",
+            "Failed to retrieve the frame!",
+        ],
+    );
+    assert_eq!(ErrorCode::classify(&error), ErrorCode::TriggerFailed);
+}
+
+#[test]
+fn errors_dealt_with_before_a_successful_step_are_skipped() {
+    let error = printed(
+        io::ErrorKind::Other,
+        &[
+            "Device is not created, or not connected!",
+            "Connection to the device test was Successful!",
+            "Frame was triggered, Frame Id: 3",
+            "Could not save frame as test.ply",
+        ],
+    );
+    assert_eq!(ErrorCode::classify(&error), ErrorCode::SaveFailed);
+}
+
+#[test]
+fn error_kind_is_the_fallback() {
+    let error = printed(io::ErrorKind::TimedOut, &["Connecting to the device test."]);
+    assert_eq!(ErrorCode::classify(&error), ErrorCode::FrameTimeout);
+
+    let error = io::Error::new(io::ErrorKind::InvalidInput, "Failed to read settings file.");
+    assert_eq!(ErrorCode::classify(&error), ErrorCode::SettingsInvalid);
+
+    let error = io::Error::new(
+        io::ErrorKind::Interrupted,
+        "Command execution was cancelled.",
+    );
+    assert_eq!(ErrorCode::classify(&error), ErrorCode::Cancelled);
+
+    let error = io::Error::other("Mock failure of stop.");
+    assert_eq!(ErrorCode::classify(&error), ErrorCode::Unknown);
+}
+
+#[test]
+fn only_transient_failures_are_recoverable() {
+    assert!(ErrorCode::ConnectionRefused.is_recoverable());
+    assert!(ErrorCode::FrameTimeout.is_recoverable());
+    assert!(!ErrorCode::DeviceNotFound.is_recoverable());
+    assert!(!ErrorCode::SettingsInvalid.is_recoverable());
+    assert!(!ErrorCode::PoorScanQuality.is_recoverable());
+    assert_eq!(
+        ErrorCode::NotInSoftwareTriggerMode.as_str(),
+        "not_in_software_trigger_mode"
+    );
+}
@@ -0,0 +1,136 @@
+use nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector3};
+use phoxi_control_interface_redis::core::hand_eye::{
+    solve, CalibrationError, CalibrationSample, HandEyeMode,
+};
+
+fn pose(x: f64, y: f64, z: f64, roll: f64, pitch: f64, yaw: f64) -> Isometry3<f64> {
+    Isometry3::from_parts(
+        Translation3::new(x, y, z),
+        UnitQuaternion::from_euler_angles(roll, pitch, yaw),
+    )
+}
+
+fn flange_poses() -> Vec<Isometry3<f64>> {
+    vec![
+        pose(0.50, 0.00, 0.40, 3.10, 0.05, 0.00),
+        pose(0.45, 0.10, 0.45, 2.90, -0.20, 0.30),
+        pose(0.55, -0.12, 0.38, 3.00, 0.25, -0.40),
+        pose(0.40, 0.05, 0.50, -2.95, 0.10, 0.80),
+        pose(0.60, 0.15, 0.42, 2.80, -0.30, -0.70),
+        pose(0.52, -0.05, 0.35, 3.05, 0.35, 0.15),
+    ]
+}
+
+// Camera observations a perfect camera would make of the target.
+fn samples(
+    mode: HandEyeMode,
+    extrinsic: &Isometry3<f64>,
+    fixed_target: &Isometry3<f64>,
+) -> Vec<CalibrationSample> {
+    flange_poses()
+        .into_iter()
+        .map(|flange| {
+            let target = match mode {
+                // base_T_target = flange * X * target
+                HandEyeMode::EyeInHand => (flange * extrinsic).inverse() * fixed_target,
+                // flange_T_target = flange^-1 * X * target
+                HandEyeMode::EyeToHand => extrinsic.inverse() * flange * fixed_target,
+            };
+            CalibrationSample { flange, target }
+        })
+        .collect()
+}
+
+fn assert_recovered(expected: &Isometry3<f64>, actual: &Isometry3<f64>) {
+    let translation = (expected.translation.vector - actual.translation.vector).norm();
+    let rotation = expected.rotation.angle_to(&actual.rotation);
+    assert!(translation < 1e-9, "translation off by {}", translation);
+    assert!(rotation < 1e-9, "rotation off by {}", rotation);
+}
+
+#[test]
+fn eye_in_hand_recovers_extrinsic() {
+    let extrinsic = pose(0.03, -0.08, 0.12, 0.10, -0.05, 1.57);
+    let target = pose(0.55, 0.02, 0.00, 0.00, 0.00, 0.30);
+    let solution = solve(
+        HandEyeMode::EyeInHand,
+        &samples(HandEyeMode::EyeInHand, &extrinsic, &target),
+    )
+    .unwrap();
+
+    assert_recovered(&extrinsic, &solution.extrinsic);
+    assert_eq!(solution.residuals.motions, 15);
+    assert!(solution.residuals.rotation_max_deg < 1e-6);
+    assert!(solution.residuals.translation_max < 1e-9);
+}
+
+#[test]
+fn eye_to_hand_recovers_extrinsic() {
+    let extrinsic = pose(0.90, 0.20, 1.40, 3.00, 0.20, -1.20);
+    let target = pose(0.00, 0.02, 0.10, 0.20, 0.00, 0.00);
+    let solution = solve(
+        HandEyeMode::EyeToHand,
+        &samples(HandEyeMode::EyeToHand, &extrinsic, &target),
+    )
+    .unwrap();
+
+    assert_recovered(&extrinsic, &solution.extrinsic);
+    assert!(solution.residuals.rotation_max_deg < 1e-6);
+    assert!(solution.residuals.translation_max < 1e-9);
+}
+
+#[test]
+fn noisy_observations_give_small_residuals() {
+    let extrinsic = pose(0.03, -0.08, 0.12, 0.10, -0.05, 1.57);
+    let target = pose(0.55, 0.02, 0.00, 0.00, 0.00, 0.30);
+    let mut noisy = samples(HandEyeMode::EyeInHand, &extrinsic, &target);
+    for (i, sample) in noisy.iter_mut().enumerate() {
+        let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+        sample.target = pose(0.0005 * sign, -0.0003 * sign, 0.0004, 0.001 * sign, 0.0, 0.0)
+            * sample.target;
+    }
+    let solution = solve(HandEyeMode::EyeInHand, &noisy).unwrap();
+
+    let translation = (extrinsic.translation.vector - solution.extrinsic.translation.vector).norm();
+    assert!(translation < 0.005, "translation off by {}", translation);
+    assert!(extrinsic.rotation.angle_to(&solution.extrinsic.rotation) < 0.01);
+    assert!(solution.residuals.rotation_rms_deg > 0.0);
+    assert!(solution.residuals.translation_rms > 0.0);
+    assert!(solution.residuals.translation_rms <= solution.residuals.translation_max);
+}
+
+#[test]
+fn too_few_samples_are_rejected() {
+    let extrinsic = pose(0.03, -0.08, 0.12, 0.10, -0.05, 1.57);
+    let target = pose(0.55, 0.02, 0.00, 0.00, 0.00, 0.30);
+    let two = samples(HandEyeMode::EyeInHand, &extrinsic, &target)[..2].to_vec();
+
+    assert_eq!(
+        solve(HandEyeMode::EyeInHand, &two),
+        Err(CalibrationError::TooFewSamples {
+            required: 3,
+            given: 2
+        })
+    );
+}
+
+#[test]
+fn rotations_about_one_axis_are_degenerate() {
+    let samples: Vec<CalibrationSample> = (0..4)
+        .map(|i| {
+            let flange = Isometry3::from_parts(
+                Translation3::new(0.1 * i as f64, 0.0, 0.4),
+                UnitQuaternion::from_axis_angle(&Vector3::z_axis(), 0.3 * i as f64),
+            );
+            CalibrationSample {
+                flange,
+                target: flange.inverse(),
+            }
+        })
+        .collect();
+
+    assert!(matches!(
+        solve(HandEyeMode::EyeInHand, &samples),
+        Err(CalibrationError::Degenerate(_))
+    ));
+}
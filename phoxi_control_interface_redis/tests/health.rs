@@ -0,0 +1,138 @@
+// The health monitor, against a listener standing in for the scanner.
+
+mod common;
+
+use common::{connect, MockScanner};
+use micro_sp::*;
+use phoxi_control_interface_redis::core::health::{probe_scanner, HealthConfig};
+use phoxi_control_interface_redis::*;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn config(port: u16) -> HealthConfig {
+    HealthConfig {
+        period_ms: 100,
+        ports: vec![port],
+        connect_timeout_ms: 200,
+        phoxi_control: false,
+    }
+}
+
+#[tokio::test]
+async fn scanner_is_reachable_while_a_port_accepts_connections() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = config(listener.local_addr().unwrap().port());
+
+    let health = probe_scanner("127.0.0.1", &config).await;
+    assert!(health.reachable);
+    assert_eq!(health.phoxi_control_running, None);
+    assert!(health.online());
+
+    drop(listener);
+    assert!(!probe_scanner("127.0.0.1", &config).await.online());
+    assert!(!probe_scanner("", &config).await.online());
+}
+
+#[test]
+fn missing_health_settings_select_the_defaults() {
+    let parameters = serde_json::json!({"ip_identification": "192.168.1.27"});
+    assert_eq!(
+        HealthConfig::from_parameters(&parameters, "test"),
+        HealthConfig::default()
+    );
+    let parameters = serde_json::json!({"health": {"period_ms": 500}});
+    let config = HealthConfig::from_parameters(&parameters, "test");
+    assert_eq!(config.period_ms, 500);
+    assert_eq!(config.ports, HealthConfig::default().ports);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn online_scanner_is_published_with_a_heartbeat() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let photoneo_id = "phoxi_test_health".to_string();
+    let installation = tempfile::tempdir().unwrap();
+    let scanners = installation.path().join("parameters/scanners");
+    std::fs::create_dir_all(&scanners).unwrap();
+    let parameters = serde_json::json!({
+        "ip_identification": "127.0.0.1",
+        "health": {
+            "period_ms": 100,
+            "ports": [port],
+            "connect_timeout_ms": 200,
+            "phoxi_control": false
+        },
+    });
+    std::fs::write(
+        scanners.join(format!("{}.json", photoneo_id)),
+        parameters.to_string(),
+    )
+    .unwrap();
+
+    let connection_manager = connect().await;
+    let state = state::generate_photoneo_interface_state(&photoneo_id);
+    StateManager::set_state(&mut connection_manager.get_connection().await, &state).await;
+
+    let id = photoneo_id.clone();
+    let interface_path = installation.path().to_string_lossy().to_string();
+    let backend: Arc<dyn ScannerBackend> = Arc::new(MockScanner::default());
+    let interface_connection = connection_manager.clone();
+    tokio::task::spawn(async move {
+        if let Err(e) =
+            photoneo_health_interface(&id, &interface_path, &backend, &interface_connection).await
+        {
+            panic!("Health interface stopped: {}", e);
+        }
+    });
+
+    let keys: Vec<String> = ["scanner_online", "last_seen", "interface_heartbeat"]
+        .iter()
+        .map(|name| format!("{}_{}", photoneo_id, name))
+        .collect();
+    let read = || async {
+        let mut con = connection_manager.get_connection().await;
+        let state = StateManager::get_state_for_keys(&mut con, &keys)
+            .await
+            .expect("state of the health interface");
+        (
+            state.get_bool_or_default_to_false(&keys[0], "test"),
+            state.get_int_or_default_to_zero(&keys[1], "test"),
+            state.get_int_or_default_to_zero(&keys[2], "test"),
+        )
+    };
+
+    let start = Instant::now();
+    let (last_seen, heartbeat) = loop {
+        let (online, last_seen, heartbeat) = read().await;
+        if online {
+            break (last_seen, heartbeat);
+        }
+        assert!(
+            start.elapsed() < RESPONSE_TIMEOUT,
+            "Scanner never came online."
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+    assert!(last_seen > 0);
+    assert!(heartbeat >= last_seen);
+
+    drop(listener);
+    let start = Instant::now();
+    loop {
+        let (online, offline_last_seen, offline_heartbeat) = read().await;
+        if !online {
+            assert!(offline_last_seen >= last_seen);
+            assert!(offline_heartbeat > offline_last_seen);
+            break;
+        }
+        assert!(
+            start.elapsed() < RESPONSE_TIMEOUT,
+            "Scanner never went offline."
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
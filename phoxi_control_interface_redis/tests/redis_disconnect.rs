@@ -0,0 +1,44 @@
+// Its own test binary, as taking the Redis stand-in offline would break the
+// tests running next to it.
+
+mod common;
+
+use common::{redis_server, start_interface, MockScanner};
+use micro_sp::*;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn interface_recovers_after_a_redis_outage() {
+    let scanner = Arc::new(MockScanner::default());
+    let interface = start_interface("disconnect", scanner.clone()).await;
+    let Some(server) = redis_server() else {
+        // An external Redis can not be stopped from here.
+        return;
+    };
+
+    interface
+        .request(vec![("command_type", "connect".to_spvalue())])
+        .await;
+    let state = interface.response(Duration::from_secs(10)).await;
+    assert_eq!(interface.request_state(&state), "succeeded");
+
+    server.set_offline();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    server.set_online();
+
+    let interface = common::Interface {
+        connection_manager: common::connect().await,
+        ..interface
+    };
+    interface
+        .request(vec![
+            ("command_type", "capture".to_spvalue()),
+            ("scene_name", "after_outage".to_spvalue()),
+        ])
+        .await;
+    let state = interface.response(Duration::from_secs(10)).await;
+    assert_eq!(interface.request_state(&state), "succeeded");
+    assert_eq!(interface.raw_info(&state), "capture after_outage");
+    assert_eq!(*scanner.commands.lock().unwrap(), vec!["connect", "capture"]);
+}
@@ -0,0 +1,150 @@
+// Measuring saved scans and checking them against the scan_quality section
+// of a settings preset.
+
+use phoxi_control_interface_redis::core::ply::{PlyFormat, PointCloud};
+use phoxi_control_interface_redis::core::scan_quality::{check_scan, ScanQuality, ScanVerdict};
+use phoxi_control_interface_redis::core::settings::{
+    ImageRegion, OnViolation, Resolution, ScanQualitySettings, ScannerSettings,
+};
+
+// A half resolution scan of a plane 800 mm away, measured only in the
+// left half of the image.
+fn half_measured() -> PointCloud {
+    let (width, height) = (Resolution::HALF.width, Resolution::HALF.height);
+    let mut points = Vec::new();
+    for row in 0..height {
+        for column in 0..width {
+            points.push(match column < width / 2 {
+                true => [column as f32, row as f32, 800.0],
+                false => [0.0, 0.0, 0.0],
+            });
+        }
+    }
+    PointCloud {
+        points,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn scan_is_measured_and_its_roi_located_in_the_image() {
+    let roi = ImageRegion {
+        left: 0.25,
+        top: 0.0,
+        right: 0.75,
+        bottom: 1.0,
+    };
+    let quality = ScanQuality::measure(&half_measured(), Some(&roi));
+    assert_eq!(quality.points, 1032 * 772);
+    assert_eq!(quality.valid_points, 516 * 772);
+    assert_eq!(quality.invalid_fraction, 0.5);
+    assert_eq!(quality.depth_range, Some((800.0, 800.0)));
+    assert_eq!(quality.roi_coverage, Some(0.5));
+
+    let unorganized = PointCloud {
+        points: vec![[1.0, 1.0, 500.0], [f32::NAN, 0.0, 0.0]],
+        ..Default::default()
+    };
+    let quality = ScanQuality::measure(&unorganized, Some(&roi));
+    assert_eq!(quality.roi_coverage, None);
+    assert_eq!(quality.depth_range, Some((500.0, 500.0)));
+}
+
+#[test]
+fn every_threshold_the_scan_misses_is_a_violation() {
+    let quality = ScanQuality::measure(&half_measured(), None);
+    let settings = ScanQualitySettings {
+        min_valid_points: Some(400000),
+        max_invalid_fraction: Some(0.6),
+        min_depth: Some(900.0),
+        min_roi_coverage: Some(0.5),
+        ..Default::default()
+    };
+    let violations = quality.violations(&settings);
+    assert_eq!(violations.len(), 3, "{:?}", violations);
+    assert!(violations[0].starts_with("398352 valid points"));
+    assert!(violations[1].starts_with("nearest point at 800.0 mm"));
+    assert!(violations[2].starts_with("roi coverage unknown"));
+    assert!(quality
+        .violations(&ScanQualitySettings::default())
+        .is_empty());
+}
+
+#[test]
+fn verdict_follows_the_thresholds_of_the_preset() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("scene.ply");
+    half_measured()
+        .write(&path, PlyFormat::BinaryLittleEndian)
+        .unwrap();
+
+    let mut settings = ScanQualitySettings {
+        max_invalid_fraction: Some(0.25),
+        ..Default::default()
+    };
+    assert_eq!(check_scan(&path, None).verdict, ScanVerdict::Passed);
+    assert_eq!(
+        check_scan(&path, Some(&settings)).verdict,
+        ScanVerdict::Failed
+    );
+    settings.on_violation = OnViolation::Degrade;
+    assert_eq!(
+        check_scan(&path, Some(&settings)).verdict,
+        ScanVerdict::Degraded
+    );
+
+    let missing = dir.path().join("missing.ply");
+    let check = check_scan(&missing, None);
+    assert_eq!(check.verdict, ScanVerdict::Degraded);
+    assert_eq!(check.quality, None);
+    settings.on_violation = OnViolation::Fail;
+    assert_eq!(
+        check_scan(&missing, Some(&settings)).verdict,
+        ScanVerdict::Failed
+    );
+}
+
+#[test]
+fn scan_quality_section_of_the_preset_is_validated() {
+    let preset = |scan_quality: serde_json::Value| {
+        let mut preset: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/parameters/settings/default.json"
+            ))
+            .unwrap(),
+        )
+        .unwrap();
+        preset["scan_quality"] = scan_quality;
+        ScannerSettings::from_value(&preset, "test.json")
+    };
+
+    let settings = preset(serde_json::json!({
+        "min_valid_points": 1000,
+        "roi": {"left": 0.1, "top": 0.1, "right": 0.9, "bottom": 0.9},
+        "min_roi_coverage": 0.7,
+        "on_violation": "degrade"
+    }))
+    .unwrap();
+    let scan_quality = settings.scan_quality.unwrap();
+    assert_eq!(scan_quality.min_valid_points, Some(1000));
+    assert_eq!(scan_quality.on_violation, OnViolation::Degrade);
+
+    let error = preset(serde_json::json!({
+        "roi": {"left": 0.9, "top": 0.1, "right": 0.1, "bottom": 0.9},
+        "max_invalid_fraction": 1.5
+    }))
+    .unwrap_err()
+    .to_string();
+    assert!(
+        error.contains("scan_quality.max_invalid_fraction"),
+        "{}",
+        error
+    );
+    assert!(error.contains("scan_quality.roi"), "{}", error);
+
+    let error = preset(serde_json::json!({"min_points": 10}))
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("unknown field `min_points`"), "{}", error);
+}
@@ -0,0 +1,118 @@
+// The interfaces of one scanner take turns, see SerializedBackend.
+
+mod common;
+
+use common::MockScanner;
+use phoxi_control_interface_redis::core::state::ScanRequest;
+use phoxi_control_interface_redis::*;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+fn request() -> ScanRequest {
+    ScanRequest {
+        name_identification: "test".to_string(),
+        hardware_identification: "test".to_string(),
+        ip_identification: "127.0.0.1".to_string(),
+        command_type: "capture".to_string(),
+        scene_name: "scene".to_string(),
+        praw: false,
+        ply: true,
+        tif: false,
+        praw_dir: String::new(),
+        ply_dir: String::new(),
+        tif_dir: String::new(),
+        timeout: 1000,
+        settings: "default".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn commands_on_one_scanner_take_turns() {
+    let scanner = Arc::new(MockScanner {
+        hanging: true,
+        ..Default::default()
+    });
+    let backend = Arc::new(SerializedBackend::new(scanner.clone()));
+
+    let first_cancel = CancellationToken::new();
+    let first = {
+        let backend = backend.clone();
+        let cancel = first_cancel.clone();
+        tokio::spawn(async move { backend.capture(&request(), &cancel).await })
+    };
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Waits behind the first capture until it is cancelled itself.
+    let second_cancel = CancellationToken::new();
+    let canceller = second_cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        canceller.cancel();
+    });
+    let error = backend
+        .capture(&request(), &second_cancel)
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::Interrupted);
+    assert_eq!(*scanner.commands.lock().unwrap(), vec!["capture"]);
+
+    first_cancel.cancel();
+    assert!(first.await.unwrap().is_err());
+    backend
+        .stop(&request(), &CancellationToken::new())
+        .await
+        .unwrap();
+    assert_eq!(*scanner.commands.lock().unwrap(), vec!["capture", "stop"]);
+}
+
+#[tokio::test]
+async fn a_request_keeps_its_turn_between_commands() {
+    let scanner = Arc::new(MockScanner::default());
+    let backend: Arc<dyn ScannerBackend> = Arc::new(SerializedBackend::new(scanner.clone()));
+
+    let session = reserve(&backend, &CancellationToken::new()).await.unwrap();
+    session
+        .capture(&request(), &CancellationToken::new())
+        .await
+        .unwrap();
+
+    // The other interface waits until the request is done, not only until
+    // its first command is.
+    let other = {
+        let backend = backend.clone();
+        tokio::spawn(async move {
+            backend
+                .disconnect(&request(), &CancellationToken::new())
+                .await
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    session
+        .connect(&request(), &CancellationToken::new())
+        .await
+        .unwrap();
+    session
+        .stop(&request(), &CancellationToken::new())
+        .await
+        .unwrap();
+    assert_eq!(
+        *scanner.commands.lock().unwrap(),
+        vec!["capture", "connect", "stop"]
+    );
+
+    drop(session);
+    other.await.unwrap().unwrap();
+    assert_eq!(
+        *scanner.commands.lock().unwrap(),
+        vec!["capture", "connect", "stop", "disconnect"]
+    );
+
+    // Waiting for the turn gives up when the request is cancelled.
+    let _session = reserve(&backend, &CancellationToken::new()).await.unwrap();
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    let error = reserve(&backend, &cancel).await.err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::Interrupted);
+}
@@ -0,0 +1,222 @@
+// Loading settings presets and checking every value against its bounds.
+
+mod common;
+
+use common::violations;
+use phoxi_control_interface_redis::core::interface::{execution_timeout, DEFAULT_SCAN_TIMEOUT_MS};
+use phoxi_control_interface_redis::core::settings::{
+    CodingQuality, Resolution, ScannerSettings, TextureSource,
+};
+
+fn default_preset() -> serde_json::Value {
+    serde_json::from_str(
+        &std::fs::read_to_string(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/parameters/settings/default.json"
+        ))
+        .unwrap(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn presets_and_builtin_settings_are_valid() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/parameters/settings");
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path().to_string_lossy().to_string();
+        if let Err(e) = ScannerSettings::load(&path) {
+            panic!("{}", e);
+        }
+    }
+    let settings = ScannerSettings::builtin().unwrap();
+    assert_eq!(
+        settings.capturing_settings.resolution.value,
+        Resolution::FULL
+    );
+    assert_eq!(
+        settings.capturing_settings.coding_quality.value,
+        CodingQuality::High
+    );
+    assert_eq!(
+        settings.capturing_settings.texture_source.value,
+        TextureSource::Led
+    );
+}
+
+#[test]
+fn values_outside_of_their_bounds_are_rejected() {
+    let mut preset = default_preset();
+    preset["capturing_settings"]["shutter_multiplier"]["value"] = 21.into();
+    preset["capturing_settings"]["laser_power"]["value"] = 4096.into();
+    preset["processing_settings"]["max_inaccuracy"]["value"] = (-0.5).into();
+    assert_eq!(
+        violations(&preset),
+        [
+            "capturing_settings.shutter_multiplier",
+            "capturing_settings.laser_power",
+            "processing_settings.max_inaccuracy",
+        ]
+    );
+
+    // Values on the bounds are allowed.
+    let mut preset = default_preset();
+    preset["capturing_settings"]["shutter_multiplier"]["value"] = 20.into();
+    preset["capturing_settings"]["laser_power"]["value"] = 0.into();
+    assert!(ScannerSettings::from_value(&preset, "test.json").is_ok());
+}
+
+#[test]
+fn enum_bounds_follow_the_declaration_order() {
+    // Fast < High < Ultra
+    let mut preset = default_preset();
+    preset["capturing_settings"]["coding_quality"]["max"] = "High".into();
+    preset["capturing_settings"]["coding_quality"]["value"] = "Ultra".into();
+    assert_eq!(violations(&preset), ["capturing_settings.coding_quality"]);
+    preset["capturing_settings"]["coding_quality"]["value"] = "Fast".into();
+    assert!(ScannerSettings::from_value(&preset, "test.json").is_ok());
+
+    // LED < Computed < Laser < Focus
+    let mut preset = default_preset();
+    preset["capturing_settings"]["texture_source"]["min"] = "Computed".into();
+    assert_eq!(violations(&preset), ["capturing_settings.texture_source"]);
+    preset["capturing_settings"]["texture_source"]["value"] = "Laser".into();
+    assert!(ScannerSettings::from_value(&preset, "test.json").is_ok());
+
+    // Local < Small < Medium < Large
+    let mut preset = default_preset();
+    preset["experimental_settings"]["pattern_decomposition_reach"]["value"] = "Large".into();
+    preset["experimental_settings"]["pattern_decomposition_reach"]["max"] = "Medium".into();
+    assert_eq!(
+        violations(&preset),
+        ["experimental_settings.pattern_decomposition_reach"]
+    );
+}
+
+#[test]
+fn unknown_enum_variants_are_rejected() {
+    let mut preset = default_preset();
+    preset["capturing_settings"]["coding_quality"]["value"] = "Medium".into();
+    preset["processing_settings"]["surface_smoothness"]["max"] = "smooth".into();
+    let error = ScannerSettings::from_value(&preset, "test.json")
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("capturing_settings.coding_quality: unknown variant `Medium`"),
+        "{}",
+        error
+    );
+    assert!(
+        error.contains("processing_settings.surface_smoothness: unknown variant `smooth`"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn resolutions_are_ordered_by_width_and_must_be_supported() {
+    assert!(Resolution::HALF < Resolution::FULL);
+
+    let mut preset = default_preset();
+    preset["capturing_settings"]["resolution"]["min"] =
+        serde_json::json!({"width": 2064, "height": 1544});
+    preset["capturing_settings"]["resolution"]["value"] =
+        serde_json::json!({"width": 1032, "height": 772});
+    assert_eq!(violations(&preset), ["capturing_settings.resolution"]);
+
+    // Inside the bounds but not a capturing mode of the scanner.
+    let mut preset = default_preset();
+    preset["capturing_settings"]["resolution"]["value"] =
+        serde_json::json!({"width": 1280, "height": 960});
+    let error = ScannerSettings::from_value(&preset, "test.json")
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("unsupported resolution 1280x960"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn only_the_discrete_exposures_are_accepted() {
+    let mut preset = default_preset();
+    for exposure in [10.24, 49.152, 100.352] {
+        preset["capturing_settings"]["single_pattern_exposure"]["value"] = exposure.into();
+        assert!(ScannerSettings::from_value(&preset, "test.json").is_ok());
+    }
+
+    // Inside the bounds, between two exposures.
+    preset["capturing_settings"]["single_pattern_exposure"]["value"] = 15.0.into();
+    assert_eq!(
+        violations(&preset),
+        ["capturing_settings.single_pattern_exposure"]
+    );
+    // Outside of the bounds and not an exposure either.
+    preset["capturing_settings"]["single_pattern_exposure"]["value"] = 120.0.into();
+    assert_eq!(
+        violations(&preset),
+        [
+            "capturing_settings.single_pattern_exposure",
+            "capturing_settings.single_pattern_exposure"
+        ]
+    );
+}
+
+#[test]
+fn every_violation_is_reported_with_the_file() {
+    let mut preset = default_preset();
+    preset["capturing_settings"]["scan_multiplier"]["value"] = 0.into();
+    preset["capturing_settings"]["coding_strategy"]["value"] = "Fast".into();
+    preset["capturing_settings"]["led_brightness"] = 1.into();
+    preset["processing_settings"]
+        .as_object_mut()
+        .unwrap()
+        .remove("interreflections_filter");
+    preset.as_object_mut().unwrap().remove("output_settings");
+
+    // Missing and malformed fields are reported before the ranges are
+    // checked, as the settings can not be built without them.
+    let error = ScannerSettings::from_value(&preset, "big_plate.json")
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.starts_with("Settings file 'big_plate.json' is invalid: "),
+        "{}",
+        error
+    );
+    let report = error.split_once(": ").unwrap().1;
+    assert_eq!(
+        report.split("; ").collect::<Vec<_>>(),
+        [
+            "capturing_settings.coding_strategy: unknown variant `Fast`, expected `Normal` or `Interreflections`",
+            "capturing_settings.led_brightness: unknown field",
+            "processing_settings.interreflections_filter: missing field",
+            "output_settings: missing section",
+        ]
+    );
+
+    let mut preset = default_preset();
+    preset["capturing_settings"]["scan_multiplier"]["value"] = 0.into();
+    preset["capturing_settings"]["maximum_fps"]["value"] = 101.0.into();
+    let error = ScannerSettings::from_value(&preset, "big_plate.json")
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        error,
+        "Settings file 'big_plate.json' is invalid: \
+         capturing_settings.scan_multiplier: value 0 is outside of the allowed range [1, 50]; \
+         capturing_settings.maximum_fps: value 101 is outside of the allowed range [0, 100]"
+    );
+}
+
+#[test]
+fn command_is_killed_unless_asked_to_run_without_a_timeout() {
+    let millis = |timeout| execution_timeout(timeout).map(|timeout| timeout.as_millis() as u64);
+
+    assert_eq!(millis(3000), Some(3000));
+    // A timeout of 0 does not fire right away, the default one applies.
+    assert_eq!(millis(0), Some(DEFAULT_SCAN_TIMEOUT_MS));
+    // Running without a limit has to be asked for.
+    assert_eq!(millis(-1), None);
+    assert_eq!(millis(i64::MIN), None);
+}
@@ -1,15 +1,19 @@
 use futures::{Stream, StreamExt};
-use r2r::{phoxi_control_msgs::srv::Scan, ServiceRequest};
+use r2r::{phoxi_control_msgs::srv::Scan, std_srvs::srv::Trigger, ServiceRequest};
 use serde_json::Value;
 use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::{fs::File, io::BufReader};
 
 use std::io::{self, BufRead};
 use std::process::{Command, Stdio};
-use std::sync::mpsc;
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+// How often a running executable checks whether it was aborted.
+const ABORT_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -45,9 +49,20 @@ pub async fn spawn_phoxi_control_interface(
         .lock()
         .unwrap()
         .create_service::<Scan::Service>("/phoxi_control_interface")?;
+    let abort_service = arc_node
+        .lock()
+        .unwrap()
+        .create_service::<Trigger::Service>("/phoxi_control_interface/abort")?;
 
+    let running = Arc::new(RunningRequest::default());
+
+    let running_clone = running.clone();
     tokio::task::spawn(async move {
-        let result = phoxi_control_interface(service).await;
+        abort_interface(abort_service, running_clone).await;
+    });
+
+    tokio::task::spawn(async move {
+        let result = phoxi_control_interface(service, running).await;
         match result {
             Ok(()) => r2r::log_info!("phoxi_control_interface", "Service call succeeded."),
             Err(e) => r2r::log_error!(
@@ -62,6 +77,7 @@ pub async fn spawn_phoxi_control_interface(
 
 async fn phoxi_control_interface(
     mut service: impl Stream<Item = ServiceRequest<Scan::Service>> + Unpin,
+    running: Arc<RunningRequest>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     r2r::log_info!("phoxi_control_interface", "Server task spawned.");
 
@@ -70,18 +86,39 @@ async fn phoxi_control_interface(
             Some(request) => {
                 r2r::log_info!("phoxi_control_interface", "Got request.");
 
-                let response = match call_blocking_exec(request.message.clone()) {
-                    Ok(val) => {
+                running.start();
+                let message = request.message.clone();
+                let running_clone = running.clone();
+                let outcome = tokio::task::spawn_blocking(move || {
+                    call_blocking_exec(&message, &running_clone.abort)
+                })
+                .await
+                .unwrap_or_else(|e| Err(io::Error::new(io::ErrorKind::Other, e.to_string())));
+                running.finish();
+
+                let response = match outcome {
+                    Ok(ExecOutcome::Finished(val)) => {
                         r2r::log_info!("phoxi_control_interface", "Succeeded.");
                         Scan::Response {
                             success: true,
+                            cancelled: false,
                             raw: val[0].clone(),
                         }
                     }
+                    Ok(ExecOutcome::Cancelled(partial)) => {
+                        r2r::log_warn!("phoxi_control_interface", "Cancelled.");
+                        stop_acquisition(request.message.clone()).await;
+                        Scan::Response {
+                            success: false,
+                            cancelled: true,
+                            raw: partial.join("\n"),
+                        }
+                    }
                     Err(e) => {
                         r2r::log_info!("phoxi_control_interface", "Failed.");
                         Scan::Response {
                             success: false,
+                            cancelled: false,
                             raw: e.to_string(),
                         }
                     }
@@ -97,8 +134,88 @@ async fn phoxi_control_interface(
     }
 }
 
-fn call_blocking_exec(request: Scan::Request) -> Result<Vec<String>, io::Error> {
-    let args = prepare_arguments(&request);
+// The request that is being executed, if any, and whether it should be
+// aborted. Shared between the scan service and the abort service.
+#[derive(Default)]
+struct RunningRequest {
+    busy: AtomicBool,
+    abort: AtomicBool,
+}
+
+impl RunningRequest {
+    fn start(&self) {
+        self.abort.store(false, Ordering::SeqCst);
+        self.busy.store(true, Ordering::SeqCst);
+    }
+
+    fn finish(&self) {
+        self.busy.store(false, Ordering::SeqCst);
+        self.abort.store(false, Ordering::SeqCst);
+    }
+}
+
+// A call to /phoxi_control_interface/abort kills the executable of the
+// running request, which is then answered with cancelled set.
+async fn abort_interface(
+    mut service: impl Stream<Item = ServiceRequest<Trigger::Service>> + Unpin,
+    running: Arc<RunningRequest>,
+) {
+    while let Some(request) = service.next().await {
+        let response = if running.busy.load(Ordering::SeqCst) {
+            running.abort.store(true, Ordering::SeqCst);
+            r2r::log_warn!("phoxi_control_interface", "Aborting the running request.");
+            Trigger::Response {
+                success: true,
+                message: "Aborting the running request.".to_string(),
+            }
+        } else {
+            Trigger::Response {
+                success: false,
+                message: "No request is running.".to_string(),
+            }
+        };
+        request
+            .respond(response)
+            .expect("Could not send service response.");
+    }
+}
+
+// A killed Capture or Freerun can leave the scanner acquiring, so it is
+// followed by a Stop, which blocks like every other command.
+async fn stop_acquisition(request: Scan::Request) {
+    let command = request.command.to_lowercase();
+    if command != "capture" && command != "freerun" {
+        return;
+    }
+    let stop = Scan::Request {
+        command: "stop".to_string(),
+        ..request
+    };
+    let outcome =
+        tokio::task::spawn_blocking(move || call_blocking_exec(&stop, &AtomicBool::new(false)))
+            .await
+            .unwrap_or_else(|e| Err(io::Error::new(io::ErrorKind::Other, e.to_string())));
+    match outcome {
+        Ok(_) => r2r::log_info!("phoxi_control_interface", "Stopped after the abort."),
+        Err(e) => r2r::log_error!(
+            "phoxi_control_interface",
+            "Stop after the abort failed with: {}.",
+            e
+        ),
+    }
+}
+
+enum ExecOutcome {
+    Finished(Vec<String>),
+    // Aborted, with what the executable printed until then.
+    Cancelled(Vec<String>),
+}
+
+fn call_blocking_exec(
+    request: &Scan::Request,
+    abort: &AtomicBool,
+) -> Result<ExecOutcome, io::Error> {
+    let args = prepare_arguments(request);
     let mut child = Command::new(&args[0])
         .args(&args[1..])
         .stdout(Stdio::piped())
@@ -117,14 +234,32 @@ fn call_blocking_exec(request: Scan::Request) -> Result<Vec<String>, io::Error>
         tx.send(lines).unwrap();
     });
 
-    let timeout_duration = Duration::from_millis(request.timeout as u64);
+    let deadline = Instant::now() + Duration::from_millis(request.timeout as u64);
 
-    match rx.recv_timeout(timeout_duration) {
-        Ok(output_lines) => Ok(output_lines),
-        Err(_) => {
-            child.kill()?;
-            let _ = child.wait(); // Clean up the process if it's still running
-            Ok(vec!["Timeout Expired".to_string()])
+    // Waits in short steps, so that an abort does not wait for the timeout.
+    loop {
+        match rx.recv_timeout(ABORT_POLL_INTERVAL) {
+            Ok(output_lines) => return Ok(ExecOutcome::Finished(output_lines)),
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "Lost the output of the executable.",
+                ))
+            }
+            Err(RecvTimeoutError::Timeout) if abort.load(Ordering::SeqCst) => {
+                child.kill()?;
+                let _ = child.wait();
+                // The output ends with the process, unless it left children
+                // behind that still hold it.
+                let partial = rx.recv_timeout(Duration::from_secs(1)).unwrap_or_default();
+                return Ok(ExecOutcome::Cancelled(partial));
+            }
+            Err(RecvTimeoutError::Timeout) if Instant::now() >= deadline => {
+                child.kill()?;
+                let _ = child.wait(); // Clean up the process if it's still running
+                return Ok(ExecOutcome::Finished(vec!["Timeout Expired".to_string()]));
+            }
+            Err(RecvTimeoutError::Timeout) => (),
         }
     }
 }
@@ -0,0 +1,37 @@
+use r2r::std_srvs::srv::Trigger;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = r2r::Context::create()?;
+    let mut node = r2r::Node::create(ctx, "phoxi_control_abort_test", "")?;
+
+    let client = node.create_client::<Trigger::Service>("/phoxi_control_interface/abort")?;
+    let waiting_for_server = node.is_available(&client)?;
+
+    let _handle = tokio::task::spawn_blocking(move || loop {
+        node.spin_once(std::time::Duration::from_millis(100));
+    });
+
+    r2r::log_warn!("phoxi_control_abort_test", "Waiting for the server...");
+    waiting_for_server.await?;
+    r2r::log_info!("phoxi_control_abort_test", "Server available.");
+
+    match client.request(&Trigger::Request {}) {
+        Ok(future) => match future.await {
+            Ok(response) => {
+                if response.success {
+                    r2r::log_info!("phoxi_control_abort_test", "Aborted.");
+                } else {
+                    r2r::log_info!("phoxi_control_abort_test", "{}", response.message);
+                }
+            }
+            Err(e) => {
+                r2r::log_info!("phoxi_control_abort_test", "Failed: {e}");
+            }
+        },
+        Err(e) => {
+            r2r::log_info!("phoxi_control_abort_test", "Failed: {e}");
+        }
+    }
+    Ok(())
+}